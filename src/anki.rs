@@ -0,0 +1,786 @@
+//! Reads and writes Anki packages, which are ZIP files holding the database of the collection
+//! and the media it refers to
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io::Write as _,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, anyhow, bail};
+
+use crate::{
+    CardBody, CardId, cloze, config::Config, export::anki_cloze, fsrs::FSRSParams, ids,
+    import::from_anki_cloze, load_card_data, save_review, tag, zip,
+};
+
+/// Decodes the few HTML entities found in Anki fields
+fn decode_entities(text: &str) -> String {
+    let mut res = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        res.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest.find(';').map(|end| &rest[..=end]);
+        let decoded = match entity {
+            Some("&nbsp;") => " ",
+            Some("&amp;") => "&",
+            Some("&lt;") => "<",
+            Some("&gt;") => ">",
+            Some("&quot;") => "\"",
+            Some("&#39;" | "&apos;") => "'",
+            _ => {
+                res.push('&');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+        res.push_str(decoded);
+        rest = &rest[entity.map_or(1, str::len)..];
+    }
+    res.push_str(rest);
+    res
+}
+
+/// The value of an attribute in an HTML tag like `<img src="a.png">`
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let start = tag.find(&format!("{name}="))? + name.len() + 1;
+    let value = &tag[start..];
+    match value.chars().next()? {
+        quote @ ('"' | '\'') => value[1..].split(quote).next(),
+        _ => value.split([' ', '>']).next(),
+    }
+}
+
+/// Converts the HTML of an Anki field into text, with images as `![](path)` relative to `media_dir`
+fn html_to_text(html: &str, media_dir: &str) -> String {
+    let mut res = String::new();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        res.push_str(&decode_entities(&rest[..start]));
+        let Some(end) = rest[start..].find('>') else {
+            break;
+        };
+        let tag = &rest[start + 1..start + end];
+        rest = &rest[start + end + 1..];
+        let name = tag
+            .trim_start_matches('/')
+            .split([' ', '/'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match name.as_str() {
+            "br" | "div" | "p" | "li" if !res.is_empty() && !res.ends_with('\n') => res.push('\n'),
+            "img" => {
+                if let Some(src) = attribute(tag, "src") {
+                    _ = write!(res, "![]({media_dir}/{src})");
+                }
+            }
+            _ => {}
+        }
+    }
+    res.push_str(&decode_entities(rest));
+    res.trim().to_string()
+}
+
+/// A number in the JSON object kept in the `data` column of Anki's cards, like `{"s":12.5,"d":5.1}`
+fn json_number(data: &str, key: &str) -> Option<f32> {
+    let start = data.find(&format!("\"{key}\":"))? + key.len() + 3;
+    data[start..].split([',', '}']).next()?.trim().parse().ok()
+}
+
+/// The state of an Anki card, taken from the memory state of FSRS if Anki kept one,
+/// and estimated from its SM-2 interval and ease factor otherwise
+fn card_state(ivl: i64, factor: i64, data: &str, sm2_retention: f32) -> FSRSParams {
+    if let (Some(s), Some(d)) = (json_number(data, "s"), json_number(data, "d")) {
+        return FSRSParams::new(s, d);
+    }
+    #[allow(clippy::cast_precision_loss)]
+    // Negative intervals are in seconds, for cards which are still being learned
+    let interval = if ivl < 0 {
+        -ivl as f32 / (60.0 * 60.0 * 24.0)
+    } else {
+        ivl as f32
+    };
+    #[allow(clippy::cast_precision_loss)]
+    let ease = if factor > 0 {
+        factor as f32 / 1000.0
+    } else {
+        2.5
+    };
+    FSRSParams::from_sm2(interval, ease, sm2_retention)
+}
+
+/// A card of an Anki note, with its state and the time of its last review unless it is new
+struct Scheduled {
+    ord: i64,
+    state: Option<(SystemTime, FSRSParams)>,
+}
+
+/// The id of the card for a note, which is kept as the guid of notes exported by cardsharp
+fn card_id(guid: &str) -> CardId {
+    CardId::parse(guid).unwrap_or_else(|_| CardId(rand::random()))
+}
+
+/// Converts the notes of an Anki package into cards, which are appended to `output`.
+/// Notes which were exported by cardsharp keep the id of their card.
+///
+/// Media are written to the `media` directory next to `output`, and the state of cards
+/// which were reviewed in Anki is saved to the database.
+pub fn import(
+    sqlite: &mut rusqlite::Connection,
+    package: &Path,
+    output: &Path,
+    sm2_retention: f32,
+) -> anyhow::Result<()> {
+    let entries = zip::read(package)?;
+    let entry = |name: &str| entries.iter().find(|(n, _)| n == name).map(|(_, c)| c);
+    // Packages for newer versions of Anki also have an `collection.anki2` which only says to upgrade
+    let collection = entry("collection.anki21")
+        .or_else(|| entry("collection.anki2"))
+        .ok_or_else(|| match entry("collection.anki21b") {
+            Some(_) => anyhow!(
+                "{} is compressed in a format only newer versions of Anki read, \
+                 export it again with `Support older Anki versions` checked",
+                package.display()
+            ),
+            None => anyhow!("{} is not an Anki package", package.display()),
+        })?;
+
+    // The `media` entry maps the numbered entries holding the files to their names. They are
+    // written first, so the notes are only added once all they refer to is there
+    let mut media = vec![];
+    if let Some(map) = entry("media") {
+        let map = String::from_utf8_lossy(map);
+        for pair in map.trim_matches(['{', '}']).split(',') {
+            let Some((number, name)) = pair.split_once(':') else {
+                continue;
+            };
+            let (number, name) = (
+                number.trim().trim_matches('"'),
+                name.trim().trim_matches('"'),
+            );
+            let Some(contents) = entry(number) else {
+                continue;
+            };
+            if name.contains(['/', '\\']) || name.starts_with('.') {
+                bail!("Invalid media file name `{name}` in {}", package.display());
+            }
+            media.push((name.to_string(), contents));
+        }
+    }
+    let dir = output.parent().unwrap_or(Path::new("")).join("media");
+    for (name, contents) in &media {
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join(name), contents)?;
+    }
+    let media = media.len();
+
+    let (db, mut file) = tag::temp_file(&std::env::temp_dir().join("cardsharp-anki.sqlite3"))?;
+    let res = file
+        .write_all(collection)
+        .map_err(anyhow::Error::from)
+        .and_then(|()| convert(sqlite, &db, output, sm2_retention));
+    std::fs::remove_file(db)?;
+    let (notes, reviewed) = res?;
+    eprintln!(
+        "Imported {notes} notes into {}, with the history of {reviewed} cards and {media} media files",
+        output.display()
+    );
+    Ok(())
+}
+
+/// Appends the notes of an Anki collection to `output`, returning the number of notes and
+/// of reviewed cards
+fn convert(
+    sqlite: &mut rusqlite::Connection,
+    collection: &Path,
+    output: &Path,
+    sm2_retention: f32,
+) -> anyhow::Result<(usize, usize)> {
+    let anki = rusqlite::Connection::open(collection)?;
+    let config = Config::load_for(&[output.to_path_buf()])?;
+    let (version, marker) = (ids::configured(&config)?, config.marker());
+    let mut last_reviews = anki.prepare("select max(id) from revlog where cid = ?1")?;
+    let mut cards = anki.prepare(
+        "select id, ord, type, ivl, factor, data from cards where nid = ?1 order by ord",
+    )?;
+    let mut notes = anki.prepare("select id, guid, flds, tags from notes order by id")?;
+    let notes = notes
+        .query_map((), |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()
+        .context("Could not read the notes of the Anki collection")?;
+
+    let now = SystemTime::now();
+    let mut res = String::new();
+    let mut states = vec![];
+    for (note, guid, fields, tags) in &notes {
+        let scheduled = cards
+            .query_map([note], |row| {
+                let (id, ord, kind): (i64, i64, i64) = (row.get(0)?, row.get(1)?, row.get(2)?);
+                if kind == 0 {
+                    return Ok(Scheduled { ord, state: None });
+                }
+                let (ivl, factor) = (row.get(3)?, row.get(4)?);
+                let data: Option<String> = row.get(5)?;
+                let last: Option<i64> = last_reviews.query_row([id], |r| r.get(0))?;
+                let time = last
+                    .and_then(|ms| u64::try_from(ms).ok())
+                    .map_or(now, |ms| SystemTime::UNIX_EPOCH + Duration::from_millis(ms));
+                let fsrs = card_state(ivl, factor, &data.unwrap_or_default(), sm2_retention);
+                Ok(Scheduled {
+                    ord,
+                    state: Some((time, fsrs)),
+                })
+            })?
+            .collect::<Result<Vec<Scheduled>, _>>()?;
+
+        let fields: Vec<String> = fields
+            .split('\x1f')
+            .map(|f| html_to_text(f, "media"))
+            .collect();
+        let Some((front, back)) = fields.split_first() else {
+            continue;
+        };
+        let cloze = front.contains("{{c");
+        let front = front.replace('\n', " ");
+        // Clozes with different numbers are kept apart, so each is still its own card
+        let numbered = cloze && cloze::numbers(&front).len() > 1;
+        let front = if cloze && !numbered {
+            from_anki_cloze(&front)
+        } else {
+            front
+        };
+        if front.trim().is_empty() {
+            continue;
+        }
+        let reversed = !cloze && scheduled.iter().any(|c| c.ord == 1);
+
+        let id = card_id(guid);
+        let colon = if reversed { ":" } else { "" };
+        _ = write!(res, "\n{marker}--{}:{colon} {front}", version.encode(id));
+        for tag in tags.split_whitespace() {
+            _ = write!(res, " #{tag}");
+        }
+        res.push('\n');
+        for field in back.iter().filter(|f| !f.is_empty()) {
+            res.push_str(field);
+            res.push('\n');
+        }
+
+        for card in &scheduled {
+            let Some((time, fsrs)) = card.state else {
+                continue;
+            };
+            let mut card_id = id;
+            match card.ord {
+                0 => {}
+                1 if reversed => card_id = id.reversed(),
+                ord if numbered => match u8::try_from(ord + 1) {
+                    Ok(n) => card_id = id.cloze(n),
+                    Err(_) => continue,
+                },
+                _ => continue,
+            }
+            states.push((card_id, time, fsrs));
+        }
+    }
+
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(output)?
+        .write_all(res.as_bytes())?;
+    let tx = sqlite.transaction()?;
+    for &(id, time, fsrs) in &states {
+        save_review(&tx, id, time, fsrs)?;
+    }
+    tx.commit()?;
+    Ok((notes.len(), states.len()))
+}
+
+/// The first 32 bits of the SHA-1 hash of `text`, which Anki keeps to find duplicate notes
+#[allow(clippy::many_single_char_names)]
+fn checksum(text: &str) -> u32 {
+    let mut h: [u32; 5] = [
+        0x6745_2301,
+        0xEFCD_AB89,
+        0x98BA_DCFE,
+        0x1032_5476,
+        0xC3D2_E1F0,
+    ];
+    let mut data = text.as_bytes().to_vec();
+    data.push(0x80);
+    while data.len() % 64 != 56 {
+        data.push(0);
+    }
+    data.extend_from_slice(&(text.len() as u64 * 8).to_be_bytes());
+    for chunk in data.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in chunk.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..20 => ((b & c) | (!b & d), 0x5A82_7999),
+                20..40 => (b ^ c ^ d, 0x6ED9_EBA1),
+                40..60 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+                _ => (b ^ c ^ d, 0xCA62_C1D6),
+            };
+            let t = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            (e, d, c, b, a) = (d, c, b.rotate_left(30), a, t);
+        }
+        for (h, v) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(v);
+        }
+    }
+    h[0]
+}
+
+/// Quotes a string for JSON
+pub fn json_string(s: &str) -> String {
+    let mut res = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            c if c.is_control() => _ = write!(res, "\\u{:04x}", u32::from(c)),
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
+
+/// The media of an exported package, by the name they have in it
+#[derive(Default)]
+struct Media {
+    names: BTreeMap<PathBuf, String>,
+}
+
+impl Media {
+    /// The name of a file in the package, which has to be unique
+    fn name(&mut self, path: PathBuf) -> String {
+        if let Some(name) = self.names.get(&path) {
+            return name.clone();
+        }
+        let file_name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut name = file_name.clone();
+        for i in 1.. {
+            if !self.names.values().any(|n| *n == name) {
+                break;
+            }
+            name = format!("{i}-{file_name}");
+        }
+        self.names.insert(path, name.clone());
+        name
+    }
+}
+
+/// Converts the text of a card into the HTML of an Anki field, with Markdown images as `<img>` tags
+fn text_to_html(text: &str, dir: &Path, media: &mut Media) -> String {
+    let mut res = String::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let image = rest.strip_prefix("![").and_then(|r| {
+            let (_, r) = r.split_once("](")?;
+            let (target, r) = r.split_once(')')?;
+            (!target.contains("://") && !target.contains('\n')).then_some((target, r))
+        });
+        if let Some((target, r)) = image {
+            let name = media.name(dir.join(target));
+            _ = write!(res, "<img src=\"{}\">", name.replace('"', "&quot;"));
+            rest = r;
+            continue;
+        }
+        match c {
+            '&' => res.push_str("&amp;"),
+            '<' => res.push_str("&lt;"),
+            '>' => res.push_str("&gt;"),
+            '\n' => res.push_str("<br>"),
+            c => res.push(c),
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    res
+}
+
+struct NoteType {
+    id: i64,
+    name: &'static str,
+    fields: [&'static str; 2],
+    /// The question and answer of each card of a note
+    templates: &'static [(&'static str, &'static str)],
+}
+
+/// The note types of exported cards: one sided, two sided and cloze cards
+const NOTE_TYPES: [NoteType; 3] = [
+    NoteType {
+        id: 1_600_000_000_001,
+        name: "cardsharp Basic",
+        fields: ["Front", "Back"],
+        templates: &[("{{Front}}", "{{FrontSide}}<hr id=answer>{{Back}}")],
+    },
+    NoteType {
+        id: 1_600_000_000_002,
+        name: "cardsharp Basic (and reversed card)",
+        fields: ["Front", "Back"],
+        templates: &[
+            ("{{Front}}", "{{FrontSide}}<hr id=answer>{{Back}}"),
+            ("{{Back}}", "{{FrontSide}}<hr id=answer>{{Front}}"),
+        ],
+    },
+    NoteType {
+        id: 1_600_000_000_003,
+        name: "cardsharp Cloze",
+        fields: ["Text", "Back Extra"],
+        templates: &[("{{cloze:Text}}", "{{cloze:Text}}<br>{{Back Extra}}")],
+    },
+];
+
+/// The `models` column of the collection, describing [`NOTE_TYPES`]
+fn models_json() -> String {
+    let mut res = String::from("{");
+    for (i, note_type) in NOTE_TYPES.iter().enumerate() {
+        let (id, name) = (note_type.id, note_type.name);
+        let kind = i32::from(name.ends_with("Cloze"));
+        let fields: Vec<String> = note_type
+            .fields
+            .iter()
+            .enumerate()
+            .map(|(ord, field)| {
+                format!(
+                    r#"{{"name":"{field}","ord":{ord},"sticky":false,"rtl":false,"font":"Arial","size":20,"media":[]}}"#
+                )
+            })
+            .collect();
+        let templates: Vec<String> = note_type
+            .templates
+            .iter()
+            .enumerate()
+            .map(|(ord, (question, answer))| {
+                format!(
+                    r#"{{"name":"Card {}","ord":{ord},"qfmt":{},"afmt":{},"bqfmt":"","bafmt":"","did":null,"bfont":"","bsize":0}}"#,
+                    ord + 1,
+                    json_string(question),
+                    json_string(answer)
+                )
+            })
+            .collect();
+        let requirements: Vec<String> = (0..templates.len())
+            .map(|ord| format!(r#"[{ord},"any",[{ord}]]"#))
+            .collect();
+        if i > 0 {
+            res.push(',');
+        }
+        _ = write!(
+            res,
+            r#""{id}":{{"id":{id},"name":"{name}","type":{kind},"mod":0,"usn":0,"sortf":0,"did":1,"tmpls":[{}],"flds":[{}],"css":".card {{ font-family: arial; font-size: 20px; text-align: center; }}","latexPre":"\\documentclass[12pt]{{article}}\n\\special{{papersize=3in,5in}}\n\\usepackage{{amssymb,amsmath}}\n\\pagestyle{{empty}}\n\\begin{{document}}\n","latexPost":"\\end{{document}}","latexsvg":false,"req":[{}],"tags":[],"vers":[]}}"#,
+            templates.join(","),
+            fields.join(","),
+            requirements.join(",")
+        );
+    }
+    res.push('}');
+    res
+}
+
+/// The `decks` column of the collection, with a deck for each name besides the default deck
+fn decks_json(decks: &[String]) -> String {
+    let deck = |id: usize, name: &str| {
+        format!(
+            r#""{id}":{{"id":{id},"name":{},"mod":0,"usn":0,"lrnToday":[0,0],"revToday":[0,0],"newToday":[0,0],"timeToday":[0,0],"collapsed":false,"browserCollapsed":false,"desc":"","dyn":0,"conf":1,"extendNew":0,"extendRev":0}}"#,
+            json_string(name)
+        )
+    };
+    let mut res = vec![deck(1, "Default")];
+    res.extend(decks.iter().enumerate().map(|(i, name)| deck(i + 2, name)));
+    format!("{{{}}}", res.join(","))
+}
+
+const DECK_CONFIG: &str = r#"{"1":{"id":1,"name":"Default","mod":0,"usn":0,"maxTaken":60,"autoplay":true,"timer":0,"replayq":true,"dyn":false,"new":{"bury":false,"delays":[1,10],"initialFactor":2500,"ints":[1,4,0],"order":1,"perDay":20},"lapse":{"delays":[10],"leechAction":1,"leechFails":8,"minInt":1,"mult":0},"rev":{"bury":false,"ease4":1.3,"ivlFct":1,"maxIvl":36500,"perDay":200,"hardFactor":1.2}}}"#;
+const COLLECTION_CONFIG: &str = r#"{"nextPos":1,"estTimes":true,"activeDecks":[1],"sortType":"noteFld","timeLim":0,"sortBackwards":false,"addToCur":true,"curDeck":1,"newSpread":0,"dueCounts":true,"curModel":1600000000001,"collapseTime":1200}"#;
+
+/// Schema 11 of the Anki collection, which every version of Anki since 2.1 can import
+const SCHEMA: &str = "
+    create table col (id integer primary key, crt integer not null, mod integer not null,
+        scm integer not null, ver integer not null, dty integer not null, usn integer not null,
+        ls integer not null, conf text not null, models text not null, decks text not null,
+        dconf text not null, tags text not null);
+    create table notes (id integer primary key, guid text not null, mid integer not null,
+        mod integer not null, usn integer not null, tags text not null, flds text not null,
+        sfld integer not null, csum integer not null, flags integer not null, data text not null);
+    create table cards (id integer primary key, nid integer not null, did integer not null,
+        ord integer not null, mod integer not null, usn integer not null, type integer not null,
+        queue integer not null, due integer not null, ivl integer not null, factor integer not null,
+        reps integer not null, lapses integer not null, left integer not null, odue integer not null,
+        odid integer not null, flags integer not null, data text not null);
+    create table revlog (id integer primary key, cid integer not null, usn integer not null,
+        ease integer not null, ivl integer not null, lastIvl integer not null, factor integer not null,
+        time integer not null, type integer not null);
+    create table graves (usn integer not null, oid integer not null, type integer not null);
+";
+
+/// Writes cards to an Anki package, with a deck for each file and the media the cards refer to.
+///
+/// Reviewed cards keep their stability and difficulty as the memory state of Anki's FSRS scheduler,
+/// and are due when their recall is predicted to fall to `retention`.
+pub fn export(
+    sqlite: &mut rusqlite::Connection,
+    cards: &[CardBody],
+    out: &Path,
+    retention: f32,
+) -> anyhow::Result<()> {
+    let (db, file) = tag::temp_file(&std::env::temp_dir().join("cardsharp-apkg.sqlite3"))?;
+    drop(file);
+    let res = write_collection(sqlite, cards, &db, retention);
+    let collection = std::fs::read(&db);
+    _ = std::fs::remove_file(&db);
+    let (notes, media) = res?;
+
+    let mut entries = vec![("collection.anki21".to_string(), collection?)];
+    let mut map = vec![];
+    for (i, (path, name)) in media.names.iter().enumerate() {
+        // Missing media are reported by `media missing`, not here
+        let Ok(contents) = std::fs::read(path) else {
+            continue;
+        };
+        map.push(format!("\"{i}\":{}", json_string(name)));
+        entries.push((i.to_string(), contents));
+    }
+    entries.push((
+        "media".to_string(),
+        format!("{{{}}}", map.join(",")).into_bytes(),
+    ));
+    zip::write(out, &entries)?;
+    eprintln!(
+        "Exported {notes} notes and {} media files to {}",
+        map.len(),
+        out.display()
+    );
+    Ok(())
+}
+
+/// Takes the `#tags` out of the first line of the front of a card, and returns them the way
+/// Anki keeps them, as ` tag1 tag2 `
+fn split_tags(front: &str) -> (String, String) {
+    let (first, rest) = front.split_once('\n').unwrap_or((front, ""));
+    let (tags, words): (Vec<&str>, Vec<&str>) = first
+        .split_whitespace()
+        .partition(|w| w.len() > 1 && w.starts_with('#'));
+    let mut text = words.join(" ");
+    if !rest.is_empty() {
+        text.push('\n');
+        text.push_str(rest);
+    }
+    let tags: Vec<&str> = tags.iter().map(|t| &t[1..]).collect();
+    let tags = if tags.is_empty() {
+        String::new()
+    } else {
+        format!(" {} ", tags.join(" "))
+    };
+    (text, tags)
+}
+
+/// The type, due day, interval and FSRS memory state of a card in Anki, whose days are counted from `created`.
+/// New cards are due in the order they were written, given by `position`
+fn schedule(
+    state: Option<(SystemTime, FSRSParams)>,
+    retention: f32,
+    created: u64,
+    position: usize,
+) -> anyhow::Result<(i32, i64, i64, String)> {
+    let Some((time, fsrs)) = state else {
+        return Ok((0, i64::try_from(position)?, 0, String::new()));
+    };
+    // The interval the scheduler itself would give the card
+    let interval = fsrs.interval(retention).round().max(1.0);
+    #[allow(clippy::cast_possible_truncation)]
+    let ivl = interval as i64;
+    let reviewed = time.duration_since(SystemTime::UNIX_EPOCH)?.as_secs();
+    let due = (i64::try_from(reviewed)? - i64::try_from(created)?).div_euclid(86400) + ivl;
+    let data = format!(
+        r#"{{"s":{},"d":{},"dr":{retention}}}"#,
+        fsrs.stability, fsrs.difficulty
+    );
+    Ok((2, due, ivl, data))
+}
+
+/// Writes the collection database of an exported package, returning the number of notes and their media
+fn write_collection(
+    sqlite: &mut rusqlite::Connection,
+    cards: &[CardBody],
+    db: &Path,
+    retention: f32,
+) -> anyhow::Result<(usize, Media)> {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_secs();
+    // Days of review cards are counted from the creation of the collection
+    let created = now - now % 86400;
+    let mut decks: Vec<String> = vec![];
+    let mut media = Media::default();
+
+    let mut anki = rusqlite::Connection::open(db)?;
+    anki.execute_batch(SCHEMA)?;
+    let tx = anki.transaction()?;
+    let mut notes = 0;
+    // Generated cards have no fixed question to export
+    for card in cards.iter().filter(|c| !c.reversed && !c.generated) {
+        let reversed = cards
+            .iter()
+            .find(|c| c.reversed && c.file == card.file && c.line == card.line);
+        let (front, tags) = split_tags(&card.front);
+        let cloze = cloze::has_deletion(&front) && reversed.is_none();
+
+        let dir = card.file.parent().unwrap_or(Path::new(""));
+        let front_html = text_to_html(&front, dir, &mut media);
+        let front_html = if cloze {
+            anki_cloze(&front_html)
+        } else {
+            front_html
+        };
+        let fields = format!(
+            "{front_html}\x1f{}",
+            text_to_html(card.back.trim(), dir, &mut media)
+        );
+        let deck = if card.deck.is_empty() {
+            "cardsharp".to_string()
+        } else {
+            card.deck.clone()
+        };
+        // The default deck is the first
+        let deck = if let Some(i) = decks.iter().position(|d| *d == deck) {
+            i + 2
+        } else {
+            decks.push(deck);
+            decks.len() + 1
+        };
+        let note_type = if cloze {
+            &NOTE_TYPES[2]
+        } else {
+            &NOTE_TYPES[usize::from(reversed.is_some())]
+        };
+        // The id of the card is kept as the guid, so that exporting again updates the notes
+        let note = i64::try_from(card.id.as_int())?;
+        tx.execute(
+            "insert into notes values (?1, ?2, ?3, ?4, -1, ?5, ?6, ?7, ?8, 0, '')",
+            rusqlite::params![
+                note,
+                card.id.to_string(),
+                note_type.id,
+                now,
+                tags,
+                fields,
+                front,
+                checksum(&front),
+            ],
+        )?;
+        notes += 1;
+
+        for (ord, id) in std::iter::once(card.id)
+            .chain(reversed.map(|c| c.id))
+            .enumerate()
+        {
+            let (kind, due, ivl, data) =
+                schedule(load_card_data(sqlite, id), retention, created, notes)?;
+            tx.execute(
+                "insert into cards values (?1, ?2, ?3, ?4, ?5, -1, ?6, ?6, ?7, ?8, ?9, ?10, 0, 0, 0, 0, 0, ?11)",
+                rusqlite::params![
+                    i64::try_from(id.as_int())?,
+                    note,
+                    i64::try_from(deck)?,
+                    ord,
+                    now,
+                    kind,
+                    due,
+                    ivl,
+                    if kind == 0 { 0 } else { 2500 },
+                    i32::from(kind != 0),
+                    data,
+                ],
+            )?;
+        }
+    }
+    tx.execute(
+        "insert into col values (1, ?1, ?2, ?2, 11, 0, 0, 0, ?3, ?4, ?5, ?6, '{}')",
+        rusqlite::params![
+            created,
+            now * 1000,
+            COLLECTION_CONFIG,
+            models_json(),
+            decks_json(&decks),
+            DECK_CONFIG
+        ],
+    )?;
+    tx.commit()?;
+    Ok((notes, media))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn fields() {
+        assert_eq!(
+            html_to_text(
+                "<div>Tokyo &amp; Kyoto</div><div>are in&nbsp;Japan<br><img src=\"map.png\"></div>",
+                "media"
+            ),
+            "Tokyo & Kyoto\nare in Japan\n![](media/map.png)"
+        );
+        assert_eq!(html_to_text("a &lt; b &c", "media"), "a < b &c");
+        assert_eq!(
+            card_state(0, 0, "{\"s\":12.5,\"d\":5.25,\"dr\":0.9}", 0.9),
+            FSRSParams::new(12.5, 5.25)
+        );
+    }
+
+    #[test]
+    pub fn notes() {
+        let mut media = Media::default();
+        assert_eq!(
+            text_to_html(
+                "a < b\n![plot](img/plot.png)",
+                Path::new("notes"),
+                &mut media
+            ),
+            "a &lt; b<br><img src=\"plot.png\">"
+        );
+        // Files with the same name in different directories are kept apart
+        assert_eq!(media.name(PathBuf::from("other/plot.png")), "1-plot.png");
+        assert_eq!(checksum("abc"), 0xa999_3e36);
+        assert_eq!(
+            split_tags("Capital of Japan? #geo #asia"),
+            ("Capital of Japan?".to_string(), " geo asia ".to_string())
+        );
+        assert_eq!(
+            split_tags("Which line? #code\n#include <a>\n#include <b>"),
+            (
+                "Which line?\n#include <a>\n#include <b>".to_string(),
+                " code ".to_string()
+            )
+        );
+    }
+}
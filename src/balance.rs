@@ -0,0 +1,205 @@
+//! Load balancing of due dates, so that the days ahead have about as many reviews each.
+//!
+//! Once answers are saved, each answered card is moved to the UTC day with the fewest cards due
+//! out of those its fuzzed interval could fall on, counting every other card already scheduled.
+//! Between days which are as busy, the one closest to its random fuzz is kept. The fuzz picked
+//! is kept in the `balance` table with the time of the review, and used instead of the random
+//! one until the card is reviewed again. `load_balance = false` in the config turns this off
+use std::{cell::RefCell, collections::HashMap, time::SystemTime};
+
+use crate::{
+    CardId,
+    config::{Config, Value},
+    deck, from_millis,
+    fsrs::FSRSParams,
+    fuzz, millis,
+};
+
+const DAY_MS: f64 = 86_400_000.0;
+
+thread_local! {
+    /// The fuzz picked for each card, with the time of the review it was picked for
+    static CHOSEN: RefCell<HashMap<CardId, (u64, f32)>> = RefCell::new(HashMap::new());
+}
+
+pub fn enabled(config: &Config) -> bool {
+    !matches!(config.get("load_balance"), Some(Value::Bool(false)))
+}
+
+/// Reads the fuzz picked for the cards from the database
+pub fn load(sqlite: &rusqlite::Connection) -> anyhow::Result<()> {
+    let mut statement = sqlite.prepare("select card, reviewed, fuzz from balance")?;
+    let chosen = statement
+        .query_map((), |row| {
+            Ok((CardId::from_int(row.get(0)?), (row.get(1)?, row.get(2)?)))
+        })?
+        .collect::<Result<_, _>>()?;
+    CHOSEN.set(chosen);
+    Ok(())
+}
+
+/// The fuzz picked for a card after its review at `reviewed`, from -1 to 1
+pub fn chosen(id: CardId, reviewed: SystemTime) -> Option<f32> {
+    let reviewed = millis(reviewed).ok()?;
+    CHOSEN.with_borrow(|chosen| {
+        chosen
+            .get(&id)
+            .and_then(|&(time, fuzz)| (time == reviewed).then_some(fuzz))
+    })
+}
+
+/// Days since the epoch
+fn days(time: SystemTime) -> f64 {
+    #[allow(clippy::cast_precision_loss)]
+    let ms = millis(time).unwrap_or_default() as f64;
+    ms / DAY_MS
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn day(days: f64) -> i64 {
+    days.floor() as i64
+}
+
+/// The time between `lo` and `hi` in days since the epoch on the day with the fewest cards due,
+/// staying at `seeded` if no other day is less busy than its own
+fn pick(seeded: f64, (lo, hi): (f64, f64), load: impl Fn(i64) -> usize) -> f64 {
+    let mut best = (usize::MAX, f64::MAX, seeded);
+    for d in day(lo)..=day(hi) {
+        #[allow(clippy::cast_precision_loss)]
+        let (start, end) = (lo.max(d as f64), hi.min((d + 1) as f64));
+        if start >= end {
+            continue;
+        }
+        let (distance, at) = if (start..end).contains(&seeded) {
+            (0.0, seeded)
+        } else {
+            (
+                (seeded - start).abs().min((seeded - end).abs()),
+                f64::midpoint(start, end),
+            )
+        };
+        if (load(d), distance) < (best.0, best.1) {
+            best = (load(d), distance, at);
+        }
+    }
+    best.2
+}
+
+/// The number of cards due on each day, counting the last state of every reviewed card which is
+/// not suspended
+pub struct Forecast {
+    due: HashMap<CardId, i64>,
+    load: HashMap<i64, usize>,
+}
+
+impl Forecast {
+    pub fn load(
+        sqlite: &rusqlite::Connection,
+        retention: &deck::Retention,
+    ) -> anyhow::Result<Self> {
+        let mut statement = sqlite.prepare(
+            "select r.card, r.last_reviewed, r.stability, r.difficulty, location.deck from review r
+                 left join location on location.card = r.card
+                 where r.card not in (select card from suspended)
+                 and r.rowid in (
+                     select (select rowid from review where card = r.card
+                                 order by last_reviewed desc, rowid desc limit 1)
+                     from review r group by card
+                 )",
+        )?;
+        let rows = statement.query_map((), |row| {
+            Ok((
+                CardId::from_int(row.get(0)?),
+                from_millis(row.get(1)?),
+                FSRSParams {
+                    stability: row.get::<_, Option<f32>>(2)?.unwrap_or(f32::NAN),
+                    difficulty: row.get::<_, Option<f32>>(3)?.unwrap_or(f32::NAN),
+                },
+                row.get::<_, Option<String>>(4)?,
+            ))
+        })?;
+        let mut forecast = Self {
+            due: HashMap::new(),
+            load: HashMap::new(),
+        };
+        for row in rows {
+            let (id, reviewed, fsrs, deck) = row?;
+            if fsrs.is_valid() {
+                let target = retention.for_deck(deck.as_deref().unwrap_or_default());
+                forecast.count(id, reviewed, fsrs, target);
+            }
+        }
+        Ok(forecast)
+    }
+
+    fn count(
+        &mut self,
+        id: CardId,
+        reviewed: SystemTime,
+        fsrs: FSRSParams,
+        (retention, max): (f32, Option<f32>),
+    ) {
+        let interval = fuzz::interval(id, reviewed, fsrs, retention, max);
+        let due = day(days(reviewed) + f64::from(interval));
+        if let Some(old) = self.due.insert(id, due) {
+            self.load.entry(old).and_modify(|n| *n -= 1);
+        }
+        *self.load.entry(due).or_default() += 1;
+    }
+
+    /// Moves a card answered at `reviewed` to the least busy day its fuzz allows, unless it was
+    /// already moved after that answer
+    pub fn place(
+        &mut self,
+        sqlite: &rusqlite::Connection,
+        id: CardId,
+        (reviewed, fsrs): (SystemTime, FSRSParams),
+        (retention, max): (f32, Option<f32>),
+    ) -> anyhow::Result<()> {
+        let interval = fsrs.interval(retention);
+        let range = fuzz::range(interval);
+        let cap = max.unwrap_or(f32::MAX);
+        let (lo, hi) = ((interval - range).min(cap), (interval + range).min(cap));
+        if chosen(id, reviewed).is_none() && lo < hi {
+            // The card itself is not in the way
+            if let Some(old) = self.due.remove(&id) {
+                self.load.entry(old).and_modify(|n| *n -= 1);
+            }
+            let start = days(reviewed);
+            let seeded = (interval + fuzz::seeded(id, reviewed) * range).min(cap);
+            let due = pick(
+                start + f64::from(seeded),
+                (start + f64::from(lo), start + f64::from(hi)),
+                |d| self.load.get(&d).copied().unwrap_or_default(),
+            );
+            #[allow(clippy::cast_possible_truncation)]
+            let fuzz = (((due - start) as f32 - interval) / range).clamp(-1.0, 1.0);
+            let reviewed = millis(reviewed)?;
+            sqlite.execute(
+                "insert or replace into balance(card, reviewed, fuzz) values (?1, ?2, ?3)",
+                (id.as_int(), reviewed, fuzz),
+            )?;
+            CHOSEN.with_borrow_mut(|chosen| chosen.insert(id, (reviewed, fuzz)));
+        }
+        self.count(id, reviewed, fsrs, (retention, max));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn picked() {
+        let load = |d: i64| [5, 3, 0, 3, 9][usize::try_from(d - 100).unwrap()];
+        // Moved into the middle of the emptiest day
+        assert!((pick(100.3, (100.2, 104.5), load) - 102.5).abs() < 1e-9);
+        // Kept where it was when no day in reach is less busy
+        assert!((pick(101.4, (100.2, 101.8), load) - 101.4).abs() < 1e-9);
+        // The nearer of two days as busy
+        assert!((pick(104.2, (101.0, 104.5), |d| load(d).max(3)) - 103.5).abs() < 1e-9);
+        // Only the part of a day which is in reach counts
+        assert!((pick(100.9, (100.8, 102.2), load) - 102.1).abs() < 1e-9);
+    }
+}
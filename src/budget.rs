@@ -0,0 +1,184 @@
+//! A weekly target of new cards, and a ceiling on the reviews they lead to.
+//!
+//! With `new_per_week` in the config, reviews introduce at most that many new cards in any
+//! 7 days. With `max_daily_reviews` too, no new cards are introduced at all while the simulator
+//! predicts that keeping up the target would average more reviews a day over the next 30 days
+use std::time::SystemTime;
+
+use anyhow::bail;
+
+use crate::{
+    config::{Config, Value},
+    cost::Costs,
+    days_between, from_millis,
+    fsrs::FSRSParams,
+    millis, simulate,
+    ui::Stylize,
+};
+
+const DAYS: u32 = 30;
+
+pub struct Budget {
+    pub per_week: usize,
+    pub ceiling: Option<f32>,
+}
+
+/// The average reviews a day over the next 30 days, and the minutes they take if any answer
+/// times are known
+#[derive(Debug, Copy, Clone)]
+pub struct Projection {
+    pub reviews: f32,
+    pub minutes: Option<f32>,
+}
+
+/// Projects the next 30 days of reviews if `per_week` new cards keep being introduced
+pub fn project(
+    sqlite: &mut rusqlite::Connection,
+    per_week: usize,
+    retention: f32,
+) -> anyhow::Result<Projection> {
+    let now = SystemTime::now();
+    let costs = Costs::load(sqlite)?;
+    let mut statement = sqlite.prepare(
+        "select r.last_reviewed, r.stability, r.difficulty, location.deck from review r
+             left join location on location.card = r.card
+             where r.rowid in (
+                 select (select rowid from review where card = r.card
+                             order by last_reviewed desc, rowid desc limit 1)
+                 from review r group by card
+             )",
+    )?;
+    let mut cards = statement
+        .query_map((), |row| {
+            Ok(simulate::SimCard {
+                last_reviewed: Some((
+                    -days_between(from_millis(row.get(0)?), now),
+                    FSRSParams {
+                        stability: row.get(1)?,
+                        difficulty: row.get(2)?,
+                    },
+                )),
+                seconds: costs.of(row.get::<_, Option<String>>(3)?.as_deref()),
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let new = per_week * DAYS as usize / 7;
+    cards.extend(std::iter::repeat_n(
+        simulate::SimCard {
+            last_reviewed: None,
+            seconds: costs.of(None),
+        },
+        new,
+    ));
+
+    let days = simulate::run(
+        cards,
+        &simulate::Options {
+            days: DAYS,
+            new_per_day: per_week.div_ceil(7),
+            retention,
+            stability_scale: 1.0,
+            seed: 0,
+        },
+    );
+    #[allow(clippy::cast_precision_loss)]
+    Ok(Projection {
+        reviews: days.iter().map(|d| d.reviews).sum::<usize>() as f32 / DAYS as f32,
+        minutes: costs
+            .known()
+            .then(|| days.iter().map(|d| d.seconds).sum::<f32>() / 60.0 / DAYS as f32),
+    })
+}
+
+impl Budget {
+    /// The budget set in the config, if any
+    pub fn from_config(config: &Config) -> anyhow::Result<Option<Self>> {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let per_week = match config.get("new_per_week") {
+            None => return Ok(None),
+            Some(Value::Number(n)) if *n >= 0.0 && n.fract() == 0.0 => *n as usize,
+            Some(_) => bail!("`new_per_week` should be a whole number"),
+        };
+        #[allow(clippy::cast_possible_truncation)]
+        let ceiling = match config.get("max_daily_reviews") {
+            None => None,
+            Some(Value::Number(n)) if *n > 0.0 => Some(*n as f32),
+            Some(_) => bail!("`max_daily_reviews` should be a positive number"),
+        };
+        Ok(Some(Self { per_week, ceiling }))
+    }
+
+    /// The reviews a day over the next 30 days, if new cards keep being introduced at the
+    /// weekly target
+    pub fn projected(
+        &self,
+        sqlite: &mut rusqlite::Connection,
+        retention: f32,
+    ) -> anyhow::Result<Projection> {
+        project(sqlite, self.per_week, retention)
+    }
+
+    /// How many more new cards can be introduced now, and the projected reviews a day if that
+    /// is none because of the ceiling
+    pub fn left(
+        &self,
+        sqlite: &mut rusqlite::Connection,
+        retention: f32,
+    ) -> anyhow::Result<(usize, Option<f32>)> {
+        if let Some(ceiling) = self.ceiling {
+            let projected = self.projected(sqlite, retention)?.reviews;
+            if projected > ceiling {
+                return Ok((0, Some(projected)));
+            }
+        }
+        Ok((self.per_week.saturating_sub(introduced(sqlite)?), None))
+    }
+}
+
+/// The number of cards first reviewed in the last 7 days
+pub fn introduced(sqlite: &mut rusqlite::Connection) -> anyhow::Result<usize> {
+    let week_ago = millis(SystemTime::now())? - 7 * 86_400_000;
+    Ok(sqlite.query_row(
+        "select count(*) from (select min(last_reviewed) as first from review group by card)
+             where first >= ?1",
+        [week_ago],
+        |row| row.get(0),
+    )?)
+}
+
+/// A bar filled by how much of `target` is `used`, in the bad style once over it
+pub fn bar(used: usize, target: usize, width: usize) -> String {
+    let filled = if target == 0 {
+        width
+    } else {
+        (used * width).div_ceil(target).min(width)
+    };
+    let bar = format!("{}{}", "█".repeat(filled), "░".repeat(width - filled));
+    if used > target {
+        bar.bad().to_string()
+    } else {
+        bar
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn budget() {
+        let budget = |s| Budget::from_config(&Config::parse(s).unwrap());
+        assert!(budget("").unwrap().is_none());
+        let set = budget("new_per_week = 70\nmax_daily_reviews = 100")
+            .unwrap()
+            .unwrap();
+        assert_eq!(set.per_week, 70);
+        assert!(set.ceiling.is_some());
+        assert!(budget("new_per_week = 2.5").is_err());
+
+        assert_eq!(bar(0, 20, 10), "░".repeat(10));
+        assert_eq!(bar(1, 20, 10), format!("█{}", "░".repeat(9)));
+        assert_eq!(bar(20, 20, 10), "█".repeat(10));
+        assert_eq!(bar(30, 20, 10), "█".repeat(10));
+    }
+}
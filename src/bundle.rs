@@ -0,0 +1,194 @@
+//! Packs card files, their media and their review history into a single file, which can be
+//! carried to another machine and reviewed from directly.
+//!
+//! A bundle starts with the line `cardsharp-bundle 1`, followed by entries which each are a line
+//! `<length in bytes> <path>` and the contents of the file. The entry `.cardsharp-files` lists the card files.
+use std::{
+    io::{BufRead, BufReader, Read},
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail};
+
+use crate::{CardBody, media};
+
+const MAGIC: &str = "cardsharp-bundle 1\n";
+/// Name of the database in the bundle
+const DB: &str = "db.sqlite3";
+/// Name of the list of card files in the bundle
+const FILES: &str = ".cardsharp-files";
+
+fn write_archive(path: &Path, entries: &[(String, Vec<u8>)]) -> anyhow::Result<()> {
+    let mut data = MAGIC.as_bytes().to_vec();
+    for (name, contents) in entries {
+        data.extend_from_slice(format!("{} {name}\n", contents.len()).as_bytes());
+        data.extend_from_slice(contents);
+    }
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    std::fs::write(&tmp, data)?;
+    std::fs::rename(tmp, path)?;
+    Ok(())
+}
+
+fn read_archive(path: &Path) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+    let mut reader = BufReader::new(std::fs::File::open(path)?);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if line != MAGIC {
+        bail!("{} is not a cardsharp bundle", path.display());
+    }
+    let mut res = vec![];
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        let (len, name) = line
+            .trim_end_matches('\n')
+            .split_once(' ')
+            .ok_or_else(|| anyhow!("Invalid entry `{line}` in bundle"))?;
+        let mut contents = vec![0; len.parse()?];
+        reader.read_exact(&mut contents)?;
+        res.push((name.to_string(), contents));
+    }
+    Ok(res)
+}
+
+/// The path of a file relative to the current directory, where it is kept in the bundle
+fn relative(path: &Path) -> anyhow::Result<String> {
+    let cwd = std::env::current_dir()?;
+    let path = std::fs::canonicalize(path)?;
+    let rel = path.strip_prefix(&cwd).map_err(|_| {
+        anyhow!(
+            "{} is outside of the current directory, so can not be bundled",
+            path.display()
+        )
+    })?;
+    Ok(rel.to_string_lossy().into_owned())
+}
+
+/// Bundles `files`, the media their cards refer to, and the history of their cards
+pub fn create(
+    sqlite: &mut rusqlite::Connection,
+    out: &Path,
+    files: &[PathBuf],
+    cards: &[CardBody],
+) -> anyhow::Result<()> {
+    let mut entries = vec![];
+    let mut list = String::new();
+    for file in files {
+        let name = relative(file)?;
+        list.push_str(&name);
+        list.push('\n');
+        entries.push((name, std::fs::read(file)?));
+    }
+    entries.push((FILES.to_string(), list.into_bytes()));
+    for (path, _) in media::all_references(cards) {
+        // Missing media are reported by `media missing`, not here
+        if path.exists() {
+            entries.push((relative(&path)?, std::fs::read(&path)?));
+        }
+    }
+
+    // Copy the database, then remove the history of every other card
+    let db = std::env::temp_dir().join(format!("cardsharp-bundle-{}.sqlite3", std::process::id()));
+    _ = std::fs::remove_file(&db);
+    sqlite.execute("vacuum into ?1", [db.to_string_lossy()])?;
+    let slice = rusqlite::Connection::open(&db)?;
+    slice.execute("create temp table keep(card int primary key)", ())?;
+    for card in cards {
+        slice.execute(
+            "insert or ignore into keep(card) values (?1)",
+            [card.id.as_int()],
+        )?;
+    }
+    for table in [
+        "review",
+        "content",
+        "custom_data",
+        "review_log",
+        "balance",
+        "snooze",
+        "suspended",
+    ] {
+        slice.execute(
+            &format!("delete from {table} where card not in (select card from keep)"),
+            (),
+        )?;
+    }
+    // Where the files are on this machine means nothing in the bundle
+    slice.execute("delete from location", ())?;
+    slice.execute("vacuum", ())?;
+    drop(slice);
+    entries.push((DB.to_string(), std::fs::read(&db)?));
+    std::fs::remove_file(db)?;
+
+    write_archive(out, &entries)?;
+    eprintln!(
+        "Bundled {} files and the history of {} cards into {}",
+        entries.len() - 2,
+        cards.len(),
+        out.display()
+    );
+    Ok(())
+}
+
+/// Checks that a path from a bundle stays inside the directory it is unpacked to
+fn check_name(name: &str) -> anyhow::Result<&Path> {
+    let path = Path::new(name);
+    if !path.components().all(|c| matches!(c, Component::Normal(_))) {
+        bail!("Invalid path `{name}` in bundle");
+    }
+    Ok(path)
+}
+
+/// Unpacks a bundle into `dir`, with the database directly inside it, returning the paths of the card files
+pub fn unpack(bundle: &Path, dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for (name, contents) in read_archive(bundle)? {
+        if name == FILES {
+            files = String::from_utf8(contents)?
+                .lines()
+                .map(|l| Ok(dir.join(check_name(l)?)))
+                .collect::<anyhow::Result<_>>()?;
+            continue;
+        }
+        let path = dir.join(check_name(&name)?);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, contents)?;
+    }
+    Ok(files)
+}
+
+/// Packs an unpacked bundle back up, after it has been reviewed
+pub fn repack(bundle: &Path, dir: &Path) -> anyhow::Result<()> {
+    let mut entries = vec![];
+    for (name, contents) in read_archive(bundle)? {
+        if name == FILES {
+            entries.push((name, contents));
+        } else {
+            entries.push((name.clone(), std::fs::read(dir.join(&name))?));
+        }
+    }
+    write_archive(bundle, &entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn archive() {
+        let path = std::env::temp_dir().join(format!("cardsharp-test-{}", std::process::id()));
+        let entries = vec![
+            ("notes/a b.md".to_string(), b"REVIEW: x\n".to_vec()),
+            (DB.to_string(), vec![0, 10, 255]),
+        ];
+        write_archive(&path, &entries).unwrap();
+        assert_eq!(read_archive(&path).unwrap(), entries);
+        std::fs::remove_file(path).unwrap();
+    }
+}
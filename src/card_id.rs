@@ -0,0 +1,136 @@
+//! Card ids, and how the ids of the cards sharing a `REVIEW` line are derived from it.
+//!
+//! An id is 6 random bytes, written after the marker like `REVIEW--BC6l5wau:`. Version 1 writes
+//! them in standard base64, and version 2 as `v2-` and URL safe base64, like
+//! `REVIEW--v2-BC6l5wau:`; both are always read. The other side of a two sided card has the
+//! highest bit of the first byte flipped, and the card asking the clozes numbered `n` has the
+//! bits of `n - 1` flipped in the second byte. The history of every card is kept under its id, so
+//! none of this ever changes
+use std::fmt;
+
+use anyhow::anyhow;
+use base64::{
+    Engine,
+    prelude::{BASE64_STANDARD, BASE64_URL_SAFE},
+};
+
+/// Cards have 6 byte identifiers.
+/// This is so that they can be conveniently represented in base64 as 8 characters
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct CardId(pub [u8; 6]);
+
+#[derive(Debug, Copy, Clone, PartialEq, clap::ValueEnum)]
+pub enum IdVersion {
+    /// `REVIEW--<id>:`, in standard base64, which may contain `+` and `/`
+    #[value(name = "1")]
+    V1,
+    /// `REVIEW--v2-<id>:`, in URL safe base64, so ids can be used in links and file names
+    #[value(name = "2")]
+    V2,
+}
+
+impl IdVersion {
+    /// The id as written after `REVIEW--`
+    #[must_use]
+    pub fn encode(self, id: CardId) -> String {
+        match self {
+            Self::V1 => BASE64_STANDARD.encode(id.0),
+            Self::V2 => format!("v2-{}", BASE64_URL_SAFE.encode(id.0)),
+        }
+    }
+}
+
+/// Parses an id written after `REVIEW--` in any version
+#[must_use]
+pub fn decode(s: &str) -> Option<(IdVersion, CardId)> {
+    let (version, bytes) = match s.strip_prefix("v2-") {
+        Some(id) => (IdVersion::V2, BASE64_URL_SAFE.decode(id)),
+        None => (IdVersion::V1, BASE64_STANDARD.decode(s)),
+    };
+    Some((version, CardId(bytes.ok()?.try_into().ok()?)))
+}
+
+impl CardId {
+    /// Parses an id as written after `REVIEW--`, in any version of [`IdVersion`]
+    ///
+    /// # Errors
+    ///
+    /// If `s` is not an id in any version
+    pub fn parse(s: &str) -> anyhow::Result<Self> {
+        decode(s.trim())
+            .map(|(_, id)| id)
+            .ok_or_else(|| anyhow!("Invalid card id `{s}`"))
+    }
+
+    /// The id as the database stores it
+    #[must_use]
+    pub fn as_int(self) -> u64 {
+        let mut res = 0;
+        for b in self.0 {
+            res |= u64::from(b);
+            res <<= 8;
+        }
+        res
+    }
+
+    /// The id stored in the database as `n`
+    #[must_use]
+    pub fn from_int(n: u64) -> Self {
+        let bytes = (n >> 8).to_be_bytes();
+        let mut id = [0; 6];
+        id.copy_from_slice(&bytes[2..]);
+        Self(id)
+    }
+
+    /// The id of the other side of a two sided card, asking for the front from the back
+    #[must_use]
+    pub fn reversed(self) -> Self {
+        let mut id = self;
+        id.0[0] ^= 0x80;
+        id
+    }
+
+    /// The id of the card asking the clozes numbered `number`, from 1. The first clozes are
+    /// asked by the card itself, so numbering the clozes of an existing card keeps its history
+    #[must_use]
+    pub fn cloze(self, number: u8) -> Self {
+        let mut id = self;
+        id.0[1] ^= number.wrapping_sub(1);
+        id
+    }
+}
+
+/// Written in version 1, as new ids are by default
+impl fmt::Display for CardId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&IdVersion::V1.encode(*self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn forms() {
+        let id = CardId([0xd4, 0x3f, 0xbe, 0xfb, 0xff, 0x01]);
+        assert_eq!(IdVersion::V1.encode(id), "1D+++/8B");
+        assert_eq!(IdVersion::V2.encode(id), "v2-1D---_8B");
+        assert_eq!(id.to_string(), "1D+++/8B");
+        for version in [IdVersion::V1, IdVersion::V2] {
+            assert_eq!(decode(&version.encode(id)), Some((version, id)));
+        }
+        assert_eq!(decode("v2-1D+++/8B"), None);
+        assert_eq!(CardId::parse(" v2-1D---_8B ").unwrap(), id);
+        assert!(CardId::parse("1D+++").is_err());
+
+        assert_eq!(id.as_int(), 0xd4_3f_be_fb_ff_01_00);
+        assert_eq!(CardId::from_int(id.as_int()), id);
+
+        // The ids of the cards of a line never change
+        assert_eq!(id.reversed(), CardId([0x54, 0x3f, 0xbe, 0xfb, 0xff, 0x01]));
+        assert_eq!(id.reversed().reversed(), id);
+        assert_eq!(id.cloze(1), id);
+        assert_eq!(id.cloze(3), CardId([0xd4, 0x3d, 0xbe, 0xfb, 0xff, 0x01]));
+    }
+}
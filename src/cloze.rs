@@ -0,0 +1,91 @@
+//! Numbered cloze deletions, written as in Anki like `{{c1::Paris}} is the capital of {{c2::France}}`.
+//!
+//! A card with numbered clozes is asked once for each number, hiding only the clozes with that
+//! number as if they were written `_Paris_`, and showing the others as plain text
+
+/// The numbered clozes in a text, as its parts before each cloze with the number and text of the cloze
+fn parse(text: &str) -> (Vec<(&str, u8, &str)>, &str) {
+    let mut res = vec![];
+    let mut rest = text;
+    // Where to look for the next cloze, after anything like `{{count}}` which is not one
+    let mut from = 0;
+    while let Some(start) = rest[from..].find("{{c").map(|i| i + from) {
+        let cloze = &rest[start + 3..];
+        let parsed = cloze.split_once("::").and_then(|(n, cloze)| {
+            let n = n.parse().ok().filter(|&n| n > 0)?;
+            let end = cloze.find("}}")?;
+            Some((n, &cloze[..end], &cloze[end + 2..]))
+        });
+        let Some((n, cloze, after)) = parsed else {
+            from = start + 3;
+            continue;
+        };
+        // A hint after a second `::` is left out
+        let cloze = cloze.split_once("::").map_or(cloze, |(c, _)| c);
+        res.push((&rest[..start], n, cloze));
+        rest = after;
+        from = 0;
+    }
+    (res, rest)
+}
+
+/// The numbers of the clozes in a text, in order and without repeats
+pub fn numbers(text: &str) -> Vec<u8> {
+    let mut res: Vec<u8> = parse(text).0.iter().map(|&(_, n, _)| n).collect();
+    res.sort_unstable();
+    res.dedup();
+    res
+}
+
+/// Whether a text has a `_cloze_` deletion, an underscore closed by another with text between,
+/// rather than only a lone underscore like in a file name
+pub fn has_deletion(text: &str) -> bool {
+    let parts: Vec<&str> = text.split('_').collect();
+    (1..parts.len().saturating_sub(1))
+        .step_by(2)
+        .any(|i| !parts[i].is_empty())
+}
+
+/// The text asking the clozes numbered `number`, with the others shown
+pub fn select(text: &str, number: u8) -> String {
+    let (clozes, rest) = parse(text);
+    let mut res = String::new();
+    for (before, n, cloze) in clozes {
+        res.push_str(before);
+        if n == number {
+            res.push('_');
+            res.push_str(cloze);
+            res.push('_');
+        } else {
+            res.push_str(cloze);
+        }
+    }
+    res.push_str(rest);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn numbered() {
+        let text = "{{c1::Paris}} is the capital of {{c2::France::country}}, on the {{c1::Seine}}";
+        assert_eq!(numbers(text), [1, 2]);
+        assert_eq!(
+            select(text, 1),
+            "_Paris_ is the capital of France, on the _Seine_"
+        );
+        assert_eq!(
+            select(text, 2),
+            "Paris is the capital of _France_, on the Seine"
+        );
+        assert!(numbers("{{c0::x}} {{cx::y}} {{c1::open").is_empty());
+        assert_eq!(select("{{count}} {{c1::x}}", 1), "{{count}} _x_");
+
+        assert!(has_deletion("_Paris_ is the capital of France"));
+        assert!(!has_deletion("Open my_notes.md"));
+        assert!(!has_deletion("What does __ mean in Python?"));
+        assert!(!has_deletion("No clozes"));
+    }
+}
@@ -0,0 +1,301 @@
+//! User configuration, read from `$XDG_CONFIG_HOME/cardsharp/config.toml`.
+//! A `.cardsharp.toml` in a directory containing the reviewed files overrides it, so a
+//! shared repository of notes can pin its own conventions.
+//!
+//! Only a small subset of TOML is understood: tables, dotted keys, and
+//! string, number, boolean and array values on a single line.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Number(f64),
+    Bool(bool),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn parse(s: &str) -> anyhow::Result<(Self, &str)> {
+        let s = s.trim_start();
+        if let Some(rest) = s.strip_prefix('"') {
+            let mut res = String::new();
+            let mut chars = rest.char_indices();
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '"' => return Ok((Self::String(res), &rest[i + 1..])),
+                    '\\' => match chars.next().map(|(_, c)| c) {
+                        Some('n') => res.push('\n'),
+                        Some('t') => res.push('\t'),
+                        Some(c @ ('"' | '\\')) => res.push(c),
+                        _ => bail!("Invalid escape sequence"),
+                    },
+                    c => res.push(c),
+                }
+            }
+            bail!("Unterminated string");
+        }
+        if let Some(rest) = s.strip_prefix('\'') {
+            let (lit, rest) = rest
+                .split_once('\'')
+                .ok_or_else(|| anyhow!("Unterminated string"))?;
+            return Ok((Self::String(lit.to_string()), rest));
+        }
+        if let Some(mut rest) = s.strip_prefix('[') {
+            let mut res = vec![];
+            loop {
+                rest = rest.trim_start();
+                if let Some(rest) = rest.strip_prefix(']') {
+                    return Ok((Self::Array(res), rest));
+                }
+                let (value, r) = Self::parse(rest)?;
+                res.push(value);
+                rest = r.trim_start();
+                rest = rest.strip_prefix(',').unwrap_or(rest);
+            }
+        }
+        let end = s.find([',', ']', '#', ' ', '\t']).unwrap_or(s.len());
+        let (word, rest) = s.split_at(end);
+        let value = match word {
+            "true" => Self::Bool(true),
+            "false" => Self::Bool(false),
+            _ => Self::Number(
+                word.replace('_', "")
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid value `{word}`"))?,
+            ),
+        };
+        Ok((value, rest))
+    }
+}
+
+/// The configuration as a flat map from dotted keys to values
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    values: BTreeMap<String, Value>,
+}
+
+impl Config {
+    pub fn parse(data: &str) -> anyhow::Result<Self> {
+        let mut values = BTreeMap::new();
+        let mut table = String::new();
+        for (lineno, line) in data.lines().enumerate() {
+            let err = |e: anyhow::Error| e.context(format!("line {}", lineno + 1));
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('[') {
+                let header = header.split('#').next().unwrap_or_default().trim_end();
+                let header = header
+                    .strip_suffix(']')
+                    .ok_or_else(|| err(anyhow!("Unterminated table header")))?;
+                table = format!("{}.", normalize_key(header));
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| err(anyhow!("Expected `key = value`")))?;
+            let (value, rest) = Value::parse(value).map_err(err)?;
+            let rest = rest.trim_start();
+            if !rest.is_empty() && !rest.starts_with('#') {
+                return Err(err(anyhow!("Unexpected `{rest}` after value")));
+            }
+            values.insert(format!("{table}{}", normalize_key(key)), value);
+        }
+        Ok(Self { values })
+    }
+
+    /// Loads the config file, which is empty if it does not exist
+    pub fn load() -> anyhow::Result<Self> {
+        let Some(path) = config_path() else {
+            return Ok(Self::default());
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(data) => Self::parse(&data).map_err(|e| e.context(path.display().to_string())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Loads the config file, with the `.cardsharp.toml` of the vaults the files are in merged over it
+    pub fn load_for(files: &[PathBuf]) -> anyhow::Result<Self> {
+        let mut config = Self::load()?;
+        let mut seen = vec![];
+        for path in files.iter().filter_map(|f| vault_config(f)) {
+            if seen.contains(&path) {
+                continue;
+            }
+            let data = std::fs::read_to_string(&path)?;
+            let vault = Self::parse(&data).map_err(|e| e.context(path.display().to_string()))?;
+            config.values.extend(vault.values);
+            seen.push(path);
+        }
+        Ok(config)
+    }
+
+    /// The target retention, from the command line if given, or the config, or 0.9
+    pub fn retention(&self, arg: Option<f32>) -> anyhow::Result<f32> {
+        if let Some(retention) = arg {
+            return Ok(retention);
+        }
+        #[allow(clippy::cast_possible_truncation)]
+        match self.get("retention") {
+            Some(Value::Number(n)) if (0.0..=1.0).contains(n) => Ok(*n as f32),
+            Some(_) => bail!("`retention` should be a number between 0 and 1"),
+            None => Ok(0.9),
+        }
+    }
+
+    /// How long snoozed cards are left out of reviews, from the command line if given,
+    /// or the config, or 4 hours
+    pub fn snooze_hours(&self, arg: Option<f32>) -> anyhow::Result<f32> {
+        if let Some(hours) = arg {
+            return Ok(hours);
+        }
+        #[allow(clippy::cast_possible_truncation)]
+        match self.get("snooze_hours") {
+            Some(Value::Number(n)) if *n > 0.0 => Ok(*n as f32),
+            Some(_) => bail!("`snooze_hours` should be a positive number"),
+            None => Ok(4.0),
+        }
+    }
+
+    /// The keyword which starts cards, `REVIEW` by default
+    pub fn marker(&self) -> &str {
+        self.get("marker")
+            .and_then(Value::as_str)
+            .unwrap_or("REVIEW")
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.values.get(key)
+    }
+
+    /// All the keys directly within the table `name`, without the table prefix
+    pub fn table<'a>(&'a self, name: &str) -> impl Iterator<Item = (&'a str, &'a Value)> {
+        let prefix = format!("{name}.");
+        self.values
+            .iter()
+            .filter_map(move |(k, v)| Some((k.strip_prefix(&prefix)?, v)))
+            .filter(|(k, _)| !k.contains('.'))
+    }
+
+    /// The names of the tables nested directly within the table `name`
+    pub fn tables<'a>(&'a self, name: &str) -> BTreeSet<&'a str> {
+        let prefix = format!("{name}.");
+        self.values
+            .keys()
+            .filter_map(|k| k.strip_prefix(&prefix)?.split_once('.'))
+            .map(|(table, _)| table)
+            .collect()
+    }
+
+    /// The config with the settings of the `[decks.<deck>]` table merged over it
+    pub fn for_deck(&self, deck: &str) -> Self {
+        let mut res = self.clone();
+        let prefix = format!("decks.{deck}.");
+        for (k, v) in &self.values {
+            if let Some(key) = k.strip_prefix(&prefix) {
+                res.values.insert(key.to_string(), v.clone());
+            }
+        }
+        res
+    }
+}
+
+/// Removes whitespace and quotes around the parts of a dotted key
+fn normalize_key(key: &str) -> String {
+    key.split('.')
+        .map(|k| k.trim().trim_matches('"'))
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// The nearest `.cardsharp.toml` in the directories above a file
+fn vault_config(file: &Path) -> Option<PathBuf> {
+    let file = std::fs::canonicalize(file).ok()?;
+    file.ancestors()
+        .skip(1)
+        .map(|dir| dir.join(".cardsharp.toml"))
+        .find(|path| path.is_file())
+}
+
+fn config_path() -> Option<PathBuf> {
+    if let Ok(path) = std::env::var("CARDSHARP_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+    let dir = std::env::var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|_| std::env::var("HOME").map(|h| PathBuf::from(h).join(".config")))
+        .ok()?;
+    Some(dir.join("cardsharp/config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn parse() {
+        let config = Config::parse(
+            r#"
+# Comment
+retention = 0.85
+smart_decks.hard_jp = "deck:jp prop:difficulty>7"
+
+[smart_decks]
+"due verbs" = 'tag:verb is:due' # trailing comment
+[keys]
+again = ["1", "a"]
+enabled = true
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.get("retention"), Some(&Value::Number(0.85)));
+        assert_eq!(
+            config.table("smart_decks").collect::<Vec<_>>(),
+            [
+                ("due verbs", &Value::String("tag:verb is:due".to_string())),
+                (
+                    "hard_jp",
+                    &Value::String("deck:jp prop:difficulty>7".to_string())
+                ),
+            ]
+        );
+        assert_eq!(
+            config.get("keys.again"),
+            Some(&Value::Array(vec![
+                Value::String("1".to_string()),
+                Value::String("a".to_string())
+            ]))
+        );
+        assert_eq!(config.get("keys.enabled"), Some(&Value::Bool(true)));
+
+        let config = Config::parse("retention = 0.9\n[decks.jp]\nretention = 0.8\n").unwrap();
+        assert_eq!(config.tables("decks"), BTreeSet::from(["jp"]));
+        assert_eq!(
+            config.for_deck("jp").get("retention"),
+            Some(&Value::Number(0.8))
+        );
+        assert_eq!(
+            config.for_deck("de").get("retention"),
+            Some(&Value::Number(0.9))
+        );
+
+        assert!(Config::parse("key = ").is_err());
+        assert!(Config::parse("key = \"open").is_err());
+        assert!(Config::parse("[table").is_err());
+    }
+}
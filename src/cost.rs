@@ -0,0 +1,78 @@
+//! How long reviews take, from the answer times in the review log, so that projected numbers
+//! of reviews can be told in minutes.
+//!
+//! Cards of some decks take much longer to answer than others, so each deck gets its own
+//! average, with the deck of a card from its `location`. With `max_daily_minutes` in the config,
+//! projections which take longer a day than that are pointed out
+use std::collections::HashMap;
+
+use anyhow::bail;
+
+use crate::config::{Config, Value};
+
+/// Answers taking longer than this count as this long, as the card was likely left on the
+/// screen rather than thought about
+const MAX_MS: u32 = 60_000;
+
+/// The average seconds a review takes, by deck
+#[derive(Debug, Default)]
+pub struct Costs {
+    decks: HashMap<String, f32>,
+    /// Over all reviews
+    overall: Option<f32>,
+}
+
+impl Costs {
+    /// Averages the answer times in the review log. Reviews without one, like those imported
+    /// or answered with `review --batch`, are left out
+    pub fn load(sqlite: &rusqlite::Connection) -> anyhow::Result<Self> {
+        let mut statement = sqlite.prepare(
+            "select location.deck, avg(min(duration_ms, ?1)) from review_log
+                 join location on location.card = review_log.card
+                 where duration_ms > 0
+                 group by location.deck",
+        )?;
+        let decks = statement
+            .query_map([MAX_MS], |row| Ok((row.get(0)?, row.get::<_, f64>(1)?)))?
+            .map(|row| row.map(|(deck, ms)| (deck, seconds(ms))))
+            .collect::<Result<_, _>>()?;
+        let overall: Option<f64> = sqlite.query_row(
+            "select avg(min(duration_ms, ?1)) from review_log where duration_ms > 0",
+            [MAX_MS],
+            |row| row.get(0),
+        )?;
+        Ok(Self {
+            decks,
+            overall: overall.map(seconds),
+        })
+    }
+
+    /// Whether any answer times are known at all
+    pub fn known(&self) -> bool {
+        self.overall.is_some()
+    }
+
+    /// The seconds a review of a card in `deck` takes, or of any card if the deck has no
+    /// answer times, or there is no deck as for new cards
+    pub fn of(&self, deck: Option<&str>) -> f32 {
+        deck.and_then(|d| self.decks.get(d))
+            .copied()
+            .or(self.overall)
+            .unwrap_or_default()
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn seconds(ms: f64) -> f32 {
+    (ms / 1000.0) as f32
+}
+
+/// The most minutes a day of reviews should take, from `max_daily_minutes` in the config
+pub fn max_daily_minutes(config: &Config) -> anyhow::Result<Option<f32>> {
+    #[allow(clippy::cast_possible_truncation)]
+    match config.get("max_daily_minutes") {
+        None => Ok(None),
+        Some(Value::Number(n)) if *n > 0.0 => Ok(Some(*n as f32)),
+        Some(_) => bail!("`max_daily_minutes` should be a positive number"),
+    }
+}
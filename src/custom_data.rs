@@ -0,0 +1,163 @@
+//! A JSON value stored for each card, where extensions can keep their own state
+//! without needing changes to the database schema.
+use anyhow::{anyhow, bail};
+use rusqlite::OptionalExtension;
+
+use crate::CardId;
+
+/// Checks that `data` is a single valid JSON value
+pub fn validate(data: &str) -> anyhow::Result<()> {
+    let mut parser = Parser {
+        data: data.as_bytes(),
+        pos: 0,
+    };
+    parser.value()?;
+    parser.whitespace();
+    if parser.pos < parser.data.len() {
+        bail!("Trailing characters at {} in JSON", parser.pos);
+    }
+    Ok(())
+}
+
+struct Parser<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn whitespace(&mut self) {
+        while self.data.get(self.pos).is_some_and(u8::is_ascii_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.whitespace();
+        self.data.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, c: u8) -> anyhow::Result<()> {
+        if self.peek() != Some(c) {
+            bail!("Expected `{}` at {} in JSON", char::from(c), self.pos);
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn string(&mut self) -> anyhow::Result<()> {
+        self.expect(b'"')?;
+        loop {
+            match self.data.get(self.pos) {
+                None => bail!("Unterminated string in JSON"),
+                Some(b'"') => break,
+                Some(b'\\') => self.pos += 2,
+                Some(_) => self.pos += 1,
+            }
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn value(&mut self) -> anyhow::Result<()> {
+        match self
+            .peek()
+            .ok_or_else(|| anyhow!("Unexpected end of JSON"))?
+        {
+            b'{' => {
+                self.pos += 1;
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                    return Ok(());
+                }
+                loop {
+                    self.string()?;
+                    self.expect(b':')?;
+                    self.value()?;
+                    if self.peek() == Some(b',') {
+                        self.pos += 1;
+                    } else {
+                        return self.expect(b'}');
+                    }
+                }
+            }
+            b'[' => {
+                self.pos += 1;
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                    return Ok(());
+                }
+                loop {
+                    self.value()?;
+                    if self.peek() == Some(b',') {
+                        self.pos += 1;
+                    } else {
+                        return self.expect(b']');
+                    }
+                }
+            }
+            b'"' => self.string(),
+            _ => {
+                let start = self.pos;
+                while self
+                    .data
+                    .get(self.pos)
+                    .is_some_and(|c| c.is_ascii_alphanumeric() || b"+-.".contains(c))
+                {
+                    self.pos += 1;
+                }
+                let token = std::str::from_utf8(&self.data[start..self.pos])?;
+                if !matches!(token, "true" | "false" | "null") && token.parse::<f64>().is_err() {
+                    bail!("Invalid value `{token}` at {start} in JSON");
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+pub fn get(sqlite: &mut rusqlite::Connection, id: CardId) -> anyhow::Result<Option<String>> {
+    Ok(sqlite
+        .query_row(
+            "select data from custom_data where card = ?1",
+            [id.as_int()],
+            |row| row.get(0),
+        )
+        .optional()?)
+}
+
+/// Replaces the data of a card, or removes it if `data` is `None`
+pub fn set(
+    sqlite: &mut rusqlite::Connection,
+    id: CardId,
+    data: Option<&str>,
+) -> anyhow::Result<()> {
+    match data {
+        Some(data) => {
+            validate(data)?;
+            sqlite.execute(
+                "insert into custom_data(card, data) values (?1, ?2)
+                     on conflict(card) do update set data = excluded.data",
+                (id.as_int(), data),
+            )?;
+        }
+        None => {
+            sqlite.execute("delete from custom_data where card = ?1", [id.as_int()])?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn json() {
+        validate(r#" {"pitch": [1.5, -2e3, null], "ok": true, "name": "a \"b\""} "#).unwrap();
+        validate("[]").unwrap();
+        assert!(validate(r#"{"a": }"#).is_err());
+        assert!(validate("[1, 2").is_err());
+        assert!(validate("nope").is_err());
+        assert!(validate("{} {}").is_err());
+    }
+}
@@ -0,0 +1,178 @@
+//! Every file is a deck, named after the file unless its YAML front matter has a `deck:` key.
+//!
+//! Decks can override settings in a `[decks.<name>]` table of the config, such as their target retention
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::bail;
+
+use crate::{
+    CardBody,
+    config::{Config, Value},
+};
+
+/// The deck of the cards in a file with the contents `data`
+pub fn name(file: &Path, data: &str) -> String {
+    front_matter(data)
+        .and_then(|fm| {
+            fm.lines().find_map(|l| {
+                let value = l.strip_prefix("deck:")?.trim();
+                let value = value.trim_matches(|c| c == '"' || c == '\'');
+                (!value.is_empty()).then(|| value.to_string())
+            })
+        })
+        .unwrap_or_else(|| {
+            file.file_stem()
+                .map_or_else(String::new, |s| s.to_string_lossy().into_owned())
+        })
+}
+
+/// The contents of a file with `deck:` set to `deck` in its front matter, which is added if
+/// there is none, or `None` if the front matter already names a deck
+pub fn with_name(data: &str, deck: &str) -> Option<String> {
+    let line = format!("deck: {deck}\n");
+    match front_matter(data) {
+        Some(fm) if fm.lines().any(|l| l.starts_with("deck:")) => None,
+        Some(_) => {
+            let start = data.find('\n').map_or(data.len(), |i| i + 1);
+            Some(format!("{}{line}{}", &data[..start], &data[start..]))
+        }
+        None => Some(format!("---\n{line}---\n{data}")),
+    }
+}
+
+/// The YAML between the `---` lines at the very start of a file
+fn front_matter(data: &str) -> Option<&str> {
+    let rest = data
+        .strip_prefix("---\n")
+        .or_else(|| data.strip_prefix("---\r\n"))?;
+    let end = rest
+        .match_indices("\n---")
+        .map(|(i, _)| i)
+        .find(|&i| matches!(rest[i + 4..].chars().next(), None | Some('\n' | '\r')))?;
+    Some(&rest[..end])
+}
+
+/// The most days a card waits between reviews, from `max_interval` in the config
+pub fn max_interval(config: &Config) -> anyhow::Result<Option<f32>> {
+    #[allow(clippy::cast_possible_truncation)]
+    match config.get("max_interval") {
+        None => Ok(None),
+        Some(Value::Number(n)) if *n >= 1.0 => Ok(Some(*n as f32)),
+        Some(_) => bail!("`max_interval` should be a number of days, at least 1"),
+    }
+}
+
+/// The target retention of each deck, which is the same for all decks when given on the command
+/// line, and the maximum interval of each deck
+pub struct Retention {
+    default: f32,
+    decks: BTreeMap<String, f32>,
+    max_default: Option<f32>,
+    max_decks: BTreeMap<String, Option<f32>>,
+}
+
+impl Retention {
+    pub fn new(config: &Config, arg: Option<f32>) -> anyhow::Result<Self> {
+        let mut decks = BTreeMap::new();
+        let mut max_decks = BTreeMap::new();
+        for deck in config.tables("decks") {
+            let deck_config = config.for_deck(deck);
+            let in_deck = |e: anyhow::Error| e.context(format!("In deck `{deck}`"));
+            if arg.is_none() {
+                decks.insert(
+                    deck.to_string(),
+                    deck_config.retention(None).map_err(in_deck)?,
+                );
+            }
+            max_decks.insert(
+                deck.to_string(),
+                max_interval(&deck_config).map_err(in_deck)?,
+            );
+        }
+        Ok(Self {
+            default: config.retention(arg)?,
+            decks,
+            max_default: max_interval(config)?,
+            max_decks,
+        })
+    }
+
+    /// The same target retention for every deck, without a maximum interval
+    pub fn fixed(retention: f32) -> Self {
+        Self {
+            default: retention,
+            decks: BTreeMap::new(),
+            max_default: None,
+            max_decks: BTreeMap::new(),
+        }
+    }
+
+    /// The most days the card waits between reviews, if that is limited
+    pub fn max_interval(&self, card: &CardBody) -> Option<f32> {
+        self.for_deck(&card.deck).1
+    }
+
+    /// The target retention and maximum interval of the cards of `deck`
+    pub fn for_deck(&self, deck: &str) -> (f32, Option<f32>) {
+        let max = self
+            .max_decks
+            .get(deck)
+            .copied()
+            .unwrap_or(self.max_default);
+        (self.decks.get(deck).copied().unwrap_or(self.default), max)
+    }
+
+    /// The target retention and maximum interval of the card
+    pub fn target(&self, card: &CardBody) -> (f32, Option<f32>) {
+        self.for_deck(&card.deck)
+    }
+
+    /// The target retention of the decks without their own
+    pub fn base(&self) -> f32 {
+        self.default
+    }
+
+    pub fn of(&self, card: &CardBody) -> f32 {
+        self.for_deck(&card.deck).0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn names() {
+        let file = Path::new("notes/bones.md");
+        assert_eq!(name(file, "REVIEW: femur?\n"), "bones");
+        assert_eq!(
+            name(
+                file,
+                "---\ntitle: Bones\ndeck: \"anatomy\"\n---\nREVIEW: femur?\n"
+            ),
+            "anatomy"
+        );
+        assert_eq!(name(file, "---\ntitle: Bones\n---\n"), "bones");
+        assert_eq!(name(file, "text\n---\ndeck: anatomy\n---\n"), "bones");
+
+        let kept = with_name("---\ntitle: Bones\n---\nREVIEW: femur?\n", "anatomy").unwrap();
+        assert_eq!(name(file, &kept), "anatomy");
+        assert_eq!(
+            with_name("REVIEW: femur?\n", "anatomy").unwrap(),
+            "---\ndeck: anatomy\n---\nREVIEW: femur?\n"
+        );
+        assert_eq!(with_name(&kept, "bones"), None);
+    }
+
+    #[test]
+    pub fn max_intervals() {
+        let config = Config::parse("max_interval = 365\n[decks.jp]\nmax_interval = 30").unwrap();
+        assert_eq!(max_interval(&config).unwrap(), Some(365.0));
+        assert_eq!(max_interval(&config.for_deck("jp")).unwrap(), Some(30.0));
+        assert_eq!(
+            max_interval(&config.for_deck("bones")).unwrap(),
+            Some(365.0)
+        );
+        assert!(max_interval(&Config::parse("max_interval = 0.5").unwrap()).is_err());
+    }
+}
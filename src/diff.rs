@@ -0,0 +1,105 @@
+//! Character by character comparison of a typed answer with the expected one, for cards whose
+//! answer is typed in like `REVIEW-TYPE: Capital of Bhutan?`
+use unicode_segmentation::UnicodeSegmentation;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Change<'a> {
+    Same(&'a str),
+    /// Typed but not in the answer
+    Extra(&'a str),
+    /// In the answer but not typed
+    Missing(&'a str),
+}
+
+/// The changes turning `typed` into `expected`, keeping as many characters in common as
+/// possible. Runs of the same change are merged
+pub fn diff<'a>(typed: &'a str, expected: &'a str) -> Vec<Change<'a>> {
+    let a: Vec<(usize, &str)> = typed.grapheme_indices(true).collect();
+    let b: Vec<(usize, &str)> = expected.grapheme_indices(true).collect();
+    // The length of the longest common subsequence of the rest of each, after `i` and `j`
+    let mut common = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            common[i][j] = if a[i].1 == b[j].1 {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut res: Vec<Change> = vec![];
+    let mut push = |change: Change<'a>| {
+        let merged = match (res.last(), change) {
+            (Some(Change::Same(last)), Change::Same(s)) => Some(Change::Same(join(typed, last, s))),
+            (Some(Change::Extra(last)), Change::Extra(s)) => {
+                Some(Change::Extra(join(typed, last, s)))
+            }
+            (Some(Change::Missing(last)), Change::Missing(s)) => {
+                Some(Change::Missing(join(expected, last, s)))
+            }
+            _ => None,
+        };
+        match merged {
+            Some(merged) => *res.last_mut().expect("merged with the last change") = merged,
+            None => res.push(change),
+        }
+    };
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i].1 == b[j].1 {
+            push(Change::Same(slice(typed, a[i])));
+            (i, j) = (i + 1, j + 1);
+        } else if j < b.len() && (i == a.len() || common[i][j + 1] >= common[i + 1][j]) {
+            push(Change::Missing(slice(expected, b[j])));
+            j += 1;
+        } else {
+            push(Change::Extra(slice(typed, a[i])));
+            i += 1;
+        }
+    }
+    res
+}
+
+fn slice<'a>(text: &'a str, (start, grapheme): (usize, &str)) -> &'a str {
+    &text[start..start + grapheme.len()]
+}
+
+/// Two adjacent slices of `text` as one
+fn join<'a>(text: &'a str, first: &str, second: &str) -> &'a str {
+    let start = first.as_ptr() as usize - text.as_ptr() as usize;
+    let end = second.as_ptr() as usize - text.as_ptr() as usize + second.len();
+    &text[start..end]
+}
+
+/// Whether the typed answer was exactly right
+pub fn is_right(changes: &[Change]) -> bool {
+    changes.iter().all(|c| matches!(c, Change::Same(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn changes() {
+        assert_eq!(
+            diff("Thimpu", "Thimphu"),
+            [
+                Change::Same("Thimp"),
+                Change::Missing("h"),
+                Change::Same("u")
+            ]
+        );
+        assert_eq!(
+            diff("Pariss", "Paris"),
+            [Change::Same("Paris"), Change::Extra("s")]
+        );
+        assert_eq!(
+            diff("東京都", "京都"),
+            [Change::Extra("東"), Change::Same("京都")]
+        );
+        assert!(is_right(&diff("Paris", "Paris")));
+        assert!(!is_right(&diff("", "Paris")));
+    }
+}
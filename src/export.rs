@@ -0,0 +1,73 @@
+//! Exports cards to the formats of other programs
+use std::fmt::Write;
+
+use crate::CardBody;
+
+#[derive(Debug, Copy, Clone, PartialEq, clap::ValueEnum)]
+pub enum Format {
+    /// The note syntax of the Obsidian to Anki plugin
+    Obsidian,
+    /// An Anki package, which needs an output file
+    Apkg,
+}
+
+/// Replaces `_cloze_` deletions with Anki's `{{c1::cloze}}`
+pub fn anki_cloze(text: &str) -> String {
+    let mut res = String::new();
+    for (i, part) in text.split('_').enumerate() {
+        if i % 2 == 1 {
+            _ = write!(res, "{{{{c1::{part}}}}}");
+        } else {
+            res.push_str(part);
+        }
+    }
+    res
+}
+
+/// Converts cards to the syntax of [Obsidian to Anki][plugin].
+///
+/// The id of each card is kept in a comment, so that importing the cards again keeps their review history.
+///
+/// [plugin]: https://github.com/ObsidianToAnki/Obsidian_to_Anki
+pub fn obsidian(cards: &[CardBody]) -> String {
+    let mut res = String::new();
+    // Generated cards have no fixed question to export
+    for card in cards.iter().filter(|c| !c.reversed && !c.generated) {
+        let front = card.front.trim();
+        let back = card.back.trim();
+        let two_sided = cards
+            .iter()
+            .any(|c| c.reversed && c.file == card.file && c.line == card.line);
+
+        res.push_str("START\n");
+        if front.contains('_') && !two_sided {
+            res.push_str("Cloze\n");
+            res.push_str(&anki_cloze(front));
+            if !back.is_empty() {
+                _ = write!(res, "\nBack Extra: {back}");
+            }
+        } else {
+            res.push_str(if two_sided {
+                "Basic (and reversed card)\n"
+            } else {
+                "Basic\n"
+            });
+            _ = write!(res, "{front}\nBack: {back}");
+        }
+        _ = writeln!(res, "\n<!--CARDSHARP: {}-->\nEND\n", card.id);
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn cloze() {
+        assert_eq!(
+            anki_cloze("_Simonides_ invented the _memory palace_"),
+            "{{c1::Simonides}} invented the {{c1::memory palace}}"
+        );
+    }
+}
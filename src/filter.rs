@@ -0,0 +1,312 @@
+//! A small query language for selecting cards.
+//!
+//! A filter is a list of space separated terms, all of which have to match.
+//! Terms can be negated with a leading `-`, and alternatives separated by `or`.
+//!
+//! - `deck:jp` matches cards in the deck `jp`, see [`crate::deck`]
+//! - `tag:verb` matches cards tagged with `#verb`
+//! - `is:due`, `is:new` and `is:reviewed` match on the review state, and `is:suspended` on
+//!   whether the card is suspended, which also keeps it from being due
+//! - `prop:difficulty>8` compares `difficulty`, `stability`, `recall` or `days` since the last review
+//! - anything else is searched for in the text of the card, and may be quoted to include spaces
+use std::time::SystemTime;
+
+use anyhow::{anyhow, bail};
+
+use crate::{CardBody, config::Config, fsrs::FSRSParams, fuzz};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Prop {
+    Difficulty,
+    Stability,
+    Recall,
+    Days,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Cmp {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl Cmp {
+    fn eval(self, a: f32, b: f32) -> bool {
+        match self {
+            Self::Lt => a < b,
+            Self::Le => a <= b,
+            Self::Gt => a > b,
+            Self::Ge => a >= b,
+            Self::Eq => (a - b).abs() < f32::EPSILON,
+            Self::Ne => (a - b).abs() >= f32::EPSILON,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Term {
+    Deck(String),
+    Tag(String),
+    Due,
+    New,
+    Reviewed,
+    Suspended,
+    Prop(Prop, Cmp, f32),
+    Text(String),
+    Not(Box<Term>),
+}
+
+/// The review state needed to evaluate a filter
+pub struct State {
+    pub last_reviewed: Option<(SystemTime, FSRSParams)>,
+    pub retention: f32,
+    /// The most days the card waits between reviews
+    pub max_interval: Option<f32>,
+    pub suspended: bool,
+}
+
+impl Term {
+    fn parse(s: &str) -> anyhow::Result<Self> {
+        if let Some(s) = s.strip_prefix('-') {
+            return Ok(Self::Not(Box::new(Self::parse(s)?)));
+        }
+        let Some((key, value)) = s.split_once(':') else {
+            return Ok(Self::Text(s.to_lowercase()));
+        };
+        Ok(match key {
+            "deck" => Self::Deck(value.to_string()),
+            "tag" => Self::Tag(value.trim_start_matches('#').to_string()),
+            "is" => match value {
+                "due" => Self::Due,
+                "new" => Self::New,
+                "reviewed" => Self::Reviewed,
+                "suspended" => Self::Suspended,
+                _ => bail!(
+                    "Unknown state `{value}`, expected one of due, new, reviewed or suspended"
+                ),
+            },
+            "prop" => {
+                let idx = value
+                    .find(['<', '>', '=', '!'])
+                    .ok_or_else(|| anyhow!("Missing comparison in `{s}`"))?;
+                let (prop, rest) = value.split_at(idx);
+                let prop = match prop {
+                    "difficulty" => Prop::Difficulty,
+                    "stability" => Prop::Stability,
+                    "recall" => Prop::Recall,
+                    "days" => Prop::Days,
+                    _ => bail!("Unknown property `{prop}`"),
+                };
+                let (cmp, num) = [
+                    ("<=", Cmp::Le),
+                    (">=", Cmp::Ge),
+                    ("!=", Cmp::Ne),
+                    ("<", Cmp::Lt),
+                    (">", Cmp::Gt),
+                    ("=", Cmp::Eq),
+                ]
+                .into_iter()
+                .find_map(|(op, cmp)| Some((cmp, rest.strip_prefix(op)?)))
+                .ok_or_else(|| anyhow!("Invalid comparison in `{s}`"))?;
+                Self::Prop(prop, cmp, num.parse()?)
+            }
+            _ => Self::Text(s.to_lowercase()),
+        })
+    }
+
+    fn matches(&self, card: &CardBody, state: &State) -> bool {
+        let days = |t: SystemTime| t.elapsed().unwrap_or_default().as_secs_f32() / 86400.0;
+        match self {
+            Self::Deck(deck) => card.deck == *deck,
+            Self::Tag(tag) => card.tags.contains(tag),
+            Self::Due => {
+                !state.suspended
+                    && state.last_reviewed.is_none_or(|last| {
+                        fuzz::is_due(card.id, last, (state.retention, state.max_interval), 0.0)
+                    })
+            }
+            Self::New => state.last_reviewed.is_none(),
+            Self::Reviewed => state.last_reviewed.is_some(),
+            Self::Suspended => state.suspended,
+            Self::Prop(prop, cmp, num) => state.last_reviewed.is_some_and(|(t, fsrs)| {
+                let value = match prop {
+                    Prop::Difficulty => fsrs.difficulty,
+                    Prop::Stability => fsrs.stability,
+                    Prop::Recall => fsrs.recall_probability(days(t)),
+                    Prop::Days => days(t),
+                };
+                cmp.eval(value, *num)
+            }),
+            Self::Text(text) => {
+                card.front.to_lowercase().contains(text) || card.back.to_lowercase().contains(text)
+            }
+            Self::Not(term) => !term.matches(card, state),
+        }
+    }
+}
+
+/// A parsed filter expression, as alternatives of terms which must all match
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter(Vec<Vec<Term>>);
+
+impl Filter {
+    pub fn parse(s: &str) -> anyhow::Result<Self> {
+        let mut alternatives = vec![vec![]];
+        for word in split_words(s)? {
+            if word.eq_ignore_ascii_case("or") {
+                alternatives.push(vec![]);
+            } else if let Some(last) = alternatives.last_mut() {
+                last.push(Term::parse(&word)?);
+            }
+        }
+        if alternatives.iter().any(Vec::is_empty) && alternatives.len() > 1 {
+            bail!("`or` needs terms on both sides");
+        }
+        Ok(Self(alternatives))
+    }
+
+    /// Matches cards in the deck `name`
+    pub fn deck(name: &str) -> Self {
+        Self(vec![vec![Term::Deck(name.to_string())]])
+    }
+
+    /// Matches cards with any of the tags
+    pub fn tags(tags: &[String]) -> Self {
+        Self(
+            tags.iter()
+                .map(|t| vec![Term::Tag(t.trim_start_matches('#').to_string())])
+                .collect(),
+        )
+    }
+
+    /// Looks up a filter saved in the `smart_decks` table of the config
+    pub fn smart_deck(config: &Config, name: &str) -> anyhow::Result<Self> {
+        let filter = config
+            .get(&format!("smart_decks.{name}"))
+            .ok_or_else(|| anyhow!("No smart deck named `{name}`"))?
+            .as_str()
+            .ok_or_else(|| anyhow!("Smart deck `{name}` should be a filter string"))?;
+        Self::parse(filter).map_err(|e| e.context(format!("In smart deck `{name}`")))
+    }
+
+    pub fn matches(&self, card: &CardBody, state: &State) -> bool {
+        self.0
+            .iter()
+            .any(|terms| terms.iter().all(|t| t.matches(card, state)))
+    }
+}
+
+/// Splits on whitespace, keeping quoted strings together
+fn split_words(s: &str) -> anyhow::Result<Vec<String>> {
+    let mut res = vec![];
+    let mut word = String::new();
+    let mut quoted = false;
+    for c in s.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !word.is_empty() {
+                    res.push(std::mem::take(&mut word));
+                }
+            }
+            c => word.push(c),
+        }
+    }
+    if quoted {
+        bail!("Unterminated quote in filter");
+    }
+    if !word.is_empty() {
+        res.push(word);
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, time::Duration};
+
+    use super::*;
+    use crate::{CardId, tag};
+
+    fn card(file: &str, front: &str) -> CardBody {
+        CardBody {
+            id: CardId([0; 6]),
+            front: front.to_string(),
+            back: String::new(),
+            file: PathBuf::from(file),
+            line: 1,
+            reversed: false,
+            generated: false,
+            typed: false,
+            tags: tag::parse(front),
+            deck: PathBuf::from(file)
+                .file_stem()
+                .map_or_else(String::new, |s| s.to_string_lossy().into_owned()),
+        }
+    }
+
+    #[test]
+    pub fn parse() {
+        assert_eq!(
+            Filter::parse("deck:jp -tag:verb or prop:difficulty>=8 \"two words\"").unwrap(),
+            Filter(vec![
+                vec![
+                    Term::Deck("jp".to_string()),
+                    Term::Not(Box::new(Term::Tag("verb".to_string())))
+                ],
+                vec![
+                    Term::Prop(Prop::Difficulty, Cmp::Ge, 8.0),
+                    Term::Text("two words".to_string())
+                ],
+            ])
+        );
+        assert!(Filter::parse("is:later").is_err());
+        assert!(Filter::parse("prop:difficulty").is_err());
+        assert!(Filter::parse("or deck:jp").is_err());
+    }
+
+    #[test]
+    pub fn matches() {
+        let new = State {
+            last_reviewed: None,
+            retention: 0.9,
+            max_interval: None,
+            suspended: false,
+        };
+        let hard = State {
+            last_reviewed: Some((
+                SystemTime::now() - Duration::from_hours(24),
+                FSRSParams::new(10.0, 9.0),
+            )),
+            retention: 0.9,
+            max_interval: None,
+            suspended: false,
+        };
+        let verb = card("notes/jp.md", "taberu #verb");
+        let filter = Filter::parse("deck:jp tag:verb").unwrap();
+        assert!(filter.matches(&verb, &new));
+        assert!(!filter.matches(&card("de.md", "essen #verb"), &new));
+
+        let filter = Filter::parse("prop:difficulty>8 -is:due").unwrap();
+        assert!(filter.matches(&verb, &hard));
+        assert!(!filter.matches(&verb, &new));
+        assert!(Filter::parse("is:due TABERU").unwrap().matches(&verb, &new));
+        let suspended = State {
+            suspended: true,
+            ..new
+        };
+        assert!(!Filter::parse("is:due").unwrap().matches(&verb, &suspended));
+        assert!(
+            Filter::parse("is:suspended is:new")
+                .unwrap()
+                .matches(&verb, &suspended)
+        );
+
+        let tags = Filter::tags(&["noun".into(), "#verb".into()]);
+        assert!(tags.matches(&verb, &new));
+        assert!(!tags.matches(&card("jp.md", "verb"), &new));
+    }
+}
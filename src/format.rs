@@ -0,0 +1,421 @@
+//! Parsers for the formats cards can be written in, chosen by the extension of their file.
+//!
+//! Markdown (`.md`, `.markdown`), Org (`.org`) and CSV lists (`.csv`) are built in, and any
+//! other file is read as plain text. More extensions can be mapped to these in the `[formats]`
+//! table of the config, and new formats only need to implement [`Format`] and be registered.
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::{anyhow, bail};
+
+use crate::{CardBody, CardId, cloze, config::Config, ids, tag};
+
+pub struct Options<'a> {
+    /// The keyword starting cards, like `REVIEW`
+    pub marker: &'a str,
+    /// End backs at the next heading which is not nested in the card's section
+    pub stop_at_headings: bool,
+    /// The deck of the cards in the file
+    pub deck: &'a str,
+}
+
+pub trait Format {
+    /// Parses the cards in the contents of a file, in which new cards already have ids
+    fn cards(&self, file: &Path, data: &str, options: &Options) -> Vec<CardBody>;
+}
+
+/// The level of a Markdown heading like `## Title`
+pub fn markdown_heading(line: &str) -> Option<usize> {
+    let level = line.len() - line.trim_start_matches('#').len();
+    ((1..=6).contains(&level) && line[level..].starts_with(' ')).then_some(level)
+}
+
+/// The level of an Org heading like `** Title`
+fn org_heading(line: &str) -> Option<usize> {
+    let level = line.len() - line.trim_start_matches('*').len();
+    (level > 0 && line[level..].starts_with(' ')).then_some(level)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Kind {
+    Card,
+    Generated,
+    /// Asked both ways, as if the front started with `:`
+    Both,
+    /// Answered by typing the back
+    Typed,
+}
+
+/// The suffixes of the marker keyword for each kind of card, like `-GEN` in `REVIEW-GEN--<id>:`
+pub const SUFFIXES: [&str; 4] = ["", "-GEN", "-BOTH", "-TYPE"];
+const KINDS: [Kind; 4] = [Kind::Card, Kind::Generated, Kind::Both, Kind::Typed];
+
+/// Whether a line after the marker keyword starts a card with an id
+fn starts_card(rest: &str) -> bool {
+    SUFFIXES
+        .iter()
+        .any(|s| rest.strip_prefix(s).is_some_and(|r| r.starts_with("--")))
+}
+
+/// Splits a marker line like `REVIEW--<id>:<rest>` into the kind of card, its id and the rest
+fn parse_marker<'a>(line: &'a str, options: &Options) -> Option<(Kind, [u8; 6], &'a str)> {
+    let rest = line.strip_prefix(options.marker)?;
+    let (kind, rest) = KINDS
+        .into_iter()
+        .zip(SUFFIXES)
+        .find_map(|(kind, suffix)| Some((kind, rest.strip_prefix(suffix)?.strip_prefix("--")?)))?;
+    let (id, rest) = rest.split_once(':')?;
+    let (_, id) = ids::decode(id)?;
+    Some((kind, id.0, rest))
+}
+
+/// The card starting at a marker line, and its reverse if it is two sided, or a card for
+/// each number of its numbered clozes
+fn push_card(
+    res: &mut Vec<CardBody>,
+    file: &Path,
+    options: &Options,
+    line: usize,
+    (kind, id, front): (Kind, [u8; 6], &str),
+    back: String,
+) {
+    let tags = tag::parse(front);
+    let mut front = front;
+    let generated = kind == Kind::Generated;
+    let typed = kind == Kind::Typed;
+    let two_sided = kind == Kind::Both || (kind == Kind::Card && front.starts_with(':'));
+    if two_sided {
+        if kind == Kind::Card {
+            front = &front[1..];
+        }
+        res.push(CardBody {
+            id: CardId(id).reversed(),
+            front: back.clone(),
+            back: front.to_string(),
+            file: file.to_path_buf(),
+            line,
+            reversed: true,
+            generated,
+            typed,
+            tags: tags.clone(),
+            deck: options.deck.to_string(),
+        });
+    }
+    let card = CardBody {
+        id: CardId(id),
+        front: front.to_string(),
+        back,
+        file: file.to_path_buf(),
+        line,
+        reversed: false,
+        generated,
+        typed,
+        tags,
+        deck: options.deck.to_string(),
+    };
+    let numbers = if two_sided || generated || typed {
+        vec![]
+    } else {
+        cloze::numbers(front)
+    };
+    for &n in &numbers {
+        res.push(CardBody {
+            id: card.id.cloze(n),
+            front: cloze::select(&card.front, n),
+            ..card.clone()
+        });
+    }
+    if numbers.is_empty() {
+        res.push(card);
+    }
+}
+
+/// A line ending fronts which continue after their marker line, with the back after it
+const FRONT_END: &str = "===";
+
+/// Splits the lines after a marker into the rest of the front, if they contain a [`FRONT_END`], and the back
+fn split_front(lines: &str) -> (Option<&str>, &str) {
+    let mut start = 0;
+    for line in lines.split_inclusive('\n') {
+        if line.trim_end() == FRONT_END {
+            return (Some(&lines[..start]), &lines[start + line.len()..]);
+        }
+        start += line.len();
+    }
+    (None, lines)
+}
+
+/// Loads cards whose backs are the lines after their marker, in files with headings found by `heading`
+fn line_cards(
+    file: &Path,
+    data: &str,
+    options: &Options,
+    heading: fn(&str) -> Option<usize>,
+) -> Vec<CardBody> {
+    let mut res = vec![];
+    let mut lines = data.lines().enumerate().peekable();
+    // Level of the heading of the current section. Outside of any section, every heading ends a back
+    let mut section = usize::MAX;
+    while let Some((lineno, i)) = lines.next() {
+        if let Some(level) = heading(i) {
+            section = level;
+        }
+        let Some(card) = parse_marker(i, options) else {
+            continue;
+        };
+
+        let mut back = String::new();
+        while let Some((_, i)) = lines.next_if(|(_, l)| {
+            let ends_section = options.stop_at_headings && heading(l).is_some_and(|h| h <= section);
+            !l.strip_prefix(options.marker).is_some_and(starts_card)
+                && !l.starts_with("---")
+                && !l.starts_with("<<<")
+                && !ends_section
+        }) {
+            // Headings nested in the back do not change where it ends
+            back.push_str(i);
+            back.push('\n');
+        }
+        let (kind, id, first_line) = card;
+        match split_front(&back) {
+            (Some(rest), back) if kind != Kind::Generated => {
+                let front = format!("{first_line}\n{}", rest.trim_end());
+                let card = (kind, id, front.as_str());
+                push_card(&mut res, file, options, lineno + 1, card, back.to_string());
+            }
+            _ => push_card(&mut res, file, options, lineno + 1, card, back),
+        }
+    }
+    res
+}
+
+struct Markdown;
+
+impl Format for Markdown {
+    fn cards(&self, file: &Path, data: &str, options: &Options) -> Vec<CardBody> {
+        line_cards(file, data, options, markdown_heading)
+    }
+}
+
+struct Org;
+
+impl Format for Org {
+    fn cards(&self, file: &Path, data: &str, options: &Options) -> Vec<CardBody> {
+        line_cards(file, data, options, org_heading)
+    }
+}
+
+struct Plain;
+
+impl Format for Plain {
+    fn cards(&self, file: &Path, data: &str, options: &Options) -> Vec<CardBody> {
+        line_cards(file, data, options, |_| None)
+    }
+}
+
+/// Splits a line of CSV into its fields, which may be quoted to contain commas
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut res = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        let field = res.last_mut().expect("there is always a field");
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => res.push(String::new()),
+            c => field.push(c),
+        }
+    }
+    res.iter().map(|f| f.trim().to_string()).collect()
+}
+
+/// A card on each line, like `REVIEW: Primary colours, red, green, blue`, where the first field
+/// is the front and the rest are the items of the back, one per line
+struct CsvList;
+
+impl Format for CsvList {
+    fn cards(&self, file: &Path, data: &str, options: &Options) -> Vec<CardBody> {
+        let mut res = vec![];
+        for (lineno, line) in data.lines().enumerate() {
+            let Some((kind, id, rest)) = parse_marker(line, options) else {
+                continue;
+            };
+            let (two_sided, rest) = match rest.strip_prefix(':') {
+                Some(rest) => (":", rest),
+                None => ("", rest),
+            };
+            let fields = csv_fields(rest);
+            let front = format!("{two_sided} {}", fields[0]);
+            let mut back = String::new();
+            for field in &fields[1..] {
+                back.push_str(field);
+                back.push('\n');
+            }
+            push_card(
+                &mut res,
+                file,
+                options,
+                lineno + 1,
+                (kind, id, &front),
+                back,
+            );
+        }
+        res
+    }
+}
+
+fn builtin(name: &str) -> Option<Box<dyn Format>> {
+    Some(match name {
+        "markdown" => Box::new(Markdown),
+        "org" => Box::new(Org),
+        "plain" => Box::new(Plain),
+        "csv-list" => Box::new(CsvList),
+        _ => return None,
+    })
+}
+
+/// The formats of files, by their extension
+pub struct Registry {
+    formats: BTreeMap<String, Box<dyn Format>>,
+}
+
+impl Registry {
+    /// The built in formats, and the extensions mapped to them in the `[formats]` table of the config
+    pub fn new(config: &Config) -> anyhow::Result<Self> {
+        let mut res = Self {
+            formats: BTreeMap::new(),
+        };
+        for (extension, name) in [
+            ("md", "markdown"),
+            ("markdown", "markdown"),
+            ("org", "org"),
+            ("csv", "csv-list"),
+        ] {
+            res.register(extension, builtin(name).expect("builtin format"));
+        }
+        for (extension, name) in config.table("formats") {
+            let Some(name) = name.as_str() else {
+                bail!("The format of `.{extension}` files should be a string");
+            };
+            let format = builtin(name).ok_or_else(|| {
+                anyhow!("Unknown format `{name}`, expected markdown, org, plain or csv-list")
+            })?;
+            res.register(extension, format);
+        }
+        Ok(res)
+    }
+
+    pub fn register(&mut self, extension: &str, format: Box<dyn Format>) {
+        self.formats.insert(extension.to_string(), format);
+    }
+
+    /// The format of a file, which is plain text unless its extension is registered
+    pub fn get(&self, file: &Path) -> &dyn Format {
+        file.extension()
+            .and_then(|e| self.formats.get(e.to_str()?))
+            .map_or(&Plain, |f| &**f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPTIONS: Options = Options {
+        marker: "REVIEW",
+        stop_at_headings: true,
+        deck: "a",
+    };
+
+    #[test]
+    pub fn heading_stop() {
+        let data = "# Geography\n\nREVIEW--fguvqzmI: Capital of India?\nNew Delhi\n\
+                    ### Details\nOn the Yamuna\n# History\nNot part of the card\n";
+        let backs = |stop_at_headings| {
+            let options = Options {
+                stop_at_headings,
+                ..OPTIONS
+            };
+            Markdown
+                .cards(Path::new("a.md"), data, &options)
+                .into_iter()
+                .map(|c| c.back)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(backs(true), ["New Delhi\n### Details\nOn the Yamuna\n"]);
+        assert!(backs(false)[0].ends_with("Not part of the card\n"));
+
+        let siblings = "# Geo\nREVIEW--fguvqzmI: Q\nA\n### One\nx\n### Two\ny\n# History\nz\n";
+        assert_eq!(
+            Markdown.cards(Path::new("a.md"), siblings, &OPTIONS)[0].back,
+            "A\n### One\nx\n### Two\ny\n"
+        );
+
+        let org = "* Geography\nREVIEW--fguvqzmI: Capital of India?\nNew Delhi\n* History\n";
+        assert_eq!(
+            Org.cards(Path::new("a.org"), org, &OPTIONS)[0].back,
+            "New Delhi\n"
+        );
+    }
+
+    #[test]
+    pub fn multiline_front() {
+        let data = "REVIEW--fguvqzmI:: What does this print?\n```\nprint(1 + 1)\n```\n===\n2\n";
+        let cards = Markdown.cards(Path::new("a.md"), data, &OPTIONS);
+        assert_eq!(
+            cards[1].front,
+            " What does this print?\n```\nprint(1 + 1)\n```"
+        );
+        assert_eq!(cards[1].back, "2\n");
+        assert_eq!(cards[0].front, "2\n");
+    }
+
+    #[test]
+    pub fn numbered_clozes() {
+        let data = "REVIEW--fguvqzmI: {{c1::Paris}} is in {{c2::France}}\n";
+        let cards = Markdown.cards(Path::new("a.md"), data, &OPTIONS);
+        assert_eq!(cards.len(), 2);
+        assert_eq!(cards[0].id, ids::decode("fguvqzmI").unwrap().1);
+        assert_eq!(cards[1].front, " Paris is in _France_");
+    }
+
+    #[test]
+    pub fn both_ways() {
+        let data =
+            "REVIEW-BOTH--fguvqzmI: Capital of India?\nNew Delhi\nREVIEW-BOTH--x: not a card\n";
+        let cards = Markdown.cards(Path::new("a.md"), data, &OPTIONS);
+        let two_sided = Markdown.cards(
+            Path::new("a.md"),
+            &data.replace("-BOTH--fguvqzmI:", "--fguvqzmI::"),
+            &OPTIONS,
+        );
+        assert_eq!(cards.len(), 2);
+        assert_eq!(cards[0].front, "New Delhi\n");
+        assert_eq!(cards[1].back, "New Delhi\n");
+        for (card, other) in cards.iter().zip(&two_sided) {
+            assert_eq!(
+                (card.id, &card.front, card.reversed),
+                (other.id, &other.front, other.reversed)
+            );
+        }
+    }
+
+    #[test]
+    pub fn csv_list() {
+        let data = "REVIEW--fguvqzmI: Primary colours, red, \"green, mostly\", blue\n";
+        let cards = CsvList.cards(Path::new("a.csv"), data, &OPTIONS);
+        assert_eq!(cards[0].front, " Primary colours");
+        assert_eq!(cards[0].back, "red\ngreen, mostly\nblue\n");
+
+        let registry = Registry::new(&Config::default()).unwrap();
+        let data = "REVIEW--fguvqzmI:: a, b\n";
+        let cards = registry
+            .get(Path::new("x.csv"))
+            .cards(Path::new("x.csv"), data, &OPTIONS);
+        assert_eq!(cards.len(), 2);
+        assert_eq!(cards[0].front, "b\n");
+    }
+}
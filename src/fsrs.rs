@@ -4,12 +4,27 @@
 //! [fsrs]: https://github.com/open-spaced-repetition/free-spaced-repetition-scheduler
 // This makes the code easier to read if you understand the algorithm
 #![allow(clippy::many_single_char_names)]
+use std::cell::Cell;
 
-const WEIGHTS: [f32; 21] = [
+pub const DEFAULT_WEIGHTS: [f32; 21] = [
     0.212, 1.2931, 2.3065, 8.2956, 6.4133, 0.8334, 3.0194, 0.001, 1.8722, 0.1666, 0.796, 1.4835,
     0.0614, 0.2629, 1.6483, 0.6014, 1.8729, 0.5425, 0.0912, 0.0658, 0.1542,
 ];
 
+thread_local! {
+    /// The weights in use, which are the defaults unless they were fitted to the review history
+    static WEIGHTS: Cell<[f32; 21]> = const { Cell::new(DEFAULT_WEIGHTS) };
+}
+
+pub fn weights() -> [f32; 21] {
+    WEIGHTS.get()
+}
+
+/// Replaces the weights used by every following calculation
+pub fn set_weights(weights: [f32; 21]) {
+    WEIGHTS.set(weights);
+}
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Grade {
     Again = 1,
@@ -18,6 +33,84 @@ pub enum Grade {
     Easy = 4,
 }
 
+impl TryFrom<u8> for Grade {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u8) -> anyhow::Result<Self> {
+        Ok(match value {
+            1 => Self::Again,
+            2 => Self::Hard,
+            3 => Self::Good,
+            4 => Self::Easy,
+            _ => anyhow::bail!("Invalid grade {value}, expected a number from 1 to 4"),
+        })
+    }
+}
+
+impl std::str::FromStr for Grade {
+    type Err = anyhow::Error;
+
+    /// A grade as its number, or its name like `good`
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "again" => Self::Again,
+            "hard" => Self::Hard,
+            "good" => Self::Good,
+            "easy" => Self::Easy,
+            s => Self::try_from(s.parse::<u8>().map_err(|_| {
+                anyhow::anyhow!("Invalid grade `{s}`, expected 1 to 4 or again, hard, good or easy")
+            })?)?,
+        })
+    }
+}
+
+/// Versions of FSRS, which differ in the shape of their forgetting curve
+#[derive(Debug, Copy, Clone, PartialEq, clap::ValueEnum)]
+pub enum Version {
+    Fsrs4,
+    /// Also used by FSRS-4.5
+    Fsrs5,
+    Fsrs6,
+}
+
+impl Version {
+    /// The decay of the forgetting curve
+    fn decay(self) -> f32 {
+        match self {
+            Self::Fsrs4 => 1.0,
+            Self::Fsrs5 => 0.5,
+            Self::Fsrs6 => weights()[20],
+        }
+    }
+
+    /// Scales time so that recall is 90% when `time == stability`
+    fn factor(self) -> f32 {
+        0.9_f32.powf(-1.0 / self.decay()) - 1.0
+    }
+
+    /// Recall probability after `time` days, for a card with the given stability
+    pub fn recall_probability(self, stability: f32, time: f32) -> f32 {
+        (1.0 + self.factor() * time / stability).powf(-self.decay())
+    }
+
+    /// Days until recall falls to `retention`
+    pub fn interval(self, stability: f32, retention: f32) -> f32 {
+        stability / self.factor() * (retention.powf(-1.0 / self.decay()) - 1.0)
+    }
+
+    /// Converts a stability from the curve of `from` to this version's, so that the
+    /// recall probability `time` days after the last review stays the same
+    pub fn convert_stability(self, from: Self, stability: f32, time: f32) -> f32 {
+        let r = from.recall_probability(stability, time);
+        let denom = r.powf(-1.0 / self.decay()) - 1.0;
+        if time <= 0.0 || denom <= f32::EPSILON {
+            // Just after a review, keep the slope of the curve the same instead
+            return stability * self.factor() * self.decay() / (from.factor() * from.decay());
+        }
+        self.factor() * time / denom
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct FSRSParams {
     pub stability: f32,
@@ -27,12 +120,21 @@ pub struct FSRSParams {
 impl FSRSParams {
     pub fn new(stability: f32, difficulty: f32) -> Self {
         Self {
-            stability,
+            // A card which is forgotten right away still has to decay at some finite rate
+            stability: stability.max(0.001),
             difficulty: difficulty.clamp(1.0, 10.0),
         }
     }
+
+    /// Whether the state could have come from reviews, rather than from a bad import or a bug.
+    /// Anything else would turn predictions into NaN
+    pub fn is_valid(self) -> bool {
+        self.stability.is_finite()
+            && self.stability > 0.0
+            && (1.0..=10.0).contains(&self.difficulty)
+    }
     pub fn from_initial_grade(grade: Grade) -> Self {
-        let w = WEIGHTS;
+        let w = weights();
         let g = f32::from(grade as u8);
         // We need to use `new` here because the value of difficulty should be clamped
         // Otherwise, the difficulty for `Grade::Easy` will end up negative
@@ -42,9 +144,25 @@ impl FSRSParams {
         )
     }
 
+    /// Estimates the state of a card reviewed with SM-2, the algorithm used by older versions of Anki,
+    /// from its current interval in days and ease factor (such as 2.5).
+    ///
+    /// `sm2_retention` is the probability of recall SM-2 is assumed to result in at the end of an interval
+    pub fn from_sm2(interval: f32, ease: f32, sm2_retention: f32) -> Self {
+        let w = weights();
+        let factor = 0.9_f32.powf(-1.0 / w[20]) - 1.0;
+        // Chosen so that the recall probability after `interval` days is `sm2_retention`
+        let s = interval.max(0.01) * factor / (sm2_retention.powf(-1.0 / w[20]) - 1.0);
+        // Solve for the difficulty which makes `update_successful` multiply the stability by `ease`
+        let r = Version::Fsrs6.recall_probability(s, 0.0);
+        let increase_s = s.powf(-w[9]);
+        let increase_r = f32::exp(w[10] * (1.0 - r) - 1.0);
+        let d = 11.0 - (ease - 1.0) / (increase_s * increase_r);
+        Self::new(s, d)
+    }
+
     pub fn update_successful(self, grade: Grade) -> Self {
-        let w = WEIGHTS;
-        let g = f32::from(grade as u8);
+        let w = weights();
         let s = self.stability;
         let d = self.difficulty;
         let r = self.recall_probability(0.0);
@@ -54,18 +172,40 @@ impl FSRSParams {
         let increase_r = f32::exp(w[10] * (1.0 - r) - 1.0);
         let increase = 1.0 + increase_d * increase_s * increase_r;
 
+        Self::new(s * increase, self.next_difficulty(grade))
+    }
+
+    /// The difficulty after a review with the given grade
+    fn next_difficulty(self, grade: Grade) -> f32 {
+        let w = weights();
+        let g = f32::from(grade as u8);
+        let d = self.difficulty;
         let delta_d = -w[6] * (g - 3.0);
 
         // Linear damping
         let d1 = d + delta_d * (10.0 - d) / 9.0;
         // Mean reversion
-        let d2 = w[7] * Self::from_initial_grade(Grade::Easy).difficulty + (1.0 - w[7]) * d1;
+        w[7] * Self::from_initial_grade(Grade::Easy).difficulty + (1.0 - w[7]) * d1
+    }
 
-        Self::new(s * increase, d2)
+    /// The state after forgetting the card, `time` days after the last review
+    pub fn update_failed(self, time: f32) -> Self {
+        let w = weights();
+        let s = self.stability;
+        let d = self.difficulty;
+        let r = self.recall_probability(time);
+
+        let s_f =
+            w[11] * d.powf(-w[12]) * ((s + 1.0).powf(w[13]) - 1.0) * f32::exp(w[14] * (1.0 - r));
+        // Forgetting can never make a card more stable
+        let s_min = s / f32::exp(w[17] * w[18]);
+
+        Self::new(s_f.min(s_min), self.next_difficulty(Grade::Again))
     }
 
+    /// The state after a review on the same day as the last one
     pub fn update_same_day(self, grade: Grade) -> Self {
-        let w = WEIGHTS;
+        let w = weights();
         let g = f32::from(grade as u8);
         let s = self.stability;
 
@@ -80,11 +220,12 @@ impl FSRSParams {
 
     /// Recall probability after `time` days
     pub fn recall_probability(self, time: f32) -> f32 {
-        let w = WEIGHTS;
-        let s = self.stability;
+        Version::Fsrs6.recall_probability(self.stability, time)
+    }
 
-        let factor = 0.9_f32.powf(-1.0 / w[20]) - 1.0;
-        (1.0 + factor * time / s).powf(-w[20])
+    /// Days until recall falls to `retention`, when the card is due again
+    pub fn interval(self, retention: f32) -> f32 {
+        Version::Fsrs6.interval(self.stability, retention)
     }
 }
 
@@ -101,7 +242,7 @@ mod tests {
 
     #[test]
     pub fn initial_state() {
-        let w = WEIGHTS;
+        let w = weights();
         let grades = [Grade::Again, Grade::Hard, Grade::Good, Grade::Easy];
         let stabilities = &w[0..4];
         let del = f32::exp(w[5]);
@@ -120,6 +261,64 @@ mod tests {
         }
     }
 
+    #[test]
+    pub fn grade_names() {
+        assert_eq!("Good".parse::<Grade>().unwrap(), Grade::Good);
+        assert_eq!("1".parse::<Grade>().unwrap(), Grade::Again);
+        assert!("5".parse::<Grade>().is_err());
+        assert!("meh".parse::<Grade>().is_err());
+    }
+
+    #[test]
+    pub fn sm2() {
+        let card = FSRSParams::from_sm2(10.0, 2.5, 0.9);
+        assert!((card.stability - 10.0).abs() < 0.01);
+        assert!((1.0..=10.0).contains(&card.difficulty));
+        // Cards with lower ease should be harder
+        assert!(FSRSParams::from_sm2(10.0, 1.3, 0.9).difficulty > card.difficulty);
+        // The first review after the import grows the interval by about the old ease
+        let next = card.update_successful(Grade::Good);
+        assert!((next.stability / card.stability - 2.5).abs() < 0.01);
+    }
+
+    #[test]
+    pub fn validity() {
+        assert!(FSRSParams::new(0.0, 0.0).is_valid());
+        let state = |stability, difficulty| FSRSParams {
+            stability,
+            difficulty,
+        };
+        assert!(!state(f32::NAN, 5.0).is_valid());
+        assert!(!state(-1.0, 5.0).is_valid());
+        assert!(!state(f32::INFINITY, 5.0).is_valid());
+        assert!(!state(10.0, 11.0).is_valid());
+        assert!(!state(10.0, f32::NAN).is_valid());
+    }
+
+    #[test]
+    pub fn convert() {
+        for from in [Version::Fsrs4, Version::Fsrs5, Version::Fsrs6] {
+            // Stability means the same in all versions, so the interval at 90% is unchanged
+            assert!((from.interval(10.0, 0.9) - 10.0).abs() < 0.01);
+            let s = Version::Fsrs6.convert_stability(from, 10.0, 30.0);
+            let (old, new) = (
+                from.recall_probability(10.0, 30.0),
+                Version::Fsrs6.recall_probability(s, 30.0),
+            );
+            assert!((old - new).abs() < 0.001);
+        }
+    }
+
+    #[test]
+    pub fn lapse() {
+        let card = FSRSParams::new(30.0, 5.0);
+        let failed = card.update_failed(30.0);
+        assert!(failed.stability < card.stability);
+        assert!(failed.difficulty > card.difficulty);
+        // Forgetting a card which should still have been remembered lowers its stability more
+        assert!(card.update_failed(1.0).stability < failed.stability);
+    }
+
     #[test]
     pub fn stability() {
         let grades = [Grade::Again, Grade::Hard, Grade::Good, Grade::Easy];
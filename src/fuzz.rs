@@ -0,0 +1,107 @@
+//! Fuzzing of intervals, so that cards learnt together do not all come due on the same day.
+//!
+//! A card is due a little before or after its recall falls to the target retention, by up to
+//! 15% of the interval for short intervals and 5% for long ones, and intervals under 2.5 days
+//! are left alone, as in Anki. How much is picked at random from the card id and the time of
+//! its last review, which the database keeps, so a card stays due on the same day until it is
+//! reviewed again. The load balancer can pick the fuzz instead, as [`balance`] explains
+use std::time::SystemTime;
+
+use crate::{CardId, balance, days_between, fsrs::FSRSParams, millis};
+
+/// Parts of an interval in days, and how much of each part it may be fuzzed by
+const RANGES: [(f32, f32, f32); 3] = [(2.5, 7.0, 0.15), (7.0, 20.0, 0.1), (20.0, f32::MAX, 0.05)];
+
+/// The most days an interval can be moved either way
+pub fn range(interval: f32) -> f32 {
+    if interval < 2.5 {
+        return 0.0;
+    }
+    1.0 + RANGES
+        .iter()
+        .map(|&(start, end, factor)| factor * (interval.min(end) - start).max(0.0))
+        .sum::<f32>()
+}
+
+/// The splitmix64 hash, which is fixed so that due dates never move when dependencies change
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// How much the interval of a card last reviewed at `reviewed` is fuzzed, from -1 to 1
+pub fn seeded(id: CardId, reviewed: SystemTime) -> f32 {
+    let hash = splitmix64(id.as_int() ^ millis(reviewed).unwrap_or_default());
+    // The top 24 bits, which an f32 holds exactly
+    #[allow(clippy::cast_precision_loss)]
+    let unit = (hash >> 40) as f32 / ((1 << 24) - 1) as f32;
+    unit * 2.0 - 1.0
+}
+
+/// Days after its last review at `reviewed` until a card with the state `fsrs` is due, which
+/// are never more than `max`
+pub fn interval(
+    id: CardId,
+    reviewed: SystemTime,
+    fsrs: FSRSParams,
+    retention: f32,
+    max: Option<f32>,
+) -> f32 {
+    let interval = fsrs.interval(retention);
+    let fuzz = balance::chosen(id, reviewed).unwrap_or_else(|| seeded(id, reviewed));
+    let fuzzed = interval + fuzz * range(interval);
+    max.map_or(fuzzed, |max| fuzzed.min(max))
+}
+
+/// Whether a card last reviewed at `reviewed` is due `ahead` days from now
+pub fn is_due(
+    id: CardId,
+    (reviewed, fsrs): (SystemTime, FSRSParams),
+    (retention, max): (f32, Option<f32>),
+    ahead: f32,
+) -> bool {
+    days_between(reviewed, SystemTime::now()) + ahead > interval(id, reviewed, fsrs, retention, max)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    pub fn fuzzed() {
+        assert!(range(2.0).abs() < f32::EPSILON);
+        assert!((range(10.0) - 1.975).abs() < 1e-4);
+        assert!((range(100.0) - 6.975).abs() < 1e-4);
+
+        let fsrs = FSRSParams {
+            stability: 30.0,
+            difficulty: 5.0,
+        };
+        let reviewed = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let exact = fsrs.interval(0.9);
+        let intervals: Vec<f32> = (0..100u8)
+            .map(|i| interval(CardId([0, 0, 0, 0, 7, i]), reviewed, fsrs, 0.9, None))
+            .collect();
+        assert!(intervals.iter().all(|i| (i - exact).abs() <= range(exact)));
+        // Spread over several days, but the same each time
+        let days: std::collections::BTreeSet<u32> =
+            intervals.iter().map(|i| i.round().to_bits()).collect();
+        assert!(days.len() > 3);
+        assert_eq!(
+            intervals[5].to_bits(),
+            interval(CardId([0, 0, 0, 0, 7, 5]), reviewed, fsrs, 0.9, None).to_bits()
+        );
+        // A fixed hash, which is the same in every version
+        assert_eq!(splitmix64(0), 0xe220_a839_7b1d_cdaf);
+        assert!((-1.0..=1.0).contains(&seeded(CardId([0; 6]), SystemTime::UNIX_EPOCH)));
+
+        // Never past the maximum interval
+        let id = CardId([0, 0, 0, 0, 7, 5]);
+        assert!((interval(id, reviewed, fsrs, 0.9, Some(10.0)) - 10.0).abs() < f32::EPSILON);
+        assert!(interval(id, reviewed, fsrs, 0.9, Some(1000.0)) < 1000.0);
+    }
+}
@@ -0,0 +1,107 @@
+//! Cards which ask a new question each time they are reviewed, like `REVIEW-GEN: mult 2-12`.
+//!
+//! The card keeps a single state for the skill, and the answer is computed and checked automatically.
+use anyhow::{anyhow, bail};
+use rand::Rng;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Operation {
+    Add,
+    Sub,
+    Mult,
+    Div,
+}
+
+/// A generator spec, an operation on two numbers from an inclusive range
+#[derive(Debug, Clone, PartialEq)]
+pub struct Generator {
+    operation: Operation,
+    low: i64,
+    high: i64,
+}
+
+impl Generator {
+    /// Parses a spec like `mult 2-12`, with one of `add`, `sub`, `mult` or `div`
+    pub fn parse(spec: &str) -> anyhow::Result<Self> {
+        let (operation, range) = spec
+            .trim()
+            .split_once(' ')
+            .ok_or_else(|| anyhow!("Expected `<operation> <low>-<high>`, found `{spec}`"))?;
+        let operation = match operation {
+            "add" => Operation::Add,
+            "sub" => Operation::Sub,
+            "mult" => Operation::Mult,
+            "div" => Operation::Div,
+            _ => bail!("Unknown operation `{operation}`"),
+        };
+        let (low, high) = range
+            .trim()
+            .split_once('-')
+            .ok_or_else(|| anyhow!("Expected a range like `2-12`, found `{range}`"))?;
+        let (low, high): (i64, i64) = (low.trim().parse()?, high.trim().parse()?);
+        if low > high {
+            bail!("Empty range `{range}`");
+        }
+        if operation == Operation::Div && low <= 0 {
+            bail!("Division needs a range of positive numbers");
+        }
+        // The largest numbers a question can have, so that asking one never overflows
+        let fits = match operation {
+            Operation::Add => low.checked_add(low).and(high.checked_add(high)),
+            Operation::Sub => high.checked_sub(low),
+            Operation::Mult | Operation::Div => low
+                .checked_mul(low)
+                .and(low.checked_mul(high))
+                .and(high.checked_mul(high)),
+        };
+        if fits.is_none() {
+            bail!("The numbers in `{range}` are too large");
+        }
+        Ok(Self {
+            operation,
+            low,
+            high,
+        })
+    }
+
+    /// A new question, and its answer
+    pub fn question(&self, rng: &mut impl Rng) -> (String, i64) {
+        let a = rng.random_range(self.low..=self.high);
+        let b = rng.random_range(self.low..=self.high);
+        match self.operation {
+            Operation::Add => (format!("{a} + {b}"), a + b),
+            Operation::Sub => (format!("{} - {}", a.max(b), a.min(b)), a.max(b) - a.min(b)),
+            Operation::Mult => (format!("{a} × {b}"), a * b),
+            // Divide a product, so the answer is a whole number
+            Operation::Div => (format!("{} ÷ {b}", a * b), a),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{SeedableRng, rngs::StdRng};
+
+    use super::*;
+
+    #[test]
+    pub fn questions() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mult = Generator::parse("mult 2-12").unwrap();
+        for _ in 0..100 {
+            let (question, answer) = mult.question(&mut rng);
+            let (a, b) = question.split_once(" × ").unwrap();
+            let (a, b): (i64, i64) = (a.parse().unwrap(), b.parse().unwrap());
+            assert!((2..=12).contains(&a) && (2..=12).contains(&b));
+            assert_eq!(a * b, answer);
+        }
+        let (_, answer) = Generator::parse("div 3-3").unwrap().question(&mut rng);
+        assert_eq!(answer, 3);
+        assert!(Generator::parse("pow 1-2").is_err());
+        assert!(Generator::parse("div 0-5").is_err());
+        assert!(Generator::parse("add 5-1").is_err());
+        assert!(Generator::parse("mult 2-9999999999").is_err());
+        assert!(Generator::parse("add 1-9223372036854775807").is_err());
+        assert!(Generator::parse("add 1-4611686018427387903").is_ok());
+    }
+}
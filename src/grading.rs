@@ -0,0 +1,177 @@
+//! The buttons offered for grading a card, and the FSRS grades they stand for
+use anyhow::{anyhow, bail};
+
+use crate::{config::Config, fsrs::Grade};
+
+#[derive(Debug, Copy, Clone, PartialEq, clap::ValueEnum)]
+pub enum GradingMode {
+    /// Again, hard, good and easy
+    Four,
+    /// Again, good and easy
+    Three,
+    /// Only fail or pass, which are treated as again and good
+    PassFail,
+}
+
+impl GradingMode {
+    fn parse(s: &str) -> anyhow::Result<Self> {
+        clap::ValueEnum::from_str(s, true).map_err(|e| anyhow!("Invalid grading mode: {e}"))
+    }
+
+    fn buttons(self) -> &'static [(&'static str, Grade)] {
+        match self {
+            Self::Four => &[
+                ("again", Grade::Again),
+                ("hard", Grade::Hard),
+                ("good", Grade::Good),
+                ("easy", Grade::Easy),
+            ],
+            Self::Three => &[
+                ("again", Grade::Again),
+                ("good", Grade::Good),
+                ("easy", Grade::Easy),
+            ],
+            Self::PassFail => &[("fail", Grade::Again), ("pass", Grade::Good)],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Button {
+    pub key: char,
+    pub label: &'static str,
+    pub grade: Grade,
+}
+
+/// The grading buttons in use, from left to right
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grading {
+    pub buttons: Vec<Button>,
+}
+
+impl Grading {
+    /// Sets up the buttons for `mode`, or the `grading` mode from the config if there is none.
+    /// Keys default to the numbers from 1, and can be changed in the `[keys]` table of the config
+    pub fn new(mode: Option<GradingMode>, config: &Config) -> anyhow::Result<Self> {
+        let mode = match (mode, config.get("grading")) {
+            (Some(mode), _) => mode,
+            (None, Some(value)) => GradingMode::parse(
+                value
+                    .as_str()
+                    .ok_or_else(|| anyhow!("`grading` should be a string"))?,
+            )?,
+            (None, None) => GradingMode::Four,
+        };
+
+        let mut buttons = vec![];
+        for (&(label, grade), default) in mode.buttons().iter().zip('1'..) {
+            let key = match config.get(&format!("keys.{label}")) {
+                Some(value) => {
+                    let key = value.as_str().unwrap_or_default();
+                    let mut chars = key.chars();
+                    match (chars.next(), chars.next()) {
+                        (Some(c), None) => c,
+                        _ => bail!("`keys.{label}` should be a single character"),
+                    }
+                }
+                None => default,
+            };
+            buttons.push(Button { key, label, grade });
+        }
+        Ok(Self { buttons })
+    }
+
+    /// The grade for a key press. Space always stands for good, or pass
+    pub fn grade(&self, key: char) -> Option<Grade> {
+        if key == ' ' {
+            return Some(Grade::Good);
+        }
+        self.buttons.iter().find(|b| b.key == key).map(|b| b.grade)
+    }
+
+    /// The index of the button selected before any arrow keys are pressed
+    pub fn default_selection(&self) -> usize {
+        self.buttons
+            .iter()
+            .position(|b| b.grade == Grade::Good)
+            .unwrap_or_default()
+    }
+
+    /// What each key does, one entry per button, with the interval in days each grade would
+    /// lead to if it is in `intervals`
+    pub fn button_help(&self, intervals: &[(Grade, f32)]) -> Vec<String> {
+        self.buttons
+            .iter()
+            .map(|b| {
+                let key = if b.grade == Grade::Good {
+                    format!("{}/space", b.key)
+                } else {
+                    b.key.to_string()
+                };
+                match intervals.iter().find(|(g, _)| *g == b.grade) {
+                    Some(&(_, days)) => format!("{key}: {} ({})", b.label, format_interval(days)),
+                    None => format!("{key}: {}", b.label),
+                }
+            })
+            .collect()
+    }
+}
+
+/// An interval in days, shortened like `10m`, `<1d`, `6d`, `3.5mo` or `1.2y`
+pub fn format_interval(days: f32) -> String {
+    if days < 1.0 / 24.0 {
+        format!("{:.0}m", days * 24.0 * 60.0)
+    } else if days < 1.0 {
+        "<1d".to_string()
+    } else if days < 30.0 {
+        format!("{days:.0}d")
+    } else if days < 365.0 {
+        format!("{:.1}mo", days / 30.0)
+    } else {
+        format!("{:.1}y", days / 365.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn modes() {
+        let config = Config::default();
+        let four = Grading::new(None, &config).unwrap();
+        assert_eq!(four.grade('2'), Some(Grade::Hard));
+        assert_eq!(
+            four.button_help(&[]),
+            ["1: again", "2: hard", "3/space: good", "4: easy"]
+        );
+        assert_eq!(
+            four.button_help(&[
+                (Grade::Again, 0.2),
+                (Grade::Good, 6.4),
+                (Grade::Easy, 400.0)
+            ]),
+            [
+                "1: again (<1d)",
+                "2: hard",
+                "3/space: good (6d)",
+                "4: easy (1.1y)"
+            ]
+        );
+        assert_eq!(format_interval(10.0 / 24.0 / 60.0), "10m");
+
+        let pass_fail = Grading::new(Some(GradingMode::PassFail), &config).unwrap();
+        assert_eq!(pass_fail.grade('1'), Some(Grade::Again));
+        assert_eq!(pass_fail.grade('2'), Some(Grade::Good));
+        assert_eq!(pass_fail.grade('3'), None);
+
+        let config = Config::parse("grading = \"three\"\n[keys]\neasy = \"e\"").unwrap();
+        let three = Grading::new(None, &config).unwrap();
+        assert_eq!(
+            three.button_help(&[]),
+            ["1: again", "2/space: good", "e: easy"]
+        );
+        assert_eq!(three.default_selection(), 1);
+        assert!(Grading::new(None, &Config::parse("keys.good = \"gg\"").unwrap()).is_err());
+    }
+}
@@ -0,0 +1,362 @@
+//! Shows the images on the backs of cards in the terminal, with the kitty graphics protocol,
+//! iTerm2's inline images or sixels.
+//!
+//! The protocol is guessed from the environment unless `images` is set in the config. Images
+//! the terminal can not show, such as any image in a terminal without graphics, or one which is
+//! not a PNG for kitty and sixels, are replaced by their alt text.
+//!
+//! Reading and encoding large images takes a while, so the images of the next card are
+//! prepared in the background while the current one is reviewed
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use anyhow::bail;
+use base64::{Engine, prelude::BASE64_STANDARD};
+
+use crate::{config::Config, png, ui::Stylize};
+
+/// The most lines an image takes up
+const MAX_ROWS: usize = 12;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Protocol {
+    Kitty,
+    Iterm,
+    Sixel,
+    /// Only the alt text
+    Text,
+}
+
+impl Protocol {
+    pub fn from_config(config: &Config) -> anyhow::Result<Self> {
+        Ok(match config.get("images").map(|v| v.as_str()) {
+            None | Some(Some("auto")) => Self::detect(),
+            Some(Some("kitty")) => Self::Kitty,
+            Some(Some("iterm")) => Self::Iterm,
+            Some(Some("sixel")) => Self::Sixel,
+            Some(Some("text")) => Self::Text,
+            Some(_) => bail!("`images` should be one of auto, kitty, iterm, sixel or text"),
+        })
+    }
+
+    /// Guesses what the terminal supports from the environment, since the answer to asking it
+    /// would be read as key presses
+    fn detect() -> Self {
+        let var = |name| std::env::var(name).unwrap_or_default();
+        let (term, program) = (var("TERM"), var("TERM_PROGRAM"));
+        if std::env::var_os("KITTY_WINDOW_ID").is_some()
+            || term == "xterm-kitty"
+            || program == "ghostty"
+        {
+            Self::Kitty
+        } else if program == "iTerm.app" || program == "WezTerm" {
+            Self::Iterm
+        } else if term.contains("sixel") || term.starts_with("foot") || term.starts_with("mlterm") {
+            Self::Sixel
+        } else {
+            Self::Text
+        }
+    }
+}
+
+/// An image being prepared, or its escape codes once ready
+type Slot = Arc<OnceLock<Option<String>>>;
+
+/// Images prepared ahead of time, by path and the size of the terminal they were prepared for
+#[derive(Default)]
+pub struct Preloaded(Mutex<HashMap<(PathBuf, [u16; 4]), Slot>>);
+
+fn size_key(winsize: &crossterm::terminal::WindowSize) -> [u16; 4] {
+    [winsize.rows, winsize.columns, winsize.width, winsize.height]
+}
+
+impl Preloaded {
+    fn slot(&self, path: &Path, winsize: &crossterm::terminal::WindowSize) -> Slot {
+        let mut slots = self
+            .0
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        slots
+            .entry((path.to_path_buf(), size_key(winsize)))
+            .or_default()
+            .clone()
+    }
+
+    /// Takes the slot of an image out, as it is only shown once
+    fn take(&self, path: &Path, winsize: &crossterm::terminal::WindowSize) -> Option<Slot> {
+        let mut slots = self
+            .0
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        slots.remove(&(path.to_path_buf(), size_key(winsize)))
+    }
+}
+
+/// The images on a back, as paths in `dir`
+fn images<'a>(back: &'a str, dir: &'a Path) -> impl Iterator<Item = PathBuf> + 'a {
+    back.lines()
+        .filter_map(parse)
+        .filter(|(_, target)| !target.contains("://"))
+        .map(|(_, target)| dir.join(target))
+}
+
+/// Starts preparing the images on the back of a card in a background thread
+pub fn preload(
+    back: &str,
+    dir: &Path,
+    protocol: Protocol,
+    winsize: &crossterm::terminal::WindowSize,
+    preloaded: &Preloaded,
+) {
+    if protocol == Protocol::Text {
+        return;
+    }
+    let slots: Vec<(PathBuf, Slot)> = images(back, dir)
+        .map(|path| {
+            let slot = preloaded.slot(&path, winsize);
+            (path, slot)
+        })
+        .collect();
+    if slots.is_empty() {
+        return;
+    }
+    let size = size_key(winsize);
+    std::thread::spawn(move || {
+        let winsize = crossterm::terminal::WindowSize {
+            rows: size[0],
+            columns: size[1],
+            width: size[2],
+            height: size[3],
+        };
+        for (path, slot) in slots {
+            slot.get_or_init(|| image(&path, protocol, &winsize));
+        }
+    });
+}
+
+/// The alt text and target of a line which is only a Markdown image
+fn parse(line: &str) -> Option<(&str, &str)> {
+    let rest = line.trim().strip_prefix("![")?.strip_suffix(')')?;
+    rest.split_once("](")
+}
+
+/// The image in a kitty graphics command, sent in chunks as the protocol requires
+fn kitty(data: &[u8], rows: usize) -> String {
+    let encoded = BASE64_STANDARD.encode(data);
+    let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(4096).collect();
+    let mut res = String::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        // Only the first chunk has the settings, and `q=2` keeps the terminal from answering
+        let settings = if i == 0 {
+            format!("f=100,a=T,t=d,q=2,r={rows},")
+        } else {
+            String::new()
+        };
+        let chunk = String::from_utf8_lossy(chunk);
+        _ = write!(res, "\x1b_G{settings}m={more};{chunk}\x1b\\");
+    }
+    res
+}
+
+fn iterm(data: &[u8], rows: usize) -> String {
+    format!(
+        "\x1b]1337;File=inline=1;size={};height={rows};preserveAspectRatio=1:{}\x07",
+        data.len(),
+        BASE64_STANDARD.encode(data)
+    )
+}
+
+/// The image as sixels, shrunk to fit in `max_width` by `max_height` pixels, with its colors
+/// rounded to a palette of 216
+fn sixel(image: &png::Image, max_width: usize, max_height: usize) -> String {
+    let step = image
+        .width
+        .div_ceil(max_width.max(1))
+        .max(image.height.div_ceil(max_height.max(1)))
+        .max(1);
+    let (width, height) = (image.width.div_ceil(step), image.height.div_ceil(step));
+    let color = |x: usize, y: usize| {
+        let [r, g, b, a] = image.pixel(x * step, y * step);
+        let level = |c: u8| (usize::from(c) * 5 + 127) / 255;
+        // Transparent pixels are left as the background
+        (a >= 128).then(|| level(r) * 36 + level(g) * 6 + level(b))
+    };
+
+    let mut res = format!("\x1bP0;1;0q\"1;1;{width};{height}");
+    for c in 0..216 {
+        _ = write!(
+            res,
+            "#{c};2;{};{};{}",
+            c / 36 * 20,
+            c / 6 % 6 * 20,
+            c % 6 * 20
+        );
+    }
+    for band in (0..height).step_by(6) {
+        // The six pixels of each column with each color, as bits
+        let mut colors: BTreeMap<usize, Vec<u8>> = BTreeMap::new();
+        for y in band..(band + 6).min(height) {
+            for x in 0..width {
+                if let Some(c) = color(x, y) {
+                    colors.entry(c).or_insert_with(|| vec![0; width])[x] |= 1 << (y - band);
+                }
+            }
+        }
+        for (c, columns) in colors {
+            _ = write!(res, "#{c}");
+            let mut i = 0;
+            while i < width {
+                let run = columns[i..]
+                    .iter()
+                    .take_while(|&&s| s == columns[i])
+                    .count();
+                let sixel = char::from(63 + columns[i]);
+                if run > 3 {
+                    _ = write!(res, "!{run}{sixel}");
+                } else {
+                    res.extend(std::iter::repeat_n(sixel, run));
+                }
+                i += run;
+            }
+            res.push('$');
+        }
+        res.push('-');
+    }
+    res.push_str("\x1b\\");
+    res
+}
+
+/// The number of lines an image takes up
+pub fn image_rows(winsize: &crossterm::terminal::WindowSize) -> usize {
+    MAX_ROWS.min(usize::from(winsize.rows) / 2).max(1)
+}
+
+/// The escape codes showing the image at `path`, or `None` if it can not be shown
+fn image(
+    path: &Path,
+    protocol: Protocol,
+    winsize: &crossterm::terminal::WindowSize,
+) -> Option<String> {
+    let data = std::fs::read(path).ok()?;
+    let rows = image_rows(winsize);
+    match protocol {
+        Protocol::Kitty if data.starts_with(png::SIGNATURE) => Some(kitty(&data, rows)),
+        Protocol::Iterm => Some(iterm(&data, rows)),
+        Protocol::Sixel => {
+            let image = png::decode(&data).ok()?;
+            // Terminals which do not tell their size in pixels usually have cells about this big
+            let (cell_width, cell_height) = match (winsize.width, winsize.height) {
+                (0, _) | (_, 0) => (10, 20),
+                (w, h) => (
+                    usize::from(w) / usize::from(winsize.columns.max(1)),
+                    usize::from(h) / usize::from(winsize.rows.max(1)),
+                ),
+            };
+            Some(sixel(
+                &image,
+                usize::from(winsize.columns) * cell_width,
+                rows * cell_height,
+            ))
+        }
+        Protocol::Kitty | Protocol::Text => None,
+    }
+}
+
+/// The back of a card in `dir`, with each line which is only a Markdown image replaced by the
+/// image. Images being preloaded are waited for rather than prepared again
+pub fn show(
+    back: &str,
+    dir: &Path,
+    protocol: Protocol,
+    winsize: &crossterm::terminal::WindowSize,
+    preloaded: &Preloaded,
+) -> String {
+    let mut res = String::new();
+    for line in back.split_inclusive('\n') {
+        let Some((alt, target)) = parse(line) else {
+            res.push_str(line);
+            continue;
+        };
+        let shown = (!target.contains("://"))
+            .then(|| {
+                let path = dir.join(target);
+                match preloaded.take(&path, winsize) {
+                    Some(slot) => slot.get_or_init(|| image(&path, protocol, winsize)).clone(),
+                    None => image(&path, protocol, winsize),
+                }
+            })
+            .flatten();
+        match shown {
+            Some(image) => res.push_str(&image),
+            None if alt.is_empty() => _ = write!(res, "{}", format!("[{target}]").muted()),
+            None => _ = write!(res, "{}", format!("[{alt}]").muted()),
+        }
+        if line.ends_with('\n') {
+            res.push('\n');
+        }
+    }
+    res
+}
+
+/// The escape codes removing the images shown, for terminals which keep them when the text is cleared
+pub fn clear(protocol: Protocol) -> &'static str {
+    match protocol {
+        Protocol::Kitty => "\x1b_Ga=d,q=2\x1b\\",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn fallback() {
+        let winsize = crossterm::terminal::WindowSize {
+            rows: 24,
+            columns: 80,
+            width: 0,
+            height: 0,
+        };
+        let back = "Femur\n![The thigh bone](missing.png)\n  ![](bones.gif)\nsee ![x](y.png)";
+        assert_eq!(
+            show(
+                back,
+                Path::new(""),
+                Protocol::Kitty,
+                &winsize,
+                &Preloaded::default()
+            ),
+            "Femur\n[The thigh bone]\n[bones.gif]\nsee ![x](y.png)"
+        );
+
+        // A preloaded image is shown from its slot, which is then dropped
+        let preloaded = Preloaded::default();
+        preload(back, Path::new("/"), Protocol::Kitty, &winsize, &preloaded);
+        let slot = preloaded.slot(Path::new("/missing.png"), &winsize);
+        assert_eq!(slot.wait(), &None);
+        assert_eq!(
+            show(back, Path::new("/"), Protocol::Kitty, &winsize, &preloaded),
+            "Femur\n[The thigh bone]\n[bones.gif]\nsee ![x](y.png)"
+        );
+        assert!(preloaded.0.lock().unwrap().is_empty());
+
+        let sixels = sixel(
+            &png::Image {
+                width: 2,
+                height: 1,
+                rgba: vec![255, 0, 0, 255, 0, 0, 0, 0],
+            },
+            100,
+            100,
+        );
+        assert!(sixels.starts_with("\x1bP0;1;0q\"1;1;2;1#0;2;0;0;0"));
+        // Only the red pixel is drawn, and the transparent one is skipped
+        assert!(sixels.ends_with("#180@?$-\x1b\\"));
+    }
+}
@@ -0,0 +1,84 @@
+//! Versions of the syntax of card ids in files.
+//!
+//! Every version is read, so files written with older versions keep working, and new ids are
+//! written in the version set by `id_version` in the config. `cardsharp upgrade-ids` rewrites
+//! the ids of existing cards, which only changes how they are written, so their history is kept
+use std::path::Path;
+
+use anyhow::bail;
+pub use cardsharp::card_id::{IdVersion, decode};
+
+use crate::{
+    config::{Config, Value},
+    format::SUFFIXES,
+    tag::write_atomic,
+};
+
+/// The version new ids are written in, 1 unless `id_version` is set in the config
+pub fn configured(config: &Config) -> anyhow::Result<IdVersion> {
+    match config.get("id_version") {
+        None => Ok(IdVersion::V1),
+        Some(Value::Number(n)) if (n - 1.0).abs() < f64::EPSILON => Ok(IdVersion::V1),
+        Some(Value::Number(n)) if (n - 2.0).abs() < f64::EPSILON => Ok(IdVersion::V2),
+        Some(_) => bail!("`id_version` should be 1 or 2"),
+    }
+}
+
+/// The marker line with its id written in `version`, or `None` if it already is or the line
+/// is not a card
+fn upgrade_line(line: &str, marker: &str, version: IdVersion) -> Option<String> {
+    let rest = line.strip_prefix(marker)?;
+    let suffix = SUFFIXES
+        .into_iter()
+        .find(|s| rest.strip_prefix(s).is_some_and(|r| r.starts_with("--")))?;
+    let (id, rest) = rest[suffix.len() + 2..].split_once(':')?;
+    let (old, id) = decode(id)?;
+    (old != version).then(|| format!("{marker}{suffix}--{}:{rest}", version.encode(id)))
+}
+
+/// Rewrites the ids in a file in `version`, returning how many changed
+pub fn upgrade(file: &Path, version: IdVersion) -> anyhow::Result<usize> {
+    let config = Config::load_for(&[file.to_path_buf()])?;
+    let data = std::fs::read_to_string(file)?;
+    let mut res = String::with_capacity(data.len());
+    let mut changed = 0;
+    for line in data.split_inclusive('\n') {
+        let text = line.trim_end_matches(['\r', '\n']);
+        match upgrade_line(text, config.marker(), version) {
+            Some(upgraded) => {
+                res.push_str(&upgraded);
+                res.push_str(&line[text.len()..]);
+                changed += 1;
+            }
+            None => res.push_str(line),
+        }
+    }
+    if changed > 0 {
+        write_atomic(file, &res)?;
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn versions() {
+        let line = "REVIEW--1D+++/8B:: front: with colons #tag";
+        assert_eq!(
+            upgrade_line(line, "REVIEW", IdVersion::V2).unwrap(),
+            "REVIEW--v2-1D---_8B:: front: with colons #tag"
+        );
+        assert_eq!(upgrade_line(line, "REVIEW", IdVersion::V1), None);
+        assert_eq!(
+            upgrade_line("REVIEW-GEN--v2-1D---_8B: 1+1", "REVIEW", IdVersion::V1).unwrap(),
+            "REVIEW-GEN--1D+++/8B: 1+1"
+        );
+        assert_eq!(
+            upgrade_line("REVIEW-BOTH--1D+++/8B: a", "REVIEW", IdVersion::V2).unwrap(),
+            "REVIEW-BOTH--v2-1D---_8B: a"
+        );
+        assert_eq!(upgrade_line("REVIEW: new", "REVIEW", IdVersion::V2), None);
+    }
+}
@@ -0,0 +1,168 @@
+//! Imports review state from other spaced repetition programs
+use std::{fmt::Write, path::Path, time::SystemTime};
+
+use anyhow::{anyhow, bail};
+
+use crate::{CardId, fsrs::FSRSParams, save_review};
+
+/// Parses an ease factor, either as a ratio like `2.5` or in permille like Anki's `2500`
+fn parse_ease(s: &str) -> anyhow::Result<f32> {
+    let ease: f32 = s.trim().parse()?;
+    Ok(if ease > 100.0 { ease / 1000.0 } else { ease })
+}
+
+/// Seeds the state of cards from a CSV file with lines of `<card id>,<interval in days>,<ease factor>`
+pub fn sm2(
+    sqlite: &mut rusqlite::Connection,
+    file: &Path,
+    sm2_retention: f32,
+) -> anyhow::Result<()> {
+    let data = std::fs::read_to_string(file)?;
+    let now = SystemTime::now();
+    let mut count = 0;
+    for (lineno, line) in data.lines().enumerate() {
+        let err = |e: anyhow::Error| e.context(format!("{}:{}", file.display(), lineno + 1));
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').collect();
+        let [id, interval, ease] = fields.as_slice() else {
+            return Err(err(anyhow!("Expected `<card id>,<interval>,<ease>`")));
+        };
+        let id = CardId::parse(id).map_err(err)?;
+        let interval: f32 = interval.trim().parse().map_err(|e| err(anyhow!("{e}")))?;
+        let ease = parse_ease(ease).map_err(err)?;
+
+        let fsrs = FSRSParams::from_sm2(interval, ease, sm2_retention);
+        save_review(sqlite, id, now, fsrs)?;
+        count += 1;
+    }
+    eprintln!("Imported {count} cards");
+    Ok(())
+}
+
+/// Replaces Anki's `{{c1::cloze::hint}}` deletions with `_cloze_`
+pub fn from_anki_cloze(text: &str) -> String {
+    let mut res = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{c") {
+        res.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let (Some(sep), Some(end)) = (rest.find("::"), rest.find("}}")) else {
+            break;
+        };
+        if sep > end {
+            res.push_str("{{");
+            rest = &rest[2..];
+            continue;
+        }
+        let cloze = &rest[sep + 2..end];
+        let cloze = cloze.split_once("::").map_or(cloze, |(c, _)| c);
+        _ = write!(res, "_{cloze}_");
+        rest = &rest[end + 2..];
+    }
+    res.push_str(rest);
+    res
+}
+
+/// Converts notes in the syntax of the Obsidian to Anki plugin into cards starting with `marker`.
+///
+/// Cards exported by cardsharp keep their id. Others are left without one, to be initialized when
+/// first loaded. Numbered clozes are kept as they are, so each number is still its own card
+pub fn obsidian(data: &str, marker: &str) -> anyhow::Result<String> {
+    let mut res = String::new();
+    let mut lines = data.lines().enumerate();
+    while let Some((start, line)) = lines.next() {
+        if line.trim() != "START" {
+            continue;
+        }
+        let err = |msg: &str| anyhow!("Note starting at line {}: {msg}", start + 1);
+        let kind = lines.next().map(|(_, l)| l.trim()).unwrap_or_default();
+        let reversed = match kind {
+            "Basic" | "Cloze" => false,
+            "Basic (and reversed card)" => true,
+            _ => return Err(err(&format!("Unsupported note type `{kind}`"))),
+        };
+
+        let mut front = vec![];
+        let mut back = vec![];
+        let mut id = None;
+        let mut in_back = false;
+        loop {
+            let Some((_, line)) = lines.next() else {
+                return Err(err("Missing END"));
+            };
+            let line = line.trim_end();
+            if line.trim() == "END" {
+                break;
+            } else if let Some(i) = line.strip_prefix("<!--CARDSHARP:") {
+                id = Some(i.trim_end_matches("-->").trim().to_string());
+            } else if line.starts_with("<!--ID:") {
+                // The id of the note in Anki, which means nothing to cardsharp
+            } else if let Some(b) = line
+                .strip_prefix("Back:")
+                .or_else(|| line.strip_prefix("Back Extra:"))
+            {
+                in_back = true;
+                back.push(b.trim());
+            } else if in_back {
+                back.push(line);
+            } else {
+                front.push(line.trim());
+            }
+        }
+
+        // The front of a card is a single line
+        let front = front.join(" ");
+        if front.is_empty() {
+            return Err(err("Empty front"));
+        }
+        let start = match id {
+            Some(id) if CardId::parse(&id).is_ok() => format!("{marker}--{id}:"),
+            Some(id) => bail!("Invalid card id `{id}`"),
+            None => format!("{marker}:"),
+        };
+        let colon = if reversed { ":" } else { "" };
+        _ = writeln!(res, "\n{start}{colon} {front}");
+        let back = back.join("\n");
+        if !back.trim().is_empty() {
+            _ = writeln!(res, "{}", back.trim_end());
+        }
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn obsidian_notes() {
+        let notes = "START\nBasic (and reversed card)\nWhere is the capital\nof India?\n\
+                     Back: New Delhi\n<!--CARDSHARP: fguvqzmI-->\nEND\n\n\
+                     START\nCloze\n{{c1::FSRS::acronym}} is based on the {{c2::DHP}} model\nEND\n";
+        assert_eq!(
+            obsidian(notes, "CARD").unwrap(),
+            "\nCARD--fguvqzmI:: Where is the capital of India?\nNew Delhi\n\
+             \nCARD: {{c1::FSRS::acronym}} is based on the {{c2::DHP}} model\n"
+        );
+        assert!(obsidian("START\nAnkiNote\nEND", "REVIEW").is_err());
+    }
+
+    #[test]
+    pub fn v2_ids() {
+        let id = CardId([0xd4, 0x3f, 0xbe, 0xfb, 0xff, 0x01]);
+        let notes = "START\nBasic\nQ\nBack: A\n<!--CARDSHARP: v2-1D---_8B-->\nEND\n";
+        let imported = obsidian(notes, "REVIEW").unwrap();
+        assert_eq!(imported, "\nREVIEW--v2-1D---_8B: Q\nA\n");
+        let written = imported
+            .split_once("--")
+            .unwrap()
+            .1
+            .split_once(':')
+            .unwrap()
+            .0;
+        assert_eq!(CardId::parse(written).unwrap(), id);
+    }
+}
@@ -0,0 +1,102 @@
+//! Initializing the cards of many files at once, such as after a large import, without
+//! seeming to hang.
+//!
+//! On a terminal, the number of new cards, how fast they are given ids and the files left are
+//! shown as they go, instead of a line for each card. Each file is replaced as a whole once its
+//! ids are written, and the database is written every few files, so Ctrl-C stops after the file
+//! being written, and running `init` again carries on with the files which are left
+use std::{
+    io::IsTerminal,
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::bail;
+use signal_hook::{consts::SIGINT, flag};
+
+use crate::{read_file, track, ui::Stylize, warn_moved};
+
+/// Files read between writes to the database
+const BATCH: usize = 50;
+
+/// How often the progress is shown again
+const REFRESH: Duration = Duration::from_millis(100);
+
+/// The progress after giving `new` cards ids in `elapsed`, with `left` files left
+fn status(new: usize, left: usize, elapsed: Duration) -> String {
+    #[allow(clippy::cast_precision_loss)]
+    let rate = new as f32 / elapsed.as_secs_f32().max(0.001);
+    let plural = if left == 1 { "" } else { "s" };
+    format!("{new} new cards, {rate:.0} a second, {left} file{plural} left")
+}
+
+pub fn run(sqlite: &mut rusqlite::Connection, files: &[PathBuf]) -> anyhow::Result<()> {
+    let stop = Arc::new(AtomicBool::new(false));
+    // A second Ctrl-C stops at once, in case a file takes too long
+    flag::register_conditional_shutdown(SIGINT, 130, Arc::clone(&stop))?;
+    flag::register(SIGINT, Arc::clone(&stop))?;
+    let progress = std::io::stderr().is_terminal();
+
+    let start = Instant::now();
+    let mut shown = start;
+    let (mut new, mut moved) = (0, 0);
+    let mut batch = vec![];
+    for (i, file) in files.iter().enumerate() {
+        let (mut cards, ids) = read_file(file, true)?;
+        new += ids.len();
+        if !progress {
+            for id in ids {
+                eprintln!("Initialized new card!: {id}");
+            }
+        }
+        batch.append(&mut cards);
+
+        let (done, stopped) = (i + 1, stop.load(Ordering::Relaxed));
+        if done % BATCH == 0 || done == files.len() || stopped {
+            moved += track(sqlite, &batch)?;
+            batch.clear();
+        }
+        if progress && (shown.elapsed() >= REFRESH || done == files.len() || stopped) {
+            shown = Instant::now();
+            eprint!(
+                "\r\x1b[K{}",
+                status(new, files.len() - done, start.elapsed()).muted()
+            );
+        }
+        if stopped && done < files.len() {
+            if progress {
+                eprintln!();
+            }
+            bail!(
+                "Stopped after {done} of {} files, running `cardsharp init` with the same files carries on",
+                files.len()
+            );
+        }
+    }
+    if progress && !files.is_empty() {
+        eprintln!();
+    }
+    warn_moved(moved);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn progress() {
+        assert_eq!(
+            status(1500, 3, Duration::from_secs(2)),
+            "1500 new cards, 750 a second, 3 files left"
+        );
+        assert_eq!(
+            status(0, 1, Duration::ZERO),
+            "0 new cards, 0 a second, 1 file left"
+        );
+    }
+}
@@ -0,0 +1,127 @@
+//! A line of text edited key by key in raw mode, for answers which are typed in while the
+//! review screen stays drawn
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// What a key press did to the line
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Edit {
+    Changed,
+    /// Enter was pressed
+    Done,
+    /// Escape or Ctrl-C was pressed
+    Cancel,
+}
+
+#[derive(Debug, Default)]
+pub struct Line {
+    pub text: String,
+    /// Byte position of the cursor, always at the start of a grapheme
+    pub cursor: usize,
+}
+
+impl Line {
+    /// The start of the grapheme before the cursor
+    fn previous(&self) -> usize {
+        self.text[..self.cursor]
+            .grapheme_indices(true)
+            .next_back()
+            .map_or(0, |(i, _)| i)
+    }
+
+    /// The end of the grapheme after the cursor
+    fn next(&self) -> usize {
+        self.text[self.cursor..]
+            .graphemes(true)
+            .next()
+            .map_or(self.cursor, |g| self.cursor + g.len())
+    }
+
+    /// The start of the word before the cursor, after any spaces before it
+    fn previous_word(&self) -> usize {
+        let before = self.text[..self.cursor].trim_end();
+        before.rfind(char::is_whitespace).map_or(0, |i| {
+            i + before[i..].chars().next().map_or(1, char::len_utf8)
+        })
+    }
+
+    /// Edits the line with a key, with the usual shortcuts of shells for moving and deleting
+    pub fn key(&mut self, event: KeyEvent) -> Edit {
+        let control = event.modifiers.contains(KeyModifiers::CONTROL);
+        match event.code {
+            KeyCode::Enter => return Edit::Done,
+            KeyCode::Esc => return Edit::Cancel,
+            KeyCode::Char('c') if control => return Edit::Cancel,
+            KeyCode::Char('a') if control => self.cursor = 0,
+            KeyCode::Char('e') if control => self.cursor = self.text.len(),
+            KeyCode::Char('u') if control => {
+                self.text.replace_range(..self.cursor, "");
+                self.cursor = 0;
+            }
+            KeyCode::Char('w') if control => {
+                let start = self.previous_word();
+                self.text.replace_range(start..self.cursor, "");
+                self.cursor = start;
+            }
+            KeyCode::Char(_) if control => {}
+            KeyCode::Char(c) => {
+                self.text.insert(self.cursor, c);
+                let end = self.cursor + c.len_utf8();
+                // A combining mark joins the grapheme before it, which the cursor stays after
+                self.cursor = self
+                    .text
+                    .grapheme_indices(true)
+                    .map(|(i, g)| i + g.len())
+                    .find(|&e| e >= end)
+                    .unwrap_or(end);
+            }
+            KeyCode::Backspace => {
+                let start = self.previous();
+                self.text.replace_range(start..self.cursor, "");
+                self.cursor = start;
+            }
+            KeyCode::Delete => {
+                let end = self.next();
+                self.text.replace_range(self.cursor..end, "");
+            }
+            KeyCode::Left => self.cursor = self.previous(),
+            KeyCode::Right => self.cursor = self.next(),
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = self.text.len(),
+            _ => {}
+        }
+        Edit::Changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn editing() {
+        let mut line = Line::default();
+        let key = |line: &mut Line, code, modifiers| line.key(KeyEvent::new(code, modifiers));
+        for c in "Thimpu".chars() {
+            key(&mut line, KeyCode::Char(c), KeyModifiers::SHIFT);
+        }
+        key(&mut line, KeyCode::Left, KeyModifiers::NONE);
+        key(&mut line, KeyCode::Char('h'), KeyModifiers::NONE);
+        assert_eq!((line.text.as_str(), line.cursor), ("Thimphu", 6));
+        key(&mut line, KeyCode::End, KeyModifiers::NONE);
+        key(&mut line, KeyCode::Char(' '), KeyModifiers::NONE);
+        key(&mut line, KeyCode::Char('é'), KeyModifiers::NONE);
+        key(&mut line, KeyCode::Backspace, KeyModifiers::NONE);
+        assert_eq!(line.text, "Thimphu ");
+        key(&mut line, KeyCode::Char('w'), KeyModifiers::CONTROL);
+        assert_eq!((line.text.as_str(), line.cursor), ("", 0));
+        assert_eq!(
+            key(&mut line, KeyCode::Enter, KeyModifiers::NONE),
+            Edit::Done
+        );
+        assert_eq!(
+            key(&mut line, KeyCode::Char('c'), KeyModifiers::CONTROL),
+            Edit::Cancel
+        );
+    }
+}
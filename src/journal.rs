@@ -0,0 +1,299 @@
+//! Keeps the new states of cards in memory until the end of a session, when they are saved
+//! in a single transaction. Each state is also appended to a journal file, so the answers
+//! of a session which crashed are saved the next time cardsharp opens the database. A session
+//! holds a lock on its journal while it goes on, so that it is not taken for one which crashed.
+//!
+//! The journal has one line per answer, as `<card id> <time> <stability> <difficulty>`, with the
+//! time in seconds to the millisecond like `1700000000.123`, followed by
+//! `<grade> <elapsed days> <recall> <duration in ms>` for the review log. The elapsed days and
+//! recall are `-` for new cards.
+use std::{
+    fmt::Write as _,
+    fs::{File, OpenOptions, TryLockError},
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, bail};
+
+use crate::{
+    Answer, CardId,
+    fsrs::{FSRSParams, Grade},
+    load_card_data, save_answer, save_review,
+};
+
+/// An answer, and the state of the card after it
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Entry {
+    pub id: CardId,
+    pub time: SystemTime,
+    pub fsrs: FSRSParams,
+    /// Missing for answers from before the review log was kept
+    pub answer: Option<Answer>,
+}
+
+/// A time as seconds since the Unix epoch, with the milliseconds after a `.` unless there are none
+pub fn format_time(time: SystemTime) -> anyhow::Result<String> {
+    let millis = crate::millis(time)?;
+    Ok(match millis % 1000 {
+        0 => (millis / 1000).to_string(),
+        ms => format!("{}.{ms:03}", millis / 1000),
+    })
+}
+
+/// Reads a time written by [`format_time`], exactly rather than through a float
+pub fn parse_time(s: &str) -> Option<SystemTime> {
+    let (secs, fraction) = s.split_once('.').unwrap_or((s, ""));
+    if fraction.len() > 3 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let ms: u64 = format!("{fraction:0<3}").parse().ok()?;
+    Some(crate::from_millis(
+        secs.parse::<u64>().ok()?.checked_mul(1000)? + ms,
+    ))
+}
+
+fn format_line(entry: &Entry) -> anyhow::Result<String> {
+    let mut res = format!(
+        "{} {} {} {}",
+        entry.id,
+        format_time(entry.time)?,
+        entry.fsrs.stability,
+        entry.fsrs.difficulty
+    );
+    if let Some(answer) = entry.answer {
+        let (days, recall) = answer
+            .last
+            .map_or(("-".to_string(), "-".to_string()), |(d, r)| {
+                (d.to_string(), r.to_string())
+            });
+        _ = write!(
+            res,
+            " {} {days} {recall} {}",
+            answer.grade as u8,
+            answer.duration.as_millis()
+        );
+    }
+    Ok(res)
+}
+
+fn parse_line(line: &str) -> anyhow::Result<Entry> {
+    let err = || anyhow!("Invalid line `{line}` in journal");
+    let fields: Vec<&str> = line.split(' ').collect();
+    let (&[id, time, stability, difficulty], answer) =
+        fields.split_at_checked(4).ok_or_else(err)?
+    else {
+        return Err(err());
+    };
+    let answer = match answer {
+        [] => None,
+        &[grade, days, recall, duration] => Some(Answer {
+            grade: Grade::try_from(grade.parse::<u8>().map_err(|_| err())?)?,
+            last: if days == "-" {
+                None
+            } else {
+                Some((
+                    days.parse().map_err(|_| err())?,
+                    recall.parse().map_err(|_| err())?,
+                ))
+            },
+            duration: Duration::from_millis(duration.parse().map_err(|_| err())?),
+        }),
+        _ => return Err(err()),
+    };
+    Ok(Entry {
+        id: CardId::parse(id)?,
+        time: parse_time(time).ok_or_else(err)?,
+        fsrs: FSRSParams {
+            stability: stability.parse().map_err(|_| err())?,
+            difficulty: difficulty.parse().map_err(|_| err())?,
+        },
+        answer,
+    })
+}
+
+fn commit_entries(sqlite: &mut rusqlite::Connection, entries: &[Entry]) -> anyhow::Result<()> {
+    let tx = sqlite.transaction()?;
+    for entry in entries {
+        save_review(&tx, entry.id, entry.time, entry.fsrs)?;
+        if let Some(answer) = &entry.answer {
+            save_answer(&tx, entry.id, entry.time, answer)?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Where the answers saved by [`recover`] wait for [`take_recovered`]
+fn recovered_path(path: &Path) -> PathBuf {
+    let mut recovered = path.as_os_str().to_owned();
+    recovered.push(".recovered");
+    recovered.into()
+}
+
+/// Saves the answers left in the journal by a session which did not finish, unless it is still
+/// going, returning how many there were. Writing them to the logs of vaults and marking leeches
+/// needs the cards, so they are kept for the next review to take with [`take_recovered`]
+pub fn recover(sqlite: &mut rusqlite::Connection, path: &Path) -> anyhow::Result<usize> {
+    let Ok(file) = File::open(path) else {
+        return Ok(0);
+    };
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => return Ok(0),
+        Err(TryLockError::Error(e)) => return Err(e.into()),
+    }
+    let data = std::fs::read_to_string(path)?;
+    // The last line may have been cut off by the crash
+    let entries: Vec<Entry> = data.lines().filter_map(|l| parse_line(l).ok()).collect();
+    commit_entries(sqlite, &entries)?;
+    let mut kept = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(recovered_path(path))?;
+    for entry in &entries {
+        writeln!(kept, "{}", format_line(entry)?)?;
+    }
+    kept.sync_data()?;
+    std::fs::remove_file(path)?;
+    Ok(entries.len())
+}
+
+/// The answers [`recover`] saved from the journal at `path` which no review took yet
+pub fn take_recovered(path: &Path) -> anyhow::Result<Vec<Entry>> {
+    let recovered = recovered_path(path);
+    let Ok(data) = std::fs::read_to_string(&recovered) else {
+        return Ok(vec![]);
+    };
+    std::fs::remove_file(recovered)?;
+    Ok(data.lines().filter_map(|l| parse_line(l).ok()).collect())
+}
+
+pub struct Journal {
+    /// The journal file, or `None` if answers are only kept in memory
+    file: Option<(PathBuf, File)>,
+    entries: Vec<Entry>,
+}
+
+impl Journal {
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                bail!("Another review is going on, finish it before starting one")
+            }
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+        Ok(Self {
+            file: Some((path.to_path_buf(), file)),
+            entries: vec![],
+        })
+    }
+
+    /// A journal which writes nothing, for sessions which are not saved
+    pub fn in_memory() -> Self {
+        Self {
+            file: None,
+            entries: vec![],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn push(&mut self, entry: Entry) -> anyhow::Result<()> {
+        if let Some((_, file)) = &mut self.file {
+            writeln!(file, "{}", format_line(&entry)?)?;
+            file.sync_data()?;
+        }
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Takes back the latest answer, returning it
+    pub fn undo(&mut self) -> anyhow::Result<Option<Entry>> {
+        let Some(entry) = self.entries.pop() else {
+            return Ok(None);
+        };
+        if let Some((_, file)) = &mut self.file {
+            // Appends go to the new end of the file
+            file.set_len(0)?;
+            for entry in &self.entries {
+                writeln!(file, "{}", format_line(entry)?)?;
+            }
+            file.sync_data()?;
+        }
+        Ok(Some(entry))
+    }
+
+    /// The latest state of a card, including answers which are not saved yet
+    pub fn card_data(
+        &self,
+        sqlite: &mut rusqlite::Connection,
+        id: CardId,
+    ) -> Option<(SystemTime, FSRSParams)> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|e| e.id == id)
+            .map(|e| (e.time, e.fsrs))
+            .or_else(|| load_card_data(sqlite, id))
+    }
+
+    /// Saves all answers to the database, unless the journal is only in memory, returning the saved answers
+    pub fn commit(self, sqlite: &mut rusqlite::Connection) -> anyhow::Result<Vec<Entry>> {
+        let Some((path, _)) = self.file else {
+            return Ok(vec![]);
+        };
+        commit_entries(sqlite, &self.entries)?;
+        std::fs::remove_file(path)?;
+        Ok(self.entries)
+    }
+
+    /// Throws away all answers
+    pub fn abandon(self) -> anyhow::Result<()> {
+        if let Some((path, _)) = self.file {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn lines() {
+        let entry = parse_line("fguvqzmI 1700000000 2.3065 5.5").unwrap();
+        assert_eq!(entry.id, CardId::parse("fguvqzmI").unwrap());
+        assert_eq!(
+            entry.time,
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+        );
+        assert_eq!(entry.fsrs, FSRSParams::new(2.3065, 5.5));
+        assert_eq!(entry.answer, None);
+        assert!(parse_line("fguvqzmI 1700000000 2.30").is_err());
+
+        let entry = Entry {
+            answer: Some(Answer {
+                grade: Grade::Hard,
+                last: Some((3.5, 0.75)),
+                duration: Duration::from_millis(4200),
+            }),
+            ..entry
+        };
+        let line = format_line(&entry).unwrap();
+        assert_eq!(line, "fguvqzmI 1700000000 2.3065 5.5 2 3.5 0.75 4200");
+        assert_eq!(parse_line(&line).unwrap(), entry);
+        assert!(parse_line("fguvqzmI 1700000000 2.3065 5.5 3 - - 10").is_ok());
+
+        let time = SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_050);
+        assert_eq!(format_time(time).unwrap(), "1700000000.050");
+        assert_eq!(parse_time("1700000000.05"), Some(time));
+        assert_eq!(parse_time("1700000000.0501"), None);
+    }
+}
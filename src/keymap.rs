@@ -0,0 +1,295 @@
+//! The keys of the review screen, which can be changed in the `[keys]` table of the config
+//! along with the grading keys, like `quit = "x"` or `snooze = ["z", "s"]`.
+//!
+//! Keys are looked up in a single table, where the first command bound to a key wins. Quit
+//! comes first so it always works, then the grades, so a grading key like `fail = "j"` takes
+//! the place of the default key of any other command
+use anyhow::bail;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::{
+    config::{Config, Value},
+    fsrs::Grade,
+    grading::Grading,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Command {
+    Grade(Grade),
+    Quit,
+    /// Reveal the back of the card
+    Show,
+    Hint,
+    Snooze,
+    /// Show the card again after the others
+    Skip,
+    /// Leave the card out until tomorrow
+    Bury,
+    Undo,
+    Edit,
+    /// Show the rest of a collapsed back
+    Expand,
+    ScrollDown,
+    ScrollUp,
+    PageDown,
+    PageUp,
+    /// Highlight the button to the left
+    Previous,
+    /// Highlight the button to the right
+    Next,
+    /// Grade with the highlighted button
+    Confirm,
+}
+
+/// The commands besides grades, by their names in the config, with their default keys
+const COMMANDS: &[(&str, Command, &[&str])] = &[
+    ("quit", Command::Quit, &["q", "esc"]),
+    ("show", Command::Show, &[]),
+    ("hint", Command::Hint, &["h"]),
+    ("snooze", Command::Snooze, &["z"]),
+    ("skip", Command::Skip, &["s"]),
+    ("bury", Command::Bury, &["b"]),
+    ("undo", Command::Undo, &["u"]),
+    ("edit", Command::Edit, &["e"]),
+    ("expand", Command::Expand, &["o"]),
+    ("down", Command::ScrollDown, &["down", "j"]),
+    ("up", Command::ScrollUp, &["up", "k"]),
+    ("page_down", Command::PageDown, &["pagedown"]),
+    ("page_up", Command::PageUp, &["pageup"]),
+    ("left", Command::Previous, &["left"]),
+    ("right", Command::Next, &["right"]),
+    ("confirm", Command::Confirm, &["enter"]),
+];
+
+/// Keys other than characters, by their names in the config and how they are shown
+const NAMED: &[(&str, KeyCode, &str)] = &[
+    ("space", KeyCode::Char(' '), "space"),
+    ("enter", KeyCode::Enter, "Enter"),
+    ("esc", KeyCode::Esc, "Esc"),
+    ("tab", KeyCode::Tab, "Tab"),
+    ("backspace", KeyCode::Backspace, "Backspace"),
+    ("up", KeyCode::Up, "↑"),
+    ("down", KeyCode::Down, "↓"),
+    ("left", KeyCode::Left, "←"),
+    ("right", KeyCode::Right, "→"),
+    ("pageup", KeyCode::PageUp, "PgUp"),
+    ("pagedown", KeyCode::PageDown, "PgDn"),
+    ("home", KeyCode::Home, "Home"),
+    ("end", KeyCode::End, "End"),
+];
+
+/// A key as written in the config, either a single character or a name like `enter`
+fn parse_key(name: &str) -> Option<KeyCode> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(KeyCode::Char(c)),
+        _ => NAMED
+            .iter()
+            .find(|(n, ..)| n.eq_ignore_ascii_case(name))
+            .map(|&(_, code, _)| code),
+    }
+}
+
+fn key_name(code: KeyCode) -> String {
+    match NAMED.iter().find(|&&(_, c, _)| c == code) {
+        Some((.., shown)) => (*shown).to_string(),
+        None => match code {
+            KeyCode::Char(c) => c.to_string(),
+            code => format!("{code:?}"),
+        },
+    }
+}
+
+/// Which command each key stands for
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    /// Keys and their commands, where the first entry for a key is the one used
+    table: Vec<(KeyCode, Command)>,
+    /// Whether keys without a command show the back, as when `show` is not set
+    show_any: bool,
+}
+
+impl Keymap {
+    /// The keys for `grading`, with the other commands bound as in the `[keys]` table of the
+    /// config, or to their defaults
+    pub fn new(config: &Config, grading: &Grading) -> anyhow::Result<Self> {
+        let mut table = vec![];
+        let mut rest = vec![];
+        let mut show_any = true;
+        for &(name, command, defaults) in COMMANDS {
+            let keys = match config.get(&format!("keys.{name}")) {
+                None => defaults.iter().map(|k| parse_key(k)).collect(),
+                Some(Value::String(key)) => vec![parse_key(key)],
+                Some(Value::Array(keys)) if !keys.is_empty() => keys
+                    .iter()
+                    .map(|k| k.as_str().and_then(parse_key))
+                    .collect(),
+                Some(_) => bail!("`keys.{name}` should be a key or a list of keys"),
+            };
+            let Some(keys) = keys.into_iter().collect::<Option<Vec<_>>>() else {
+                bail!(
+                    "`keys.{name}` should be single characters, or one of {}",
+                    NAMED
+                        .iter()
+                        .map(|(n, ..)| *n)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            };
+            if command == Command::Show {
+                show_any = keys.is_empty();
+            }
+            let entries = keys.into_iter().map(|k| (k, command));
+            if command == Command::Quit {
+                table.extend(entries);
+            } else {
+                rest.extend(entries);
+            }
+        }
+        for button in &grading.buttons {
+            table.push((KeyCode::Char(button.key), Command::Grade(button.grade)));
+        }
+        if let Some(good) = grading.grade(' ') {
+            table.push((KeyCode::Char(' '), Command::Grade(good)));
+        }
+        table.extend(rest);
+        Ok(Self { table, show_any })
+    }
+
+    /// The keys for the front of a card, which can not be graded yet
+    pub fn without_grades(&self) -> Self {
+        Self {
+            table: self
+                .table
+                .iter()
+                .filter(|(_, c)| !matches!(c, Command::Grade(_)))
+                .copied()
+                .collect(),
+            show_any: self.show_any,
+        }
+    }
+
+    /// The command of a key press. Ctrl-C always quits
+    pub fn command(&self, event: KeyEvent) -> Option<Command> {
+        if event.code == KeyCode::Char('c') && event.modifiers.contains(KeyModifiers::CONTROL) {
+            return Some(Command::Quit);
+        }
+        self.table
+            .iter()
+            .find(|(code, _)| *code == event.code)
+            .map(|&(_, command)| command)
+    }
+
+    /// The command of a line typed in the accessible interface, where an empty line stands for
+    /// Enter and a single character for its key
+    pub fn line(&self, line: &str) -> Option<Command> {
+        let mut chars = line.chars();
+        let code = match (chars.next(), chars.next()) {
+            (None, _) => KeyCode::Enter,
+            (Some(c), None) => KeyCode::Char(c),
+            _ => return None,
+        };
+        self.command(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    /// Whether a key shows the back of the card
+    pub fn shows(&self, event: KeyEvent) -> bool {
+        match self.command(event) {
+            Some(Command::Show) => true,
+            _ => self.show_any,
+        }
+    }
+
+    /// The keys standing for `command`, like `↓/j`, leaving out those taken by an earlier command
+    pub fn keys(&self, command: Command) -> String {
+        self.table
+            .iter()
+            .filter(|&&(code, c)| c == command && self.command(KeyEvent::from(code)) == Some(c))
+            .map(|&(code, _)| key_name(code))
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    /// What pressing keys does, like `z to snooze, ← and → to choose`, for the commands which
+    /// have keys
+    pub fn help(&self, commands: &[(&[Command], &str)]) -> String {
+        commands
+            .iter()
+            .filter_map(|&(commands, does)| {
+                let keys: Vec<String> = commands
+                    .iter()
+                    .map(|&c| self.keys(c))
+                    .filter(|k| !k.is_empty())
+                    .collect();
+                (!keys.is_empty()).then(|| format!("{} to {does}", keys.join(" and ")))
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// The keys showing the back, or `None` if any key without another command does
+    pub fn show_keys(&self) -> Option<String> {
+        (!self.show_any).then(|| self.keys(Command::Show))
+    }
+
+    /// The keys which can be typed as a line in the accessible interface, where Enter is an
+    /// empty line
+    pub fn plain(&self) -> Self {
+        Self {
+            table: self
+                .table
+                .iter()
+                .filter(|(code, _)| matches!(code, KeyCode::Char(_) | KeyCode::Enter))
+                .copied()
+                .collect(),
+            show_any: self.show_any,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn bindings() {
+        let config = Config::parse(
+            "[keys]\nfail = \"j\"\npass = \"k\"\nundo = [\"backspace\", \"U\"]\nshow = \"space\"",
+        )
+        .unwrap();
+        let grading = Grading::new(Some(crate::grading::GradingMode::PassFail), &config).unwrap();
+        let keys = Keymap::new(&config, &grading).unwrap();
+        let press = |code| keys.command(KeyEvent::from(code));
+        assert_eq!(
+            press(KeyCode::Char('j')),
+            Some(Command::Grade(Grade::Again))
+        );
+        assert_eq!(press(KeyCode::Down), Some(Command::ScrollDown));
+        assert_eq!(press(KeyCode::Backspace), Some(Command::Undo));
+        assert_eq!(press(KeyCode::Char('u')), None);
+        assert_eq!(keys.keys(Command::ScrollDown), "↓");
+        assert_eq!(
+            keys.help(&[
+                (&[Command::Undo], "undo"),
+                (&[Command::ScrollUp, Command::ScrollDown], "scroll")
+            ]),
+            "Backspace/U to undo, ↑ and ↓ to scroll"
+        );
+        assert_eq!(keys.plain().keys(Command::Quit), "q");
+        assert_eq!(keys.plain().line(""), Some(Command::Confirm));
+        assert_eq!(keys.line(""), Some(Command::Confirm));
+        assert_eq!(keys.line("q"), Some(Command::Quit));
+        assert_eq!(keys.line("quit"), None);
+
+        // Space is good once the back is shown, and shows it before
+        assert_eq!(press(KeyCode::Char(' ')), Some(Command::Grade(Grade::Good)));
+        let front = keys.without_grades();
+        assert_eq!(front.show_keys().as_deref(), Some("space"));
+        assert!(front.shows(KeyEvent::from(KeyCode::Char(' '))));
+        assert!(!front.shows(KeyEvent::from(KeyCode::Enter)));
+
+        let bad = |s| Keymap::new(&Config::parse(s).unwrap(), &grading).is_err();
+        assert!(bad("keys.quit = \"ctrl-q\""));
+        assert!(bad("keys.edit = []"));
+    }
+}
@@ -0,0 +1,363 @@
+//! Turns the math between `$` signs in cards into Unicode, so that `$\alpha^2 + \frac{1}{2}$`
+//! reads as `α² + ¹⁄₂` in the terminal.
+//!
+//! Only simple LaTeX is understood: Greek letters and common symbols, superscripts, subscripts,
+//! fractions, roots and text. Other commands are kept, and scripts of characters without a
+//! Unicode version are written like `x^(αβ)`. As in Pandoc, a `$` only opens math when followed
+//! by something other than a space, so prices like `$5 and $10` are kept
+use std::{fmt::Write, iter::Peekable, str::Chars};
+
+const SYMBOLS: &[(&str, &str)] = &[
+    ("alpha", "α"),
+    ("beta", "β"),
+    ("gamma", "γ"),
+    ("delta", "δ"),
+    ("epsilon", "ε"),
+    ("varepsilon", "ε"),
+    ("zeta", "ζ"),
+    ("eta", "η"),
+    ("theta", "θ"),
+    ("iota", "ι"),
+    ("kappa", "κ"),
+    ("lambda", "λ"),
+    ("mu", "μ"),
+    ("nu", "ν"),
+    ("xi", "ξ"),
+    ("pi", "π"),
+    ("rho", "ρ"),
+    ("sigma", "σ"),
+    ("tau", "τ"),
+    ("upsilon", "υ"),
+    ("phi", "φ"),
+    ("varphi", "φ"),
+    ("chi", "χ"),
+    ("psi", "ψ"),
+    ("omega", "ω"),
+    ("Gamma", "Γ"),
+    ("Delta", "Δ"),
+    ("Theta", "Θ"),
+    ("Lambda", "Λ"),
+    ("Xi", "Ξ"),
+    ("Pi", "Π"),
+    ("Sigma", "Σ"),
+    ("Phi", "Φ"),
+    ("Psi", "Ψ"),
+    ("Omega", "Ω"),
+    ("cdot", "·"),
+    ("times", "×"),
+    ("div", "÷"),
+    ("pm", "±"),
+    ("mp", "∓"),
+    ("le", "≤"),
+    ("leq", "≤"),
+    ("ge", "≥"),
+    ("geq", "≥"),
+    ("neq", "≠"),
+    ("ne", "≠"),
+    ("approx", "≈"),
+    ("equiv", "≡"),
+    ("sim", "∼"),
+    ("propto", "∝"),
+    ("infty", "∞"),
+    ("partial", "∂"),
+    ("nabla", "∇"),
+    ("sum", "∑"),
+    ("prod", "∏"),
+    ("int", "∫"),
+    ("oint", "∮"),
+    ("in", "∈"),
+    ("notin", "∉"),
+    ("subset", "⊂"),
+    ("subseteq", "⊆"),
+    ("cup", "∪"),
+    ("cap", "∩"),
+    ("emptyset", "∅"),
+    ("forall", "∀"),
+    ("exists", "∃"),
+    ("neg", "¬"),
+    ("land", "∧"),
+    ("lor", "∨"),
+    ("to", "→"),
+    ("rightarrow", "→"),
+    ("leftarrow", "←"),
+    ("Rightarrow", "⇒"),
+    ("Leftarrow", "⇐"),
+    ("leftrightarrow", "↔"),
+    ("iff", "⇔"),
+    ("mapsto", "↦"),
+    ("ldots", "…"),
+    ("cdots", "⋯"),
+    ("circ", "∘"),
+    ("degree", "°"),
+    ("hbar", "ℏ"),
+    ("ell", "ℓ"),
+    ("angle", "∠"),
+    ("perp", "⊥"),
+    ("parallel", "∥"),
+    ("langle", "⟨"),
+    ("rangle", "⟩"),
+    ("quad", "  "),
+    ("qquad", "    "),
+];
+
+const SUPERSCRIPTS: &[(char, char)] = &[
+    ('0', '⁰'),
+    ('1', '¹'),
+    ('2', '²'),
+    ('3', '³'),
+    ('4', '⁴'),
+    ('5', '⁵'),
+    ('6', '⁶'),
+    ('7', '⁷'),
+    ('8', '⁸'),
+    ('9', '⁹'),
+    ('+', '⁺'),
+    ('-', '⁻'),
+    ('=', '⁼'),
+    ('(', '⁽'),
+    (')', '⁾'),
+    ('a', 'ᵃ'),
+    ('b', 'ᵇ'),
+    ('c', 'ᶜ'),
+    ('d', 'ᵈ'),
+    ('e', 'ᵉ'),
+    ('f', 'ᶠ'),
+    ('g', 'ᵍ'),
+    ('h', 'ʰ'),
+    ('i', 'ⁱ'),
+    ('j', 'ʲ'),
+    ('k', 'ᵏ'),
+    ('l', 'ˡ'),
+    ('m', 'ᵐ'),
+    ('n', 'ⁿ'),
+    ('o', 'ᵒ'),
+    ('p', 'ᵖ'),
+    ('r', 'ʳ'),
+    ('s', 'ˢ'),
+    ('t', 'ᵗ'),
+    ('u', 'ᵘ'),
+    ('v', 'ᵛ'),
+    ('w', 'ʷ'),
+    ('x', 'ˣ'),
+    ('y', 'ʸ'),
+    ('z', 'ᶻ'),
+    ('T', 'ᵀ'),
+];
+
+const SUBSCRIPTS: &[(char, char)] = &[
+    ('0', '₀'),
+    ('1', '₁'),
+    ('2', '₂'),
+    ('3', '₃'),
+    ('4', '₄'),
+    ('5', '₅'),
+    ('6', '₆'),
+    ('7', '₇'),
+    ('8', '₈'),
+    ('9', '₉'),
+    ('+', '₊'),
+    ('-', '₋'),
+    ('=', '₌'),
+    ('(', '₍'),
+    (')', '₎'),
+    ('a', 'ₐ'),
+    ('e', 'ₑ'),
+    ('h', 'ₕ'),
+    ('i', 'ᵢ'),
+    ('j', 'ⱼ'),
+    ('k', 'ₖ'),
+    ('l', 'ₗ'),
+    ('m', 'ₘ'),
+    ('n', 'ₙ'),
+    ('o', 'ₒ'),
+    ('p', 'ₚ'),
+    ('r', 'ᵣ'),
+    ('s', 'ₛ'),
+    ('t', 'ₜ'),
+    ('u', 'ᵤ'),
+    ('v', 'ᵥ'),
+    ('x', 'ₓ'),
+];
+
+/// The text written with the characters of `table`, if they all have one
+fn script(text: &str, table: &[(char, char)]) -> Option<String> {
+    text.chars()
+        .map(|c| {
+            table
+                .iter()
+                .find(|&&(from, _)| from == c)
+                .map(|&(_, to)| to)
+        })
+        .collect()
+}
+
+/// Wraps text of more than one character in parentheses
+fn parenthesize(text: &str) -> String {
+    if text.chars().count() > 1 {
+        format!("({text})")
+    } else {
+        text.to_string()
+    }
+}
+
+/// Converts the math read from `chars`, up to the end of the current `{}` group
+fn convert(chars: &mut Peekable<Chars>) -> String {
+    let mut res = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '}' => break,
+            '{' => res.push_str(&convert(chars)),
+            '^' => {
+                let arg = argument(chars);
+                match script(&arg, SUPERSCRIPTS) {
+                    Some(sup) => res.push_str(&sup),
+                    None => _ = write!(res, "^{}", parenthesize(&arg)),
+                }
+            }
+            '_' => {
+                let arg = argument(chars);
+                match script(&arg, SUBSCRIPTS) {
+                    Some(sub) => res.push_str(&sub),
+                    None => _ = write!(res, "_{}", parenthesize(&arg)),
+                }
+            }
+            '*' => res.push('∗'),
+            '\'' => res.push('′'),
+            '\\' => res.push_str(&command(chars)),
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+/// Converts the argument of a command or script: a group, a command or a single character
+fn argument(chars: &mut Peekable<Chars>) -> String {
+    while chars.next_if_eq(&' ').is_some() {}
+    match chars.next() {
+        Some('{') => convert(chars),
+        Some('\\') => command(chars),
+        Some(c) => c.to_string(),
+        None => String::new(),
+    }
+}
+
+/// Converts a command, after its backslash
+fn command(chars: &mut Peekable<Chars>) -> String {
+    let mut name = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_alphabetic) {
+        name.push(c);
+    }
+    if name.is_empty() {
+        // Escaped characters like `\{`, and spacing like `\,`
+        return match chars.next() {
+            Some(',' | ';' | ':' | ' ') => " ".to_string(),
+            Some('!') | None => String::new(),
+            Some(c) => c.to_string(),
+        };
+    }
+    if let Some((_, symbol)) = SYMBOLS.iter().find(|(n, _)| *n == name) {
+        return (*symbol).to_string();
+    }
+    match name.as_str() {
+        "frac" | "dfrac" | "tfrac" => {
+            let (numerator, denominator) = (argument(chars), argument(chars));
+            // Only numbers read well as small figures
+            let digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+            match (
+                script(&numerator, SUPERSCRIPTS),
+                script(&denominator, SUBSCRIPTS),
+            ) {
+                (Some(sup), Some(sub)) if digits(&numerator) && digits(&denominator) => {
+                    format!("{sup}⁄{sub}")
+                }
+                _ => format!(
+                    "{}/{}",
+                    parenthesize(&numerator),
+                    parenthesize(&denominator)
+                ),
+            }
+        }
+        "sqrt" => format!("√{}", parenthesize(&argument(chars))),
+        "text" | "textrm" | "mathrm" | "mathit" | "mathbf" | "operatorname" => argument(chars),
+        "mathbb" => argument(chars)
+            .chars()
+            .map(|c| match c {
+                'R' => 'ℝ',
+                'N' => 'ℕ',
+                'Z' => 'ℤ',
+                'Q' => 'ℚ',
+                'C' => 'ℂ',
+                c => c,
+            })
+            .collect(),
+        "left" | "right" | "displaystyle" => String::new(),
+        name => format!("\\{name}"),
+    }
+}
+
+/// The text with the math between `$` or `$$` converted, leaving code spans as they are
+pub fn render(text: &str) -> String {
+    let mut res = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(['$', '`']) {
+        res.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(code) = rest.strip_prefix('`') {
+            let end = code.find('`').map_or(rest.len(), |e| e + 2);
+            res.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+        let delimiter = if rest.starts_with("$$") { "$$" } else { "$" };
+        let math = &rest[delimiter.len()..];
+        // Math does not start or end with a space, and a closing `$` is not followed by a digit.
+        // Only display math spans several lines, and none goes into code
+        let stops: &[char] = if delimiter == "$" {
+            &['`', '\n']
+        } else {
+            &['`']
+        };
+        let within = &math[..math.find(stops).unwrap_or(math.len())];
+        let end = within.match_indices(delimiter).map(|(i, _)| i).find(|&i| {
+            i > 0
+                && !math[..i].ends_with([' ', '\\'])
+                && !math[i + delimiter.len()..].starts_with(|c: char| c.is_ascii_digit())
+        });
+        match end {
+            Some(end) if !math.starts_with(char::is_whitespace) && !res.ends_with('\\') => {
+                res.push_str(&convert(&mut math[..end].chars().peekable()));
+                rest = &math[end + delimiter.len()..];
+            }
+            _ => {
+                res.push_str(delimiter);
+                rest = math;
+            }
+        }
+    }
+    res.push_str(rest);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn unicode() {
+        assert_eq!(
+            render(r"Euler: $e^{i\pi} + 1 = 0$, and $\frac{1}{2} \cdot x_{n+1}$"),
+            "Euler: e^(iπ) + 1 = 0, and ¹⁄₂ · xₙ₊₁"
+        );
+        assert_eq!(
+            render(r"$$\sqrt{a^2+b^2} \leq \frac{x+y}{2}$$"),
+            "√(a²+b²) ≤ (x+y)/2"
+        );
+        assert_eq!(
+            render(r"$x \in \mathbb{R}$, $\text{speed}_{max}$, $x_\beta$"),
+            "x ∈ ℝ, speedₘₐₓ, x_β"
+        );
+        assert_eq!(render("costs $5 or $10, `$x$`"), "costs $5 or $10, `$x$`");
+        assert_eq!(render(r"$\unknown{x}$ $"), r"\unknownx $");
+        assert_eq!(render(r"\$x$ costs"), r"\$x$ costs");
+    }
+}
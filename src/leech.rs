@@ -0,0 +1,218 @@
+//! Leeches, cards which are forgotten again and again. Reviewing them more rarely helps, as they
+//! are usually badly written rather than hard, so they are set aside to be rewritten.
+//!
+//! A lapse is forgetting a card at least a day after it was last answered, once a day at most,
+//! as Anki counts them, so failing a card while learning or relearning it is not one. Once a card
+//! has lapsed `leech_lapses` times, 8 unless set in the config, it is tagged `#leech` in its file
+//! and suspended, or only tagged with `leech_action = "tag"`. It is set aside again after every
+//! half as many lapses, in case it was unsuspended without being rewritten
+use anyhow::bail;
+
+use crate::{
+    CardBody, CardId,
+    config::{Config, Value},
+    fsrs::Grade,
+    journal::Entry,
+    suspend, tag,
+    ui::{Stylize, hide_cloze},
+};
+
+pub const TAG: &str = "leech";
+
+/// When cards become leeches, and what happens to them then
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Leeches {
+    pub lapses: usize,
+    /// Suspend leeches, rather than only tagging them
+    pub suspend: bool,
+}
+
+impl Leeches {
+    pub fn from_config(config: &Config) -> anyhow::Result<Self> {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let lapses = match config.get("leech_lapses") {
+            None => 8,
+            Some(Value::Number(n)) if *n >= 1.0 && n.fract() == 0.0 => *n as usize,
+            Some(_) => bail!("`leech_lapses` should be a positive whole number"),
+        };
+        let suspend = match config.get("leech_action").map(|v| v.as_str()) {
+            None | Some(Some("suspend")) => true,
+            Some(Some("tag")) => false,
+            Some(_) => bail!("`leech_action` should be suspend or tag"),
+        };
+        Ok(Self { lapses, suspend })
+    }
+
+    /// Whether one of the last `new` answers of a card was a lapse upon which it is set aside
+    fn turned(self, answers: &[Logged], new: usize) -> bool {
+        let again = (self.lapses / 2).max(1);
+        let mut lapses = 0;
+        for (i, lapse) in lapsed(answers).enumerate() {
+            if lapse {
+                lapses += 1;
+                let set_aside =
+                    lapses >= self.lapses && (lapses - self.lapses).is_multiple_of(again);
+                if set_aside && i + new >= answers.len() {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
+
+/// An answer from the review log: its grade, the days since the answer before unless the card
+/// was new, and the UTC day it was given on
+pub type Logged = (Grade, Option<f32>, u64);
+
+/// Whether each answer was a lapse
+fn lapsed(answers: &[Logged]) -> impl Iterator<Item = bool> + '_ {
+    let mut last_day = None;
+    answers.iter().map(move |&(grade, elapsed, day)| {
+        let lapse =
+            grade == Grade::Again && elapsed.is_some_and(|e| e >= 1.0) && last_day != Some(day);
+        if lapse {
+            last_day = Some(day);
+        }
+        lapse
+    })
+}
+
+/// The number of lapses in a history of answers
+pub fn lapses(answers: &[Logged]) -> usize {
+    lapsed(answers).filter(|&lapse| lapse).count()
+}
+
+/// Every answer to a card, oldest first
+pub fn history(sqlite: &rusqlite::Connection, id: CardId) -> anyhow::Result<Vec<Logged>> {
+    let mut statement = sqlite.prepare(
+        "select grade, elapsed_days, time / 86400000 from review_log where card = ?1 order by time",
+    )?;
+    statement
+        .query_map([id.as_int()], |row| {
+            Ok((row.get::<_, u8>(0)?, row.get(1)?, row.get(2)?))
+        })?
+        .map(|row| {
+            let (grade, elapsed, day) = row?;
+            Ok((Grade::try_from(grade)?, elapsed, day))
+        })
+        .collect()
+}
+
+/// Sets aside the cards which became leeches with the answers in `entries`
+pub fn mark(
+    sqlite: &rusqlite::Connection,
+    leeches: Leeches,
+    cards: &[CardBody],
+    entries: &[Entry],
+) -> anyhow::Result<()> {
+    // The cards answered, and how many times
+    let mut answers: Vec<(CardId, usize)> = vec![];
+    for entry in entries.iter().filter(|e| e.answer.is_some()) {
+        match answers.iter_mut().find(|(id, _)| *id == entry.id) {
+            Some((_, n)) => *n += 1,
+            None => answers.push((entry.id, 1)),
+        }
+    }
+    let mut marked = vec![];
+    let mut suspended = vec![];
+    for (id, new) in answers {
+        let Some(card) = cards.iter().find(|c| c.id == id) else {
+            continue;
+        };
+        let history = history(sqlite, id)?;
+        if !leeches.turned(&history, new) {
+            continue;
+        }
+        if leeches.suspend {
+            suspend::suspend(sqlite, id)?;
+            suspended.push(card.clone());
+        }
+        eprintln!(
+            "{} {}:{} was forgotten {} times, it may need to be rewritten",
+            "Leech:".bad(),
+            card.file.display(),
+            card.line,
+            lapses(&history)
+        );
+        if !card.tags.iter().any(|t| t == TAG) {
+            marked.push(card.clone());
+        }
+    }
+    tag::rewrite(&marked, TAG, true)?;
+    suspend::write(&suspended, true)
+}
+
+/// Prints the cards which lapsed at least as often as leeches do, most lapses first
+pub fn list(
+    sqlite: &rusqlite::Connection,
+    leeches: Leeches,
+    cards: &[CardBody],
+) -> anyhow::Result<()> {
+    let mut rows = vec![];
+    for card in cards {
+        let lapses = lapses(&history(sqlite, card.id)?);
+        if lapses >= leeches.lapses {
+            rows.push((card, lapses, suspend::is_suspended(sqlite, card)?));
+        }
+    }
+    rows.sort_by_key(|&(_, lapses, _)| std::cmp::Reverse(lapses));
+    for (i, (card, lapses, suspended)) in rows.iter().enumerate() {
+        println!("{}. {}", i + 1, hide_cloze(card.front.trim()).bold());
+        println!(
+            "{}",
+            format!("{}:{}", card.file.display(), card.line).muted()
+        );
+        let suspended = if *suspended { ", suspended" } else { "" };
+        println!("lapses: {lapses}{suspended}\n");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn lapsing() {
+        use Grade::{Again, Good};
+        let leeches = Leeches {
+            lapses: 2,
+            suspend: true,
+        };
+        // Failing a new card while learning it is not a lapse, nor is failing it again when
+        // relearning it, even the next day
+        let answers = [
+            (Again, None, 0),
+            (Good, Some(0.01), 0),
+            (Again, Some(0.02), 0),
+            (Good, Some(0.01), 0),
+            (Again, Some(3.0), 3),
+            (Again, Some(0.01), 3),
+            (Good, Some(1.0), 4),
+            (Again, Some(5.0), 9),
+        ];
+        assert_eq!(lapses(&answers), 2);
+        assert!(leeches.turned(&answers, 1));
+        assert!(!leeches.turned(&answers, 0));
+        assert!(!leeches.turned(&answers[..7], 7));
+        assert!(!leeches.turned(&[&answers[..], &[(Good, Some(0.1), 9)]].concat(), 1));
+        // At most once a day
+        assert!(!leeches.turned(&[&answers[..], &[(Again, Some(1.5), 9)]].concat(), 1));
+        // And again after every lapse past that
+        let more = [(Good, Some(1.0), 10), (Again, Some(4.0), 14)];
+        assert!(leeches.turned(&[&answers[..], &more].concat(), 2));
+
+        let config = Config::parse("leech_lapses = 5\nleech_action = \"tag\"").unwrap();
+        assert_eq!(
+            Leeches::from_config(&config).unwrap(),
+            Leeches {
+                lapses: 5,
+                suspend: false
+            }
+        );
+        assert!(
+            Leeches::from_config(&Config::parse("leech_action = \"delete\"").unwrap()).is_err()
+        );
+    }
+}
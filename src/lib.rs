@@ -0,0 +1,6 @@
+#![deny(clippy::pedantic)]
+//! What other tools need to read and write cards the way cardsharp does.
+//!
+//! cardsharp itself is the binary; this library only holds the parts which must stay the same
+//! between them, like how cards are identified
+pub mod card_id;
@@ -0,0 +1,126 @@
+//! Daily limits on new cards and reviews, so that coming back after a break does not bring
+//! every card which fell due in the meantime at once.
+//!
+//! With `new_limit` and `review_limit` in the config, or `review --new-limit` and
+//! `--review-limit`, sessions introduce at most that many unseen cards and review at most that
+//! many due cards each UTC day. What was done earlier in the day is counted from the saved
+//! states, so the limits hold over any number of sessions. A card is new until its first saved
+//! state, as in a review, which an imported card already has even if it has no answers logged
+use anyhow::bail;
+
+use crate::config::{Config, Value};
+
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Limits {
+    /// New cards a day
+    pub new: Option<usize>,
+    /// Reviews of cards seen before, a day
+    pub reviews: Option<usize>,
+}
+
+/// What is left of a limit for today, and how often it was gone over
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Allowance {
+    left: usize,
+    pub over: usize,
+}
+
+impl Allowance {
+    fn new(limit: Option<usize>, done: usize) -> Self {
+        Self {
+            left: limit.map_or(usize::MAX, |l| l.saturating_sub(done)),
+            over: 0,
+        }
+    }
+
+    /// Uses up one, returning whether that was within the limit
+    pub fn take(&mut self) -> bool {
+        if self.left == 0 {
+            self.over += 1;
+            return false;
+        }
+        self.left -= 1;
+        true
+    }
+}
+
+fn whole(config: &Config, key: &str) -> anyhow::Result<Option<usize>> {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    match config.get(key) {
+        None => Ok(None),
+        Some(Value::Number(n)) if *n >= 0.0 && n.fract() == 0.0 => Ok(Some(*n as usize)),
+        Some(_) => bail!("`{key}` should be a whole number"),
+    }
+}
+
+impl Limits {
+    /// The limits given, or else those in the config
+    pub fn new(
+        config: &Config,
+        new: Option<usize>,
+        reviews: Option<usize>,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            new: new.or(whole(config, "new_limit")?),
+            reviews: reviews.or(whole(config, "review_limit")?),
+        })
+    }
+
+    /// How many more new cards and reviews there can be on the UTC day `today`
+    pub fn left(
+        self,
+        sqlite: &rusqlite::Connection,
+        today: u64,
+    ) -> anyhow::Result<(Allowance, Allowance)> {
+        let (new, reviews) = if self == Self::default() {
+            (0, 0)
+        } else {
+            done(sqlite, today)?
+        };
+        Ok((
+            Allowance::new(self.new, new),
+            Allowance::new(self.reviews, reviews),
+        ))
+    }
+}
+
+/// The number of cards first reviewed on the UTC day `today`, and of other cards reviewed on it
+pub fn done(sqlite: &rusqlite::Connection, today: u64) -> anyhow::Result<(usize, usize)> {
+    let start = today * 86_400_000;
+    Ok(sqlite.query_row(
+        "select coalesce(sum(first >= ?1), 0), coalesce(sum(first < ?1), 0)
+             from (select min(last_reviewed) as first, max(last_reviewed) as last from review
+                   group by card)
+             where last >= ?1",
+        [start],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn limits() {
+        let config = Config::parse("new_limit = 10\nreview_limit = 200").unwrap();
+        assert_eq!(
+            Limits::new(&config, None, Some(50)).unwrap(),
+            Limits {
+                new: Some(10),
+                reviews: Some(50)
+            }
+        );
+        assert_eq!(
+            Limits::new(&Config::parse("").unwrap(), None, None).unwrap(),
+            Limits::default()
+        );
+        assert!(Limits::new(&Config::parse("new_limit = -1").unwrap(), None, None).is_err());
+
+        let mut allowance = Allowance::new(Some(5), 3);
+        assert!(allowance.take() && allowance.take());
+        assert!(!allowance.take() && !allowance.take());
+        assert_eq!(allowance.over, 2);
+        assert!(Allowance::new(None, 100).take());
+    }
+}
@@ -0,0 +1,99 @@
+//! Checking typed answers to cards whose back is a list, like "Name the 7 OSI layers"
+use std::collections::HashSet;
+
+/// The items of a back which is a bullet or numbered list, or `None` if it is something else
+pub fn items(back: &str) -> Option<Vec<&str>> {
+    let mut res = vec![];
+    for line in back.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        let item = line
+            .strip_prefix("- ")
+            .or_else(|| line.strip_prefix("* "))
+            .or_else(|| {
+                let (number, rest) = line.split_once(". ")?;
+                number.chars().all(|c| c.is_ascii_digit()).then_some(rest)
+            })?;
+        res.push(item.trim());
+    }
+    (res.len() > 1).then_some(res)
+}
+
+/// Lowercases and drops punctuation, so that small differences in typing still match
+fn normalize(item: &str) -> String {
+    item.chars()
+        .filter(|c| c.is_alphanumeric() || c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Comparison<'a> {
+    pub found: usize,
+    /// Expected items which were not typed
+    pub missed: Vec<&'a str>,
+    /// Typed items which were not expected
+    pub extra: Vec<String>,
+}
+
+/// Compares typed items to the expected ones, in any order
+pub fn compare<'a>(expected: &[&'a str], typed: &[String]) -> Comparison<'a> {
+    let typed_set: HashSet<String> = typed.iter().map(|t| normalize(t)).collect();
+    let expected_set: HashSet<String> = expected.iter().map(|e| normalize(e)).collect();
+    let missed: Vec<&str> = expected
+        .iter()
+        .copied()
+        .filter(|e| !typed_set.contains(&normalize(e)))
+        .collect();
+    Comparison {
+        found: expected.len() - missed.len(),
+        missed,
+        extra: typed
+            .iter()
+            .filter(|t| !expected_set.contains(&normalize(t)))
+            .cloned()
+            .collect(),
+    }
+}
+
+impl Comparison<'_> {
+    /// A line summarizing the comparison, like `5 of 7, missed: Session, Presentation`
+    pub fn summary(&self) -> String {
+        let mut res = format!("{} of {}", self.found, self.found + self.missed.len());
+        if !self.missed.is_empty() {
+            res.push_str(", missed: ");
+            res.push_str(&self.missed.join(", "));
+        }
+        if !self.extra.is_empty() {
+            res.push_str(", not in the list: ");
+            res.push_str(&self.extra.join(", "));
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn compare_items() {
+        let back = "- Physical\n- Data link\n- Network\n";
+        let expected = items(back).unwrap();
+        assert_eq!(expected, ["Physical", "Data link", "Network"]);
+        assert_eq!(items("1. One\n2. Two"), Some(vec!["One", "Two"]));
+        assert_eq!(items("Just some text\n- and an item"), None);
+
+        let typed = [
+            "network".to_string(),
+            "Data-link".to_string(),
+            "Layer 8".to_string(),
+        ];
+        let comparison = compare(&expected, &typed);
+        assert_eq!(
+            comparison.summary(),
+            "1 of 3, missed: Physical, Data link, not in the list: Data-link, Layer 8"
+        );
+    }
+}
@@ -1,154 +1,813 @@
 #![deny(clippy::pedantic)]
 
-use base64::{Engine, prelude::BASE64_STANDARD};
-use clap::Parser;
-use crossterm::style::Stylize;
-use rand::seq::SliceRandom;
+use crate::ui::Stylize;
+use cardsharp::card_id::CardId;
+use clap::{Parser, Subcommand};
+use rand::{SeedableRng, rngs::StdRng, seq::SliceRandom};
 use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Write as _,
     fs::OpenOptions,
-    io::{Read, Seek, SeekFrom, Write},
+    io::Write,
     path::{Path, PathBuf},
+    sync::OnceLock,
     time::{Duration, SystemTime},
 };
 
 use crate::fsrs::{FSRSParams, Grade};
 
+mod anki;
+mod balance;
+mod budget;
+mod bundle;
+mod cloze;
+mod config;
+mod cost;
+mod custom_data;
+mod deck;
+mod diff;
+mod export;
+mod filter;
+mod format;
 mod fsrs;
+mod fuzz;
+mod generate;
+mod grading;
+mod graphics;
+mod ids;
+mod import;
+mod init;
+mod input;
+mod journal;
+mod keymap;
+mod latex;
+mod leech;
+mod limit;
+mod list;
+mod markdown;
+mod media;
+mod merged;
+mod migrate;
+mod notify;
+mod open;
+mod optimize;
+mod pager;
+mod png;
+mod queue;
+mod record;
+mod relink;
+mod relocate;
+mod report;
+mod sample;
+mod section;
+mod session;
+mod similar;
+mod simulate;
+mod snooze;
+mod spell;
+mod stats;
+mod suspend;
+mod tag;
+mod template;
+mod theme;
+mod timer;
 mod ui;
+mod vault;
+mod zip;
 
-/// Cards have 6 byte identifiers.
-/// This is so that they can be conveniently represented in base64 as 8 characters
-#[derive(Debug, Copy, Clone)]
-struct CardId(pub [u8; 6]);
-
-impl CardId {
-    fn as_int(self) -> u64 {
-        let mut res = 0;
-        for b in self.0 {
-            res |= u64::from(b);
-            res <<= 8;
-        }
-        res
-    }
-}
-
+#[derive(Debug, Clone)]
 struct CardBody {
     id: CardId,
     front: String,
     back: String,
+    /// File the card was loaded from
+    file: PathBuf,
+    /// Line of the `REVIEW` marker, starting from 1
+    line: usize,
+    /// Whether this is the backwards side of a two sided card
+    reversed: bool,
+    /// Whether the front is a spec for [`generate::Generator`], rather than a question
+    generated: bool,
+    /// Whether the back is a short answer to type, compared with [`diff::diff`]
+    typed: bool,
+    /// The `#tag`s on the `REVIEW` line, without the `#`, shared by both sides of two sided cards
+    tags: Vec<String>,
+    /// The deck of the file, see [`deck::name`]
+    deck: String,
 }
 
-/// Loads cards from the given string representing a file
-fn load_card_bodies(data: &str) -> Vec<CardBody> {
-    let mut res = vec![];
-    let mut lines = data.lines().peekable();
-    while let Some(i) = lines.next() {
-        let Some(i) = i.strip_prefix("REVIEW--") else {
-            continue;
-        };
-
-        let Some((id, i)) = i.find(':').map(|idx| i.split_at(idx)) else {
-            continue;
-        };
-        let mut front = &i[1..];
-
-        let Ok(id) = BASE64_STANDARD.decode(id) else {
-            continue;
-        };
-        let Ok(id) = id.try_into() else {
-            continue;
-        };
-
-        let mut back = String::new();
-        while let Some(i) = lines.next_if(|l| {
-            !l.starts_with("REVIEW--") && !l.starts_with("---") && !l.starts_with("<<<")
-        }) {
-            back.push_str(i);
-            back.push('\n');
+impl CardBody {
+    /// A hash of the text of the card, which stays the same across versions of cardsharp
+    fn content_hash(&self) -> i64 {
+        // 64 bit FNV-1a
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for b in self
+            .front
+            .trim()
+            .bytes()
+            .chain([0])
+            .chain(self.back.trim().bytes())
+        {
+            hash ^= u64::from(b);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
         }
-
-        if let Some(actual_front) = front.strip_prefix(':') {
-            front = actual_front;
-            let mut back_id: [u8; 6] = id;
-
-            back_id[0] = back_id[0] ^ 0x80;
-            res.push(CardBody {
-                id: CardId(back_id),
-                front: back.clone(),
-                back: front.to_string(),
-            });
-        };
-        res.push(CardBody {
-            id: CardId(id),
-            front: front.to_string(),
-            back,
-        });
+        hash.cast_signed()
     }
-    res
 }
 
 #[derive(Debug, Parser)]
 #[command(version)]
+struct Cli {
+    /// Database file to use, instead of `db.sqlite3` in `$CARDSHARP_DB` or
+    /// `$XDG_DATA_HOME/cardsharp`
+    ///
+    /// The journal and session of an unfinished review are kept next to it, as `<db>.journal`
+    /// and `<db>.session`
+    #[arg(long, global = true, value_name = "FILE")]
+    db: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Debug, Subcommand)]
 enum Commands {
     /// Review all cards due
     Review {
-        /// Target retention for study
+        /// Target retention for study. Defaults to `retention` in the config, or 0.9
         ///
         /// Clamped between 0.0 and 1.0
-        #[arg(short, long, default_value = "0.9")]
-        retention: f32,
+        #[arg(short, long)]
+        retention: Option<f32>,
+        /// Only review cards matching the filter, such as `deck:jp tag:verb is:due`
+        #[arg(short, long)]
+        filter: Option<String>,
+        /// Only review cards in the smart deck saved under this name in the config
+        #[arg(short, long)]
+        smart: Option<String>,
+        /// Only review cards tagged `#<tag>`, or with any of the tags if given several times
+        #[arg(short, long)]
+        tag: Vec<String>,
+        /// Only review cards in this deck
+        #[arg(short, long)]
+        deck: Option<String>,
+        /// Append every answer of the session to this file, to be used with `replay`
+        #[arg(long)]
+        record: Option<PathBuf>,
+        /// Buttons to grade cards with, defaulting to the `grading` setting in the config
+        #[arg(short, long)]
+        grading: Option<grading::GradingMode>,
+        /// Print plain text suited for screen readers, instead of drawing on the screen
+        #[arg(long)]
+        accessible: bool,
+        /// Continue the last session where it stopped, with the same files and cards
+        #[arg(long, conflicts_with_all = ["filter", "smart", "tag", "deck", "files"])]
+        resume: bool,
+        /// Print the cards the session would show, in order, without reviewing them
+        #[arg(long)]
+        preview: bool,
+        /// Run the session without saving anything, neither answers nor new card ids
+        #[arg(long)]
+        read_only: bool,
+        /// Introduce new cards even past `new_per_week` and `max_daily_reviews` in the config
+        #[arg(long)]
+        over_budget: bool,
+        /// Introduce at most this many new cards a day. Defaults to `new_limit` in the config
+        #[arg(long)]
+        new_limit: Option<usize>,
+        /// Review at most this many cards seen before a day. Defaults to `review_limit` in the
+        /// config
+        #[arg(long)]
+        review_limit: Option<usize>,
+        /// Order of the cards, where mixed takes turns between decks and tags and blocked groups
+        /// them. Defaults to `order` in the config, or shuffled
+        #[arg(long)]
+        order: Option<queue::Order>,
+        /// Read answers from stdin instead of showing cards, as lines of `<card id> <grade>`
+        ///
+        /// Each answer is saved the same way as in a review, and the card id and days until it is
+        /// due again are printed. Grades are 1 to 4, or again, hard, good or easy
+        #[arg(long, conflicts_with_all = ["resume", "preview", "accessible"])]
+        batch: bool,
         /// List of files to look for cards
+        ///
+        /// A file can be given as `file.md:120` or `file.md#heading`, to only review the cards in
+        /// the section around that line or under that heading
         files: Vec<PathBuf>,
     },
 
     /// Initializes all the given cards in the database
     ///
-    /// usually unnecessary to do manually, as all commands automatically do this by default
+    /// usually unnecessary to do manually, as all commands automatically do this by default.
+    /// Ctrl-C stops after the file being written, and running it again carries on from there
     Init { files: Vec<PathBuf> },
 
+    /// Applies the answers from a session recorded with `review --record`
+    ///
+    /// Meant to be used with a fresh database, by setting `CARDSHARP_DB`
+    Replay { recording: PathBuf },
+
+    /// Simulates reviewing a deck for some days, showing the daily workload and retention
+    ///
+    /// Without any files, a deck of new cards is simulated
+    Simulate {
+        /// Number of days to simulate
+        #[arg(short, long, default_value = "365")]
+        days: u32,
+        /// Maximum number of new cards introduced each day
+        #[arg(short, long, default_value = "20")]
+        new_per_day: usize,
+        /// Number of cards in the simulated deck, when no files are given
+        #[arg(short, long, default_value = "1000")]
+        cards: usize,
+        /// Target retention for study
+        #[arg(short, long, default_value = "0.9")]
+        retention: f32,
+        /// How much longer the simulated learner remembers cards than predicted
+        #[arg(long, default_value = "1.0")]
+        stability_scale: f32,
+        /// Seed for the random number generator
+        #[arg(long, default_value = "0")]
+        seed: u64,
+        files: Vec<PathBuf>,
+    },
+
+    /// Shows the number of cards due, in total and for each smart deck
+    Due {
+        /// Target retention, below which cards are due. Defaults to `retention` in the config, or 0.9
+        #[arg(short, long)]
+        retention: Option<f32>,
+        files: Vec<PathBuf>,
+    },
+
+    /// Sends a notification if any cards are due, with the notifiers set in the config
+    Notify {
+        /// Target retention, below which cards are due. Defaults to `retention` in the config, or 0.9
+        #[arg(short, long)]
+        retention: Option<f32>,
+        files: Vec<PathBuf>,
+    },
+
+    /// Installs a systemd user timer which runs `notify` for the given files every day
+    InstallTimer {
+        /// Times of day to remind at, like `09:00`. Defaults to `notify_times` in the config, or 09:00
+        #[arg(long)]
+        at: Vec<String>,
+        /// Only write the unit files, without enabling the timer
+        #[arg(long)]
+        no_enable: bool,
+        files: Vec<PathBuf>,
+    },
+
     /// Lists all the cards in the given file
-    Cards { files: Vec<PathBuf> },
+    Cards {
+        /// Target retention, below which cards are due. Defaults to `retention` in the config, or 0.9
+        #[arg(short, long)]
+        retention: Option<f32>,
+        /// Only list cards matching the filter
+        #[arg(short, long)]
+        filter: Option<String>,
+        /// Only list cards tagged `#<tag>`, or with any of the tags if given several times
+        #[arg(short, long)]
+        tag: Vec<String>,
+        /// Only list cards in this deck
+        #[arg(short, long)]
+        deck: Option<String>,
+        /// Print totals for each deck instead of every card
+        #[arg(short, long)]
+        summary: bool,
+        files: Vec<PathBuf>,
+    },
+
+    /// Checks the cards in the given files for problems
+    #[command(visible_alias = "doctor")]
+    Check {
+        /// Flag words not found in a hunspell dictionary
+        #[arg(long)]
+        spelling: bool,
+        /// Comma separated list of dictionary languages to use for spell checking
+        #[arg(long, value_delimiter = ',', default_value = "en")]
+        lang: Vec<String>,
+        /// Split lines with more than one card, so every card is on a line of its own, and reset
+        /// broken saved states to the last good one
+        #[arg(long)]
+        fix: bool,
+        files: Vec<PathBuf>,
+    },
+
+    /// Lists groups of cards with similar fronts, which might be worth merging
+    Similar {
+        /// Minimum similarity between 0.0 and 1.0 for cards to be grouped together
+        #[arg(short, long, default_value = "0.6")]
+        threshold: f32,
+        files: Vec<PathBuf>,
+    },
+
+    /// Lists the cards which are forgotten again and again, which might be worth rewriting
+    ///
+    /// These are the cards forgotten at least `leech_lapses` times from the config, or 8, when
+    /// they were due
+    Leeches { files: Vec<PathBuf> },
+
+    /// Lists the hardest cards, which might be worth rewriting
+    ///
+    /// Cards are ranked by difficulty, then by how often they lapsed, then by how long they take
+    /// to answer
+    Hard {
+        /// Number of cards to show
+        #[arg(short, long, default_value = "20")]
+        top: usize,
+        files: Vec<PathBuf>,
+    },
+
+    /// Imports review state from other programs
+    Import {
+        #[command(subcommand)]
+        command: ImportCommand,
+    },
+
+    /// Lists cards whose text has not changed in a long time, but which are still forgotten
+    Stale {
+        /// Minimum number of days since the text of a card changed
+        #[arg(short, long, default_value = "365")]
+        days: u32,
+        /// Minimum number of times the card was forgotten when it was due
+        #[arg(long, default_value = "3")]
+        min_lapses: usize,
+        files: Vec<PathBuf>,
+    },
+
+    /// Writes cards in the format of another program
+    Export {
+        #[arg(short, long, value_enum)]
+        format: export::Format,
+        /// File to write to, instead of standard output
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        files: Vec<PathBuf>,
+    },
+
+    /// Fits the weights of FSRS to the review log, to be used instead of the defaults from then on
+    ///
+    /// Only cards whose every answer is in the review log are used
+    Optimize {
+        /// Only show the fitted weights, without saving them
+        #[arg(long)]
+        dry_run: bool,
+        /// Goes back to the default weights
+        #[arg(long, conflicts_with = "dry_run")]
+        reset: bool,
+    },
+
+    /// Rewrites the ids of the cards in the given files in another version of their syntax
+    ///
+    /// The ids stay the same, so cards keep their history. Set `id_version` in the config to
+    /// write the ids of new cards in the same version
+    UpgradeIds {
+        #[arg(long, value_enum, default_value = "2")]
+        to: ids::IdVersion,
+        files: Vec<PathBuf>,
+    },
+
+    /// Converts the stored state of cards after a change in the version of FSRS
+    ///
+    /// Keeps the predicted recall of each card the same. Only reports the change in intervals unless `--apply` is given
+    MigrateWeights {
+        #[arg(long, value_enum)]
+        from: fsrs::Version,
+        #[arg(long, value_enum, default_value = "fsrs6")]
+        to: fsrs::Version,
+        /// Target retention, at which to compare intervals
+        #[arg(short, long, default_value = "0.9")]
+        retention: f32,
+        /// Save the converted states, after backing up the database
+        #[arg(long)]
+        apply: bool,
+    },
+
+    /// Leaves a card out of reviews for a few hours, until later in the day
+    Snooze {
+        /// The id of the card, as written after `REVIEW--`
+        id: String,
+        /// Defaults to `snooze_hours` in the config, or 4
+        #[arg(long)]
+        hours: Option<f32>,
+    },
+
+    /// Keeps a card out of reviews until it is unsuspended
+    ///
+    /// With `suspend_in_files = true` in the config, the card is also tagged `#suspended` in
+    /// whichever of the files it is in
+    Suspend {
+        /// The id of the card, as written after `REVIEW--`
+        id: String,
+        /// The files to look for the card in
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+
+    /// Lets a suspended card be reviewed again, removing its `#suspended` tag from the files
+    Unsuspend {
+        /// The id of the card, as written after `REVIEW--`
+        id: String,
+        /// The files to look for the card in
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+
+    /// Moves a card, with its id and so its history, to the end of another file or section
+    ///
+    /// The target is a file, or a section of one given as `notes.md#heading` or `notes.md:120`
+    Move {
+        /// The id of the card, as written after `REVIEW--`
+        id: String,
+        target: PathBuf,
+        /// The files to look for the card in
+        files: Vec<PathBuf>,
+    },
+
+    /// Finds which of the given files were moved or renamed since their cards were last seen
+    ///
+    /// A moved file keeps the deck it had, if the deck has settings in the config, and a
+    /// session to resume follows it
+    Relink { files: Vec<PathBuf> },
+
+    /// Prints the file and line of a card, or opens it there with `--open`
+    ///
+    /// Cards are opened with `open_command` in the config, or `$EDITOR`
+    Locate {
+        /// The id of the card, as written after `REVIEW--`
+        id: String,
+        #[arg(long)]
+        open: bool,
+        /// The files to look for the card in
+        files: Vec<PathBuf>,
+    },
+
+    /// Adds or removes a tag on the cards matching a filter, editing their files
+    Tag {
+        #[command(subcommand)]
+        command: TagCommand,
+    },
+
+    /// Packs the given files, their media and the history of their cards into a single file
+    Bundle { out: PathBuf, files: Vec<PathBuf> },
+
+    /// Reviews the cards in a bundle, saving the answers back into it
+    Open {
+        bundle: PathBuf,
+        /// Target retention for study
+        #[arg(short, long, default_value = "0.9")]
+        retention: f32,
+    },
+
+    /// Reads and writes the JSON data extensions store for each card
+    Data {
+        #[command(subcommand)]
+        command: DataCommand,
+    },
+
+    /// Manages images and other media referenced by cards
+    Media {
+        #[command(subcommand)]
+        command: MediaCommand,
+    },
+
+    /// Shows statistics about the collection
+    ///
+    /// Without a subcommand, shows reviews per day with a heatmap, the true retention,
+    /// and the average stability and difficulty
+    Stats {
+        #[command(subcommand)]
+        command: Option<StatsCommand>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum ImportCommand {
+    /// Seeds FSRS state from SM-2 intervals and ease factors
+    ///
+    /// Reads a CSV file with lines of `<card id>,<interval in days>,<ease factor>`,
+    /// where the ease factor can be given as `2.5` or in permille as `2500`
+    Sm2 {
+        file: PathBuf,
+        /// Probability of recall SM-2 is assumed to achieve at the end of each interval
+        #[arg(long, default_value = "0.9")]
+        sm2_retention: f32,
+    },
+    /// Converts notes written for the Obsidian to Anki plugin into cards
+    ///
+    /// The cards are printed, or appended to the output file if one is given
+    Obsidian {
+        file: PathBuf,
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Converts the notes of an Anki package (`.apkg`) into cards, keeping their review state
+    ///
+    /// The cards are appended to the output file, and media are put in a `media` directory next to it.
+    /// Packages exported by newer versions of Anki have to be exported with `Support older Anki versions`
+    Anki {
+        file: PathBuf,
+        #[arg(short, long)]
+        output: PathBuf,
+        /// Probability of recall SM-2 is assumed to achieve, for cards Anki did not schedule with FSRS
+        #[arg(long, default_value = "0.9")]
+        sm2_retention: f32,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum TagCommand {
+    /// Adds `#<tag>` to the `REVIEW` line of each matching card
+    Add {
+        tag: String,
+        /// Only change cards matching the filter
+        #[arg(short, long)]
+        filter: String,
+        files: Vec<PathBuf>,
+    },
+    /// Removes `#<tag>` from each matching card
+    Remove {
+        tag: String,
+        /// Only change cards matching the filter
+        #[arg(short, long)]
+        filter: String,
+        files: Vec<PathBuf>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum DataCommand {
+    /// Prints the data of a card, if it has any
+    Get { card: String },
+    /// Replaces the data of a card with a JSON value
+    Set { card: String, data: String },
+    /// Removes the data of a card
+    Clear { card: String },
+}
+
+#[derive(Debug, Subcommand)]
+enum MediaCommand {
+    /// Lists all media referenced by the cards
+    List { files: Vec<PathBuf> },
+    /// Lists referenced media which do not exist
+    Missing { files: Vec<PathBuf> },
+    /// Lists, or deletes, files in the media directory which no card refers to
+    Gc {
+        /// Directory containing the media
+        #[arg(short, long, default_value = "media")]
+        dir: PathBuf,
+        /// Delete the unreferenced files, instead of only listing them
+        #[arg(long)]
+        delete: bool,
+        /// Every file with cards which may refer to the media
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
 }
 
-fn load_file(file: &Path) -> anyhow::Result<String> {
-    let mut file = OpenOptions::new().read(true).write(true).open(file)?;
-    let mut data = String::new();
-    file.read_to_string(&mut data)?;
+#[derive(Debug, Subcommand)]
+enum StatsCommand {
+    /// Shows card counts and review burden for each file
+    Files {
+        /// Target retention, below which cards are due. Defaults to `retention` in the config, or 0.9
+        #[arg(short, long)]
+        retention: Option<f32>,
+        files: Vec<PathBuf>,
+    },
+    /// Shows how many cards were added each week, for each deck
+    Added { files: Vec<PathBuf> },
+    /// Compares how often cards were remembered to what FSRS predicted, for each group of cards
+    ///
+    /// The stability column is how much longer memories of the group last than predicted,
+    /// so groups which are forgotten faster than predicted are below 1.
+    /// Only due cards count, and groups need at least 10 reviews of them
+    Forgetting {
+        #[arg(long, value_enum, default_value = "tag")]
+        group_by: stats::GroupBy,
+        files: Vec<PathBuf>,
+    },
+}
+
+/// Reads a file, giving ids to new cards, and returns it with the ids given. Unless `write` is
+/// set, the ids are not saved, so they change each time the file is read
+fn load_file(
+    file: &Path,
+    marker: &str,
+    version: ids::IdVersion,
+    write: bool,
+) -> anyhow::Result<(String, Vec<String>)> {
+    let mut data = std::fs::read_to_string(file)?;
 
-    let is: Vec<usize> = data.rmatch_indices("\nREVIEW:").map(|(i, _)| i).collect();
-    is.into_iter()
+    // Positions after which to insert the ids, from the end so earlier positions stay valid
+    let mut is: Vec<usize> = format::SUFFIXES
+        .map(|suffix| format!("\n{marker}{suffix}"))
+        .iter()
+        .flat_map(|marker| {
+            data.match_indices(&format!("{marker}:"))
+                .map(|(i, _)| i + marker.len())
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    is.sort_unstable_by(|a, b| b.cmp(a));
+    let ids: Vec<String> = is
+        .into_iter()
         .map(|i| {
-            let newid = CardId(rand::random());
-            data.insert_str(i + "\nREVIEW".len(), "--");
-            data.insert_str(i + "\nREVIEW--".len(), &BASE64_STANDARD.encode(newid.0));
+            let newid = version.encode(CardId(rand::random()));
+            data.insert_str(i, "--");
+            data.insert_str(i + "--".len(), &newid);
             newid
         })
-        .for_each(|i| {
-            eprintln!("Initialized new card!: {}", BASE64_STANDARD.encode(i.0));
-        });
+        .collect();
+    if write && !ids.is_empty() {
+        // Written to a new file which replaces the old one, so a crash can not leave it half written
+        tag::write_atomic(file, &data)?;
+    }
+    Ok((data, ids))
+}
+
+/// Initializes and loads the cards from all the given files
+fn load_cards(files: &[PathBuf]) -> anyhow::Result<Vec<CardBody>> {
+    read_cards(files, true)
+}
+
+/// Loads the cards from all the given files, only saving the ids of new cards if `write` is set
+fn read_cards(files: &[PathBuf], write: bool) -> anyhow::Result<Vec<CardBody>> {
+    let mut cards = Vec::new();
+    for file in files {
+        let (mut read, ids) = read_file(file, write)?;
+        if write {
+            for id in ids {
+                eprintln!("Initialized new card!: {id}");
+            }
+        }
+        cards.append(&mut read);
+    }
+    Ok(cards)
+}
+
+/// Loads the cards from a file, and the ids given to new cards
+fn read_file(file: &Path, write: bool) -> anyhow::Result<(Vec<CardBody>, Vec<String>)> {
+    // Files in different vaults may be configured differently
+    let config = config::Config::load_for(&[file.to_path_buf()])?;
+    let version = ids::configured(&config)?;
+    let (data, ids) = load_file(file, config.marker(), version, write)?;
+    let deck = deck::name(file, &data);
+    let options = format::Options {
+        marker: config.marker(),
+        stop_at_headings: matches!(
+            config.get("stop_at_headings"),
+            Some(config::Value::Bool(true))
+        ),
+        deck: &deck,
+    };
+    merged::warn(file, &data, options.marker);
+    let format = format::Registry::new(&config)?;
+    Ok((format.get(file).cards(file, &data, &options), ids))
+}
+
+/// Keeps only the cards matching all of the filters
+fn filter_cards(
+    sqlite: &mut rusqlite::Connection,
+    cards: Vec<CardBody>,
+    filters: &[filter::Filter],
+    retention: &deck::Retention,
+) -> Vec<CardBody> {
+    if filters.is_empty() {
+        return cards;
+    }
+    cards
+        .into_iter()
+        .filter(|card| {
+            let state = filter::State {
+                last_reviewed: load_card_data(sqlite, card.id),
+                retention: retention.of(card),
+                max_interval: retention.max_interval(card),
+                suspended: suspend::is_suspended(sqlite, card).unwrap_or_default(),
+            };
+            filters.iter().all(|f| f.matches(card, &state))
+        })
+        .collect()
+}
+
+/// The sides of the card with the id among `cards`, which must be there unless no files were given
+fn cards_with_id(
+    cards: &[CardBody],
+    id: CardId,
+    files: &[PathBuf],
+) -> anyhow::Result<Vec<CardBody>> {
+    let found: Vec<CardBody> = cards.iter().filter(|c| c.id == id).cloned().collect();
+    if found.is_empty() && !files.is_empty() {
+        anyhow::bail!("Card not found in the given files");
+    }
+    Ok(found)
+}
+
+/// Remembers when the text of each card last changed, and which file it is in
+fn track_content(sqlite: &mut rusqlite::Connection, cards: &[CardBody]) -> anyhow::Result<()> {
+    warn_moved(track(sqlite, cards)?);
+    Ok(())
+}
+
+/// Points out cards whose files seem to have been moved, if there are any
+fn warn_moved(moved: usize) {
+    if moved > 0 {
+        eprintln!(
+            "{}",
+            format!("{moved} cards are in files which seem to have been moved, `cardsharp relink` with the files keeps their decks").muted()
+        );
+    }
+}
+
+/// Does what [`track_content`] does, returning the number of cards in files which were moved
+fn track(sqlite: &mut rusqlite::Connection, cards: &[CardBody]) -> anyhow::Result<usize> {
+    let now = SystemTime::UNIX_EPOCH.elapsed()?.as_secs();
+    let tx = sqlite.transaction()?;
+    for card in cards {
+        // Cards with a history from before they were tracked, such as imported ones, were added by their first review
+        tx.execute(
+            "insert into content(card, hash, changed, added) values (?1, ?2, ?3,
+                 coalesce((select min(last_reviewed) / 1000 from review where card = ?1), ?3))
+                 on conflict(card) do update set hash = ?2, changed = ?3 where hash != ?2",
+            (card.id.as_int(), card.content_hash(), now),
+        )?;
+    }
+    let moved = relink::track(&tx, cards)?;
+    suspend::sync(&tx, cards)?;
+    tx.commit()?;
+    Ok(moved)
+}
+
+/// When a card was first seen
+fn card_added(sqlite: &mut rusqlite::Connection, id: CardId) -> Option<SystemTime> {
+    sqlite
+        .query_row(
+            "select added from content where card = ?1",
+            [id.as_int()],
+            |row| Ok(SystemTime::UNIX_EPOCH + Duration::from_secs(row.get(0)?)),
+        )
+        .ok()
+}
 
-    file.seek(SeekFrom::Start(0))?;
-    file.write_all(data.as_bytes())?;
-    Ok(data)
+/// When the text of a card was last seen to change
+fn content_changed(sqlite: &mut rusqlite::Connection, id: CardId) -> Option<SystemTime> {
+    sqlite
+        .query_row(
+            "select changed from content where card = ?1",
+            [id.as_int()],
+            |row| Ok(SystemTime::UNIX_EPOCH + Duration::from_secs(row.get(0)?)),
+        )
+        .ok()
+}
+
+/// Milliseconds since the Unix epoch, as the times of reviews are stored
+fn millis(time: SystemTime) -> anyhow::Result<u64> {
+    Ok(u64::try_from(
+        time.duration_since(SystemTime::UNIX_EPOCH)?.as_millis(),
+    )?)
 }
 
+fn from_millis(millis: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_millis(millis)
+}
+
+/// The state of a card after its last review, and when it was. A broken state, as left by a bad
+/// import, is ignored as if the card had never been reviewed, so that it does not make
+/// predictions NaN
 fn load_card_data(
     sqlite: &mut rusqlite::Connection,
     id: CardId,
 ) -> Option<(SystemTime, FSRSParams)> {
+    static WARNED: std::sync::Once = std::sync::Once::new();
+    let (time, fsrs) = load_state(sqlite, id)?;
+    if !fsrs.is_valid() {
+        WARNED.call_once(|| {
+            eprintln!(
+                "{}",
+                "Ignoring the broken saved state of some cards, `cardsharp check` lists them".bad()
+            );
+        });
+        return None;
+    }
+    Some((time, fsrs))
+}
+
+/// The state of a card after its last review, even if it is broken. Missing numbers, which is how
+/// NaN is stored, are read as NaN
+fn load_state(sqlite: &rusqlite::Connection, id: CardId) -> Option<(SystemTime, FSRSParams)> {
     sqlite
         .query_row(
             "select last_reviewed, stability, difficulty from review
                  where card = ?1
-                 order by last_reviewed desc
+                 order by last_reviewed desc, rowid desc
                  limit 1",
             [id.as_int()],
             |row| {
                 Ok((
-                    SystemTime::UNIX_EPOCH + Duration::from_secs(row.get(0)?),
+                    from_millis(row.get(0)?),
                     FSRSParams {
-                        stability: row.get(1)?,
-                        difficulty: row.get(2)?,
+                        stability: row.get::<_, Option<f32>>(1)?.unwrap_or(f32::NAN),
+                        difficulty: row.get::<_, Option<f32>>(2)?.unwrap_or(f32::NAN),
                     },
                 ))
             },
@@ -156,19 +815,74 @@ fn load_card_data(
         .ok()
 }
 
-fn open_db() -> anyhow::Result<rusqlite::Connection> {
-    let mut cardsharp_dir = std::env::var("CARDSHARP_DB")
+/// Deletes the broken states of a card, leaving its last good one, or none so that it is new
+fn drop_broken_states(tx: &rusqlite::Transaction, id: CardId) -> anyhow::Result<()> {
+    let mut statement =
+        tx.prepare("select rowid, stability, difficulty from review where card = ?1")?;
+    let rows = statement
+        .query_map([id.as_int()], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                FSRSParams {
+                    stability: row.get::<_, Option<f32>>(1)?.unwrap_or(f32::NAN),
+                    difficulty: row.get::<_, Option<f32>>(2)?.unwrap_or(f32::NAN),
+                },
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    for (rowid, fsrs) in rows {
+        if !fsrs.is_valid() {
+            tx.execute("delete from review where rowid = ?1", [rowid])?;
+        }
+    }
+    Ok(())
+}
+
+/// Replaces the data directory for the rest of the run, such as while reviewing a bundle
+static DATA_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// The database file given with `--db`
+static DB_FILE: OnceLock<PathBuf> = OnceLock::new();
+
+/// The directory holding the database, and other state which is not part of the cards
+fn data_dir() -> anyhow::Result<PathBuf> {
+    if let Some(dir) = DATA_DIR.get() {
+        return Ok(dir.clone());
+    }
+    if let Some(db) = DB_FILE.get() {
+        return Ok(db.parent().unwrap_or(Path::new(".")).to_path_buf());
+    }
+    let cardsharp_dir = std::env::var("CARDSHARP_DB")
         .map(PathBuf::from)
         .or_else(|_| {
-            std::env::var("HOME").map(|h| {
-                let mut p = PathBuf::from(h);
-                p.push(".local/share/cardsharp/");
-                p
-            })
+            std::env::var("XDG_DATA_HOME")
+                .map(|d| PathBuf::from(d).join("cardsharp"))
+                .or_else(|_| {
+                    std::env::var("HOME").map(|h| PathBuf::from(h).join(".local/share/cardsharp"))
+                })
         })?;
-    _ = std::fs::create_dir(&cardsharp_dir);
-    cardsharp_dir.push("db.sqlite3");
-    let sqlite = rusqlite::Connection::open(cardsharp_dir)?;
+    std::fs::create_dir_all(&cardsharp_dir)?;
+    Ok(cardsharp_dir)
+}
+
+/// The file `name` of the state kept next to the database, such as the journal
+fn state_file(name: &str) -> anyhow::Result<PathBuf> {
+    match DB_FILE.get() {
+        Some(db) => {
+            let mut file = db.as_os_str().to_owned();
+            file.push(format!(".{name}"));
+            Ok(file.into())
+        }
+        None => Ok(data_dir()?.join(name)),
+    }
+}
+
+fn open_db() -> anyhow::Result<rusqlite::Connection> {
+    let db = match DB_FILE.get() {
+        Some(db) => db.clone(),
+        None => data_dir()?.join("db.sqlite3"),
+    };
+    let mut sqlite = rusqlite::Connection::open(db)?;
     sqlite.execute(
         "create table if not exists review(
              card int,
@@ -178,102 +892,1399 @@ fn open_db() -> anyhow::Result<rusqlite::Connection> {
         )",
         (),
     )?;
+    sqlite.execute(
+        "create table if not exists content(
+             card int primary key,
+             hash int,
+             changed int,
+             added int
+        )",
+        (),
+    )?;
+    // Databases from before cards had an added time take the earliest time known of them. This
+    // comes before reviews are stored in milliseconds, which databases this old do not do yet
+    let has_added: bool = sqlite.query_row(
+        "select count(*) > 0 from pragma_table_info('content') where name = 'added'",
+        (),
+        |row| row.get(0),
+    )?;
+    if !has_added {
+        sqlite.execute("alter table content add column added int", ())?;
+        sqlite.execute(
+            "update content set added = min(changed,
+                 coalesce((select min(last_reviewed) from review where card = content.card), changed))",
+            (),
+        )?;
+    }
+    sqlite.execute(
+        "create table if not exists custom_data(
+             card int primary key,
+             data text
+        )",
+        (),
+    )?;
+    sqlite.execute(
+        "create table if not exists review_log(
+             card int,
+             time int,
+             grade int,
+             elapsed_days real,
+             recall real,
+             duration_ms int
+        )",
+        (),
+    )?;
+    // Databases from before reviews were stored in milliseconds have them in seconds
+    let version: u32 = sqlite.query_row("pragma user_version", (), |row| row.get(0))?;
+    if version < 1 {
+        sqlite.execute_batch(
+            "begin;
+             update review set last_reviewed = last_reviewed * 1000;
+             update review_log set time = time * 1000;
+             pragma user_version = 1;
+             commit;",
+        )?;
+    }
+    sqlite.execute(
+        "create table if not exists location(
+             card int primary key,
+             path text,
+             deck text
+        )",
+        (),
+    )?;
+    sqlite.execute("create table if not exists weights(weights text)", ())?;
+    sqlite.execute(
+        "create table if not exists snooze(
+             card int primary key,
+             until int
+        )",
+        (),
+    )?;
+    sqlite.execute(
+        "create table if not exists suspended(card int primary key)",
+        (),
+    )?;
+    sqlite.execute(
+        "create table if not exists balance(
+             card int primary key,
+             reviewed int,
+             fuzz real
+        )",
+        (),
+    )?;
+    optimize::load_weights(&sqlite)?;
+    // Every command sees the answers of a session which did not finish
+    let recovered = journal::recover(&mut sqlite, &state_file("journal")?)?;
+    if recovered > 0 {
+        eprintln!("Saved {recovered} answers from a session which did not finish");
+    }
+    balance::load(&sqlite)?;
     Ok(sqlite)
 }
 
-fn main() -> anyhow::Result<()> {
-    let command = Commands::parse();
-    match command {
-        Commands::Init { files } => {
-            for file in &files {
-                _ = load_file(file)?;
+/// The state of a card after a review, from its last state and the days since it
+fn next_state(last: Option<(f32, FSRSParams)>, grade: Grade) -> FSRSParams {
+    match last {
+        None => FSRSParams::from_initial_grade(grade),
+        Some((days, fsrs)) if days < 1.0 => fsrs.update_same_day(grade),
+        Some((days, fsrs)) if grade == Grade::Again => fsrs.update_failed(days),
+        Some((_, fsrs)) => fsrs.update_successful(grade),
+    }
+}
+
+/// The days until a card would be due again after each grade, if it was answered now, up to
+/// the maximum interval
+fn intervals(
+    last: Option<(SystemTime, FSRSParams)>,
+    grading: &grading::Grading,
+    (retention, max): (f32, Option<f32>),
+) -> Vec<(Grade, f32)> {
+    let now = SystemTime::now();
+    grading
+        .buttons
+        .iter()
+        .map(|b| {
+            let last = last.map(|(time, fsrs)| (days_between(time, now), fsrs));
+            let interval = next_state(last, b.grade).interval(retention);
+            (b.grade, max.map_or(interval, |max| interval.min(max)))
+        })
+        .collect()
+}
+
+/// Days between two times, or 0 if `to` is before `from`
+fn days_between(from: SystemTime, to: SystemTime) -> f32 {
+    to.duration_since(from).unwrap_or_default().as_secs_f32() / (60.0 * 60.0 * 24.0)
+}
+
+fn save_review(
+    sqlite: &rusqlite::Connection,
+    id: CardId,
+    time: SystemTime,
+    fsrs: FSRSParams,
+) -> anyhow::Result<()> {
+    sqlite.execute(
+        "insert into review(card, last_reviewed, stability, difficulty)
+                     values (?1, ?2, ?3, ?4)",
+        (id.as_int(), millis(time)?, fsrs.stability, fsrs.difficulty),
+    )?;
+    Ok(())
+}
+
+/// How a card was answered, kept in the review log
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Answer {
+    grade: Grade,
+    /// Days since the last review, and the recall probability predicted for then,
+    /// or `None` for new cards
+    last: Option<(f32, f32)>,
+    /// Time from showing the card to grading it
+    duration: Duration,
+}
+
+impl Answer {
+    /// The answer with `grade` at `answered`, given the last state of the card
+    fn new(
+        grade: Grade,
+        shown: SystemTime,
+        answered: SystemTime,
+        last: Option<(SystemTime, FSRSParams)>,
+    ) -> Self {
+        Self {
+            grade,
+            last: last.map(|(time, fsrs)| {
+                let days = days_between(time, answered);
+                (days, fsrs.recall_probability(days))
+            }),
+            duration: answered.duration_since(shown).unwrap_or_default(),
+        }
+    }
+}
+
+fn save_answer(
+    sqlite: &rusqlite::Connection,
+    id: CardId,
+    time: SystemTime,
+    answer: &Answer,
+) -> anyhow::Result<()> {
+    sqlite.execute(
+        "insert into review_log(card, time, grade, elapsed_days, recall, duration_ms)
+                     values (?1, ?2, ?3, ?4, ?5, ?6)",
+        (
+            id.as_int(),
+            millis(time)?,
+            answer.grade as u8,
+            answer.last.map(|l| l.0),
+            answer.last.map(|l| l.1),
+            u64::try_from(answer.duration.as_millis())?,
+        ),
+    )?;
+    Ok(())
+}
+
+struct ReviewOptions<'a> {
+    retention: deck::Retention,
+    /// Limits on new cards, unless overridden
+    budget: Option<budget::Budget>,
+    /// Limits on new cards and reviews each day
+    limits: limit::Limits,
+    /// How long failed cards wait before they are shown again
+    delay: queue::Delay,
+    /// Minutes between the learning steps of new cards
+    steps: Vec<f64>,
+    order: queue::Order,
+    filters: &'a [filter::Filter],
+    record: Option<&'a Path>,
+    ui: &'a ui::Ui,
+    /// Continue the last session instead of starting a new one
+    resume: bool,
+    /// Only print the queue, without reviewing
+    preview: bool,
+    /// Sections of files the review is restricted to
+    sections: &'a [section::Section],
+    /// Decks of which only a few cards are reviewed each day
+    samples: sample::Samples,
+    leeches: leech::Leeches,
+    /// Spread the due dates of answered cards over the days they could fall on
+    balance: bool,
+}
+
+/// The files, cards and queue of the session `review` would start
+fn review_queue(
+    sqlite: &mut rusqlite::Connection,
+    options: &ReviewOptions,
+    files: &[PathBuf],
+    session_path: &Path,
+) -> anyhow::Result<(Vec<PathBuf>, Vec<CardBody>, queue::Queue)> {
+    let write = !options.ui.read_only;
+    Ok(if options.resume {
+        let session = session::Session::load(session_path)?;
+        if write {
+            vault::sync(sqlite, &session.files)?;
+        }
+        let cards = read_cards(&session.files, write)?;
+        let queue = session.queue(&cards);
+        (session.files, cards, queue)
+    } else {
+        let cards = read_cards(files, write)?;
+        if write {
+            vault::sync(sqlite, files)?;
+            track_content(sqlite, &cards)?;
+        }
+        let mut cards = filter_cards(sqlite, cards, options.filters, &options.retention);
+        cards.retain(|c| section::contains(options.sections, c));
+        // Shuffled the same way all day, so the queue can be previewed
+        let today = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_secs()
+            / 86400;
+        cards.shuffle(&mut StdRng::seed_from_u64(today));
+
+        let (mut new_left, projected) = match &options.budget {
+            Some(budget) => budget.left(sqlite, options.retention.base())?,
+            None => (usize::MAX, None),
+        };
+        let (mut new_today, mut reviews_today) = options.limits.left(sqlite, today)?;
+        let mut due = vec![];
+        let mut left_out = 0;
+        let mut broken = 0;
+        // The cards of sampled decks, and how many of each were reviewed today
+        let mut sampled: BTreeMap<&str, Vec<(usize, f32)>> = BTreeMap::new();
+        let mut done: BTreeMap<&str, usize> = BTreeMap::new();
+        for (i, card) in cards.iter().enumerate() {
+            if snooze::is_snoozed(sqlite, card.id)? || suspend::is_suspended(sqlite, card)? {
+                continue;
+            }
+            let state = load_state(sqlite, card.id);
+            if state.is_some_and(|(_, fsrs)| !fsrs.is_valid()) {
+                broken += 1;
+                continue;
             }
+            if options.samples.of(&card.deck).is_some() {
+                match sample::weight(state, today) {
+                    Some(weight) => sampled.entry(&card.deck).or_default().push((i, weight)),
+                    None => *done.entry(&card.deck).or_default() += 1,
+                }
+                continue;
+            }
+            if let Some(state @ (last_reviewed, _)) = state {
+                if !fuzz::is_due(card.id, state, options.retention.target(card), 0.0) {
+                    continue;
+                }
+                // Cards already reviewed today were counted then
+                if millis(last_reviewed)? / 86_400_000 != today && !reviews_today.take() {
+                    continue;
+                }
+            } else if new_left == 0 {
+                left_out += 1;
+                continue;
+            } else if !new_today.take() {
+                continue;
+            } else {
+                new_left -= 1;
+            }
+            due.push(i);
         }
-        Commands::Review { retention, files } => {
-            let mut cards = Vec::new();
-            for file in &files {
-                let data = load_file(file)?;
-                cards.append(&mut load_card_bodies(&data));
+        let mut rng = StdRng::seed_from_u64(today);
+        for (deck, weighted) in &sampled {
+            let n = options.samples.of(deck).unwrap_or_default();
+            let n = n.saturating_sub(done.get(deck).copied().unwrap_or_default());
+            due.extend(sample::pick(weighted, n, &mut rng));
+        }
+        if left_out > 0 {
+            let reason = match projected {
+                Some(projected) => format!(
+                    "{projected:.0} reviews a day are expected over the next month, more than `max_daily_reviews`"
+                ),
+                None => "the weekly budget of `new_per_week` is used up".to_string(),
+            };
+            eprintln!(
+                "Left out {left_out} new cards since {reason}, use --over-budget to add them"
+            );
+        }
+        if new_today.over + reviews_today.over > 0 {
+            eprintln!(
+                "Left out {} new cards and {} reviews over the daily limits, use --new-limit and --review-limit to raise them",
+                new_today.over, reviews_today.over
+            );
+        }
+        if broken > 0 {
+            eprintln!(
+                "{}",
+                format!("Left out {broken} cards whose saved state is broken, `cardsharp check --fix` resets them").bad()
+            );
+        }
+        // Cards take turns or are grouped by their deck and first tag
+        let due = queue::arrange(options.order, due, |i| {
+            (&cards[i].deck, cards[i].tags.first())
+        });
+        (files.to_vec(), cards, queue::Queue::new(due))
+    })
+}
+
+/// Shows a card, or asks the question of a generated card, and returns what to do next
+fn show_card(
+    ui: &ui::Ui,
+    card: &CardBody,
+    status: &str,
+    intervals: &[(Grade, f32)],
+) -> anyhow::Result<ui::Action> {
+    if card.generated {
+        let generator = generate::Generator::parse(&card.front)
+            .map_err(|e| e.context(format!("In card at {}:{}", card.file.display(), card.line)))?;
+        let (question, answer) = generator.question(&mut rand::rng());
+        Ok(ui
+            .review_generated(&question, answer, status)?
+            .map_or(ui::Action::Quit, ui::Action::Grade))
+    } else {
+        let mut card = template::expand(card, &mut rand::rng());
+        card.front = latex::render(&card.front);
+        card.back = latex::render(&card.back);
+        ui.review_card(&card, status, intervals)
+    }
+}
+
+/// Prints the cards of a queue in the order they would be shown
+fn print_preview(cards: &[CardBody], queue: &queue::Queue) {
+    for (n, i) in queue.due().chain(queue.learning()).enumerate() {
+        let card = &cards[i];
+        println!(
+            "{}. {} {}",
+            n + 1,
+            ui::hide_cloze(card.front.trim()),
+            format!("{}:{}", card.file.display(), card.line).muted()
+        );
+    }
+}
+
+/// Ends a session early, saving its answers unless they should be abandoned
+fn quit(
+    sqlite: &mut rusqlite::Connection,
+    journal: journal::Journal,
+    cards: &[CardBody],
+    session_path: &Path,
+    options: &ReviewOptions,
+) -> anyhow::Result<()> {
+    let plural = if journal.len() == 1 { "" } else { "s" };
+    let question = format!("Save {} answer{plural} from this session?", journal.len());
+    if journal.len() > 0 && !ui::confirm(&question)? {
+        journal.abandon()?;
+        std::fs::remove_file(session_path)?;
+        eprintln!("Abandoned the session");
+        return Ok(());
+    }
+    let entries = journal.commit(sqlite)?;
+    saved(sqlite, options, cards, &entries)
+}
+
+/// Writes answers which were saved to the database to the logs of their vaults, spreads out
+/// the days the cards are due, and sets aside the cards which became leeches with them
+fn saved(
+    sqlite: &rusqlite::Connection,
+    options: &ReviewOptions,
+    cards: &[CardBody],
+    entries: &[journal::Entry],
+) -> anyhow::Result<()> {
+    vault::append(cards, entries)?;
+    if options.balance && !entries.is_empty() {
+        let mut forecast = balance::Forecast::load(sqlite, &options.retention)?;
+        let mut seen = HashSet::new();
+        // Only the last answer to a card decides when it is due
+        for entry in entries.iter().rev().filter(|e| seen.insert(e.id)) {
+            if let Some(card) = cards.iter().find(|c| c.id == entry.id) {
+                let target = options.retention.target(card);
+                forecast.place(sqlite, entry.id, (entry.time, entry.fsrs), target)?;
             }
-            cards.shuffle(&mut rand::rng());
+        }
+    }
+    leech::mark(sqlite, options.leeches, cards, entries)
+}
 
-            let mut sqlite = open_db()?;
+/// Reads the cards of a session in the file of `cards[i]` again after it was edited, returning
+/// whether that card is still there
+fn reload_file(cards: &mut [CardBody], i: usize, write: bool) -> anyhow::Result<bool> {
+    let (id, file) = (cards[i].id, cards[i].file.clone());
+    let reloaded = read_cards(std::slice::from_ref(&file), write)?;
+    for card in cards.iter_mut().filter(|c| c.file == file) {
+        if let Some(new) = reloaded.iter().find(|r| r.id == card.id) {
+            *card = new.clone();
+        }
+    }
+    Ok(reloaded.iter().any(|r| r.id == id))
+}
+
+#[allow(clippy::too_many_lines)]
+fn review(options: &ReviewOptions, files: &[PathBuf]) -> anyhow::Result<()> {
+    let mut sqlite = open_db()?;
+    let session_path = state_file("session")?;
+    let journal_path = state_file("journal")?;
+    let read_only = options.ui.read_only;
+    let mut recovered = vec![];
+    // Taken first, so that a second review does not take the answers recovered for this one
+    let mut journal = if read_only {
+        eprintln!("Reviewing read only, nothing will be saved");
+        journal::Journal::in_memory()
+    } else {
+        let journal = journal::Journal::create(&journal_path)?;
+        recovered = journal::take_recovered(&journal_path)?;
+        journal
+    };
+    let (files, mut cards, queue) = review_queue(&mut sqlite, options, files, &session_path)?;
+    let new: HashSet<usize> = queue
+        .due()
+        .filter(|&i| load_card_data(&mut sqlite, cards[i].id).is_none())
+        .collect();
+    let mut queue = queue
+        .with_delay(options.delay)
+        .with_steps(&options.steps, new.iter().copied());
+    saved(&sqlite, options, &cards, &recovered)?;
+
+    if options.preview {
+        print_preview(&cards, &queue);
+        return journal.abandon();
+    }
 
-            'main: loop {
-                let mut iters = 0;
-                for card in &cards {
-                    let res = load_card_data(&mut sqlite, card.id);
-                    let fsrs = if let Some((last_reviewed, fsrs)) = res {
-                        let days_elapsed =
-                            last_reviewed.elapsed()?.as_secs_f32() / (60.0 * 60.0 * 24.0);
-
-                        if fsrs.recall_probability(days_elapsed) >= retention {
-                            continue;
-                        }
-                        let Some(grade) = ui::review_card(card)? else {
-                            break 'main;
-                        };
-                        if let Grade::Again = grade {
-                            iters += 1;
-                            continue;
-                        }
-                        fsrs.update_successful(grade)
-                    } else {
-                        let Some(grade) = ui::review_card(card)? else {
-                            break 'main;
-                        };
-                        if let Grade::Again = grade {
-                            iters += 1;
-                            continue;
-                        }
-                        FSRSParams::from_initial_grade(grade)
-                    };
-                    iters += 1;
-                    sqlite.execute(
-                        "insert into review(card, last_reviewed, stability, difficulty)
-                                     values (?1, ?2, ?3, ?4)",
-                        (
-                            card.id.as_int(),
-                            SystemTime::UNIX_EPOCH.elapsed()?.as_secs(),
-                            fsrs.stability,
-                            fsrs.difficulty,
-                        ),
-                    )?;
+    let mut recorder = options.record.map(record::Recorder::create).transpose()?;
+
+    let mut progress = session::Progress::new();
+    // The answers which can be undone, with the progress before each
+    let mut answered_cards = vec![];
+    loop {
+        if !read_only {
+            session::Session::new(&files, &cards, &queue).save(&session_path)?;
+        }
+        let Some(i) = queue.next(SystemTime::now()) else {
+            break;
+        };
+        let card = &cards[i];
+        if let Some(next) = queue.peek() {
+            options.ui.preload(&cards[next]);
+        }
+        let shown = SystemTime::now();
+        let status = progress.status(&card.deck, &session::Left::of(&queue, &new));
+        let last = journal.card_data(&mut sqlite, card.id);
+        let target = options.retention.target(card);
+        let mut intervals = intervals(last, &options.ui.grading, target);
+        for (grade, days) in &mut intervals {
+            if let Some(minutes) = queue.step_minutes(i, *grade) {
+                #[allow(clippy::cast_possible_truncation)]
+                let step = (minutes / 60.0 / 24.0) as f32;
+                *days = step;
+            }
+        }
+        let grade = match show_card(options.ui, card, &status, &intervals)? {
+            ui::Action::Grade(grade) => grade,
+            ui::Action::Snooze => {
+                if !read_only {
+                    snooze::snooze(&sqlite, card.id, options.ui.snooze_hours)?;
                 }
-                if iters == 0 {
-                    break;
+                continue;
+            }
+            ui::Action::Skip => {
+                queue.skip(i);
+                continue;
+            }
+            ui::Action::Bury => {
+                if !read_only {
+                    snooze::bury(&sqlite, card.id)?;
                 }
+                continue;
             }
+            ui::Action::Undo => {
+                queue.put_back(i);
+                if let Some((previous, grade, before)) = answered_cards.pop() {
+                    journal.undo()?;
+                    if let Some(recorder) = &mut recorder {
+                        recorder.undo()?;
+                    }
+                    queue.unanswer(previous, grade);
+                    progress = before;
+                }
+                continue;
+            }
+            ui::Action::Edited => {
+                if reload_file(&mut cards, i, !read_only)? {
+                    queue.put_back(i);
+                } else {
+                    eprintln!("{}", "The card is gone from its file, skipping it".muted());
+                }
+                continue;
+            }
+            ui::Action::Quit if read_only => return Ok(()),
+            ui::Action::Quit => {
+                return quit(&mut sqlite, journal, &cards, &session_path, options);
+            }
+        };
+        let answered = SystemTime::now();
+        if let Some(recorder) = &mut recorder {
+            recorder.record(record::Record {
+                card: card.id,
+                shown,
+                answered,
+                grade,
+            })?;
         }
-        Commands::Cards { files } => {
-            let mut cards = Vec::new();
-            for file in &files {
-                let data = load_file(file)?;
-                cards.append(&mut load_card_bodies(&data));
+        queue.answer(i, grade, answered);
+        answered_cards.push((i, grade, progress.clone()));
+        progress.answer(grade);
+
+        let answer = Answer::new(grade, shown, answered, last);
+        journal.push(journal::Entry {
+            id: card.id,
+            time: answered,
+            fsrs: next_state(
+                last.map(|(time, fsrs)| (days_between(time, answered), fsrs)),
+                grade,
+            ),
+            answer: Some(answer),
+        })?;
+    }
+    if !read_only {
+        let entries = journal.commit(&mut sqlite)?;
+        saved(&sqlite, options, &cards, &entries)?;
+        std::fs::remove_file(session_path)?;
+    }
+    Ok(())
+}
+
+/// Answers a card from a line of `<card id> <grade>`, returning the days until it is due again
+fn batch_answer(
+    sqlite: &mut rusqlite::Connection,
+    journal: &mut journal::Journal,
+    cards: &[CardBody],
+    retention: &deck::Retention,
+    forecast: Option<&mut balance::Forecast>,
+    line: &str,
+) -> anyhow::Result<f32> {
+    let [id, grade] = line.split_whitespace().collect::<Vec<_>>()[..] else {
+        anyhow::bail!("Expected `<card id> <grade>`, not `{line}`");
+    };
+    let grade = grade.parse()?;
+    let card = CardId::parse(id)
+        .ok()
+        .and_then(|id| cards.iter().find(|c| c.id == id))
+        .ok_or_else(|| anyhow::anyhow!("No card with the id `{id}` in the given files"))?;
+    let last = journal.card_data(sqlite, card.id);
+    let answered = SystemTime::now();
+    let fsrs = next_state(
+        last.map(|(time, fsrs)| (days_between(time, answered), fsrs)),
+        grade,
+    );
+    journal.push(journal::Entry {
+        id: card.id,
+        time: answered,
+        fsrs,
+        answer: Some(Answer::new(grade, answered, answered, last)),
+    })?;
+    if let Some(forecast) = forecast {
+        forecast.place(sqlite, card.id, (answered, fsrs), retention.target(card))?;
+    }
+    Ok(fuzz::interval(
+        card.id,
+        answered,
+        fsrs,
+        retention.of(card),
+        retention.max_interval(card),
+    ))
+}
+
+/// Applies answers read from stdin to the cards in the files, saving them like a review would.
+/// Every answer before an invalid line is still saved
+fn review_batch(options: &ReviewOptions, files: &[PathBuf]) -> anyhow::Result<()> {
+    let mut sqlite = open_db()?;
+    let journal_path = state_file("journal")?;
+    let read_only = options.ui.read_only;
+    let cards = read_cards(files, !read_only)?;
+    let mut journal = if read_only {
+        journal::Journal::in_memory()
+    } else {
+        let journal = journal::Journal::create(&journal_path)?;
+        let recovered = journal::take_recovered(&journal_path)?;
+        vault::sync(&mut sqlite, files)?;
+        saved(&sqlite, options, &cards, &recovered)?;
+        journal
+    };
+    // Placed as they are answered, so the days printed are those they are due on
+    let mut forecast = (options.balance && !read_only)
+        .then(|| balance::Forecast::load(&sqlite, &options.retention))
+        .transpose()?;
+
+    let mut res = Ok(());
+    for (lineno, line) in std::io::stdin().lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match batch_answer(
+            &mut sqlite,
+            &mut journal,
+            &cards,
+            &options.retention,
+            forecast.as_mut(),
+            &line,
+        ) {
+            Ok(days) => println!(
+                "{} {days:.2}",
+                line.split_whitespace().next().unwrap_or_default()
+            ),
+            Err(e) => {
+                res = Err(e.context(format!("On line {} of the answers", lineno + 1)));
+                break;
             }
+        }
+    }
+    if !read_only {
+        let entries = journal.commit(&mut sqlite)?;
+        saved(&sqlite, options, &cards, &entries)?;
+    }
+    res
+}
 
-            let mut sqlite = open_db()?;
+fn replay(recording: &Path) -> anyhow::Result<()> {
+    let mut sqlite = open_db()?;
+    let records = record::read(recording)?;
+    for record in &records {
+        let last = load_card_data(&mut sqlite, record.card);
+        let answer = Answer::new(record.grade, record.shown, record.answered, last);
+        let fsrs = next_state(
+            last.map(|(time, fsrs)| (days_between(time, record.answered), fsrs)),
+            record.grade,
+        );
+        save_review(&sqlite, record.card, record.answered, fsrs)?;
+        save_answer(&sqlite, record.card, record.answered, &answer)?;
+    }
+    eprintln!("Replayed {} answers", records.len());
+    Ok(())
+}
 
-            for (i, card) in cards.iter().enumerate() {
-                println!("{}. {}", i + 1, ui::hide_cloze(card.front.trim()).bold());
-                let res = load_card_data(&mut sqlite, card.id);
-                if let Some((last_reviewed, fsrs)) = res {
-                    let days_elapsed =
-                        last_reviewed.elapsed()?.as_secs_f32() / (60.0 * 60.0 * 24.0);
-                    let recall = fsrs.recall_probability(days_elapsed);
-                    println!(
-                        "stability: {:.2?}\ndifficulty: {:.2?}\npredicted recall: {:.2}%",
-                        fsrs.stability,
-                        fsrs.difficulty,
-                        recall * 100.0
-                    );
+fn list_cards(
+    retention: &deck::Retention,
+    filters: &[filter::Filter],
+    files: &[PathBuf],
+) -> anyhow::Result<()> {
+    let mut sqlite = open_db()?;
+
+    let cards = load_cards(files)?;
+    vault::sync(&mut sqlite, files)?;
+    track_content(&mut sqlite, &cards)?;
+    let cards = filter_cards(&mut sqlite, cards, filters, retention);
+
+    let mut out = String::new();
+    for (i, card) in cards.iter().enumerate() {
+        _ = writeln!(
+            out,
+            "{}. {} {}",
+            i + 1,
+            ui::hide_cloze(card.front.trim()).bold(),
+            format!("({})", card.deck).muted()
+        );
+        let res = load_card_data(&mut sqlite, card.id);
+        if let Some((last_reviewed, fsrs)) = res {
+            let days_elapsed = last_reviewed.elapsed()?.as_secs_f32() / (60.0 * 60.0 * 24.0);
+            let recall = fsrs.recall_probability(days_elapsed);
+            _ = writeln!(
+                out,
+                "stability: {:.2?}\ndifficulty: {:.2?}\npredicted recall: {:.2}%",
+                fsrs.stability,
+                fsrs.difficulty,
+                recall * 100.0
+            );
+            let max = retention.max_interval(card);
+            let interval = fuzz::interval(card.id, last_reviewed, fsrs, retention.of(card), max);
+            let left = interval - days_elapsed;
+            let capped = if max.is_some_and(|max| interval >= max) {
+                ", at the maximum interval"
+            } else {
+                ""
+            };
+            if left > 0.0 {
+                let left = grading::format_interval(left);
+                _ = writeln!(out, "due in {left}{capped}");
+            } else {
+                _ = writeln!(out, "due now{capped}");
+            }
+        } else {
+            _ = writeln!(out, "{}", "Not yet reviewed".muted());
+        }
+        if suspend::is_suspended(&sqlite, card)? {
+            _ = writeln!(out, "{}", "suspended".highlight());
+        }
+
+        out.push('\n');
+    }
+    pager::page(&out)
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn simulate(options: &simulate::Options, cards: usize, files: &[PathBuf]) -> anyhow::Result<()> {
+    let mut sqlite = open_db()?;
+    let costs = cost::Costs::load(&sqlite)?;
+    let deck = if files.is_empty() {
+        vec![
+            simulate::SimCard {
+                last_reviewed: None,
+                seconds: costs.of(None),
+            };
+            cards
+        ]
+    } else {
+        let mut deck = vec![];
+        for card in load_cards(files)? {
+            let last_reviewed = load_card_data(&mut sqlite, card.id)
+                .map(|(t, fsrs)| {
+                    Ok::<_, anyhow::Error>((-t.elapsed()?.as_secs_f32() / 86400.0, fsrs))
+                })
+                .transpose()?;
+            deck.push(simulate::SimCard {
+                last_reviewed,
+                seconds: costs.of(Some(&card.deck)),
+            });
+        }
+        deck
+    };
+
+    let days = simulate::run(deck, options);
+    let header = if costs.known() {
+        "day\treviews\tnew\tpassed\tretention\tminutes"
+    } else {
+        "day\treviews\tnew\tpassed\tretention"
+    };
+    println!("{}", header.bold());
+    for (i, day) in days.iter().enumerate() {
+        let passed = if day.due == 0 {
+            100.0
+        } else {
+            day.passed as f32 / day.due as f32 * 100.0
+        };
+        print!(
+            "{}\t{}\t{}\t{passed:.1}%\t{:.1}%",
+            i + 1,
+            day.reviews,
+            day.new,
+            day.retention * 100.0
+        );
+        if costs.known() {
+            print!("\t{:.1}", day.seconds / 60.0);
+        }
+        println!();
+    }
+    let total: usize = days.iter().map(|d| d.reviews).sum();
+    println!(
+        "\ntotal reviews: {total}\naverage reviews per day: {:.1}\nfinal retention: {:.1}%",
+        total as f32 / days.len().max(1) as f32,
+        days.last().map_or(0.0, |d| d.retention * 100.0)
+    );
+    if costs.known() {
+        let minutes = days.iter().map(|d| d.seconds).sum::<f32>() / 60.0;
+        println!(
+            "average minutes per day: {:.1}",
+            minutes / days.len().max(1) as f32
+        );
+        if let Some(max) = cost::max_daily_minutes(&config::Config::load_for(files)?)? {
+            let over = days.iter().filter(|d| d.seconds / 60.0 > max).count();
+            if over > 0 {
+                println!(
+                    "{}",
+                    format!("{over} of {} days take more than {max} minutes", days.len()).bad()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn due(retention: &deck::Retention, files: &[PathBuf]) -> anyhow::Result<()> {
+    let config = config::Config::load()?;
+    let mut sqlite = open_db()?;
+    let cards = load_cards(files)?;
+
+    let is_due = filter::Filter::parse("is:due")?;
+    let due = filter_cards(&mut sqlite, cards, &[is_due], retention);
+    println!("{}: {}", "due".bold(), due.len());
+    for (name, _) in config.table("smart_decks") {
+        let smart = filter::Filter::smart_deck(&config, name)?;
+        let count = filter_cards(&mut sqlite, due.clone(), &[smart], retention).len();
+        println!("{name}: {count}");
+    }
+    Ok(())
+}
+
+fn media(command: MediaCommand) -> anyhow::Result<()> {
+    match command {
+        MediaCommand::List { files } => {
+            for (path, _) in media::all_references(&load_cards(&files)?) {
+                println!("{}", path.display());
+            }
+        }
+        MediaCommand::Missing { files } => {
+            let cards = load_cards(&files)?;
+            let missing: Vec<_> = media::all_references(&cards)
+                .into_iter()
+                .filter(|(path, _)| !path.exists())
+                .collect();
+            for (path, card) in &missing {
+                println!(
+                    "{}:{}: missing {}",
+                    card.file.display(),
+                    card.line,
+                    path.display().to_string().highlight()
+                );
+            }
+            if !missing.is_empty() {
+                anyhow::bail!("{} media files are missing", missing.len());
+            }
+        }
+        MediaCommand::Gc { dir, delete, files } => {
+            let cards = load_cards(&files)?;
+            // Without cards, every file would look unreferenced
+            if delete && cards.is_empty() {
+                anyhow::bail!(
+                    "No cards in the given files, so nothing is known to be unreferenced"
+                );
+            }
+            for path in media::unreferenced(&dir, &cards)? {
+                if delete {
+                    std::fs::remove_file(&path)?;
+                    println!("deleted {}", path.display());
                 } else {
-                    println!("{}", "Not yet reviewed".dark_grey());
+                    println!("{}", path.display());
                 }
+            }
+        }
+    }
+    Ok(())
+}
 
-                println!();
+fn check(spelling: bool, lang: &[String], fix: bool, files: &[PathBuf]) -> anyhow::Result<()> {
+    // Fixed before loading, so the cards which were split off get ids
+    let mut merged = 0;
+    for file in files {
+        let config = config::Config::load_for(std::slice::from_ref(file))?;
+        let data = std::fs::read_to_string(file)?;
+        if fix {
+            let (data, split) = merged::split(&data, config.marker());
+            if split > 0 {
+                tag::write_atomic(file, &data)?;
+                eprintln!("Split {split} lines of {}", file.display());
+            }
+        } else {
+            for line in merged::lines(&data, config.marker()) {
+                println!("{}:{line}: more than one card on the line", file.display());
+                merged += 1;
             }
         }
     }
+    if merged > 0 {
+        anyhow::bail!("found {merged} lines with more than one card, split them with --fix");
+    }
+
+    let cards = load_cards(files)?;
+    if spelling {
+        let dicts = lang
+            .iter()
+            .map(|l| spell::Dictionary::load(l))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let typos = spell::check_cards(&cards, &dicts);
+        for typo in &typos {
+            println!(
+                "{}:{}: unknown word {}",
+                typo.file.display(),
+                typo.line,
+                typo.word.as_str().highlight()
+            );
+        }
+        if !typos.is_empty() {
+            anyhow::bail!("found {} possible typos", typos.len());
+        }
+    }
+
+    let mut sqlite = open_db()?;
+    let broken: Vec<&CardBody> = cards
+        .iter()
+        .filter(|c| load_state(&sqlite, c.id).is_some_and(|(_, fsrs)| !fsrs.is_valid()))
+        .collect();
+    if fix {
+        let tx = sqlite.transaction()?;
+        for card in &broken {
+            drop_broken_states(&tx, card.id)?;
+        }
+        tx.commit()?;
+        if !broken.is_empty() {
+            eprintln!("Reset {} cards to their last good state", broken.len());
+        }
+    } else {
+        for card in &broken {
+            let (_, fsrs) = load_state(&sqlite, card.id).expect("broken state");
+            println!(
+                "{}:{}: broken saved state, with stability {} and difficulty {}",
+                card.file.display(),
+                card.line,
+                fsrs.stability,
+                fsrs.difficulty
+            );
+        }
+        if !broken.is_empty() {
+            anyhow::bail!(
+                "found {} cards with a broken state, reset them with --fix",
+                broken.len()
+            );
+        }
+    }
+    Ok(())
+}
+
+fn similar(threshold: f32, files: &[PathBuf]) -> anyhow::Result<()> {
+    let cards = load_cards(files)?;
+    for (i, group) in similar::clusters(&cards, threshold).iter().enumerate() {
+        println!("{}", format!("Group {}", i + 1).bold());
+        for &c in group {
+            let card = &cards[c];
+            println!(
+                "{}:{}: {}",
+                card.file.display(),
+                card.line,
+                ui::hide_cloze(card.front.trim())
+            );
+        }
+        println!();
+    }
     Ok(())
 }
+
+// This only dispatches to the commands, so its length grows with their number
+#[allow(clippy::too_many_lines)]
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    if let Some(db) = cli.db {
+        if db.is_dir() {
+            anyhow::bail!(
+                "`--db` takes the database file, not a directory, try `--db {}`",
+                db.join("db.sqlite3").display()
+            );
+        }
+        if let Some(parent) = db.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        _ = DB_FILE.set(db);
+    }
+    theme::Theme::from_config(&config::Config::load()?)?.set();
+    match cli.command {
+        Commands::Init { files } => init::run(&mut open_db()?, &files),
+        Commands::Review {
+            retention,
+            filter,
+            smart,
+            tag,
+            deck,
+            record,
+            grading,
+            accessible,
+            resume,
+            preview,
+            read_only,
+            over_budget,
+            new_limit,
+            review_limit,
+            order,
+            batch,
+            files,
+        } => {
+            let config = config::Config::load_for(&files)?;
+            let retention = deck::Retention::new(&config, retention)?;
+            let budget = if over_budget {
+                None
+            } else {
+                budget::Budget::from_config(&config)?
+            };
+            let mut filters = vec![];
+            if let Some(filter) = filter {
+                filters.push(filter::Filter::parse(&filter)?);
+            }
+            if let Some(smart) = smart {
+                filters.push(filter::Filter::smart_deck(&config, &smart)?);
+            }
+            if !tag.is_empty() {
+                filters.push(filter::Filter::tags(&tag));
+            }
+            if let Some(deck) = deck {
+                filters.push(filter::Filter::deck(&deck));
+            }
+            let ui = ui::Ui::new(&config, grading, accessible, read_only)?;
+            let (files, sections) = section::resolve(&files)?;
+            let options = ReviewOptions {
+                retention,
+                budget,
+                limits: limit::Limits::new(&config, new_limit, review_limit)?,
+                delay: queue::Delay::from_config(&config)?,
+                steps: queue::learning_steps(&config)?,
+                order: queue::Order::configured(order, &config)?,
+                filters: &filters,
+                record: record.as_deref(),
+                ui: &ui,
+                resume,
+                preview,
+                sections: &sections,
+                samples: sample::Samples::from_config(&config)?,
+                leeches: leech::Leeches::from_config(&config)?,
+                balance: balance::enabled(&config),
+            };
+            if batch {
+                review_batch(&options, &files)
+            } else {
+                review(&options, &files)
+            }
+        }
+        Commands::Replay { recording } => replay(&recording),
+        Commands::Simulate {
+            days,
+            new_per_day,
+            cards,
+            retention,
+            stability_scale,
+            seed,
+            files,
+        } => simulate(
+            &simulate::Options {
+                days,
+                new_per_day,
+                retention,
+                stability_scale,
+                seed,
+            },
+            cards,
+            &files,
+        ),
+        Commands::Due { retention, files } => due(
+            &deck::Retention::new(&config::Config::load_for(&files)?, retention)?,
+            &files,
+        ),
+        Commands::Notify { retention, files } => {
+            let config = config::Config::load_for(&files)?;
+            let notifiers = notify::from_config(&config)?;
+            let retention = deck::Retention::new(&config, retention)?;
+            let is_due = filter::Filter::parse("is:due")?;
+            let due = filter_cards(&mut open_db()?, load_cards(&files)?, &[is_due], &retention);
+            if due.is_empty() {
+                return Ok(());
+            }
+            let plural = if due.len() == 1 { "" } else { "s" };
+            notify::send(
+                &notifiers,
+                "cardsharp",
+                &format!("{} card{plural} due", due.len()),
+            );
+            Ok(())
+        }
+        Commands::InstallTimer {
+            mut at,
+            no_enable,
+            files,
+        } => {
+            if at.is_empty() {
+                at = match config::Config::load()?.get("notify_times") {
+                    Some(config::Value::Array(times)) => times
+                        .iter()
+                        .map(|t| {
+                            t.as_str()
+                                .map(str::to_string)
+                                .ok_or_else(|| anyhow::anyhow!("`notify_times` should be strings"))
+                        })
+                        .collect::<anyhow::Result<_>>()?,
+                    Some(_) => anyhow::bail!("`notify_times` should be an array"),
+                    None => vec!["09:00".to_string()],
+                };
+            }
+            timer::install(&at, &files, !no_enable)
+        }
+        Commands::Cards {
+            retention,
+            filter,
+            tag,
+            deck,
+            summary,
+            files,
+        } => {
+            let retention = deck::Retention::new(&config::Config::load_for(&files)?, retention)?;
+            let mut filters = filter
+                .map(|f| filter::Filter::parse(&f))
+                .transpose()?
+                .into_iter()
+                .collect::<Vec<_>>();
+            if !tag.is_empty() {
+                filters.push(filter::Filter::tags(&tag));
+            }
+            if let Some(deck) = deck {
+                filters.push(filter::Filter::deck(&deck));
+            }
+            if summary {
+                let mut sqlite = open_db()?;
+                let cards = filter_cards(&mut sqlite, load_cards(&files)?, &filters, &retention);
+                report::decks(&mut sqlite, &cards, &retention);
+                Ok(())
+            } else {
+                list_cards(&retention, &filters, &files)
+            }
+        }
+        Commands::Check {
+            spelling,
+            lang,
+            fix,
+            files,
+        } => check(spelling, &lang, fix, &files),
+        Commands::Similar { threshold, files } => similar(threshold, &files),
+        Commands::Leeches { files } => {
+            let cards = load_cards(&files)?;
+            let config = config::Config::load_for(&files)?;
+            leech::list(&open_db()?, leech::Leeches::from_config(&config)?, &cards)
+        }
+        Commands::Hard { top, files } => {
+            let cards = load_cards(&files)?;
+            report::hard(&mut open_db()?, &cards, top)
+        }
+        Commands::Import { command } => match command {
+            ImportCommand::Sm2 {
+                file,
+                sm2_retention,
+            } => import::sm2(&mut open_db()?, &file, sm2_retention),
+            ImportCommand::Obsidian { file, output } => {
+                let config = config::Config::load_for(&[output.clone().unwrap_or(file.clone())])?;
+                let cards = import::obsidian(&std::fs::read_to_string(file)?, config.marker())?;
+                match output {
+                    Some(output) => OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(output)?
+                        .write_all(cards.as_bytes())?,
+                    None => print!("{cards}"),
+                }
+                Ok(())
+            }
+            ImportCommand::Anki {
+                file,
+                output,
+                sm2_retention,
+            } => anki::import(&mut open_db()?, &file, &output, sm2_retention),
+        },
+        Commands::Stale {
+            days,
+            min_lapses,
+            files,
+        } => {
+            let cards = load_cards(&files)?;
+            let mut sqlite = open_db()?;
+            track_content(&mut sqlite, &cards)?;
+            report::stale(&mut sqlite, &cards, days, min_lapses)
+        }
+        Commands::Export {
+            format,
+            output,
+            files,
+        } => {
+            let cards = load_cards(&files)?;
+            let data = match format {
+                export::Format::Obsidian => export::obsidian(&cards),
+                export::Format::Apkg => {
+                    let output = output.ok_or_else(|| {
+                        anyhow::anyhow!("Exporting to an Anki package needs `--output`")
+                    })?;
+                    let retention = config::Config::load_for(&files)?.retention(None)?;
+                    return anki::export(&mut open_db()?, &cards, &output, retention);
+                }
+            };
+            match output {
+                Some(output) => std::fs::write(output, data)?,
+                None => print!("{data}"),
+            }
+            Ok(())
+        }
+        Commands::Optimize { dry_run, reset } => {
+            let sqlite = open_db()?;
+            if reset {
+                optimize::reset(&sqlite)
+            } else {
+                optimize::optimize(&sqlite, dry_run)
+            }
+        }
+        Commands::UpgradeIds { to, files } => {
+            for file in &files {
+                let changed = ids::upgrade(file, to)?;
+                if changed > 0 {
+                    eprintln!("Rewrote {changed} ids in {}", file.display());
+                }
+            }
+            Ok(())
+        }
+        Commands::MigrateWeights {
+            from,
+            to,
+            retention,
+            apply,
+        } => {
+            let backup = data_dir()?.join(format!(
+                "db-{}-{}.sqlite3",
+                report::format_date(SystemTime::now()),
+                clap::ValueEnum::to_possible_value(&from)
+                    .map_or_else(String::new, |v| v.get_name().to_string())
+            ));
+            migrate::run(
+                &mut open_db()?,
+                from,
+                to,
+                retention,
+                apply.then_some(backup.as_path()),
+            )
+        }
+        Commands::Tag { command } => {
+            let (tag, filter, files, add) = match command {
+                TagCommand::Add { tag, filter, files } => (tag, filter, files, true),
+                TagCommand::Remove { tag, filter, files } => (tag, filter, files, false),
+            };
+            let filters = [filter::Filter::parse(&filter)?];
+            let mut sqlite = open_db()?;
+            let retention = deck::Retention::new(&config::Config::load_for(&files)?, None)?;
+            let cards = filter_cards(&mut sqlite, load_cards(&files)?, &filters, &retention);
+            let changed = tag::rewrite(&cards, &tag, add)?;
+            eprintln!("Changed {changed} cards");
+            Ok(())
+        }
+        Commands::Snooze { id, hours } => {
+            let hours = config::Config::load()?.snooze_hours(hours)?;
+            let until = snooze::snooze(&open_db()?, CardId::parse(&id)?, hours)?;
+            let minutes = until.duration_since(SystemTime::UNIX_EPOCH)?.as_secs() / 60;
+            eprintln!(
+                "Snoozed the card until {:02}:{:02} UTC",
+                minutes / 60 % 24,
+                minutes % 60
+            );
+            Ok(())
+        }
+        Commands::Suspend { id, files } => {
+            let id = CardId::parse(&id)?;
+            let cards = load_cards(&files)?;
+            // The files count over the database, so they are written first
+            suspend::write(&cards_with_id(&cards, id, &files)?, true)?;
+            suspend::suspend(&open_db()?, id)
+        }
+        Commands::Unsuspend { id, files } => {
+            let id = CardId::parse(&id)?;
+            let cards = load_cards(&files)?;
+            let cards = cards_with_id(&cards, id, &files)?;
+            let tagged = cards
+                .iter()
+                .any(|c| c.tags.iter().any(|t| t == suspend::TAG));
+            suspend::write(&cards, false)?;
+            if !suspend::unsuspend(&open_db()?, id)? && !tagged {
+                eprintln!("The card was not suspended");
+            }
+            Ok(())
+        }
+        Commands::Move { id, target, files } => {
+            let cards = load_cards(&files)?;
+            relocate::move_card(&cards, CardId::parse(&id)?, &target)
+        }
+        Commands::Relink { files } => {
+            let mut sqlite = open_db()?;
+            let cards = load_cards(&files)?;
+            let config = config::Config::load_for(&files)?;
+            let relinked = relink::run(&mut sqlite, &config, &cards, &state_file("session")?)?;
+            let plural = if relinked == 1 { "" } else { "s" };
+            eprintln!("Relinked {relinked} file{plural}");
+            Ok(())
+        }
+        Commands::Locate { id, open, files } => {
+            let id = CardId::parse(&id)?;
+            let cards = load_cards(&files)?;
+            let card = cards
+                .iter()
+                .find(|c| c.id == id)
+                .ok_or_else(|| anyhow::anyhow!("No card with this id in the given files"))?;
+            if open {
+                open::Opener::from_config(&config::Config::load_for(&files)?)?.open(card)
+            } else {
+                println!("{}:{}", card.file.display(), card.line);
+                Ok(())
+            }
+        }
+        Commands::Bundle { out, files } => {
+            let cards = load_cards(&files)?;
+            bundle::create(&mut open_db()?, &out, &files, &cards)
+        }
+        Commands::Open { bundle, retention } => {
+            let dir = std::env::temp_dir().join(format!("cardsharp-open-{}", std::process::id()));
+            // The bundle has its own database
+            if DB_FILE.get().is_some() || DATA_DIR.set(dir.clone()).is_err() {
+                anyhow::bail!("`--db` can not be used with `open`");
+            }
+            let files = bundle::unpack(&bundle, &dir)?;
+
+            let config = config::Config::load()?;
+            let ui = ui::Ui::new(&config, None, false, false)?;
+            let options = ReviewOptions {
+                retention: deck::Retention::fixed(retention),
+                budget: None,
+                limits: limit::Limits::default(),
+                delay: queue::Delay::from_config(&config)?,
+                steps: queue::learning_steps(&config)?,
+                order: queue::Order::configured(None, &config)?,
+                filters: &[],
+                record: None,
+                ui: &ui,
+                resume: false,
+                preview: false,
+                sections: &[],
+                samples: sample::Samples::from_config(&config)?,
+                leeches: leech::Leeches::from_config(&config)?,
+                balance: balance::enabled(&config),
+            };
+            // Keep whatever was answered, even if the session fails
+            let res = review(&options, &files);
+            // The files are only ever where they were unpacked this time
+            open_db()?.execute("delete from location", ())?;
+            bundle::repack(&bundle, &dir)?;
+            std::fs::remove_dir_all(dir)?;
+            res
+        }
+        Commands::Data { command } => {
+            let mut sqlite = open_db()?;
+            match command {
+                DataCommand::Get { card } => {
+                    if let Some(data) = custom_data::get(&mut sqlite, CardId::parse(&card)?)? {
+                        println!("{data}");
+                    }
+                    Ok(())
+                }
+                DataCommand::Set { card, data } => {
+                    custom_data::set(&mut sqlite, CardId::parse(&card)?, Some(&data))
+                }
+                DataCommand::Clear { card } => {
+                    custom_data::set(&mut sqlite, CardId::parse(&card)?, None)
+                }
+            }
+        }
+        Commands::Media { command } => media(command),
+        Commands::Stats { command } => match command {
+            None => stats::overview(&mut open_db()?, &config::Config::load()?),
+            Some(StatsCommand::Files { retention, files }) => {
+                let config = config::Config::load_for(&files)?;
+                let retention = config.retention(retention)?;
+                let cards = load_cards(&files)?;
+                report::files(
+                    &mut open_db()?,
+                    &cards,
+                    retention,
+                    deck::max_interval(&config)?,
+                )
+            }
+            Some(StatsCommand::Added { files }) => {
+                let mut sqlite = open_db()?;
+                let cards = load_cards(&files)?;
+                track_content(&mut sqlite, &cards)?;
+                report::added(&mut sqlite, &cards)
+            }
+            Some(StatsCommand::Forgetting { group_by, files }) => {
+                let cards = load_cards(&files)?;
+                stats::forgetting(&mut open_db()?, &cards, group_by)
+            }
+        },
+    }
+}
@@ -0,0 +1,91 @@
+//! Renders the Markdown of cards for the terminal: headings, emphasis, inline code, lists,
+//! quotes and code blocks.
+//!
+//! Only `*` marks emphasis, since `_` is kept for cloze deletions. Each line of the text is
+//! one line of the result, so it can still be cut after a number of lines
+use std::fmt::Write;
+
+use crate::{format::markdown_heading, ui::Stylize};
+
+/// Styles the inline code, bold and italic text of a line
+fn inline(line: &str) -> String {
+    let mut res = String::new();
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        if let Some(r) = rest.strip_prefix('`')
+            && let Some(end) = r.find('`')
+        {
+            _ = write!(res, "{}", r[..end].highlight());
+            rest = &r[end + 1..];
+        } else if let Some(r) = rest.strip_prefix("**")
+            && let Some(end) = r.find("**").filter(|&e| e > 0)
+        {
+            _ = write!(res, "{}", r[..end].bold());
+            rest = &r[end + 2..];
+        } else if let Some(r) = rest.strip_prefix('*')
+            && !r.starts_with([' ', '*'])
+            && let Some(end) = r.find('*').filter(|&e| e > 0 && !r[..e].ends_with(' '))
+        {
+            _ = write!(res, "{}", r[..end].italic());
+            rest = &r[end + 1..];
+        } else {
+            res.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    res
+}
+
+/// The text with its Markdown styled
+pub fn render(text: &str) -> String {
+    let mut res = String::new();
+    let mut code_block = false;
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        let indent = &line[..line.len() - trimmed.len()];
+        if trimmed.starts_with("```") {
+            code_block = !code_block;
+            _ = write!(res, "{}", line.muted());
+        } else if code_block {
+            _ = write!(res, "{}", line.highlight());
+        } else if let Some(level) = markdown_heading(line) {
+            _ = write!(res, "{}", line[level + 1..].bold());
+        } else if let Some(item) = ["- ", "* ", "+ "]
+            .iter()
+            .find_map(|bullet| trimmed.strip_prefix(bullet))
+        {
+            _ = write!(res, "{indent}• {}", inline(item));
+        } else if let Some(quote) = trimmed.strip_prefix('>') {
+            _ = write!(
+                res,
+                "{indent}{} {}",
+                "│".muted(),
+                inline(quote.trim_start())
+            );
+        } else {
+            res.push_str(&inline(line));
+        }
+        res.push('\n');
+    }
+    if !text.ends_with('\n') {
+        res.pop();
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn plain() {
+        // Styles are not printed in tests, so only the markup is left out
+        assert_eq!(
+            render(
+                "## Rules\n**Bold**, *italic* and `co*de*`\n  - 2 * 3 * 4\n> quoted\n```\n# not a heading\n```"
+            ),
+            "Rules\nBold, italic and co*de*\n  • 2 * 3 * 4\n│ quoted\n```\n# not a heading\n```"
+        );
+        assert_eq!(render("a\n"), "a\n");
+    }
+}
@@ -0,0 +1,100 @@
+//! Images and other files referenced by cards
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use crate::CardBody;
+
+/// Finds the targets of Markdown images `![alt](target)` and Asciidoc images `image::target[]`
+fn targets(text: &str) -> Vec<&str> {
+    let mut res = vec![];
+    let mut rest = text;
+    while let Some(i) = rest.find("](") {
+        let before = &rest[..i];
+        rest = &rest[i + 2..];
+        // Skip links, which have no `!` before their opening bracket
+        if before.rfind("![").is_none_or(|j| before[j..].contains(']')) {
+            continue;
+        }
+        let end = rest.find([')', ' ', '\n']).unwrap_or(rest.len());
+        res.push(&rest[..end]);
+    }
+    for line in text.lines() {
+        let Some(i) = line.find("image:") else {
+            continue;
+        };
+        let target = line[i..]
+            .trim_start_matches("image:")
+            .trim_start_matches(':');
+        if let Some(end) = target.find('[') {
+            res.push(&target[..end]);
+        }
+    }
+    res.retain(|t| !t.is_empty() && !t.contains("://") && !t.starts_with("data:"));
+    res
+}
+
+/// The media files referenced by a card, relative to the file it is in
+pub fn references(card: &CardBody) -> Vec<PathBuf> {
+    let dir = card.file.parent().unwrap_or(Path::new(""));
+    targets(&card.front)
+        .into_iter()
+        .chain(targets(&card.back))
+        .map(|t| dir.join(t))
+        .collect()
+}
+
+/// All references of all cards, with the card they first appear in
+pub fn all_references(cards: &[CardBody]) -> Vec<(PathBuf, &CardBody)> {
+    let mut seen = BTreeSet::new();
+    let mut res = vec![];
+    for card in cards {
+        for path in references(card) {
+            if seen.insert(path.clone()) {
+                res.push((path, card));
+            }
+        }
+    }
+    res
+}
+
+/// Files in `dir` and its subdirectories which no card refers to
+pub fn unreferenced(dir: &Path, cards: &[CardBody]) -> anyhow::Result<Vec<PathBuf>> {
+    let referenced: BTreeSet<PathBuf> = all_references(cards)
+        .into_iter()
+        .filter_map(|(p, _)| std::fs::canonicalize(p).ok())
+        .collect();
+
+    let mut res = vec![];
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if !referenced.contains(&std::fs::canonicalize(&path)?) {
+                res.push(path);
+            }
+        }
+    }
+    res.sort();
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn image_targets() {
+        assert_eq!(
+            targets(
+                "![brain](media/brain.png) and [a link](notes.md)\n\
+                 ![](media/b.png \"title\") ![web](https://example.com/x.png)\n\
+                 image::media/heart.svg[Heart]"
+            ),
+            ["media/brain.png", "media/b.png", "media/heart.svg"]
+        );
+    }
+}
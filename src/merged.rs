@@ -0,0 +1,78 @@
+//! Finds lines on which several cards ended up, such as after a botched merge,
+//! and splits them back onto lines of their own
+use std::path::Path;
+
+use crate::format::SUFFIXES;
+
+/// Positions in a line where a card starts after the beginning of the line
+fn starts(line: &str, marker: &str) -> Vec<usize> {
+    line.match_indices(marker)
+        .map(|(i, _)| i)
+        .filter(|&i| {
+            let rest = &line[i + marker.len()..];
+            i > 0
+                && SUFFIXES.iter().any(|s| {
+                    rest.strip_prefix(s)
+                        .is_some_and(|r| r.starts_with("--") || r.starts_with(':'))
+                })
+        })
+        .collect()
+}
+
+/// Line numbers, counting from 1, which contain more than one card
+pub fn lines(data: &str, marker: &str) -> Vec<usize> {
+    data.lines()
+        .enumerate()
+        .filter(|(_, l)| !starts(l, marker).is_empty())
+        .map(|(i, _)| i + 1)
+        .collect()
+}
+
+/// Splits every card onto a line of its own, returning the new data and the number of lines split
+pub fn split(data: &str, marker: &str) -> (String, usize) {
+    let mut res = String::with_capacity(data.len());
+    let mut split = 0;
+    for line in data.split_inclusive('\n') {
+        let starts = starts(line, marker);
+        if !starts.is_empty() {
+            split += 1;
+        }
+        let mut last = 0;
+        for i in starts {
+            res.push_str(line[last..i].trim_end());
+            res.push('\n');
+            last = i;
+        }
+        res.push_str(&line[last..]);
+    }
+    (res, split)
+}
+
+/// Warns about lines of a file with more than one card, which are not read correctly
+pub fn warn(file: &Path, data: &str, marker: &str) {
+    for line in lines(data, marker) {
+        eprintln!(
+            "warning: {}:{line} has more than one card, split it with `cardsharp check --fix`",
+            file.display()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn split_lines() {
+        let data = "# Merged\nREVIEW--fguvqzmI: One? REVIEW: Two?\nAnswer\nREVIEW: Three? Not a REVIEW here\n";
+        assert_eq!(lines(data, "REVIEW"), [2]);
+        assert_eq!(
+            split(data, "REVIEW"),
+            (
+                "# Merged\nREVIEW--fguvqzmI: One?\nREVIEW: Two?\nAnswer\nREVIEW: Three? Not a REVIEW here\n"
+                    .to_string(),
+                1
+            )
+        );
+    }
+}
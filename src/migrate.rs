@@ -0,0 +1,103 @@
+//! Converts stored memory states between versions of FSRS, so that changing the
+//! algorithm does not silently reschedule the whole collection
+use std::{path::Path, time::SystemTime};
+
+use anyhow::bail;
+
+use crate::{fsrs::Version, ui::Stylize};
+
+struct Row {
+    rowid: i64,
+    last_reviewed: u64,
+    stability: f32,
+}
+
+/// Loads the latest state of every card
+fn latest(sqlite: &mut rusqlite::Connection) -> anyhow::Result<Vec<Row>> {
+    let mut stmt =
+        sqlite.prepare("select rowid, max(last_reviewed), stability from review group by card")?;
+    let rows = stmt
+        .query_map((), |row| {
+            Ok(Row {
+                rowid: row.get(0)?,
+                last_reviewed: row.get(1)?,
+                stability: row.get(2)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(rows)
+}
+
+/// Reports how intervals at `retention` would shift when moving from `from` to `to`.
+/// With `backup`, the database is first copied there, and the new states are saved
+#[allow(clippy::cast_precision_loss)]
+pub fn run(
+    sqlite: &mut rusqlite::Connection,
+    from: Version,
+    to: Version,
+    retention: f32,
+    backup: Option<&Path>,
+) -> anyhow::Result<()> {
+    if from == to {
+        bail!("Nothing to migrate, both versions are the same");
+    }
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_millis();
+    let rows = latest(sqlite)?;
+
+    let mut shifts = vec![];
+    let mut updated = vec![];
+    for row in &rows {
+        let elapsed = now.saturating_sub(u128::from(row.last_reviewed)) as f32 / 86_400_000.0;
+        let stability = to.convert_stability(from, row.stability, elapsed);
+        let (old, new) = (
+            from.interval(row.stability, retention),
+            to.interval(stability, retention),
+        );
+        shifts.push(new / old);
+        updated.push((row.rowid, stability));
+    }
+
+    let count = |f: &dyn Fn(f32) -> bool| shifts.iter().filter(|&&s| f(s)).count();
+    let shorter = count(&|s| s < 0.9);
+    let longer = count(&|s| s > 1.1);
+    println!(
+        "{}",
+        format!("Intervals of {} cards at {retention} retention", rows.len()).bold()
+    );
+    println!("Shorter by more than 10%:\t{shorter}");
+    println!("Within 10%:\t\t\t{}", rows.len() - shorter - longer);
+    println!("Longer by more than 10%:\t{longer}");
+    if !shifts.is_empty() {
+        let mean = shifts.iter().sum::<f32>() / shifts.len() as f32;
+        let (min, max) = shifts
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(a, b), &s| (a.min(s), b.max(s)));
+        println!("Change:\t\t\t\t{mean:.2}× on average, from {min:.2}× to {max:.2}×");
+    }
+
+    let Some(backup) = backup else {
+        println!(
+            "{}",
+            "Nothing was changed, run with --apply to migrate".muted()
+        );
+        return Ok(());
+    };
+    if backup.exists() {
+        bail!("Backup {} already exists", backup.display());
+    }
+    sqlite.execute("vacuum into ?1", [backup.to_string_lossy()])?;
+    eprintln!("Backed up the database to {}", backup.display());
+
+    let tx = sqlite.transaction()?;
+    for (rowid, stability) in updated {
+        tx.execute(
+            "update review set stability = ?1 where rowid = ?2",
+            (stability, rowid),
+        )?;
+    }
+    tx.commit()?;
+    eprintln!("Migrated {} cards", rows.len());
+    Ok(())
+}
@@ -0,0 +1,259 @@
+//! Notifications reminding to review, sent by the notifiers listed in `notifiers` in the config.
+//!
+//! Desktop notifications are the default. Webhooks post to Slack, Discord or ntfy, and email
+//! is sent over SMTP, both with `curl`. Any other way of notifying only needs to implement
+//! [`Notifier`] and be added to [`from_config`]
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use anyhow::{anyhow, bail, ensure};
+
+use crate::{
+    anki::json_string,
+    config::{Config, Value},
+};
+
+pub trait Notifier {
+    fn name(&self) -> &'static str;
+    fn send(&self, summary: &str, body: &str) -> anyhow::Result<()>;
+}
+
+/// Checks how a command finished, naming it in the error
+fn check(program: &str, status: std::io::Result<std::process::ExitStatus>) -> anyhow::Result<()> {
+    let status = status.map_err(|e| anyhow!("Could not run `{program}`: {e}"))?;
+    ensure!(status.success(), "`{program}` failed with {status}");
+    Ok(())
+}
+
+/// Runs `curl` with the given arguments, writing `input` to it
+fn curl(args: &[&str], input: &str) -> anyhow::Result<()> {
+    let mut child = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail"])
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .map_err(|e| anyhow!("Could not run `curl`: {e}"))?;
+    child
+        .stdin
+        .take()
+        .expect("piped stdin")
+        .write_all(input.as_bytes())?;
+    check("curl", child.wait())
+}
+
+pub struct Desktop;
+
+impl Notifier for Desktop {
+    fn name(&self) -> &'static str {
+        "desktop"
+    }
+
+    fn send(&self, summary: &str, body: &str) -> anyhow::Result<()> {
+        if cfg!(target_os = "macos") {
+            let script = format!("display notification {body:?} with title {summary:?}");
+            check(
+                "osascript",
+                Command::new("osascript").args(["-e", &script]).status(),
+            )
+        } else {
+            check(
+                "notify-send",
+                Command::new("notify-send")
+                    .args(["--app-name=cardsharp", summary, body])
+                    .status(),
+            )
+        }
+    }
+}
+
+/// Posts to a webhook, as a Slack or Discord message if the URL is one of theirs, or as plain
+/// text with a title like ntfy expects otherwise
+pub struct Webhook {
+    pub url: String,
+}
+
+impl Webhook {
+    /// The headers and body of the request
+    fn request(&self, summary: &str, body: &str) -> (Vec<String>, String) {
+        let json = vec!["Content-Type: application/json".to_string()];
+        if self.url.contains("hooks.slack.com") {
+            (
+                json,
+                format!(
+                    "{{\"text\":{}}}",
+                    json_string(&format!("*{summary}*: {body}"))
+                ),
+            )
+        } else if self.url.contains("discord.com/api/webhooks")
+            || self.url.contains("discordapp.com/api/webhooks")
+        {
+            (
+                json,
+                format!(
+                    "{{\"content\":{}}}",
+                    json_string(&format!("**{summary}**: {body}"))
+                ),
+            )
+        } else {
+            (vec![format!("Title: {summary}")], body.to_string())
+        }
+    }
+}
+
+impl Notifier for Webhook {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    fn send(&self, summary: &str, body: &str) -> anyhow::Result<()> {
+        let (headers, data) = self.request(summary, body);
+        let mut args = vec![];
+        for header in &headers {
+            args.extend(["--header", header]);
+        }
+        args.extend(["--data-binary", "@-", &self.url]);
+        curl(&args, &data)
+    }
+}
+
+/// Sends an email through an SMTP server like `smtps://smtp.example.com`. The login is read
+/// from `~/.netrc` by `curl`, so the password is not kept in the config
+pub struct Email {
+    pub url: String,
+    pub from: String,
+    pub to: String,
+}
+
+impl Email {
+    fn message(&self, summary: &str, body: &str) -> String {
+        format!(
+            "From: {}\r\nTo: {}\r\nSubject: {summary}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n{body}\r\n",
+            self.from, self.to
+        )
+    }
+}
+
+impl Notifier for Email {
+    fn name(&self) -> &'static str {
+        "email"
+    }
+
+    fn send(&self, summary: &str, body: &str) -> anyhow::Result<()> {
+        curl(
+            &[
+                "--netrc-optional",
+                "--ssl-reqd",
+                "--url",
+                &self.url,
+                "--mail-from",
+                &self.from,
+                "--mail-rcpt",
+                &self.to,
+                "--upload-file",
+                "-",
+            ],
+            &self.message(summary, body),
+        )
+    }
+}
+
+/// A string setting which a notifier needs
+fn required(config: &Config, key: &str) -> anyhow::Result<String> {
+    match config.get(key).map(|v| v.as_str()) {
+        Some(Some(value)) if !value.trim().is_empty() => Ok(value.to_string()),
+        Some(_) => bail!("`{key}` should be a string"),
+        None => bail!(
+            "`{key}` should be set to notify by {}",
+            key.split('.').next().unwrap_or(key)
+        ),
+    }
+}
+
+/// The notifiers listed in `notifiers` in the config, or only desktop notifications
+pub fn from_config(config: &Config) -> anyhow::Result<Vec<Box<dyn Notifier>>> {
+    let names = match config.get("notifiers") {
+        None => vec!["desktop"],
+        Some(Value::Array(names)) => names
+            .iter()
+            .map(|n| {
+                n.as_str()
+                    .ok_or_else(|| anyhow!("`notifiers` should be strings"))
+            })
+            .collect::<anyhow::Result<_>>()?,
+        Some(_) => bail!("`notifiers` should be an array like [\"desktop\", \"webhook\"]"),
+    };
+    names
+        .into_iter()
+        .map(|name| -> anyhow::Result<Box<dyn Notifier>> {
+            Ok(match name {
+                "desktop" => Box::new(Desktop),
+                "webhook" => Box::new(Webhook {
+                    url: required(config, "webhook.url")?,
+                }),
+                "email" => Box::new(Email {
+                    url: required(config, "email.url")?,
+                    from: required(config, "email.from")?,
+                    to: required(config, "email.to")?,
+                }),
+                name => bail!("Unknown notifier `{name}`, expected desktop, webhook or email"),
+            })
+        })
+        .collect()
+}
+
+/// Notifies with each notifier, or prints the message if none of them worked
+pub fn send(notifiers: &[Box<dyn Notifier>], summary: &str, body: &str) {
+    let mut sent = false;
+    for notifier in notifiers {
+        match notifier.send(summary, body) {
+            Ok(()) => sent = true,
+            Err(e) => eprintln!("Could not send a {} notification: {e}", notifier.name()),
+        }
+    }
+    if !sent {
+        println!("{summary}: {body}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn notifiers() {
+        let config = |s| Config::parse(s).unwrap();
+        let names =
+            |s| from_config(&config(s)).map(|n| n.iter().map(|n| n.name()).collect::<Vec<_>>());
+        assert_eq!(names("").unwrap(), ["desktop"]);
+        assert_eq!(
+            names(
+                "notifiers = [\"webhook\", \"desktop\"]\n[webhook]\nurl = \"https://ntfy.sh/cards\""
+            )
+            .unwrap(),
+            ["webhook", "desktop"]
+        );
+        assert!(names("notifiers = [\"email\"]").is_err());
+        assert!(names("notifiers = [\"pager\"]").is_err());
+
+        let slack = Webhook {
+            url: "https://hooks.slack.com/services/T0/B0/x".to_string(),
+        };
+        assert_eq!(
+            slack.request("cardsharp", "3 cards \"due\"").1,
+            r#"{"text":"*cardsharp*: 3 cards \"due\""}"#
+        );
+        let ntfy = Webhook {
+            url: "https://ntfy.sh/cards".to_string(),
+        };
+        assert_eq!(
+            ntfy.request("cardsharp", "3 cards due"),
+            (
+                vec!["Title: cardsharp".to_string()],
+                "3 cards due".to_string()
+            )
+        );
+    }
+}
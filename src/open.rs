@@ -0,0 +1,103 @@
+//! Opens the file of a card at its line, with the command set by `open_command` in the config.
+//!
+//! The command is a template in which `{file}` and `{line}` are replaced, like
+//! `code -g {file}:{line}`, or a link like `obsidian://open?path={file}` which is opened by the
+//! system. Without it, cards are opened in `$VISUAL` or `$EDITOR` like `vi +{line} {file}`
+use std::{fmt::Write, path::Path, process::Command};
+
+use anyhow::{bail, ensure};
+
+use crate::{CardBody, config::Config};
+
+pub struct Opener {
+    template: String,
+}
+
+impl Opener {
+    pub fn from_config(config: &Config) -> anyhow::Result<Self> {
+        let template = if let Some(value) = config.get("open_command") {
+            match value.as_str() {
+                Some(template) if !template.trim().is_empty() => template.to_string(),
+                _ => bail!("`open_command` should be a command like `code -g {{file}}:{{line}}`"),
+            }
+        } else {
+            let editor = ["VISUAL", "EDITOR"]
+                .iter()
+                .find_map(|v| std::env::var(v).ok().filter(|e| !e.trim().is_empty()))
+                .unwrap_or_else(|| "vi".to_string());
+            format!("{editor} +{{line}} {{file}}")
+        };
+        Ok(Self { template })
+    }
+
+    /// The program and arguments opening `file` at `line`
+    fn command(&self, file: &Path, line: usize) -> Vec<String> {
+        let file = std::fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf());
+        let file = file.to_string_lossy();
+        if self.template.contains("://") {
+            let uri = self
+                .template
+                .replace("{file}", &percent_encode(&file))
+                .replace("{line}", &line.to_string());
+            let opener = if cfg!(target_os = "macos") {
+                "open"
+            } else {
+                "xdg-open"
+            };
+            return vec![opener.to_string(), uri];
+        }
+        // Replaced after splitting, so paths with spaces stay one argument
+        self.template
+            .split_whitespace()
+            .map(|arg| {
+                arg.replace("{file}", &file)
+                    .replace("{line}", &line.to_string())
+            })
+            .collect()
+    }
+
+    /// Opens the file of a card at its line, waiting until the command finishes
+    pub fn open(&self, card: &CardBody) -> anyhow::Result<()> {
+        let command = self.command(&card.file, card.line);
+        let status = Command::new(&command[0])
+            .args(&command[1..])
+            .status()
+            .map_err(|e| anyhow::anyhow!("Could not run `{}`: {e}", command[0]))?;
+        ensure!(status.success(), "`{}` failed with {status}", command[0]);
+        Ok(())
+    }
+}
+
+/// Escapes the characters of a path which are not allowed in a link
+fn percent_encode(s: &str) -> String {
+    let mut res = String::new();
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-_.~/".contains(&b) {
+            res.push(char::from(b));
+        } else {
+            _ = write!(res, "%{b:02X}");
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn templates() {
+        let opener = |template: &str| Opener {
+            template: template.to_string(),
+        };
+        let file = Path::new("/notes/my bones.md");
+        assert_eq!(
+            opener("code -g {file}:{line}").command(file, 12),
+            ["code", "-g", "/notes/my bones.md:12"]
+        );
+        assert_eq!(
+            opener("obsidian://open?path={file}").command(file, 12)[1],
+            "obsidian://open?path=/notes/my%20bones.md"
+        );
+    }
+}
@@ -0,0 +1,226 @@
+//! Fits the weights of FSRS to the review log, by gradient descent on the log loss of its
+//! predictions of whether each card would be remembered.
+//!
+//! The weights are kept in the `weights` table of the database, and used whenever it is opened
+use anyhow::bail;
+
+use crate::{
+    fsrs::{self, DEFAULT_WEIGHTS, FSRSParams, Grade},
+    next_state,
+};
+
+/// The range each weight is kept in, as in the reference optimizer of FSRS
+const BOUNDS: [(f32, f32); 21] = [
+    (0.001, 100.0),
+    (0.001, 100.0),
+    (0.001, 100.0),
+    (0.001, 100.0),
+    (1.0, 10.0),
+    (0.001, 4.0),
+    (0.001, 4.0),
+    (0.001, 0.75),
+    (0.0, 4.5),
+    (0.0, 0.8),
+    (0.001, 3.5),
+    (0.001, 5.0),
+    (0.001, 0.25),
+    (0.001, 0.9),
+    (0.0, 4.0),
+    (0.0, 1.0),
+    (1.0, 6.0),
+    (0.0, 2.0),
+    (0.0, 2.0),
+    (0.0, 0.8),
+    (0.1, 0.8),
+];
+
+/// Fewer predictions than this are too few to fit 21 weights to
+const MIN_REVIEWS: usize = 100;
+const ITERATIONS: usize = 200;
+const LEARNING_RATE: f32 = 0.04;
+
+/// Sets the weights saved in the database, if any
+pub fn load_weights(sqlite: &rusqlite::Connection) -> anyhow::Result<()> {
+    let saved: Option<String> = sqlite
+        .query_row("select weights from weights", (), |row| row.get(0))
+        .ok();
+    if let Some(saved) = saved {
+        let weights: Vec<f32> = saved
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<_, _>>()?;
+        let Ok(weights) = weights.try_into() else {
+            bail!("Expected 21 saved weights, found `{saved}`");
+        };
+        fsrs::set_weights(weights);
+    }
+    Ok(())
+}
+
+/// Saves weights to be used from now on, or goes back to the default weights
+fn save_weights(sqlite: &rusqlite::Connection, weights: Option<[f32; 21]>) -> anyhow::Result<()> {
+    sqlite.execute("delete from weights", ())?;
+    if let Some(weights) = weights {
+        let weights: Vec<String> = weights.iter().map(f32::to_string).collect();
+        sqlite.execute("insert into weights values (?1)", [weights.join(" ")])?;
+    }
+    Ok(())
+}
+
+/// An answer, with the days since the last review of the card, or `None` for new cards
+type Review = (Grade, Option<f32>);
+
+/// The logged answers to each card which was new when the log started, in order
+fn histories(sqlite: &rusqlite::Connection) -> anyhow::Result<Vec<Vec<Review>>> {
+    let mut statement = sqlite
+        .prepare("select card, grade, elapsed_days from review_log order by card, time, rowid")?;
+    let rows = statement
+        .query_map((), |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, u8>(1)?,
+                row.get::<_, Option<f32>>(2)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut res: Vec<Vec<Review>> = vec![];
+    let mut last_card = None;
+    for (card, grade, days) in rows {
+        if last_card != Some(card) {
+            res.push(vec![]);
+            last_card = Some(card);
+        }
+        if let Some(history) = res.last_mut() {
+            history.push((Grade::try_from(grade)?, days));
+        }
+    }
+    // Without its earlier answers, the state of a card at its first logged answer is unknown
+    res.retain(|h| h.first().is_some_and(|(_, days)| days.is_none()));
+    Ok(res)
+}
+
+/// The mean log loss of predicting each review which was at least a day after the last
+/// with `weights`, and the number of such reviews
+fn loss(histories: &[Vec<Review>], weights: [f32; 21]) -> (f64, usize) {
+    fsrs::set_weights(weights);
+    let mut total = 0.0;
+    let mut count = 0;
+    for history in histories {
+        let mut state: Option<FSRSParams> = None;
+        for &(grade, days) in history {
+            let days = days.unwrap_or_default();
+            if let Some(fsrs) = state
+                && days >= 1.0
+            {
+                let recall = f64::from(fsrs.recall_probability(days)).clamp(1e-4, 1.0 - 1e-4);
+                total -= if grade == Grade::Again {
+                    (1.0 - recall).ln()
+                } else {
+                    recall.ln()
+                };
+                count += 1;
+            }
+            state = Some(next_state(state.map(|s| (days, s)), grade));
+        }
+    }
+    #[allow(clippy::cast_precision_loss)]
+    let mean = total / count.max(1) as f64;
+    (mean, count)
+}
+
+/// Fits the weights with Adam, starting from `start`
+fn fit(histories: &[Vec<Review>], start: [f32; 21]) -> [f32; 21] {
+    let mut weights = start;
+    let mut m = [0.0_f32; 21];
+    let mut v = [0.0_f32; 21];
+    let (beta1, beta2) = (0.9_f32, 0.999_f32);
+    for step in 1..=ITERATIONS {
+        // The gradient is estimated by central differences
+        let mut gradient = [0.0_f32; 21];
+        for i in 0..21 {
+            let h = 1e-3 * (BOUNDS[i].1 - BOUNDS[i].0).min(1.0);
+            let (mut up, mut down) = (weights, weights);
+            up[i] += h;
+            down[i] -= h;
+            #[allow(clippy::cast_possible_truncation)]
+            {
+                gradient[i] =
+                    ((loss(histories, up).0 - loss(histories, down).0) / f64::from(2.0 * h)) as f32;
+            }
+        }
+        #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+        let step = step as i32;
+        for i in 0..21 {
+            m[i] = beta1 * m[i] + (1.0 - beta1) * gradient[i];
+            v[i] = beta2 * v[i] + (1.0 - beta2) * gradient[i] * gradient[i];
+            let m_hat = m[i] / (1.0 - beta1.powi(step));
+            let v_hat = v[i] / (1.0 - beta2.powi(step));
+            weights[i] = (weights[i] - LEARNING_RATE * m_hat / (v_hat.sqrt() + 1e-8))
+                .clamp(BOUNDS[i].0, BOUNDS[i].1);
+        }
+    }
+    weights
+}
+
+/// Fits the weights to the review log, and saves them unless `dry_run` is set
+pub fn optimize(sqlite: &rusqlite::Connection, dry_run: bool) -> anyhow::Result<()> {
+    let histories = histories(sqlite)?;
+    let current = fsrs::weights();
+    let (before, count) = loss(&histories, current);
+    if count < MIN_REVIEWS {
+        bail!(
+            "Only {count} reviews of cards whose whole history is logged, at least {MIN_REVIEWS} are needed"
+        );
+    }
+    eprintln!("Fitting the weights to {count} reviews...");
+    let weights = fit(&histories, current);
+    let (after, _) = loss(&histories, weights);
+    fsrs::set_weights(current);
+
+    println!("log loss: {before:.4} -> {after:.4}");
+    let formatted: Vec<String> = weights.iter().map(|w| format!("{w:.4}")).collect();
+    println!("weights: {}", formatted.join(", "));
+    if after.is_nan() || after >= before {
+        println!("The current weights already fit best, keeping them");
+    } else if !dry_run {
+        save_weights(sqlite, Some(weights))?;
+        println!("Saved the weights, `cardsharp optimize --reset` goes back to the defaults");
+    }
+    Ok(())
+}
+
+/// Goes back to the default weights
+pub fn reset(sqlite: &rusqlite::Connection) -> anyhow::Result<()> {
+    save_weights(sqlite, None)?;
+    fsrs::set_weights(DEFAULT_WEIGHTS);
+    println!("Using the default weights again");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn fits_history() {
+        // A learner who forgets every card after a week, despite grading it good at first
+        let history = vec![
+            (Grade::Good, None),
+            (Grade::Good, Some(3.0)),
+            (Grade::Again, Some(7.0)),
+            (Grade::Good, Some(1.0)),
+        ];
+        let histories = vec![history; 20];
+        let (before, count) = loss(&histories, DEFAULT_WEIGHTS);
+        assert_eq!(count, 60);
+        let weights = fit(&histories, DEFAULT_WEIGHTS);
+        assert!(loss(&histories, weights).0 < before);
+        assert!(
+            weights
+                .iter()
+                .zip(BOUNDS)
+                .all(|(w, (low, high))| (low..=high).contains(w))
+        );
+    }
+}
@@ -0,0 +1,48 @@
+//! Shows output longer than the screen through `$PAGER`, or `less` if it is not set
+use std::{
+    io::{ErrorKind, IsTerminal, Write},
+    process::{Command, Stdio},
+};
+
+use crate::ui;
+
+/// Writes to stdout, stopping quietly if it was closed, such as by `head`
+fn print(text: &str) -> anyhow::Result<()> {
+    match std::io::stdout().write_all(text.as_bytes()) {
+        Err(e) if e.kind() == ErrorKind::BrokenPipe => Ok(()),
+        res => Ok(res?),
+    }
+}
+
+/// Prints text, through the pager if it does not fit on the screen
+pub fn page(text: &str) -> anyhow::Result<()> {
+    let fits =
+        crossterm::terminal::size().is_ok_and(|(_, rows)| text.lines().count() < usize::from(rows));
+    if fits || !std::io::stdout().is_terminal() || ui::is_dumb_terminal() {
+        return print(text);
+    }
+
+    let pager = std::env::var("PAGER")
+        .ok()
+        .filter(|p| !p.trim().is_empty())
+        .unwrap_or_else(|| "less".to_string());
+    let mut args = pager.split_whitespace();
+    let mut command = Command::new(args.next().unwrap_or("less"));
+    command.args(args).stdin(Stdio::piped());
+    // Like git, have less keep the styles and quit right away if the text fits after all
+    if std::env::var_os("LESS").is_none() {
+        command.env("LESS", "FRX");
+    }
+    let Ok(mut child) = command.spawn() else {
+        return print(text);
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // The pager may be quit before reading everything
+        match stdin.write_all(text.as_bytes()) {
+            Err(e) if e.kind() == ErrorKind::BrokenPipe => {}
+            res => res?,
+        }
+    }
+    child.wait()?;
+    Ok(())
+}
@@ -0,0 +1,223 @@
+//! Just enough of the PNG format to show images as sixels: images which are not interlaced,
+//! in any color type with 8 or 16 bits per sample, or palettes of any depth
+use anyhow::{anyhow, bail};
+
+use crate::zip::inflate;
+
+pub const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Decoded pixels, as red, green, blue and alpha bytes row by row
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub rgba: Vec<u8>,
+}
+
+impl Image {
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 4] {
+        let i = (y * self.width + x) * 4;
+        [
+            self.rgba[i],
+            self.rgba[i + 1],
+            self.rgba[i + 2],
+            self.rgba[i + 3],
+        ]
+    }
+}
+
+fn be_u32(data: &[u8], i: usize) -> anyhow::Result<usize> {
+    let bytes = data
+        .get(i..i + 4)
+        .ok_or_else(|| anyhow!("Truncated PNG file"))?;
+    Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let (pa, pb, pc) = (
+        (i16::from(b) - i16::from(c)).abs(),
+        (i16::from(a) - i16::from(c)).abs(),
+        (i16::from(a) + i16::from(b) - 2 * i16::from(c)).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Undoes the filter before each row, which predicts bytes from those to the left and above
+fn unfilter(data: &[u8], stride: usize, height: usize, bpp: usize) -> anyhow::Result<Vec<u8>> {
+    let mut res = vec![0; stride * height];
+    for y in 0..height {
+        let row = data
+            .get(y * (stride + 1)..(y + 1) * (stride + 1))
+            .ok_or_else(|| anyhow!("Truncated PNG image data"))?;
+        for x in 0..stride {
+            let left = if x >= bpp {
+                res[y * stride + x - bpp]
+            } else {
+                0
+            };
+            let up = if y > 0 { res[(y - 1) * stride + x] } else { 0 };
+            let up_left = if x >= bpp && y > 0 {
+                res[(y - 1) * stride + x - bpp]
+            } else {
+                0
+            };
+            let predicted = match row[0] {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => u8::try_from(u16::midpoint(u16::from(left), u16::from(up)))?,
+                4 => paeth(left, up, up_left),
+                _ => bail!("Invalid PNG filter"),
+            };
+            res[y * stride + x] = row[x + 1].wrapping_add(predicted);
+        }
+    }
+    Ok(res)
+}
+
+pub fn decode(data: &[u8]) -> anyhow::Result<Image> {
+    if !data.starts_with(SIGNATURE) {
+        bail!("Not a PNG file");
+    }
+    let (mut header, mut palette, mut transparency, mut compressed) =
+        (None, vec![], vec![], vec![]);
+    let mut i = SIGNATURE.len();
+    while i + 8 <= data.len() {
+        let len = be_u32(data, i)?;
+        let body = data
+            .get(i + 8..i + 8 + len)
+            .ok_or_else(|| anyhow!("Truncated PNG file"))?;
+        match &data[i + 4..i + 8] {
+            b"IHDR" => header = Some(body),
+            b"PLTE" => palette = body.to_vec(),
+            b"tRNS" => transparency = body.to_vec(),
+            b"IDAT" => compressed.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {}
+        }
+        // After the length, type, body and checksum
+        i += 12 + len;
+    }
+    let header = header
+        .filter(|h| h.len() >= 13)
+        .ok_or_else(|| anyhow!("PNG file without a header"))?;
+    let (width, height) = (be_u32(header, 0)?, be_u32(header, 4)?);
+    let (depth, color, interlaced) = (usize::from(header[8]), header[9], header[12] != 0);
+    let channels = match color {
+        0 | 3 => 1,
+        2 => 3,
+        4 => 2,
+        6 => 4,
+        _ => bail!("Invalid PNG color type"),
+    };
+    if interlaced || !(depth >= 8 || color == 3) {
+        bail!("Unsupported PNG image");
+    }
+
+    // After the two bytes of the zlib header
+    let raw = inflate(compressed.get(2..).unwrap_or_default())?;
+    let stride = (width * channels * depth).div_ceil(8);
+    let bpp = (channels * depth / 8).max(1);
+    let pixels = unfilter(&raw, stride, height, bpp)?;
+
+    let mut rgba = Vec::with_capacity(width * height * 4);
+    for y in 0..height {
+        let row = &pixels[y * stride..(y + 1) * stride];
+        for x in 0..width {
+            // The most significant byte of each sample
+            let sample = |c: usize| row[(x * channels + c) * depth / 8];
+            rgba.extend_from_slice(&match color {
+                0 => [sample(0), sample(0), sample(0), 255],
+                2 => [sample(0), sample(1), sample(2), 255],
+                4 => [sample(0), sample(0), sample(0), sample(1)],
+                6 => [sample(0), sample(1), sample(2), sample(3)],
+                _ => {
+                    let bit = x * depth;
+                    let index =
+                        usize::from(row[bit / 8] >> (8 - depth - bit % 8) & u8::MAX >> (8 - depth));
+                    let rgb = palette
+                        .get(index * 3..index * 3 + 3)
+                        .ok_or_else(|| anyhow!("PNG palette index out of range"))?;
+                    let alpha = transparency.get(index).copied().unwrap_or(255);
+                    [rgb[0], rgb[1], rgb[2], alpha]
+                }
+            });
+        }
+    }
+    Ok(Image {
+        width,
+        height,
+        rgba,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A PNG of the given header and rows, which are stored without compression
+    pub fn encode(
+        width: u32,
+        height: u32,
+        depth: u8,
+        color: u8,
+        rows: &[u8],
+        palette: &[u8],
+    ) -> Vec<u8> {
+        let chunk = |kind: &[u8], body: &[u8]| {
+            let mut res = u32::try_from(body.len()).unwrap().to_be_bytes().to_vec();
+            res.extend_from_slice(kind);
+            res.extend_from_slice(body);
+            // Checksums are not checked
+            res.extend_from_slice(&[0; 4]);
+            res
+        };
+        let mut header = width.to_be_bytes().to_vec();
+        header.extend_from_slice(&height.to_be_bytes());
+        header.extend_from_slice(&[depth, color, 0, 0, 0]);
+        let len = u16::try_from(rows.len()).unwrap();
+        let mut zlib = vec![0x78, 0x01, 0x01];
+        zlib.extend_from_slice(&len.to_le_bytes());
+        zlib.extend_from_slice(&(!len).to_le_bytes());
+        zlib.extend_from_slice(rows);
+
+        let mut res = SIGNATURE.to_vec();
+        res.extend(chunk(b"IHDR", &header));
+        if !palette.is_empty() {
+            res.extend(chunk(b"PLTE", palette));
+        }
+        res.extend(chunk(b"IDAT", &zlib));
+        res.extend(chunk(b"IEND", &[]));
+        res
+    }
+
+    #[test]
+    pub fn pixels() {
+        // Two rows of red then green, the second predicted from the first with the up filter
+        let png = encode(
+            2,
+            2,
+            8,
+            2,
+            &[0, 255, 0, 0, 0, 255, 0, 2, 0, 0, 0, 0, 0, 0],
+            &[],
+        );
+        let image = decode(&png).unwrap();
+        assert_eq!((image.width, image.height), (2, 2));
+        assert_eq!(image.pixel(0, 1), [255, 0, 0, 255]);
+        assert_eq!(image.pixel(1, 1), [0, 255, 0, 255]);
+
+        let palette = [0, 0, 0, 255, 255, 255];
+        let png = encode(3, 1, 1, 3, &[0, 0b0100_0000], &palette);
+        let image = decode(&png).unwrap();
+        assert_eq!(image.pixel(1, 0), [255, 255, 255, 255]);
+        assert_eq!(image.pixel(2, 0), [0, 0, 0, 255]);
+
+        assert!(decode(b"GIF89a").is_err());
+    }
+}
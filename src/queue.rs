@@ -0,0 +1,527 @@
+//! The order in which cards are shown during a review session.
+//!
+//! Cards which are due are each shown once. Cards answered with [`Grade::Again`]
+//! move to the learning queue until they are remembered.
+//!
+//! New cards go through the learning steps of `learning_steps` in the config first, 1 and 10
+//! minutes unless set. Each answer waits for a step before the card is shown again: again goes
+//! back to the first step, hard repeats the step, and good moves on to the next one, until the
+//! last step is done and the card is left to FSRS. Easy is done with the steps at once.
+//!
+//! The learning queue is shown after the due cards, unless `again_after_cards` or
+//! `again_after_minutes` is set in the config. Then a failed card comes back as soon as that
+//! many other cards were shown and minutes passed, or earlier if nothing else is left.
+//!
+//! Due cards are shuffled, and can be [`arrange`]d to take turns between topics or to be
+//! grouped by topic
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, bail};
+
+use crate::{
+    config::{Config, Value},
+    fsrs::Grade,
+};
+
+/// How long a failed card waits before it is shown again
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Delay {
+    pub cards: Option<usize>,
+    pub minutes: Option<f64>,
+}
+
+impl Delay {
+    pub fn from_config(config: &Config) -> anyhow::Result<Self> {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let cards = match config.get("again_after_cards") {
+            None => None,
+            Some(Value::Number(n)) if *n >= 0.0 && n.fract() == 0.0 => Some(*n as usize),
+            Some(_) => bail!("`again_after_cards` should be a whole number"),
+        };
+        let minutes = match config.get("again_after_minutes") {
+            None => None,
+            Some(Value::Number(n)) if *n >= 0.0 => Some(*n),
+            Some(_) => bail!("`again_after_minutes` should be a number of minutes"),
+        };
+        Ok(Self { cards, minutes })
+    }
+}
+
+/// The minutes new cards wait between their learning steps. An empty list turns them off
+pub fn learning_steps(config: &Config) -> anyhow::Result<Vec<f64>> {
+    let error = "`learning_steps` should be a list of minutes, like [1, 10]";
+    match config.get("learning_steps") {
+        None => Ok(vec![1.0, 10.0]),
+        Some(Value::Array(steps)) => steps
+            .iter()
+            .map(|step| match step {
+                Value::Number(n) if *n > 0.0 => Ok(*n),
+                _ => bail!(error),
+            })
+            .collect(),
+        Some(_) => bail!(error),
+    }
+}
+
+/// How due cards are ordered in a session
+#[derive(Debug, Default, Copy, Clone, PartialEq, clap::ValueEnum)]
+pub enum Order {
+    /// In random order
+    #[default]
+    Shuffled,
+    /// Taking turns between topics, so that cards on the same topic rarely follow each other
+    Mixed,
+    /// Grouped by topic, one topic after another
+    Blocked,
+}
+
+impl Order {
+    /// The order given on the command line, or `order` in the config
+    pub fn configured(order: Option<Self>, config: &Config) -> anyhow::Result<Self> {
+        match (order, config.get("order")) {
+            (Some(order), _) => Ok(order),
+            (None, Some(value)) => {
+                let name = value
+                    .as_str()
+                    .ok_or_else(|| anyhow!("`order` should be a string"))?;
+                clap::ValueEnum::from_str(name, true)
+                    .map_err(|e| anyhow!("Invalid review order: {e}"))
+            }
+            (None, None) => Ok(Self::Shuffled),
+        }
+    }
+}
+
+/// Orders shuffled cards by their topic. Topics keep the order in which they first appear, and
+/// mixed practice takes the next card from the topic with the most left, other than the last one
+pub fn arrange<T: PartialEq>(
+    order: Order,
+    cards: Vec<usize>,
+    topic: impl Fn(usize) -> T,
+) -> Vec<usize> {
+    let mut topics: Vec<(T, VecDeque<usize>)> = vec![];
+    for card in &cards {
+        let t = topic(*card);
+        match topics.iter_mut().find(|(other, _)| *other == t) {
+            Some((_, group)) => group.push_back(*card),
+            None => topics.push((t, VecDeque::from([*card]))),
+        }
+    }
+    match order {
+        Order::Shuffled => cards,
+        Order::Blocked => topics.into_iter().flat_map(|(_, group)| group).collect(),
+        Order::Mixed => {
+            let mut res = Vec::with_capacity(cards.len());
+            let mut last = None;
+            while res.len() < cards.len() {
+                let next = (0..topics.len())
+                    .filter(|&t| !topics[t].1.is_empty())
+                    // The first of the largest, since `max_by_key` returns the last
+                    .rev()
+                    .max_by_key(|&t| (Some(t) != last, topics[t].1.len()))
+                    .expect("cards left");
+                res.extend(topics[next].1.pop_front());
+                last = Some(next);
+            }
+            res
+        }
+    }
+}
+
+/// A failed card, and when it may be shown again
+#[derive(Debug, Copy, Clone)]
+struct Waiting {
+    card: usize,
+    /// The number of cards which must have been shown
+    after: usize,
+    at: SystemTime,
+}
+
+/// A queue of indices into the list of cards being reviewed
+#[derive(Debug, Default)]
+pub struct Queue {
+    due: VecDeque<usize>,
+    learning: VecDeque<Waiting>,
+    delay: Delay,
+    /// Minutes between the learning steps of new cards
+    steps: Vec<f64>,
+    /// The step each new card is at, until it is done with the steps
+    stepping: HashMap<usize, usize>,
+    /// The step cards were at before each answer given during the steps, for undoing them
+    previous: Vec<(usize, usize)>,
+    /// The number of cards taken from the queue so far
+    shown: usize,
+    /// The last card taken, if it came from the learning queue
+    taken: Option<Waiting>,
+}
+
+impl Queue {
+    pub fn new(due: impl IntoIterator<Item = usize>) -> Self {
+        Self {
+            due: due.into_iter().collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Restores a queue with cards which are already being learnt
+    pub fn with_learning(
+        due: impl IntoIterator<Item = usize>,
+        learning: impl IntoIterator<Item = usize>,
+    ) -> Self {
+        Self {
+            due: due.into_iter().collect(),
+            learning: learning
+                .into_iter()
+                .map(|card| Waiting {
+                    card,
+                    after: 0,
+                    at: SystemTime::UNIX_EPOCH,
+                })
+                .collect(),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_delay(self, delay: Delay) -> Self {
+        Self { delay, ..self }
+    }
+
+    /// Takes the `new` cards through learning steps of the given minutes
+    pub fn with_steps(self, steps: &[f64], new: impl IntoIterator<Item = usize>) -> Self {
+        let stepping = if steps.is_empty() {
+            HashMap::new()
+        } else {
+            new.into_iter().map(|card| (card, 0)).collect()
+        };
+        Self {
+            steps: steps.to_vec(),
+            stepping,
+            ..self
+        }
+    }
+
+    pub fn due(&self) -> impl Iterator<Item = usize> + '_ {
+        self.due.iter().copied()
+    }
+
+    pub fn learning(&self) -> impl Iterator<Item = usize> + '_ {
+        self.learning.iter().map(|w| w.card)
+    }
+
+    /// The number of cards taken from the queue so far
+    pub fn shown(&self) -> usize {
+        self.shown
+    }
+
+    /// Takes the next card to show at `now`, or `None` once the session is over
+    pub fn next(&mut self, now: SystemTime) -> Option<usize> {
+        let delayed = self.delay != Delay::default();
+        // Cards in their learning steps wait even without a delay
+        let ready = self.learning.iter().position(|w| {
+            (delayed || self.stepping.contains_key(&w.card)) && self.shown >= w.after && now >= w.at
+        });
+        self.taken = None;
+        let card = if let Some(i) = ready {
+            self.taken = self.learning.remove(i);
+            self.taken.map(|w| w.card)
+        } else if let Some(card) = self.due.pop_front() {
+            Some(card)
+        } else {
+            self.taken = self.learning.pop_front();
+            self.taken.map(|w| w.card)
+        };
+        self.shown += 1;
+        card
+    }
+
+    /// Puts back the card just taken from the queue, so that it is shown next
+    pub fn put_back(&mut self, card: usize) {
+        match self.taken.take() {
+            Some(waiting) => self.learning.push_front(waiting),
+            None => self.due.push_front(card),
+        }
+        self.shown = self.shown.saturating_sub(1);
+    }
+
+    /// Moves the card just taken from the queue to its end, to be shown after the others
+    pub fn skip(&mut self, card: usize) {
+        match self.taken.take() {
+            // Shown after at least one other card, even if it is ready now
+            Some(waiting) => self.learning.push_back(Waiting {
+                after: self.shown + 1,
+                ..waiting
+            }),
+            None => self.due.push_back(card),
+        }
+    }
+
+    /// Takes back the answer for a card which was answered with `grade`, so that it is shown
+    /// next. The card taken after it should be put back first
+    pub fn unanswer(&mut self, card: usize, grade: Grade) {
+        let stepped = self.previous.last().is_some_and(|&(c, _)| c == card);
+        if (grade == Grade::Again || stepped)
+            && let Some(i) = self.learning.iter().rposition(|w| w.card == card)
+        {
+            self.learning.remove(i);
+        }
+        if stepped && let Some((_, step)) = self.previous.pop() {
+            self.stepping.insert(card, step);
+        }
+        self.due.push_front(card);
+        self.shown = self.shown.saturating_sub(1);
+    }
+
+    /// The card which will most likely be shown after the current one
+    pub fn peek(&self) -> Option<usize> {
+        self.due
+            .front()
+            .copied()
+            .or_else(|| self.learning.front().map(|w| w.card))
+    }
+
+    /// The step a card in its learning steps moves to when answered with `grade`, or `None`
+    /// once it is done with them
+    fn next_step(&self, step: usize, grade: Grade) -> Option<usize> {
+        match grade {
+            Grade::Again => Some(0),
+            Grade::Hard => Some(step),
+            Grade::Good => Some(step + 1),
+            Grade::Easy => None,
+        }
+        .filter(|&next| next < self.steps.len())
+    }
+
+    /// The minutes until a card is shown again if it is answered with `grade`, if it is in its
+    /// learning steps and stays in them
+    pub fn step_minutes(&self, card: usize, grade: Grade) -> Option<f64> {
+        let step = self.next_step(*self.stepping.get(&card)?, grade)?;
+        Some(self.steps[step])
+    }
+
+    /// Records the answer given at `now` for a card taken from the queue
+    pub fn answer(&mut self, card: usize, grade: Grade, now: SystemTime) {
+        if let Some(&step) = self.stepping.get(&card) {
+            self.previous.push((card, step));
+            match self.next_step(step, grade) {
+                Some(next) => {
+                    self.stepping.insert(card, next);
+                    self.wait(card, self.shown, now, self.steps[next]);
+                }
+                None => {
+                    self.stepping.remove(&card);
+                }
+            }
+        } else if grade == Grade::Again {
+            let minutes = self.delay.minutes.unwrap_or(0.0);
+            self.wait(
+                card,
+                self.shown + self.delay.cards.unwrap_or(0),
+                now,
+                minutes,
+            );
+        }
+    }
+
+    /// Puts a card in the learning queue until `after` cards were shown and `minutes` passed
+    /// since `now`, before the cards which become ready later
+    fn wait(&mut self, card: usize, after: usize, now: SystemTime, minutes: f64) {
+        let at = now + Duration::from_secs_f64(minutes * 60.0);
+        let i = self.learning.partition_point(|w| w.at <= at);
+        self.learning.insert(i, Waiting { card, after, at });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn order() {
+        let now = SystemTime::UNIX_EPOCH;
+        let mut queue = Queue::new([0, 1, 2]);
+        let mut shown = vec![];
+        // Card 1 is forgotten twice
+        let mut failures = 2;
+        while let Some(card) = queue.next(now) {
+            shown.push(card);
+            let grade = if card == 1 && failures > 0 {
+                failures -= 1;
+                Grade::Again
+            } else {
+                Grade::Good
+            };
+            queue.answer(card, grade, now);
+        }
+        assert_eq!(shown, [0, 1, 2, 1, 1]);
+        assert_eq!(queue.next(now), None);
+    }
+
+    #[test]
+    pub fn delay() {
+        let start = SystemTime::UNIX_EPOCH;
+        let minute = Duration::from_mins(1);
+        // Card 0 is forgotten once, and comes back after two other cards
+        let mut queue = Queue::new(0..5).with_delay(Delay {
+            cards: Some(2),
+            minutes: None,
+        });
+        let mut shown = vec![];
+        while let Some(card) = queue.next(start) {
+            shown.push(card);
+            let grade = if shown == [0] {
+                Grade::Again
+            } else {
+                Grade::Good
+            };
+            queue.answer(card, grade, start);
+        }
+        assert_eq!(shown, [0, 1, 2, 0, 3, 4]);
+
+        // Or after 5 minutes, or once the other cards are done
+        let mut queue = Queue::new(0..3).with_delay(Delay {
+            cards: None,
+            minutes: Some(5.0),
+        });
+        let card = queue.next(start).unwrap();
+        queue.answer(card, Grade::Again, start);
+        assert_eq!(queue.next(start + minute), Some(1));
+        assert_eq!(queue.next(start + 6 * minute), Some(0));
+        queue.answer(0, Grade::Again, start + 6 * minute);
+        assert_eq!(queue.next(start + 7 * minute), Some(2));
+        assert_eq!(queue.next(start + 7 * minute), Some(0));
+        assert_eq!(queue.next(start + 7 * minute), None);
+
+        let config = Config::parse("again_after_cards = 3\nagain_after_minutes = 1.5").unwrap();
+        assert_eq!(
+            Delay::from_config(&config).unwrap(),
+            Delay {
+                cards: Some(3),
+                minutes: Some(1.5),
+            }
+        );
+        assert!(Delay::from_config(&Config::parse("again_after_cards = -1").unwrap()).is_err());
+    }
+
+    #[test]
+    pub fn steps() {
+        let start = SystemTime::UNIX_EPOCH;
+        let minute = Duration::from_mins(1);
+        // Card 0 is new, and waits for steps of 1 and 10 minutes
+        let mut queue = Queue::new(0..4).with_steps(&[1.0, 10.0], [0]);
+        assert_eq!(queue.step_minutes(0, Grade::Good), Some(10.0));
+        assert_eq!(queue.step_minutes(0, Grade::Easy), None);
+        assert_eq!(queue.step_minutes(1, Grade::Again), None);
+        assert_eq!(queue.next(start), Some(0));
+        queue.answer(0, Grade::Again, start);
+        assert_eq!(queue.next(start), Some(1));
+        queue.answer(1, Grade::Good, start);
+        assert_eq!(queue.next(start + 2 * minute), Some(0));
+        queue.answer(0, Grade::Good, start + 2 * minute);
+        assert_eq!(queue.next(start + 3 * minute), Some(2));
+        queue.answer(2, Grade::Good, start + 3 * minute);
+        // Undoing an answer puts the card back at its step
+        assert_eq!(queue.next(start + 3 * minute), Some(3));
+        queue.put_back(3);
+        queue.unanswer(2, Grade::Good);
+        assert_eq!(queue.next(start + 3 * minute), Some(2));
+        queue.put_back(2);
+        queue.unanswer(0, Grade::Good);
+        assert_eq!(queue.learning().count(), 0);
+        assert_eq!(queue.step_minutes(0, Grade::Good), Some(10.0));
+        assert_eq!(queue.next(start + 3 * minute), Some(0));
+        queue.answer(0, Grade::Good, start + 3 * minute);
+        assert_eq!(queue.next(start + 4 * minute), Some(2));
+        queue.answer(2, Grade::Good, start + 4 * minute);
+        assert_eq!(queue.next(start + 5 * minute), Some(3));
+        queue.answer(3, Grade::Good, start + 5 * minute);
+        // Shown early once nothing else is left, and done after the last step
+        assert_eq!(queue.next(start + 6 * minute), Some(0));
+        queue.answer(0, Grade::Good, start + 6 * minute);
+        assert_eq!(queue.next(start + 7 * minute), None);
+
+        assert!(learning_steps(&Config::parse("learning_steps = [1, 0]").unwrap()).is_err());
+        assert_eq!(
+            learning_steps(&Config::parse("learning_steps = []").unwrap()).unwrap(),
+            Vec::<f64>::new()
+        );
+    }
+
+    #[test]
+    pub fn undo() {
+        let now = SystemTime::UNIX_EPOCH;
+        let mut queue = Queue::new([0, 1]);
+        let card = queue.next(now).unwrap();
+        queue.answer(card, Grade::Again, now);
+        let current = queue.next(now).unwrap();
+        queue.put_back(current);
+        queue.unanswer(card, Grade::Again);
+        assert_eq!(queue.learning().count(), 0);
+        assert_eq!(queue.shown(), 0);
+
+        // A failed card shown again straight away is not put back twice
+        let card = queue.next(now).unwrap();
+        queue.answer(card, Grade::Good, now);
+        let card = queue.next(now).unwrap();
+        assert_eq!(card, 1);
+        queue.answer(card, Grade::Again, now);
+        assert_eq!(queue.next(now), Some(1));
+        queue.put_back(1);
+        queue.unanswer(1, Grade::Again);
+        assert_eq!(queue.due().collect::<Vec<_>>(), [1]);
+        assert_eq!(queue.learning().count(), 0);
+    }
+
+    #[test]
+    pub fn skip() {
+        let now = SystemTime::UNIX_EPOCH;
+        let mut queue = Queue::new([0, 1, 2]);
+        let card = queue.next(now).unwrap();
+        queue.skip(card);
+        assert_eq!(queue.due().collect::<Vec<_>>(), [1, 2, 0]);
+
+        // A failed card goes to the end of the learning queue instead
+        queue.next(now);
+        queue.answer(1, Grade::Again, now);
+        queue.next(now);
+        queue.answer(2, Grade::Again, now);
+        queue.next(now);
+        queue.answer(0, Grade::Good, now);
+        assert_eq!(queue.next(now), Some(1));
+        queue.skip(1);
+        assert_eq!(queue.learning().collect::<Vec<_>>(), [2, 1]);
+        assert_eq!(queue.due().count(), 0);
+    }
+
+    #[test]
+    pub fn topics() {
+        let decks = ["jp", "jp", "jp", "bones", "jp", "bones"];
+        let arranged = |order| arrange(order, (0..6).collect(), |i| decks[i]);
+        assert_eq!(arranged(Order::Shuffled), [0, 1, 2, 3, 4, 5]);
+        assert_eq!(arranged(Order::Blocked), [0, 1, 2, 4, 3, 5]);
+        assert_eq!(arranged(Order::Mixed), [0, 3, 1, 5, 2, 4]);
+
+        let config = Config::parse("order = \"mixed\"").unwrap();
+        assert_eq!(Order::configured(None, &config).unwrap(), Order::Mixed);
+        assert_eq!(
+            Order::configured(Some(Order::Blocked), &config).unwrap(),
+            Order::Blocked
+        );
+    }
+
+    #[test]
+    pub fn terminates() {
+        let now = SystemTime::UNIX_EPOCH;
+        let mut queue = Queue::new([]);
+        assert_eq!(queue.next(now), None);
+
+        let mut queue = Queue::new([0, 1]);
+        for _ in 0..2 {
+            let card = queue.next(now).unwrap();
+            queue.answer(card, Grade::Hard, now);
+        }
+        assert_eq!(queue.next(now), None);
+    }
+}
@@ -0,0 +1,152 @@
+//! Recordings of review sessions, stored as one JSON object per line.
+//!
+//! Each line looks like `{"card":"BC6l5wau","shown":1700000000000,"answered":1700000004000,"grade":3}`,
+//! with times in milliseconds since the Unix epoch.
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, bail};
+
+use crate::{CardId, fsrs::Grade};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Record {
+    pub card: CardId,
+    pub shown: SystemTime,
+    pub answered: SystemTime,
+    pub grade: Grade,
+}
+
+fn millis(time: SystemTime) -> u128 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+}
+
+impl Record {
+    fn to_json(self) -> String {
+        format!(
+            r#"{{"card":"{}","shown":{},"answered":{},"grade":{}}}"#,
+            self.card,
+            millis(self.shown),
+            millis(self.answered),
+            self.grade as u8
+        )
+    }
+
+    /// Parses a single line. Because we write the recordings ourselves,
+    /// only flat objects with string and integer values are understood.
+    fn from_json(line: &str) -> anyhow::Result<Self> {
+        let body = line
+            .trim()
+            .strip_prefix('{')
+            .and_then(|l| l.strip_suffix('}'))
+            .ok_or_else(|| anyhow!("Expected a JSON object"))?;
+
+        let (mut card, mut shown, mut answered, mut grade) = (None, None, None, None);
+        for field in body.split(',') {
+            let (key, value) = field
+                .split_once(':')
+                .ok_or_else(|| anyhow!("Invalid field `{field}`"))?;
+            let value = value.trim();
+            let time = || -> anyhow::Result<SystemTime> {
+                Ok(SystemTime::UNIX_EPOCH + Duration::from_millis(value.parse()?))
+            };
+            match key.trim().trim_matches('"') {
+                "card" => {
+                    card = Some(CardId::parse(value.trim_matches('"'))?);
+                }
+                "shown" => shown = Some(time()?),
+                "answered" => answered = Some(time()?),
+                "grade" => grade = Some(Grade::try_from(value.parse::<u8>()?)?),
+                _ => {}
+            }
+        }
+        let (Some(card), Some(shown), Some(answered), Some(grade)) = (card, shown, answered, grade)
+        else {
+            bail!("Missing fields in record");
+        };
+        Ok(Self {
+            card,
+            shown,
+            answered,
+            grade,
+        })
+    }
+}
+
+/// Appends every answer of a session to a file
+pub struct Recorder {
+    file: File,
+    /// The length of the file before each record, to undo them
+    lengths: Vec<u64>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        Ok(Self {
+            file: std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?,
+            lengths: vec![],
+        })
+    }
+
+    pub fn record(&mut self, record: Record) -> anyhow::Result<()> {
+        self.lengths.push(self.file.metadata()?.len());
+        writeln!(self.file, "{}", record.to_json())?;
+        Ok(())
+    }
+
+    /// Removes the latest record
+    pub fn undo(&mut self) -> anyhow::Result<()> {
+        if let Some(len) = self.lengths.pop() {
+            self.file.set_len(len)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads all the records from a recording
+pub fn read(path: &Path) -> anyhow::Result<Vec<Record>> {
+    let file = BufReader::new(File::open(path)?);
+    let mut res = vec![];
+    for (lineno, line) in file.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        res.push(
+            Record::from_json(&line)
+                .map_err(|e| e.context(format!("{}:{}", path.display(), lineno + 1)))?,
+        );
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn roundtrip() {
+        let record = Record {
+            card: CardId([1, 2, 3, 4, 5, 6]),
+            shown: SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_123),
+            answered: SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_004),
+            grade: Grade::Hard,
+        };
+        let json = record.to_json();
+        assert_eq!(
+            json,
+            r#"{"card":"AQIDBAUG","shown":1700000000123,"answered":1700000004000,"grade":2}"#
+        );
+        assert_eq!(Record::from_json(&json).unwrap(), record);
+        assert!(Record::from_json(r#"{"card":"AQIDBAUG"}"#).is_err());
+    }
+}
@@ -0,0 +1,149 @@
+//! Notices files which were moved or renamed, by where their cards were last seen.
+//!
+//! The `location` table keeps the file and deck of every card. A file which is gone is
+//! recognized in its new place by its cards, whose ids stay the same however the text around
+//! them is edited. Relinking keeps the deck of the file when its settings would otherwise be
+//! lost with its old name, and points a session to resume at the new path
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use crate::{CardBody, config::Config, deck, session::Session, tag::write_atomic};
+
+/// The path a card's file is tracked under, which is the same from any directory
+fn tracked_path(file: &Path) -> String {
+    std::fs::canonicalize(file)
+        .unwrap_or_else(|_| file.to_path_buf())
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Records where each card is. Cards whose old file is gone are left at it for
+/// [`run`] to find, returning how many there were
+pub fn track(tx: &rusqlite::Transaction, cards: &[CardBody]) -> anyhow::Result<usize> {
+    let mut moved = 0;
+    let mut paths = BTreeMap::new();
+    for card in cards {
+        let path = paths
+            .entry(&card.file)
+            .or_insert_with(|| tracked_path(&card.file));
+        let old: Option<String> = tx
+            .query_row(
+                "select path from location where card = ?1",
+                [card.id.as_int()],
+                |row| row.get(0),
+            )
+            .ok();
+        if old.is_some_and(|old| old != *path && !Path::new(&old).exists()) {
+            moved += 1;
+            continue;
+        }
+        tx.execute(
+            "insert into location(card, path, deck) values (?1, ?2, ?3)
+                 on conflict(card) do update set path = ?2, deck = ?3",
+            (card.id.as_int(), &*path, &card.deck),
+        )?;
+    }
+    Ok(moved)
+}
+
+/// Finds where the files which are gone were moved to among the files of `cards`, and
+/// relinks them. Returns the number of files relinked
+pub fn run(
+    sqlite: &mut rusqlite::Connection,
+    config: &Config,
+    cards: &[CardBody],
+    session_path: &Path,
+) -> anyhow::Result<usize> {
+    // The cards of each file which is gone, and its deck
+    let mut gone: BTreeMap<String, (String, BTreeSet<u64>)> = BTreeMap::new();
+    {
+        let mut statement = sqlite.prepare("select card, path, deck from location")?;
+        let rows = statement.query_map((), |row| {
+            Ok((
+                row.get::<_, u64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?;
+        for row in rows {
+            let (card, path, deck) = row?;
+            if !Path::new(&path).exists() {
+                gone.entry(path)
+                    .or_insert((deck, BTreeSet::new()))
+                    .1
+                    .insert(card);
+            }
+        }
+    }
+
+    let mut files: BTreeMap<&PathBuf, (&str, Vec<u64>)> = BTreeMap::new();
+    for card in cards {
+        files
+            .entry(&card.file)
+            .or_insert((&card.deck, vec![]))
+            .1
+            .push(card.id.as_int());
+    }
+    let decks = config.tables("decks");
+    let mut session = Session::load(session_path).ok();
+    // The decks of files which were given the name of the deck they were moved from
+    let mut kept: BTreeMap<&PathBuf, &str> = BTreeMap::new();
+    let mut relinked = 0;
+    for (old, (old_deck, ids)) in &gone {
+        // The file with most of the cards, if it has at least half of them
+        let Some((file, (deck, _))) = files
+            .iter()
+            .map(|(file, (deck, new))| {
+                (file, (deck, new.iter().filter(|i| ids.contains(i)).count()))
+            })
+            .filter(|(_, (_, found))| found * 2 >= ids.len())
+            .max_by_key(|(_, (_, found))| *found)
+        else {
+            eprintln!("{old} is gone, and most of its cards were not found");
+            continue;
+        };
+        let mut note = String::new();
+        if deck != old_deck && decks.contains(old_deck.as_str()) {
+            let data = std::fs::read_to_string(file)?;
+            match deck::with_name(&data, old_deck) {
+                Some(data) => {
+                    write_atomic(file, &data)?;
+                    kept.insert(file, old_deck);
+                    note = format!(", keeping the settings of the deck `{old_deck}`");
+                }
+                None => {
+                    note =
+                        format!(", whose deck `{deck}` does not have the settings of `{old_deck}`");
+                }
+            }
+        }
+        if let Some(session) = &mut session {
+            for path in &mut session.files {
+                if path.as_os_str() == old.as_str() {
+                    *path = PathBuf::from(tracked_path(file));
+                }
+            }
+        }
+        println!("{old} was moved to {}{note}", file.display());
+        relinked += 1;
+    }
+    if let Some(session) = session
+        && relinked > 0
+    {
+        session.save(session_path)?;
+    }
+
+    let tx = sqlite.transaction()?;
+    for card in cards {
+        let deck = kept.get(&card.file).copied().unwrap_or(&card.deck);
+        tx.execute(
+            "insert into location(card, path, deck) values (?1, ?2, ?3)
+                 on conflict(card) do update set path = ?2, deck = ?3",
+            (card.id.as_int(), tracked_path(&card.file), deck),
+        )?;
+    }
+    tx.commit()?;
+    Ok(relinked)
+}
@@ -0,0 +1,133 @@
+//! Moves a card, with its id, to another file or section, so that reorganizing notes keeps
+//! its review history
+use std::{io::ErrorKind, path::Path};
+
+use anyhow::anyhow;
+
+use crate::{CardBody, CardId, format::markdown_heading, section, tag::write_atomic};
+
+/// The range of lines, counting from 0, of the card starting at `line`: up to the next card
+/// or heading, without the blank lines before it
+fn block(lines: &[&str], line: usize, next_card: Option<usize>) -> std::ops::Range<usize> {
+    let start = line - 1;
+    let mut end = next_card.map_or(lines.len(), |l| l - 1).min(lines.len());
+    if let Some(heading) = (start + 1..end).find(|&i| markdown_heading(lines[i]).is_some()) {
+        end = heading;
+    }
+    while end > start + 1 && lines[end - 1].trim().is_empty() {
+        end -= 1;
+    }
+    start..end
+}
+
+/// Inserts a card before the line `before`, counting from 1, or at the end, keeping it apart
+/// from the text around it with blank lines
+fn insert(data: &str, card: &str, before: Option<usize>) -> String {
+    let lines: Vec<&str> = data.lines().collect();
+    let at = before.map_or(lines.len(), |l| l - 1).min(lines.len());
+    // Blank lines before the end of the section stay before the next heading
+    let mut split = at;
+    while split > 0 && lines[split - 1].trim().is_empty() {
+        split -= 1;
+    }
+    let mut res = String::new();
+    for line in &lines[..split] {
+        res.push_str(line);
+        res.push('\n');
+    }
+    if split > 0 {
+        res.push('\n');
+    }
+    res.push_str(card);
+    res.push('\n');
+    if at < lines.len() {
+        res.push('\n');
+        for line in &lines[at..] {
+            res.push_str(line);
+            res.push('\n');
+        }
+    }
+    res
+}
+
+/// Moves the card with the given id to the end of `target`, which is a file or a section like
+/// `file.md#heading`
+pub fn move_card(cards: &[CardBody], id: CardId, target: &Path) -> anyhow::Result<()> {
+    let card = cards
+        .iter()
+        .find(|c| c.id == id && !c.reversed)
+        .or_else(|| cards.iter().find(|c| c.id == id))
+        .ok_or_else(|| anyhow!("No card with this id in the given files"))?;
+    let next_card = cards
+        .iter()
+        .filter(|c| c.file == card.file && c.line > card.line)
+        .map(|c| c.line)
+        .min();
+
+    let data = std::fs::read_to_string(&card.file)?;
+    let lines: Vec<&str> = data.lines().collect();
+    let range = block(&lines, card.line, next_card);
+    let text = lines[range.clone()].join("\n");
+    let mut rest: Vec<&str> = lines[..range.start].to_vec();
+    // Only one of the blank lines around the card is kept
+    let mut after = &lines[range.end..];
+    if rest.last().is_some_and(|l| l.trim().is_empty()) {
+        while after.first().is_some_and(|l| l.trim().is_empty()) {
+            after = &after[1..];
+        }
+    }
+    rest.extend(after);
+    let mut rest = rest.join("\n");
+    rest.push('\n');
+
+    let same = |file: &Path| {
+        file == card.file
+            || std::fs::canonicalize(file)
+                .is_ok_and(|f| std::fs::canonicalize(&card.file).is_ok_and(|c| c == f))
+    };
+    // The target is found after removing the card, in case it is in the same file
+    let (file, data, before) = section::end_of(target, |file| {
+        if same(file) {
+            return Ok(rest.clone());
+        }
+        match std::fs::read_to_string(file) {
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(String::new()),
+            res => Ok(res?),
+        }
+    })?;
+    let moved = insert(&data, &text, before);
+    // Nothing is written until both files are known, and the card is added before it is
+    // removed, so it is never lost
+    if same(&file) {
+        write_atomic(&card.file, &moved)?;
+    } else {
+        write_atomic(&file, &moved)?;
+        write_atomic(&card.file, &rest)?;
+    }
+    eprintln!(
+        "Moved the card from {}:{} to {}",
+        card.file.display(),
+        card.line,
+        file.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn card_lines() {
+        let lines: Vec<&str> = "# A\nREVIEW: one\nback\n\n## B\nREVIEW: two\n\nREVIEW: three"
+            .lines()
+            .collect();
+        assert_eq!(block(&lines, 2, Some(6)), 1..3);
+        assert_eq!(block(&lines, 6, Some(8)), 5..6);
+        assert_eq!(
+            insert("# A\ntext\n\n# B\n", "REVIEW: one", Some(4)),
+            "# A\ntext\n\nREVIEW: one\n\n# B\n"
+        );
+        assert_eq!(insert("", "REVIEW: one", None), "REVIEW: one\n");
+    }
+}
@@ -0,0 +1,303 @@
+//! Reports summarizing the review history of a collection
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use crate::ui::Stylize;
+
+use crate::{
+    CardBody, CardId, card_added, content_changed, deck, from_millis, fuzz, leech, load_card_data,
+    ui,
+};
+
+/// Formats a time as a `YYYY-MM-DD` date in UTC
+pub fn format_date(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    // See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = i64::try_from(secs / 86400).unwrap_or_default() + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+#[derive(Default)]
+struct FileStats {
+    cards: usize,
+    new: usize,
+    due: usize,
+    total_difficulty: f32,
+    last_added: Option<SystemTime>,
+}
+
+/// Prints card counts and review burden for each source file
+///
+/// Cards are considered added when they were first reviewed
+#[allow(clippy::cast_precision_loss)]
+pub fn files(
+    sqlite: &mut rusqlite::Connection,
+    cards: &[CardBody],
+    retention: f32,
+    max_interval: Option<f32>,
+) -> anyhow::Result<()> {
+    let mut stats: BTreeMap<&Path, FileStats> = BTreeMap::new();
+    for card in cards {
+        let file = stats.entry(&card.file).or_default();
+        file.cards += 1;
+        let Some((last_reviewed, fsrs)) = load_card_data(sqlite, card.id) else {
+            file.new += 1;
+            continue;
+        };
+        if fuzz::is_due(
+            card.id,
+            (last_reviewed, fsrs),
+            (retention, max_interval),
+            0.0,
+        ) {
+            file.due += 1;
+        }
+        file.total_difficulty += fsrs.difficulty;
+
+        let added: u64 = sqlite.query_row(
+            "select min(last_reviewed) from review where card = ?1",
+            [card.id.as_int()],
+            |row| row.get(0),
+        )?;
+        let added = from_millis(added);
+        file.last_added = file.last_added.max(Some(added));
+    }
+
+    for (path, file) in &stats {
+        println!("{}", path.display().to_string().bold());
+        let reviewed = file.cards - file.new;
+        let difficulty = if reviewed == 0 {
+            "-".to_string()
+        } else {
+            format!("{:.2}", file.total_difficulty / reviewed as f32)
+        };
+        println!(
+            "cards: {}\nnew: {}\ndue: {}\naverage difficulty: {difficulty}\nlast added: {}",
+            file.cards,
+            file.new,
+            file.due,
+            file.last_added.map_or("-".to_string(), format_date),
+        );
+        println!();
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct DeckStats {
+    new: usize,
+    young: usize,
+    mature: usize,
+    total_stability: f32,
+    due_today: usize,
+    due_week: usize,
+}
+
+/// Prints card counts for each deck, by maturity and by when they are due.
+///
+/// Cards are mature once their stability reaches 21 days
+#[allow(clippy::cast_precision_loss)]
+pub fn decks(sqlite: &mut rusqlite::Connection, cards: &[CardBody], retention: &deck::Retention) {
+    let mut stats: BTreeMap<String, DeckStats> = BTreeMap::new();
+    for card in cards {
+        let deck = stats.entry(card.deck.clone()).or_default();
+        let Some((last_reviewed, fsrs)) = load_card_data(sqlite, card.id) else {
+            deck.new += 1;
+            deck.due_today += 1;
+            deck.due_week += 1;
+            continue;
+        };
+        if fsrs.stability >= 21.0 {
+            deck.mature += 1;
+        } else {
+            deck.young += 1;
+        }
+        deck.total_stability += fsrs.stability;
+
+        let target = retention.target(card);
+        if fuzz::is_due(card.id, (last_reviewed, fsrs), target, 1.0) {
+            deck.due_today += 1;
+        }
+        if fuzz::is_due(card.id, (last_reviewed, fsrs), target, 7.0) {
+            deck.due_week += 1;
+        }
+    }
+
+    println!(
+        "{}",
+        "deck\tnew\tyoung\tmature\tavg stability\tdue today\tdue this week".bold()
+    );
+    for (name, deck) in &stats {
+        let reviewed = deck.young + deck.mature;
+        let stability = if reviewed == 0 {
+            "-".to_string()
+        } else {
+            format!("{:.1}", deck.total_stability / reviewed as f32)
+        };
+        println!(
+            "{name}\t{}\t{}\t{}\t{stability}\t\t{}\t\t{}",
+            deck.new, deck.young, deck.mature, deck.due_today, deck.due_week
+        );
+    }
+}
+
+/// Prints how many cards were added in each week, starting on Monday, with a column for each deck
+pub fn added(sqlite: &mut rusqlite::Connection, cards: &[CardBody]) -> anyhow::Result<()> {
+    let mut weeks: BTreeMap<u64, BTreeMap<&str, usize>> = BTreeMap::new();
+    for card in cards {
+        let Some(added) = card_added(sqlite, card.id) else {
+            continue;
+        };
+        let day = added.duration_since(SystemTime::UNIX_EPOCH)?.as_secs() / 86400;
+        // The Unix epoch was a Thursday
+        let monday = day - (day + 3) % 7;
+        *weeks
+            .entry(monday)
+            .or_default()
+            .entry(&card.deck)
+            .or_default() += 1;
+    }
+    let decks: Vec<&str> = cards
+        .iter()
+        .map(|c| c.deck.as_str())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let (Some(&first), Some(&last)) = (weeks.keys().next(), weeks.keys().next_back()) else {
+        return Ok(());
+    };
+
+    println!("{}", format!("week\t{}\ttotal", decks.join("\t")).bold());
+    // Weeks without new cards are shown too, so bursts stand out
+    for monday in (first..=last).step_by(7) {
+        let week = weeks.get(&monday);
+        let count = |deck| week.and_then(|w| w.get(deck)).copied().unwrap_or_default();
+        let counts: Vec<String> = decks.iter().map(|d| count(d).to_string()).collect();
+        let total: usize = week.map_or(0, |w| w.values().sum());
+        println!(
+            "{}\t{}\t{total}",
+            format_date(SystemTime::UNIX_EPOCH + Duration::from_secs(monday * 86400)),
+            counts.join("\t")
+        );
+    }
+    Ok(())
+}
+
+/// Prints cards which lapsed at least `min_lapses` times and whose text has not changed in
+/// `days` days, which suggests they should be rewritten rather than reviewed again
+pub fn stale(
+    sqlite: &mut rusqlite::Connection,
+    cards: &[CardBody],
+    days: u32,
+    min_lapses: usize,
+) -> anyhow::Result<()> {
+    let mut rows = vec![];
+    for card in cards {
+        let Some((_, fsrs)) = load_card_data(sqlite, card.id) else {
+            continue;
+        };
+        let Some(changed) = content_changed(sqlite, card.id) else {
+            continue;
+        };
+        let age = changed.elapsed()?.as_secs() / 86400;
+        let lapses = leech::lapses(&leech::history(sqlite, card.id)?);
+        if age >= u64::from(days) && lapses >= min_lapses {
+            rows.push((card, fsrs, lapses, changed));
+        }
+    }
+    rows.sort_by(|(_, a, al, _), (_, b, bl, _)| {
+        bl.cmp(al).then(b.difficulty.total_cmp(&a.difficulty))
+    });
+
+    for (card, fsrs, lapses, changed) in rows {
+        println!("{}", ui::hide_cloze(card.front.trim()).bold());
+        println!(
+            "{}",
+            format!("{}:{}", card.file.display(), card.line).muted()
+        );
+        println!(
+            "lapses: {lapses}\ndifficulty: {:.2}\nunchanged since: {}",
+            fsrs.difficulty,
+            format_date(changed)
+        );
+        println!();
+    }
+    Ok(())
+}
+
+/// The average seconds taken to answer a card, if any answer was timed
+fn answer_seconds(sqlite: &rusqlite::Connection, id: CardId) -> anyhow::Result<Option<f32>> {
+    Ok(sqlite.query_row(
+        "select avg(duration_ms) / 1000.0 from review_log where card = ?1 and duration_ms > 0",
+        [id.as_int()],
+        |row| row.get(0),
+    )?)
+}
+
+/// Prints the `top` hardest cards, which are good candidates for rewriting. Cards are ranked by
+/// their difficulty as shown, then their lapses, then how long they take to answer
+pub fn hard(
+    sqlite: &mut rusqlite::Connection,
+    cards: &[CardBody],
+    top: usize,
+) -> anyhow::Result<()> {
+    let mut rows = vec![];
+    for card in cards {
+        let Some((_, fsrs)) = load_card_data(sqlite, card.id) else {
+            continue;
+        };
+        let lapses = leech::lapses(&leech::history(sqlite, card.id)?);
+        let seconds = answer_seconds(sqlite, card.id)?;
+        rows.push((card, fsrs, lapses, seconds));
+    }
+    // Difficulties only a little apart are as hard, so the other measures decide between them
+    let shown = |d: f32| (d * 10.0).round();
+    rows.sort_by(|(_, a, al, at), (_, b, bl, bt)| {
+        shown(b.difficulty)
+            .total_cmp(&shown(a.difficulty))
+            .then(bl.cmp(al))
+            .then(bt.unwrap_or_default().total_cmp(&at.unwrap_or_default()))
+    });
+
+    for (i, (card, fsrs, lapses, seconds)) in rows.iter().take(top).enumerate() {
+        println!("{}. {}", i + 1, ui::hide_cloze(card.front.trim()).bold());
+        println!(
+            "{}",
+            format!("{}:{}", card.file.display(), card.line).muted()
+        );
+        let seconds = seconds.map_or_else(|| "not timed".to_string(), |s| format!("{s:.1}s"));
+        println!(
+            "difficulty: {:.1}\nlapses: {lapses}\naverage answer: {seconds}",
+            fsrs.difficulty
+        );
+        println!();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn dates() {
+        let date = |secs| format_date(SystemTime::UNIX_EPOCH + Duration::from_secs(secs));
+        assert_eq!(date(0), "1970-01-01");
+        assert_eq!(date(951_782_400), "2000-02-29");
+        assert_eq!(date(1_791_936_000), "2026-10-14");
+    }
+}
@@ -0,0 +1,102 @@
+//! Decks which are only loosely kept up, by reviewing a few of their cards each day instead of
+//! every card once it is due.
+//!
+//! With `sample = 5` in the `[decks.<name>]` table of the config, the cards of a deck are never
+//! due on their own. Instead 5 of them are picked each day, at random but each as likely as it is
+//! predicted to be forgotten, so the cards most likely forgotten come up most often. Cards
+//! reviewed earlier the same day are not picked again
+use std::{collections::BTreeMap, time::SystemTime};
+
+use anyhow::bail;
+use rand::Rng;
+
+use crate::{
+    config::{Config, Value},
+    fsrs::FSRSParams,
+};
+
+/// The number of cards to review each day, for each deck which is sampled
+#[derive(Debug, Default)]
+pub struct Samples(BTreeMap<String, usize>);
+
+impl Samples {
+    pub fn from_config(config: &Config) -> anyhow::Result<Self> {
+        let mut decks = BTreeMap::new();
+        for deck in config.tables("decks") {
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            match config.get(&format!("decks.{deck}.sample")) {
+                None => {}
+                Some(Value::Number(n)) if *n >= 1.0 && n.fract() == 0.0 => {
+                    decks.insert(deck.to_string(), *n as usize);
+                }
+                Some(_) => bail!("`decks.{deck}.sample` should be a positive whole number"),
+            }
+        }
+        Ok(Self(decks))
+    }
+
+    /// The number of cards of `deck` to review each day, if it is sampled
+    pub fn of(&self, deck: &str) -> Option<usize> {
+        self.0.get(deck).copied()
+    }
+}
+
+/// How likely a card with the state `last` is forgotten, which is how likely it is picked, or
+/// `None` if it was reviewed on the UTC day `today`. New cards count as forgotten
+pub fn weight(last: Option<(SystemTime, FSRSParams)>, today: u64) -> Option<f32> {
+    let Some((reviewed, fsrs)) = last else {
+        return Some(1.0);
+    };
+    let secs = reviewed
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let days = reviewed.elapsed().unwrap_or_default().as_secs_f32() / 86400.0;
+    (secs / 86400 != today).then(|| 1.0 - fsrs.recall_probability(days))
+}
+
+/// Picks up to `n` of the items, each with a chance in proportion to its weight, without
+/// picking any twice. Items with no weight are never picked
+pub fn pick(weighted: &[(usize, f32)], n: usize, rng: &mut impl Rng) -> Vec<usize> {
+    // Each item gets a random key which tends to be higher the more it weighs, and the items
+    // with the highest keys are taken
+    let mut keyed: Vec<(f32, usize)> = weighted
+        .iter()
+        .filter(|(_, weight)| *weight > 0.0)
+        .map(|&(item, weight)| (rng.random::<f32>().powf(1.0 / weight), item))
+        .collect();
+    keyed.sort_by(|a, b| b.0.total_cmp(&a.0));
+    keyed.into_iter().take(n).map(|(_, item)| item).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{SeedableRng, rngs::StdRng};
+
+    use super::*;
+
+    #[test]
+    pub fn weighted() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let weighted = [(0, 0.9), (1, 0.01), (2, 0.0), (3, 0.5)];
+        let mut counts = [0; 4];
+        for _ in 0..1000 {
+            let picked = pick(&weighted, 2, &mut rng);
+            assert_eq!(picked.len(), 2);
+            assert_ne!(picked[0], picked[1]);
+            for i in picked {
+                counts[i] += 1;
+            }
+        }
+        assert_eq!(counts[2], 0);
+        assert!(counts[0] > counts[3] && counts[3] > counts[1]);
+        assert_eq!(pick(&weighted, 10, &mut rng).len(), 3);
+
+        let config =
+            Config::parse("[decks.trivia]\nsample = 5\n[decks.jp]\nretention = 0.8").unwrap();
+        let samples = Samples::from_config(&config).unwrap();
+        assert_eq!(samples.of("trivia"), Some(5));
+        assert_eq!(samples.of("jp"), None);
+        assert!(Samples::from_config(&Config::parse("decks.a.sample = 0.5").unwrap()).is_err());
+    }
+}
@@ -0,0 +1,144 @@
+//! Restricting a review to one section of a Markdown file, given as `file.md:120` for the
+//! section around line 120, or `file.md#heading` for the section under a heading
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use anyhow::bail;
+
+use crate::{CardBody, format::markdown_heading};
+
+enum Anchor {
+    Line(usize),
+    Heading(String),
+}
+
+/// The lines of a file which a review is restricted to
+pub struct Section {
+    file: PathBuf,
+    lines: Range<usize>,
+}
+
+/// Splits the anchor off a file argument, unless the argument is the name of an existing file
+fn split(arg: &Path) -> (PathBuf, Option<Anchor>) {
+    if arg.exists() {
+        return (arg.to_path_buf(), None);
+    }
+    let text = arg.to_string_lossy();
+    if let Some((file, line)) = text.rsplit_once(':')
+        && let Ok(line) = line.parse()
+    {
+        return (PathBuf::from(file), Some(Anchor::Line(line)));
+    }
+    if let Some((file, heading)) = text.split_once('#') {
+        return (
+            PathBuf::from(file),
+            Some(Anchor::Heading(heading.to_string())),
+        );
+    }
+    (arg.to_path_buf(), None)
+}
+
+/// Whether a heading matches an anchor, either by its text or as a link like `#memory-palace`
+fn heading_matches(text: &str, anchor: &str) -> bool {
+    let slug = text
+        .to_lowercase()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join("-");
+    text.eq_ignore_ascii_case(anchor) || slug == anchor.to_lowercase()
+}
+
+/// The range of line numbers, counting from 1, of the section containing the anchor
+fn lines(data: &str, anchor: &Anchor) -> anyhow::Result<Range<usize>> {
+    let headings: Vec<(usize, usize, &str)> = data
+        .lines()
+        .enumerate()
+        .filter_map(|(i, l)| markdown_heading(l).map(|level| (i + 1, level, l[level..].trim())))
+        .collect();
+    let start = match anchor {
+        Anchor::Line(line) => headings.iter().rposition(|h| h.0 <= *line),
+        Anchor::Heading(anchor) => {
+            let found = headings.iter().position(|h| heading_matches(h.2, anchor));
+            if found.is_none() {
+                bail!("No heading `{anchor}`");
+            }
+            found
+        }
+    };
+    // Before the first heading, the section is the start of the file
+    let Some(start) = start else {
+        return Ok(1..headings.first().map_or(usize::MAX, |h| h.0));
+    };
+    let (line, level, _) = headings[start];
+    let end = headings[start + 1..]
+        .iter()
+        .find(|h| h.1 <= level)
+        .map_or(usize::MAX, |h| h.0);
+    Ok(line..end)
+}
+
+/// The file a target like `file.md#heading` is in, and the line number, counting from 1,
+/// before which text is put to add it at the end of the section. `None` is the end of the file.
+/// The contents of the file come from `read`, and are returned with them
+pub fn end_of(
+    target: &Path,
+    read: impl FnOnce(&Path) -> anyhow::Result<String>,
+) -> anyhow::Result<(PathBuf, String, Option<usize>)> {
+    let (file, anchor) = split(target);
+    let data = read(&file)?;
+    let Some(anchor) = anchor else {
+        return Ok((file, data, None));
+    };
+    let end = lines(&data, &anchor)
+        .map_err(|e| e.context(format!("In {}", file.display())))?
+        .end;
+    Ok((file, data, (end != usize::MAX).then_some(end)))
+}
+
+/// Splits file arguments into the files to load, and the sections to restrict them to
+pub fn resolve(args: &[PathBuf]) -> anyhow::Result<(Vec<PathBuf>, Vec<Section>)> {
+    let mut files = vec![];
+    let mut sections = vec![];
+    for arg in args {
+        let (file, anchor) = split(arg);
+        if let Some(anchor) = anchor {
+            let data = std::fs::read_to_string(&file)?;
+            sections.push(Section {
+                lines: lines(&data, &anchor)
+                    .map_err(|e| e.context(format!("In {}", file.display())))?,
+                file: file.clone(),
+            });
+        }
+        if !files.contains(&file) {
+            files.push(file);
+        }
+    }
+    Ok((files, sections))
+}
+
+/// Whether a card is in one of the sections, or in a file which is not restricted to any
+pub fn contains(sections: &[Section], card: &CardBody) -> bool {
+    let mut in_file = sections.iter().filter(|s| s.file == card.file).peekable();
+    in_file.peek().is_none() || in_file.any(|s| s.lines.contains(&card.line))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn section_lines() {
+        let data = "intro\n# Memory\nREVIEW: a\n## Palace\nREVIEW: b\n# Other\nREVIEW: c\n";
+        assert_eq!(lines(data, &Anchor::Line(1)).unwrap(), 1..2);
+        assert_eq!(lines(data, &Anchor::Line(3)).unwrap(), 2..6);
+        assert_eq!(lines(data, &Anchor::Line(5)).unwrap(), 4..6);
+        assert_eq!(lines(data, &Anchor::Line(7)).unwrap(), 6..usize::MAX);
+        assert_eq!(
+            lines(data, &Anchor::Heading("palace".to_string())).unwrap(),
+            4..6
+        );
+        assert!(lines(data, &Anchor::Heading("nope".to_string())).is_err());
+    }
+}
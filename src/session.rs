@@ -0,0 +1,210 @@
+//! Saves the progress of a review session, so it can be resumed if cardsharp stops unexpectedly.
+//!
+//! The session file lists the reviewed files and the ids of the cards remaining in the queue,
+//! one per line, as `file <path>`, `due <id>` or `learning <id>`.
+use std::{
+    collections::HashSet,
+    fmt::Write,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use anyhow::{anyhow, bail};
+
+use crate::{CardBody, CardId, fsrs::Grade, queue::Queue};
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Session {
+    pub files: Vec<PathBuf>,
+    pub due: Vec<CardId>,
+    pub learning: Vec<CardId>,
+}
+
+impl Session {
+    pub fn new(files: &[PathBuf], cards: &[CardBody], queue: &Queue) -> Self {
+        Self {
+            // Sessions may be resumed from another directory
+            files: files
+                .iter()
+                .map(|f| std::fs::canonicalize(f).unwrap_or_else(|_| f.clone()))
+                .collect(),
+            due: queue.due().map(|i| cards[i].id).collect(),
+            learning: queue.learning().map(|i| cards[i].id).collect(),
+        }
+    }
+
+    fn parse(data: &str) -> anyhow::Result<Self> {
+        let mut res = Self::default();
+        for line in data.lines() {
+            let (kind, value) = line
+                .split_once(' ')
+                .ok_or_else(|| anyhow!("Invalid line `{line}` in session"))?;
+            let id = || CardId::parse(value);
+            match kind {
+                "file" => res.files.push(PathBuf::from(value)),
+                "due" => res.due.push(id()?),
+                "learning" => res.learning.push(id()?),
+                _ => bail!("Invalid line `{line}` in session"),
+            }
+        }
+        Ok(res)
+    }
+
+    fn serialize(&self) -> String {
+        let mut res = String::new();
+        for file in &self.files {
+            _ = writeln!(res, "file {}", file.display());
+        }
+        for (kind, ids) in [("due", &self.due), ("learning", &self.learning)] {
+            for id in ids {
+                _ = writeln!(res, "{kind} {id}");
+            }
+        }
+        res
+    }
+
+    /// Writes the session, replacing the file atomically so a crash can not leave it half written
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, self.serialize())?;
+        std::fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let data = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Could not read session to resume: {e}"))?;
+        Self::parse(&data)
+    }
+
+    /// Rebuilds the queue with indices into `cards`, skipping cards which no longer exist
+    pub fn queue(&self, cards: &[CardBody]) -> Queue {
+        let index = |id: &CardId| cards.iter().position(|c| c.id == *id);
+        Queue::with_learning(
+            self.due.iter().filter_map(index),
+            self.learning.iter().filter_map(index),
+        )
+    }
+}
+
+/// Where the session is, and the cards left after the current one
+#[derive(Debug, Default, PartialEq)]
+pub struct Left {
+    /// The number of cards shown so far, including the current one
+    pub position: usize,
+    pub new: usize,
+    /// Cards forgotten in this session
+    pub learning: usize,
+    pub due: usize,
+}
+
+impl Left {
+    /// Counts what is left in `queue`, where `new` are the cards which were never reviewed
+    pub fn of(queue: &Queue, new: &HashSet<usize>) -> Self {
+        let new_left = queue.due().filter(|i| new.contains(i)).count();
+        Self {
+            position: queue.shown(),
+            new: new_left,
+            learning: queue.learning().count(),
+            due: queue.due().count() - new_left,
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.position + self.new + self.learning + self.due
+    }
+}
+
+/// How the current session is going, shown above each card and updated after each answer
+#[derive(Debug, Clone)]
+pub struct Progress {
+    started: Instant,
+    /// Answers graded again
+    pub again: usize,
+    /// Answers in a row which were not again
+    pub streak: usize,
+}
+
+impl Progress {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            again: 0,
+            streak: 0,
+        }
+    }
+
+    pub fn answer(&mut self, grade: Grade) {
+        if grade == Grade::Again {
+            self.again += 1;
+            self.streak = 0;
+        } else {
+            self.streak += 1;
+        }
+    }
+
+    /// The line shown next to the banner, for a card of `deck`
+    pub fn status(&self, deck: &str, left: &Left) -> String {
+        let elapsed = self.started.elapsed().as_secs();
+        let mut res = String::new();
+        if !deck.is_empty() {
+            _ = write!(res, "{deck} · ");
+        }
+        _ = write!(
+            res,
+            "card {} / {} · {} new · {} learning · {} due · {} again · {}:{:02} · streak {}",
+            left.position,
+            left.total(),
+            left.new,
+            left.learning,
+            left.due,
+            self.again,
+            elapsed / 60,
+            elapsed % 60,
+            self.streak
+        );
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn roundtrip() {
+        let session = Session {
+            files: vec![PathBuf::from("notes/some file.md")],
+            due: vec![CardId([1; 6]), CardId([2; 6])],
+            learning: vec![CardId([3; 6])],
+        };
+        assert_eq!(Session::parse(&session.serialize()).unwrap(), session);
+        assert!(Session::parse("due ???").is_err());
+    }
+
+    #[test]
+    pub fn progress() {
+        let mut progress = Progress::new();
+        for grade in [Grade::Good, Grade::Again, Grade::Good, Grade::Easy] {
+            progress.answer(grade);
+        }
+        let mut queue = Queue::new([0, 1, 2, 3]);
+        let card = queue.next(std::time::SystemTime::UNIX_EPOCH).unwrap();
+        queue.answer(card, Grade::Again, std::time::SystemTime::UNIX_EPOCH);
+        queue.next(std::time::SystemTime::UNIX_EPOCH);
+        let left = Left::of(&queue, &HashSet::from([0, 3]));
+        assert_eq!(
+            left,
+            Left {
+                position: 2,
+                new: 1,
+                learning: 1,
+                due: 1,
+            }
+        );
+        assert_eq!(
+            progress.status("anatomy", &left),
+            "anatomy · card 2 / 5 · 1 new · 1 learning · 1 due · 1 again · 0:00 · streak 2"
+        );
+    }
+}
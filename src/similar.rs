@@ -0,0 +1,115 @@
+//! Finds clusters of cards asking about much the same thing.
+//!
+//! Card fronts are reduced to their content words, hashed into character trigrams,
+//! and compared pairwise by their Jaccard similarity.
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use crate::CardBody;
+
+/// Words which only phrase the question, and say nothing about its content
+const STOPWORDS: &[&str] = &[
+    "a", "an", "and", "are", "define", "describe", "do", "does", "explain", "for", "how", "in",
+    "is", "it", "meaning", "name", "of", "on", "or", "the", "to", "what", "when", "where", "which",
+    "who", "why",
+];
+
+/// Sorted and deduplicated hashes of the trigrams in the content words of `text`
+fn fingerprint(text: &str) -> Vec<u64> {
+    let text = text.to_lowercase();
+    let words: Vec<&str> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty() && !STOPWORDS.contains(w))
+        .collect();
+    let chars: Vec<char> = format!(" {} ", words.join(" ")).chars().collect();
+
+    let mut res: Vec<u64> = chars
+        .windows(3)
+        .map(|w| {
+            let mut hasher = DefaultHasher::new();
+            w.hash(&mut hasher);
+            hasher.finish()
+        })
+        .collect();
+    res.sort_unstable();
+    res.dedup();
+    res
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn jaccard(a: &[u64], b: &[u64]) -> f32 {
+    let (mut i, mut j, mut common) = (0, 0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                common += 1;
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    let union = a.len() + b.len() - common;
+    if union == 0 {
+        return 0.0;
+    }
+    common as f32 / union as f32
+}
+
+fn find(parents: &mut [usize], i: usize) -> usize {
+    let mut root = i;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    parents[i] = root;
+    root
+}
+
+/// Groups the cards whose fronts are at least `threshold` similar, returning indices into `cards`.
+/// Only groups of more than one card are returned.
+pub fn clusters(cards: &[CardBody], threshold: f32) -> Vec<Vec<usize>> {
+    let prints: Vec<Vec<u64>> = cards.iter().map(|c| fingerprint(&c.front)).collect();
+    let mut parents: Vec<usize> = (0..cards.len()).collect();
+
+    for i in 0..cards.len() {
+        if cards[i].reversed || cards[i].generated {
+            continue;
+        }
+        for j in i + 1..cards.len() {
+            if !cards[j].reversed
+                && !cards[j].generated
+                && jaccard(&prints[i], &prints[j]) >= threshold
+            {
+                let (a, b) = (find(&mut parents, i), find(&mut parents, j));
+                parents[b] = a;
+            }
+        }
+    }
+
+    let mut groups: Vec<Vec<usize>> = vec![vec![]; cards.len()];
+    for i in 0..cards.len() {
+        let root = find(&mut parents, i);
+        groups[root].push(i);
+    }
+    groups.retain(|g| g.len() > 1);
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn rephrased_questions() {
+        let a = fingerprint("What is a memory palace?");
+        let b = fingerprint("Define memory palace");
+        let c = fingerprint("Who invented the memory palace?");
+        let d = fingerprint("What is the capital of India?");
+        assert!((jaccard(&a, &b) - 1.0).abs() < f32::EPSILON);
+        assert!(jaccard(&a, &c) > 0.4);
+        assert!(jaccard(&a, &d) < 0.2);
+    }
+}
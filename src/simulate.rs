@@ -0,0 +1,152 @@
+//! Simulates days of reviews with a virtual learner, to compare scheduling settings.
+//!
+//! The learner remembers a card with the probability predicted by FSRS,
+//! with the stability scaled by `stability_scale` to model a learner forgetting
+//! faster or slower than the scheduler expects.
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use crate::{fsrs::FSRSParams, fsrs::Grade, next_state};
+
+pub struct Options {
+    pub days: u32,
+    pub new_per_day: usize,
+    pub retention: f32,
+    pub stability_scale: f32,
+    pub seed: u64,
+}
+
+/// A simulated card, with the day it was last reviewed on
+#[derive(Debug, Copy, Clone)]
+pub struct SimCard {
+    pub last_reviewed: Option<(f32, FSRSParams)>,
+    /// Seconds each review of the card takes
+    pub seconds: f32,
+}
+
+/// Results for one simulated day
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Day {
+    /// Number of times any card was shown
+    pub reviews: usize,
+    pub new: usize,
+    /// Number of previously seen cards which were due
+    pub due: usize,
+    /// Number of due cards which were remembered
+    pub passed: usize,
+    /// Average probability of recalling any introduced card, at the end of the day
+    pub retention: f32,
+    /// Time all the reviews take, from the seconds of each card
+    pub seconds: f32,
+}
+
+fn grade(rng: &mut StdRng, recalled: bool) -> Grade {
+    if !recalled {
+        return Grade::Again;
+    }
+    match rng.random_range(0..20) {
+        0..3 => Grade::Hard,
+        3..18 => Grade::Good,
+        _ => Grade::Easy,
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+pub fn run(mut cards: Vec<SimCard>, options: &Options) -> Vec<Day> {
+    let mut rng = StdRng::seed_from_u64(options.seed);
+    let true_recall = |fsrs: FSRSParams, elapsed: f32| {
+        FSRSParams {
+            stability: fsrs.stability * options.stability_scale,
+            ..fsrs
+        }
+        .recall_probability(elapsed)
+    };
+
+    let mut res = vec![];
+    for today in 0..options.days {
+        let today = today as f32;
+        let mut day = Day::default();
+        for card in &mut cards {
+            let (recall, mut last) = match card.last_reviewed {
+                Some((t, fsrs)) => {
+                    if fsrs.recall_probability(today - t) >= options.retention {
+                        continue;
+                    }
+                    (true_recall(fsrs, today - t), Some((today - t, fsrs)))
+                }
+                None if day.new < options.new_per_day => {
+                    day.new += 1;
+                    (0.0, None)
+                }
+                None => continue,
+            };
+            let recalled = rng.random::<f32>() < recall;
+            day.reviews += 1;
+            day.seconds += card.seconds;
+            if last.is_some() {
+                day.due += 1;
+                day.passed += usize::from(recalled);
+            }
+            // Failed cards are shown again on the same day until they pass
+            let mut grade = grade(&mut rng, recalled);
+            while grade == Grade::Again {
+                last = Some((0.0, next_state(last, grade)));
+                day.reviews += 1;
+                day.seconds += card.seconds;
+                grade = grade_after_failure(&mut rng);
+            }
+            card.last_reviewed = Some((today, next_state(last, grade)));
+        }
+
+        let introduced: Vec<f32> = cards
+            .iter()
+            .filter_map(|c| c.last_reviewed)
+            .map(|(t, fsrs)| true_recall(fsrs, today + 1.0 - t))
+            .collect();
+        if !introduced.is_empty() {
+            day.retention = introduced.iter().sum::<f32>() / introduced.len() as f32;
+        }
+        res.push(day);
+    }
+    res
+}
+
+/// Right after being shown the answer, the learner usually remembers the card
+fn grade_after_failure(rng: &mut StdRng) -> Grade {
+    let recalled = rng.random::<f32>() < 0.9;
+    grade(rng, recalled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(seed: u64) -> Options {
+        Options {
+            days: 60,
+            new_per_day: 10,
+            retention: 0.9,
+            stability_scale: 1.0,
+            seed,
+        }
+    }
+
+    #[test]
+    pub fn deterministic() {
+        let cards = vec![
+            SimCard {
+                last_reviewed: None,
+                seconds: 8.0,
+            };
+            200
+        ];
+        let a = run(cards.clone(), &options(1));
+        assert_eq!(a, run(cards.clone(), &options(1)));
+        assert_ne!(a, run(cards, &options(2)));
+
+        assert_eq!(a.iter().map(|d| d.new).sum::<usize>(), 200);
+        assert!(a.iter().all(|d| d.retention <= 1.0));
+        assert!(a.iter().all(|d| {
+            (d.seconds - f32::from(u16::try_from(d.reviews).unwrap()) * 8.0).abs() < 1e-3
+        }));
+    }
+}
@@ -0,0 +1,72 @@
+//! Snoozing cards for a few hours, which leaves them out of reviews until later in the day, and
+//! burying them, which leaves them out until the next day
+use std::time::{Duration, SystemTime};
+
+use crate::CardId;
+
+/// Leaves a card out of reviews for the given number of hours, returning when it is back
+pub fn snooze(sqlite: &rusqlite::Connection, id: CardId, hours: f32) -> anyhow::Result<SystemTime> {
+    leave_out(
+        sqlite,
+        id,
+        SystemTime::now() + Duration::from_secs_f32(hours * 60.0 * 60.0),
+    )
+}
+
+/// The start of the day after `time`, in UTC like the days of the review log
+fn next_day(time: SystemTime) -> SystemTime {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    SystemTime::UNIX_EPOCH + Duration::from_secs((secs / 86400 + 1) * 86400)
+}
+
+/// Leaves a card out of reviews for the rest of the day, returning when it is back
+pub fn bury(sqlite: &rusqlite::Connection, id: CardId) -> anyhow::Result<SystemTime> {
+    leave_out(sqlite, id, next_day(SystemTime::now()))
+}
+
+fn leave_out(
+    sqlite: &rusqlite::Connection,
+    id: CardId,
+    until: SystemTime,
+) -> anyhow::Result<SystemTime> {
+    sqlite.execute(
+        "insert or replace into snooze(card, until) values (?1, ?2)",
+        (
+            id.as_int(),
+            until.duration_since(SystemTime::UNIX_EPOCH)?.as_secs(),
+        ),
+    )?;
+    Ok(until)
+}
+
+/// Whether a card is snoozed right now
+pub fn is_snoozed(sqlite: &rusqlite::Connection, id: CardId) -> anyhow::Result<bool> {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_secs();
+    let until: Option<u64> = sqlite
+        .query_row(
+            "select until from snooze where card = ?1",
+            [id.as_int()],
+            |row| row.get(0),
+        )
+        .ok();
+    Ok(until.is_some_and(|until| until > now))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn buried_until_midnight() {
+        let day = Duration::from_hours(24);
+        let start = SystemTime::UNIX_EPOCH + 3 * day;
+        assert_eq!(next_day(start), start + day);
+        assert_eq!(next_day(start + Duration::from_secs(1)), start + day);
+        assert_eq!(next_day(start + day - Duration::from_secs(1)), start + day);
+    }
+}
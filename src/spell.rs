@@ -0,0 +1,363 @@
+//! A small spell checker reading [hunspell][hunspell] dictionaries.
+//!
+//! Only the subset of the affix format needed to expand dictionary words is supported,
+//! that is plain `PFX` and `SFX` rules. Compounding, suggestions and morphology are ignored.
+//! Dictionaries may be in UTF-8, ISO8859-1 or ISO8859-15, as named by the `SET` line.
+//!
+//! [hunspell]: https://manpages.debian.org/unstable/hunspell/hunspell.5.en.html
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, anyhow, bail};
+
+use crate::CardBody;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum FlagType {
+    Ascii,
+    Long,
+    Num,
+    Utf8,
+}
+
+impl FlagType {
+    fn split(self, flags: &str) -> Vec<String> {
+        match self {
+            Self::Ascii | Self::Utf8 => flags.chars().map(String::from).collect(),
+            Self::Long => flags
+                .chars()
+                .collect::<Vec<_>>()
+                .chunks(2)
+                .map(|c| c.iter().collect())
+                .collect(),
+            Self::Num => flags.split(',').map(String::from).collect(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Affix {
+    flag: String,
+    prefix: bool,
+    strip: String,
+    add: String,
+    condition: String,
+}
+
+impl Affix {
+    /// Checks the affix condition, which is a tiny subset of regular expressions
+    /// consisting of characters, `.` and bracketed (possibly negated) character classes
+    fn matches(&self, word: &str) -> bool {
+        let mut pattern = vec![];
+        let mut chars = self.condition.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '.' => pattern.push((true, String::new())),
+                '[' => {
+                    let mut class: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                    let negated = class.starts_with('^');
+                    if negated {
+                        class.remove(0);
+                    }
+                    pattern.push((negated, class));
+                }
+                c => pattern.push((false, c.to_string())),
+            }
+        }
+
+        let word: Vec<char> = word.chars().collect();
+        if pattern.len() > word.len() {
+            return false;
+        }
+        let offset = if self.prefix {
+            0
+        } else {
+            word.len() - pattern.len()
+        };
+        pattern
+            .iter()
+            .zip(&word[offset..])
+            .all(|((negated, class), c)| class.contains(*c) != *negated)
+    }
+
+    fn apply(&self, word: &str) -> Option<String> {
+        if !self.matches(word) {
+            return None;
+        }
+        if self.prefix {
+            let stem = word.strip_prefix(self.strip.as_str())?;
+            Some(format!("{}{stem}", self.add))
+        } else {
+            let stem = word.strip_suffix(self.strip.as_str())?;
+            Some(format!("{stem}{}", self.add))
+        }
+    }
+}
+
+/// The encoding named by the `SET` line of an affix file, which is ISO8859-1 without one
+fn encoding(aff: &[u8]) -> String {
+    aff.split(|&b| b == b'\n')
+        .filter_map(|line| line.strip_prefix(b"SET"))
+        .find_map(|rest| {
+            let name = String::from_utf8_lossy(rest).trim().to_string();
+            (!name.is_empty()).then_some(name)
+        })
+        .unwrap_or_else(|| "ISO8859-1".to_string())
+}
+
+/// Decodes a dictionary file in `encoding`
+fn decode(data: &[u8], encoding: &str) -> anyhow::Result<String> {
+    // The characters ISO8859-15 has in place of those of ISO8859-1
+    const LATIN_9: [(u8, char); 8] = [
+        (0xa4, '€'),
+        (0xa6, 'Š'),
+        (0xa8, 'š'),
+        (0xb4, 'Ž'),
+        (0xb8, 'ž'),
+        (0xbc, 'Œ'),
+        (0xbd, 'œ'),
+        (0xbe, 'Ÿ'),
+    ];
+    let latin = |replaced: &[(u8, char)]| {
+        data.iter()
+            .map(|&b| {
+                replaced
+                    .iter()
+                    .find(|&&(from, _)| from == b)
+                    .map_or(char::from(b), |&(_, c)| c)
+            })
+            .collect()
+    };
+    match encoding.to_ascii_uppercase().replace('_', "-").as_str() {
+        "UTF-8" | "UTF8" => Ok(String::from_utf8(data.to_vec())?),
+        "ISO8859-1" | "ISO-8859-1" | "LATIN1" => Ok(latin(&[])),
+        "ISO8859-15" | "ISO-8859-15" | "LATIN9" => Ok(latin(&LATIN_9)),
+        _ => bail!("Unsupported dictionary encoding `{encoding}`"),
+    }
+}
+
+/// The set of all words known by a dictionary, with affixes already applied
+#[derive(Debug, Default)]
+pub struct Dictionary {
+    words: HashSet<String>,
+}
+
+impl Dictionary {
+    /// Parses the contents of a `.aff` and `.dic` file pair
+    pub fn parse(aff: &str, dic: &str) -> Self {
+        let mut flag_type = FlagType::Ascii;
+        let mut affixes = vec![];
+        for line in aff.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                ["FLAG", "long", ..] => flag_type = FlagType::Long,
+                ["FLAG", "num", ..] => flag_type = FlagType::Num,
+                ["FLAG", "UTF-8", ..] => flag_type = FlagType::Utf8,
+                // Rule lines have at least 5 fields, headers have exactly 4
+                [kind @ ("PFX" | "SFX"), flag, strip, add, condition, ..] => {
+                    let filter = |s: &str| {
+                        if s == "0" {
+                            String::new()
+                        } else {
+                            s.to_string()
+                        }
+                    };
+                    affixes.push(Affix {
+                        flag: (*flag).to_string(),
+                        prefix: *kind == "PFX",
+                        strip: filter(strip),
+                        // Continuation classes are not supported
+                        add: filter(add.split('/').next().unwrap_or_default()),
+                        condition: (*condition).to_string(),
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        let mut words = HashSet::new();
+        // The first line is only an approximate word count
+        for entry in dic.lines().skip(1) {
+            let entry = entry.split_whitespace().next().unwrap_or_default();
+            let (word, flags) = entry.split_once('/').unwrap_or((entry, ""));
+            if word.is_empty() {
+                continue;
+            }
+            for flag in flag_type.split(flags) {
+                for affix in affixes.iter().filter(|a| a.flag == flag) {
+                    if let Some(w) = affix.apply(word) {
+                        words.insert(w.to_lowercase());
+                    }
+                }
+            }
+            words.insert(word.to_lowercase());
+        }
+        Self { words }
+    }
+
+    /// Finds and loads the dictionary for `lang`, such as `en` or `de_DE`
+    pub fn load(lang: &str) -> anyhow::Result<Self> {
+        let dic = find_dictionary(lang)
+            .ok_or_else(|| anyhow!("Could not find a hunspell dictionary for `{lang}`"))?;
+        let aff_path = dic.with_extension("aff");
+        let aff = std::fs::read(&aff_path)
+            .with_context(|| format!("Could not read {}", aff_path.display()))?;
+        let data =
+            std::fs::read(&dic).with_context(|| format!("Could not read {}", dic.display()))?;
+        let encoding = encoding(&aff);
+        let text = |data| decode(data, &encoding).with_context(|| dic.display().to_string());
+        Ok(Self::parse(&text(&aff)?, &text(&data)?))
+    }
+
+    pub fn contains(&self, word: &str) -> bool {
+        self.words.contains(&word.to_lowercase())
+    }
+}
+
+fn dictionary_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = std::env::var("DICPATH")
+        .map(|p| std::env::split_paths(&p).collect())
+        .unwrap_or_default();
+    if let Ok(home) = std::env::var("HOME") {
+        dirs.push(Path::new(&home).join(".local/share/hunspell"));
+    }
+    dirs.extend(
+        [
+            "/usr/share/hunspell",
+            "/usr/share/myspell",
+            "/usr/share/myspell/dicts",
+        ]
+        .map(PathBuf::from),
+    );
+    dirs
+}
+
+/// Looks for `<lang>.dic`, falling back to any regional variant like `<lang>_US.dic`
+fn find_dictionary(lang: &str) -> Option<PathBuf> {
+    for dir in dictionary_dirs() {
+        let exact = dir.join(format!("{lang}.dic"));
+        if exact.exists() {
+            return Some(exact);
+        }
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut variants: Vec<PathBuf> = entries
+            .filter_map(|e| Some(e.ok()?.path()))
+            .filter(|p| {
+                p.extension().is_some_and(|e| e == "dic")
+                    && p.file_stem()
+                        .and_then(|s| s.to_str())
+                        .is_some_and(|s| s.starts_with(&format!("{lang}_")))
+            })
+            .collect();
+        variants.sort();
+        if let Some(p) = variants.into_iter().next() {
+            return Some(p);
+        }
+    }
+    None
+}
+
+/// Splits text into words, keeping apostrophes inside of words
+fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .map(|w| w.trim_matches('\''))
+        .filter(|w| !w.is_empty() && w.chars().all(|c| c.is_alphabetic() || c == '\''))
+}
+
+/// A word not found in any dictionary
+pub struct Typo {
+    pub file: PathBuf,
+    pub line: usize,
+    pub word: String,
+}
+
+/// Lists unknown words in the cards, in the order in which they appear
+pub fn check_cards(cards: &[CardBody], dicts: &[Dictionary]) -> Vec<Typo> {
+    let mut seen = HashSet::new();
+    let mut res = vec![];
+    for card in cards.iter().filter(|c| !c.generated) {
+        // Reversed cards share their text with the original, so only the
+        // location is used to tell the sides apart
+        let (marker, body) = if card.reversed {
+            (&card.back, &card.front)
+        } else {
+            (&card.front, &card.back)
+        };
+        let lines = std::iter::once(marker.as_str()).chain(body.lines());
+        for (offset, text) in lines.enumerate() {
+            let line = card.line + offset;
+            for word in words(text) {
+                if dicts.iter().any(|d| d.contains(word)) {
+                    continue;
+                }
+                if seen.insert((card.file.clone(), line, word.to_string())) {
+                    res.push(Typo {
+                        file: card.file.clone(),
+                        line,
+                        word: word.to_string(),
+                    });
+                }
+            }
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AFF: &str = "SET UTF-8
+SFX S Y 2
+SFX S 0 s [^y]
+SFX S y ies y
+PFX U Y 1
+PFX U 0 un .
+";
+    const DIC: &str = "3
+card/S
+study/SU
+happy/U
+";
+
+    #[test]
+    pub fn affixes() {
+        let dict = Dictionary::parse(AFF, DIC);
+        for word in [
+            "card", "cards", "study", "studies", "unstudy", "unhappy", "Card",
+        ] {
+            assert!(dict.contains(word), "{word}");
+        }
+        for word in ["studys", "happies", "uncard"] {
+            assert!(!dict.contains(word), "{word}");
+        }
+    }
+
+    #[test]
+    pub fn latin_1() {
+        let aff = b"SET ISO8859-1\nSFX N Y 1\nSFX N 0 n .\n";
+        let dic = b"1\nM\xfcller/N\nStra\xdfe\n";
+        let set = encoding(aff);
+        assert_eq!(set, "ISO8859-1");
+        let dict = Dictionary::parse(&decode(aff, &set).unwrap(), &decode(dic, &set).unwrap());
+        for word in ["Müller", "müllern", "Straße"] {
+            assert!(dict.contains(word), "{word}");
+        }
+        assert_eq!(decode(b"\xa4", "ISO8859-15").unwrap(), "€");
+        assert_eq!(encoding(b"FLAG long\n"), "ISO8859-1");
+        assert!(decode(b"\xfc", "UTF-8").is_err());
+        assert!(decode(b"", "KOI8-R").is_err());
+    }
+
+    #[test]
+    pub fn tokenize() {
+        assert_eq!(
+            words("_Don't_ forget: 42 cards, e.g. x2").collect::<Vec<_>>(),
+            ["Don't", "forget", "cards", "e", "g"]
+        );
+    }
+}
@@ -0,0 +1,328 @@
+//! Statistics about the whole review history: reviews per day, a heatmap calendar of them,
+//! and how well cards are actually remembered
+use std::collections::BTreeMap;
+
+use crate::{CardBody, budget, config::Config, cost, fsrs::Version, ui::Stylize};
+
+const WEEKS: i64 = 26;
+const SHADES: [char; 5] = ['·', '░', '▒', '▓', '█'];
+
+/// The number of reviews on each day, counted in days since the Unix epoch in UTC
+fn reviews_per_day(sqlite: &mut rusqlite::Connection) -> anyhow::Result<BTreeMap<i64, usize>> {
+    let mut statement =
+        sqlite.prepare("select last_reviewed / 86400000, count(*) from review group by 1")?;
+    let res = statement
+        .query_map((), |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+    Ok(res)
+}
+
+/// Rows of a calendar of the last weeks up to `today`, one for each day of the week,
+/// shading each day by how many reviews there were
+fn heatmap(per_day: &BTreeMap<i64, usize>, today: i64, weeks: i64) -> Vec<String> {
+    // The Unix epoch was a Thursday, so this is 0 on Mondays
+    let weekday = |day: i64| (day + 3).rem_euclid(7);
+    let start = today - weekday(today) - (weeks - 1) * 7;
+    let max = per_day
+        .range(start..=today)
+        .map(|(_, &n)| n)
+        .max()
+        .unwrap_or_default();
+    ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"]
+        .iter()
+        .enumerate()
+        .map(|(row, name)| {
+            let mut line = format!("{name} ");
+            for week in 0..weeks {
+                let day = start + week * 7 + i64::try_from(row).unwrap_or_default();
+                if day > today {
+                    break;
+                }
+                let n = per_day.get(&day).copied().unwrap_or_default();
+                // Any review at all gets at least the lightest shade
+                let shade = if n == 0 { 0 } else { 1 + (n * 4 - 1) / max };
+                line.push(SHADES[shade]);
+            }
+            line
+        })
+        .collect()
+}
+
+/// Prints the reviews per day, a heatmap of the last half year, the true retention, and the
+/// average stability and difficulty of the reviewed cards, and the new cards budget and
+/// projected workload if they are limited
+pub fn overview(sqlite: &mut rusqlite::Connection, config: &Config) -> anyhow::Result<()> {
+    let per_day = reviews_per_day(sqlite)?;
+    let today = i64::try_from(
+        std::time::SystemTime::now()
+            .duration_since(std::time::SystemTime::UNIX_EPOCH)?
+            .as_secs()
+            / 86400,
+    )?;
+
+    println!("{}", format!("Reviews in the last {WEEKS} weeks").bold());
+    for line in heatmap(&per_day, today, WEEKS) {
+        println!("{line}");
+    }
+    println!(
+        "{}\n",
+        format!("less {} more", SHADES[1..].iter().collect::<String>()).muted()
+    );
+
+    let last_month: usize = per_day.range(today - 29..=today).map(|(_, n)| n).sum();
+    println!(
+        "reviews today: {}",
+        per_day.get(&today).copied().unwrap_or_default()
+    );
+    #[allow(clippy::cast_precision_loss)]
+    let average = last_month as f32 / 30.0;
+    println!("average reviews per day, last 30 days: {average:.1}");
+
+    // Only reviews of cards which were due count, not new cards or those shown again on the same day
+    let (passed, total): (usize, usize) = sqlite.query_row(
+        "select coalesce(sum(grade > 1), 0), count(*) from review_log where elapsed_days >= 1",
+        (),
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    if total > 0 {
+        #[allow(clippy::cast_precision_loss)]
+        let retention = passed as f32 / total as f32 * 100.0;
+        println!("true retention: {retention:.1}% of {total} reviews");
+    } else {
+        println!("true retention: {}", "no reviews of due cards yet".muted());
+    }
+
+    let (cards, stability, difficulty): (usize, Option<f64>, Option<f64>) = sqlite.query_row(
+        "select count(*), avg(stability), avg(difficulty) from review
+             where rowid in (
+                 select (select rowid from review where card = r.card
+                             order by last_reviewed desc, rowid desc limit 1)
+                 from review r group by card
+             )",
+        (),
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    if let (Some(stability), Some(difficulty)) = (stability, difficulty) {
+        println!("average stability: {stability:.1} days, over {cards} cards");
+        println!("average difficulty: {difficulty:.2}");
+    }
+
+    workload(sqlite, config)
+}
+
+/// Prints the new cards budget, and the projected workload, if either is limited in the config
+fn workload(sqlite: &mut rusqlite::Connection, config: &Config) -> anyhow::Result<()> {
+    let budget = budget::Budget::from_config(config)?;
+    let max_minutes = cost::max_daily_minutes(config)?;
+    if budget.is_none() && max_minutes.is_none() {
+        return Ok(());
+    }
+    if let Some(budget) = &budget {
+        let introduced = budget::introduced(sqlite)?;
+        println!(
+            "\n{}\n{} {introduced}/{}",
+            "New cards in the last 7 days".bold(),
+            budget::bar(introduced, budget.per_week, 30),
+            budget.per_week
+        );
+    } else {
+        println!();
+    }
+    let per_week = budget.as_ref().map_or(0, |b| b.per_week);
+    let projected = budget::project(sqlite, per_week, config.retention(None)?)?;
+    let reviews = projected.reviews;
+    match budget.and_then(|b| b.ceiling) {
+        Some(ceiling) if reviews > ceiling => println!(
+            "projected reviews per day, next 30 days: {} of at most {ceiling}, so no new cards are introduced",
+            format!("{reviews:.1}").bad()
+        ),
+        Some(ceiling) => {
+            println!("projected reviews per day, next 30 days: {reviews:.1} of at most {ceiling}");
+        }
+        None => println!("projected reviews per day, next 30 days: {reviews:.1}"),
+    }
+    match (projected.minutes, max_minutes) {
+        (Some(minutes), Some(max)) if minutes > max => println!(
+            "projected minutes per day, next 30 days: {} of at most {max}",
+            format!("{minutes:.1}").bad()
+        ),
+        (Some(minutes), Some(max)) => {
+            println!("projected minutes per day, next 30 days: {minutes:.1} of at most {max}");
+        }
+        (Some(minutes), None) => println!("projected minutes per day, next 30 days: {minutes:.1}"),
+        (None, _) => println!(
+            "projected minutes per day, next 30 days: {}",
+            "no answer times yet".muted()
+        ),
+    }
+    Ok(())
+}
+
+/// How cards are grouped to compare their forgetting
+#[derive(Debug, Copy, Clone, PartialEq, clap::ValueEnum)]
+pub enum GroupBy {
+    /// The `#tags` of each card, so a card can be in several groups
+    Tag,
+    /// The file of each card
+    Deck,
+}
+
+/// Groups need this many reviews of due cards to be compared
+const MIN_REVIEWS: usize = 10;
+
+/// The recall predicted for a review if the card's stability had been `scale` times as large
+fn scaled_recall(recall: f32, scale: f32) -> f32 {
+    // The time elapsed, in units of the card's stability
+    let time = Version::Fsrs6.interval(1.0, recall.clamp(0.001, 0.999));
+    Version::Fsrs6.recall_probability(scale, time)
+}
+
+/// Fits the forgetting curve of a group, as the factor by which the stability of its cards
+/// would have to be scaled to best predict whether each review, given as whether it was
+/// remembered and the recall predicted for it, was remembered
+fn fit_stability_scale(reviews: &[(bool, f32)]) -> f32 {
+    let loss = |log_scale: f32| -> f32 {
+        reviews
+            .iter()
+            .map(|&(remembered, recall)| {
+                let r = scaled_recall(recall, log_scale.exp()).clamp(1e-4, 1.0 - 1e-4);
+                -(if remembered { r } else { 1.0 - r }).ln()
+            })
+            .sum()
+    };
+    // The loss has a single minimum, so a ternary search over the logarithm finds it
+    let (mut low, mut high) = (0.01_f32.ln(), 100_f32.ln());
+    for _ in 0..60 {
+        let a = low + (high - low) / 3.0;
+        let b = high - (high - low) / 3.0;
+        if loss(a) < loss(b) {
+            high = b;
+        } else {
+            low = a;
+        }
+    }
+    f32::midpoint(low, high).exp()
+}
+
+/// The groups a card is in
+fn groups(card: &CardBody, group_by: GroupBy) -> Vec<String> {
+    match group_by {
+        GroupBy::Tag if card.tags.is_empty() => vec!["(untagged)".to_string()],
+        GroupBy::Tag => card.tags.clone(),
+        GroupBy::Deck => vec![card.deck.clone()],
+    }
+}
+
+/// Compares how often the due cards of each group were remembered to what FSRS predicted,
+/// starting with the groups where they differ most
+#[allow(clippy::cast_precision_loss)]
+pub fn forgetting(
+    sqlite: &mut rusqlite::Connection,
+    cards: &[CardBody],
+    group_by: GroupBy,
+) -> anyhow::Result<()> {
+    let mut reviews: BTreeMap<String, Vec<(bool, f32)>> = BTreeMap::new();
+    let mut statement = sqlite.prepare(
+        "select grade, recall from review_log where card = ?1 and elapsed_days >= 1 and recall is not null",
+    )?;
+    for card in cards {
+        let answers = statement
+            .query_map([card.id.as_int()], |row| {
+                Ok((row.get::<_, u8>(0)? > 1, row.get::<_, f32>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        if answers.is_empty() {
+            continue;
+        }
+        for group in groups(card, group_by) {
+            reviews.entry(group).or_default().extend(&answers);
+        }
+    }
+
+    let mut rows: Vec<(&str, usize, f32, f32, f32)> = reviews
+        .iter()
+        .filter(|(_, r)| r.len() >= MIN_REVIEWS)
+        .map(|(group, r)| {
+            let actual = r.iter().filter(|(remembered, _)| *remembered).count() as f32;
+            let predicted: f32 = r.iter().map(|(_, recall)| recall).sum();
+            let n = r.len() as f32;
+            (
+                group.as_str(),
+                r.len(),
+                actual / n,
+                predicted / n,
+                fit_stability_scale(r),
+            )
+        })
+        .collect();
+    if rows.is_empty() {
+        println!(
+            "{}",
+            format!("No group has {MIN_REVIEWS} reviews of due cards yet").muted()
+        );
+        return Ok(());
+    }
+    rows.sort_by(|a, b| (b.2 - b.3).abs().total_cmp(&(a.2 - a.3).abs()));
+
+    let width = rows
+        .iter()
+        .map(|r| r.0.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max(5);
+    println!(
+        "{}",
+        format!(
+            "{:width$}  reviews  actual  predicted  stability",
+            match group_by {
+                GroupBy::Tag => "tag",
+                GroupBy::Deck => "deck",
+            }
+        )
+        .bold()
+    );
+    for (group, n, actual, predicted, scale) in rows {
+        let line = format!(
+            "{group:width$}  {n:>7}  {:>5.1}%  {:>8.1}%  {scale:>8.2}x",
+            actual * 100.0,
+            predicted * 100.0
+        );
+        // Forgetting much faster than predicted is worth a closer look
+        if predicted - actual > 0.05 {
+            println!("{}", line.bad());
+        } else if actual - predicted > 0.05 {
+            println!("{}", line.good());
+        } else {
+            println!("{line}");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn calendar() {
+        // Day 20_000 since the epoch was a Friday, and 19_989 the Monday of the week before
+        let per_day = BTreeMap::from([(19_989, 1), (19_996, 8), (19_998, 4)]);
+        let rows = heatmap(&per_day, 20_000, 2);
+        assert_eq!(rows[0], "Mon ░█");
+        assert_eq!(rows[2], "Wed ·▒");
+        assert_eq!(rows[4], "Fri ··");
+        assert_eq!(rows[5], "Sat ·");
+    }
+
+    #[test]
+    pub fn forgetting_curve() {
+        assert!((scaled_recall(0.8, 1.0) - 0.8).abs() < 0.001);
+        // Remembering 9 of 10 cards predicted at 90% matches the model
+        let mut reviews = vec![(true, 0.9); 9];
+        reviews.push((false, 0.9));
+        assert!((fit_stability_scale(&reviews) - 1.0).abs() < 0.05);
+        // Remembering only half of them means memories fade much faster
+        let reviews: Vec<(bool, f32)> = (0..10).map(|i| (i % 2 == 0, 0.9)).collect();
+        assert!(fit_stability_scale(&reviews) < 0.3);
+    }
+}
@@ -0,0 +1,85 @@
+//! Suspending cards, which keeps them out of reviews until they are unsuspended, while they stay
+//! in their files and keep their history.
+//!
+//! A card tagged `#suspended` on its marker line is always suspended. With
+//! `suspend_in_files = true` in the config, suspending a card also tags it, so that the state is
+//! seen in the notes and survives losing the database, and the file is what counts: removing the
+//! tag unsuspends the card the next time its file is read
+use std::{collections::BTreeMap, path::Path};
+
+use rusqlite::OptionalExtension;
+
+use crate::{
+    CardBody, CardId,
+    config::{Config, Value},
+    tag,
+};
+
+pub const TAG: &str = "suspended";
+
+/// Whether suspending cards in the vault of `file` writes it to the file
+fn in_files(file: &Path) -> bool {
+    Config::load_for(&[file.to_path_buf()])
+        .is_ok_and(|c| matches!(c.get("suspend_in_files"), Some(Value::Bool(true))))
+}
+
+pub fn suspend(sqlite: &rusqlite::Connection, id: CardId) -> anyhow::Result<()> {
+    sqlite.execute(
+        "insert or ignore into suspended(card) values (?1)",
+        [id.as_int()],
+    )?;
+    Ok(())
+}
+
+/// Lets a card be reviewed again, returning whether it was suspended
+pub fn unsuspend(sqlite: &rusqlite::Connection, id: CardId) -> anyhow::Result<bool> {
+    Ok(sqlite.execute("delete from suspended where card = ?1", [id.as_int()])? > 0)
+}
+
+fn in_db(sqlite: &rusqlite::Connection, id: CardId) -> anyhow::Result<bool> {
+    Ok(sqlite
+        .query_row(
+            "select 1 from suspended where card = ?1",
+            [id.as_int()],
+            |_| Ok(()),
+        )
+        .optional()?
+        .is_some())
+}
+
+fn tagged(card: &CardBody) -> bool {
+    card.tags.iter().any(|t| t == TAG)
+}
+
+pub fn is_suspended(sqlite: &rusqlite::Connection, card: &CardBody) -> anyhow::Result<bool> {
+    Ok(tagged(card) || in_db(sqlite, card.id)?)
+}
+
+/// Writes whether the cards are suspended to their files, where that is set in the config.
+/// The tag is always removed when unsuspending, as it would suspend the card again
+pub fn write(cards: &[CardBody], suspended: bool) -> anyhow::Result<()> {
+    let cards: Vec<CardBody> = cards
+        .iter()
+        .filter(|c| tagged(c) != suspended && (!suspended || in_files(&c.file)))
+        .cloned()
+        .collect();
+    tag::rewrite(&cards, TAG, suspended)?;
+    Ok(())
+}
+
+/// Brings the database in line with the files: cards tagged `#suspended` are suspended, and
+/// cards whose tag was removed are unsuspended where the files are what counts
+pub fn sync(tx: &rusqlite::Transaction, cards: &[CardBody]) -> anyhow::Result<()> {
+    let mut files: BTreeMap<&Path, bool> = BTreeMap::new();
+    for card in cards {
+        if tagged(card) {
+            suspend(tx, card.id)?;
+        } else if *files
+            .entry(&card.file)
+            .or_insert_with(|| in_files(&card.file))
+        {
+            unsuspend(tx, card.id)?;
+        }
+    }
+    Ok(())
+}
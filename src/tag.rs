@@ -0,0 +1,150 @@
+//! Adds or removes `#tag`s on the `REVIEW` lines of cards, editing their files in place
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::File,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::bail;
+
+use crate::{CardBody, config::Config};
+
+/// The tags written as `#tag` in the text of a marker line
+pub fn parse(line: &str) -> Vec<String> {
+    line.split_whitespace()
+        .filter_map(|w| w.strip_prefix('#'))
+        .filter(|t| !t.is_empty() && !t.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+/// The marker line with `tag` added or removed, or `None` if it is already as wanted
+fn retag(line: &str, tag: &str, add: bool) -> Option<String> {
+    let tag = format!("#{tag}");
+    let has_tag = line.split_whitespace().any(|w| w == tag);
+    match (add, has_tag) {
+        (true, false) => Some(format!("{} {tag}", line.trim_end())),
+        (false, true) => Some(
+            line.split(' ')
+                .filter(|w| *w != tag)
+                .collect::<Vec<_>>()
+                .join(" ")
+                .trim_end()
+                .to_string(),
+        ),
+        _ => None,
+    }
+}
+
+/// A new file named after `path` with a random suffix, which no other file had
+pub fn temp_file(path: &Path) -> anyhow::Result<(PathBuf, File)> {
+    loop {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(format!(".{:08x}.tmp", rand::random::<u32>()));
+        match File::create_new(&tmp) {
+            Ok(file) => return Ok((tmp.into(), file)),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Replaces a file atomically, so it is never left half written. Through a symlink, the file
+/// it points to is replaced, and the file keeps its permissions
+pub fn write_atomic(path: &Path, data: &str) -> anyhow::Result<()> {
+    let path = match std::fs::canonicalize(path) {
+        Ok(path) => path,
+        Err(e) if e.kind() == ErrorKind::NotFound => path.to_path_buf(),
+        Err(e) => return Err(e.into()),
+    };
+    let (tmp, mut file) = temp_file(&path)?;
+    let res = (|| {
+        file.write_all(data.as_bytes())?;
+        if let Ok(metadata) = std::fs::metadata(&path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        // On the disk before it replaces the old file, or a crash could leave neither
+        file.sync_all()?;
+        std::fs::rename(&tmp, &path)
+    })();
+    if res.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    Ok(res?)
+}
+
+/// Adds `tag` to, or removes it from, each card, returning the number of cards changed,
+/// counting two sided cards once
+pub fn rewrite(cards: &[CardBody], tag: &str, add: bool) -> anyhow::Result<usize> {
+    let tag = tag.trim_start_matches('#');
+    if tag.is_empty() || tag.contains(char::is_whitespace) {
+        bail!("Invalid tag `{tag}`");
+    }
+
+    // Both sides of a two sided card share their marker line
+    let mut lines: BTreeMap<&Path, BTreeSet<usize>> = BTreeMap::new();
+    for card in cards {
+        lines.entry(&card.file).or_default().insert(card.line);
+    }
+
+    let mut changed = 0;
+    for (file, lines) in lines {
+        let config = Config::load_for(&[file.to_path_buf()])?;
+        let data = std::fs::read_to_string(file)?;
+        let mut res = String::with_capacity(data.len());
+        for (i, line) in data.split_inclusive('\n').enumerate() {
+            if !lines.contains(&(i + 1)) {
+                res.push_str(line);
+                continue;
+            }
+            let (text, ending) = line
+                .strip_suffix("\r\n")
+                .map(|l| (l, "\r\n"))
+                .or_else(|| line.strip_suffix('\n').map(|l| (l, "\n")))
+                .unwrap_or((line, ""));
+            if !text.starts_with(config.marker()) {
+                bail!(
+                    "{}:{} is no longer a card, has the file changed?",
+                    file.display(),
+                    i + 1
+                );
+            }
+            match retag(text, tag, add) {
+                Some(text) => {
+                    res.push_str(&text);
+                    res.push_str(ending);
+                    changed += 1;
+                }
+                None => res.push_str(line),
+            }
+        }
+        write_atomic(file, &res)?;
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn retag_line() {
+        let line = "REVIEW--fguvqzmI: Where is #geo the capital of India?";
+        assert_eq!(
+            retag(line, "asia", true).unwrap(),
+            "REVIEW--fguvqzmI: Where is #geo the capital of India? #asia"
+        );
+        assert_eq!(retag(line, "geo", true), None);
+        assert_eq!(
+            retag(line, "geo", false).unwrap(),
+            "REVIEW--fguvqzmI: Where is the capital of India?"
+        );
+        assert_eq!(retag(line, "asia", false), None);
+        assert_eq!(parse(line), ["geo"]);
+        assert_eq!(
+            parse(": Is # or ## a tag? #week3 #anatomy"),
+            ["week3", "anatomy"]
+        );
+    }
+}
@@ -0,0 +1,106 @@
+//! Placeholders in card text which are filled in each time the card is shown.
+//!
+//! - `{{date}}` is the current date
+//! - `{{deck}}` is the deck of the card
+//! - `{{random:1-10}}` is a random whole number from 1 to 10
+//! - `{{random:x=1-10}}` also names the number, so `{{x}}` repeats it elsewhere on the card
+//!
+//! Unknown placeholders are left as they are.
+use std::{collections::HashMap, fmt::Write, time::SystemTime};
+
+use rand::Rng;
+
+use crate::{CardBody, report::format_date};
+
+/// Values chosen while expanding a card, shared between its front and back
+#[derive(Debug, Default)]
+struct Vars(HashMap<String, String>);
+
+impl Vars {
+    fn random(&mut self, spec: &str, rng: &mut impl Rng) -> Option<String> {
+        let (name, range) = match spec.split_once('=') {
+            Some((name, range)) => (Some(name.trim()), range),
+            None => (None, spec),
+        };
+        let (low, high) = range.split_once('-')?;
+        let (low, high): (i64, i64) = (low.trim().parse().ok()?, high.trim().parse().ok()?);
+        if low > high {
+            return None;
+        }
+        let value = rng.random_range(low..=high).to_string();
+        if let Some(name) = name {
+            self.0.insert(name.to_string(), value.clone());
+        }
+        Some(value)
+    }
+
+    fn expand(&mut self, text: &str, card: &CardBody, rng: &mut impl Rng) -> String {
+        let mut res = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find("{{") {
+            res.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let Some(end) = rest.find("}}") else {
+                break;
+            };
+            let placeholder = rest[2..end].trim();
+            let value = match placeholder {
+                "date" => Some(format_date(SystemTime::now())),
+                "deck" => Some(card.deck.clone()),
+                _ => match placeholder.strip_prefix("random:") {
+                    Some(spec) => self.random(spec, rng),
+                    None => self.0.get(placeholder).cloned(),
+                },
+            };
+            match value {
+                Some(value) => _ = write!(res, "{value}"),
+                None => res.push_str(&rest[..end + 2]),
+            }
+            rest = &rest[end + 2..];
+        }
+        res.push_str(rest);
+        res
+    }
+}
+
+/// The card as it should be shown, with its placeholders filled in
+pub fn expand(card: &CardBody, rng: &mut impl Rng) -> CardBody {
+    let mut vars = Vars::default();
+    CardBody {
+        front: vars.expand(&card.front, card, rng),
+        back: vars.expand(&card.back, card, rng),
+        ..card.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use rand::{SeedableRng, rngs::StdRng};
+
+    use super::*;
+    use crate::CardId;
+
+    #[test]
+    pub fn placeholders() {
+        let card = CardBody {
+            id: CardId([0; 6]),
+            front: "{{deck}}: what is {{random:a=3-3}} times {{random:b=1-9}}? {{unknown}}".into(),
+            back: "{{a}} times {{b}}".into(),
+            file: PathBuf::from("notes/math.md"),
+            line: 1,
+            reversed: false,
+            generated: false,
+            typed: false,
+            tags: vec![],
+            deck: "math".into(),
+        };
+        let card = expand(&card, &mut StdRng::seed_from_u64(0));
+        let b = card.back.strip_prefix("3 times ").unwrap();
+        assert_eq!(
+            card.front,
+            format!("math: what is 3 times {b}? {{{{unknown}}}}")
+        );
+    }
+}
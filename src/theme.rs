@@ -0,0 +1,227 @@
+//! Named sets of styles for the roles text plays, chosen with `theme` in the config, and
+//! changed role by role in its `[colors]` table.
+//!
+//! The dark theme, which is the default, tells good from bad by red and yellow, which some
+//! people can not, so `deuteranopia` uses blue and orange instead, and `high-contrast` only
+//! bold, underlined and reversed text in the terminal's own colors. `light` uses darker colors
+//! which stay readable on a white background
+use std::{fmt::Display, sync::OnceLock};
+
+use anyhow::{anyhow, bail};
+use crossterm::style::{Attribute, Color, ContentStyle, StyledContent, Stylize};
+
+use crate::config::{Config, Value};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Preset {
+    Dark,
+    Light,
+    HighContrast,
+    Deuteranopia,
+}
+
+/// What a piece of styled text is for
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Role {
+    /// The banner at the top of the review screen
+    Title,
+    /// The label before questions
+    Prompt,
+    /// The front of the card being reviewed
+    Question,
+    /// The back of the card being reviewed
+    Answer,
+    /// A hint shown before the answer
+    Hint,
+    /// The grading buttons
+    Grade,
+    /// Wrong answers, and cards doing worse than expected
+    Bad,
+    /// Cards doing better than expected
+    Good,
+    /// Things to look at, like missing files or misspelled words
+    Highlight,
+    /// Help and other secondary text
+    Muted,
+}
+
+/// The roles by their names in the `[colors]` table
+const ROLES: &[(&str, Role)] = &[
+    ("title", Role::Title),
+    ("prompt", Role::Prompt),
+    ("question", Role::Question),
+    ("answer", Role::Answer),
+    ("hint", Role::Hint),
+    ("grade", Role::Grade),
+    ("bad", Role::Bad),
+    ("good", Role::Good),
+    ("highlight", Role::Highlight),
+    ("muted", Role::Muted),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    pub preset: Preset,
+    /// Styles from the `[colors]` table, used instead of those of the preset
+    colors: Vec<(Role, ContentStyle)>,
+}
+
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+static DEFAULT: Theme = Theme {
+    preset: Preset::Dark,
+    colors: Vec::new(),
+};
+
+/// A style like `bold dark_blue on white` or `#e69f00`: attributes, a color, and a
+/// background color after `on`
+fn parse_style(s: &str) -> Option<ContentStyle> {
+    let color = |word: &str| match word.strip_prefix('#') {
+        Some(hex) if hex.len() == 6 => {
+            let channel = |i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+            Some(Color::Rgb {
+                r: channel(0)?,
+                g: channel(2)?,
+                b: channel(4)?,
+            })
+        }
+        Some(_) => None,
+        None => Color::try_from(word.replace("gray", "grey").as_str()).ok(),
+    };
+    let mut style = ContentStyle::new();
+    let mut words = s.split_whitespace();
+    while let Some(word) = words.next() {
+        match word {
+            "bold" => style.attributes.set(Attribute::Bold),
+            "dim" => style.attributes.set(Attribute::Dim),
+            "italic" => style.attributes.set(Attribute::Italic),
+            "underlined" => style.attributes.set(Attribute::Underlined),
+            "reverse" => style.attributes.set(Attribute::Reverse),
+            "plain" => {}
+            "on" => style.background_color = Some(color(words.next()?)?),
+            word => style.foreground_color = Some(color(word)?),
+        }
+    }
+    Some(style)
+}
+
+impl Theme {
+    /// The theme set by `theme` in the config, or the dark one, with the styles of the
+    /// `[colors]` table
+    pub fn from_config(config: &Config) -> anyhow::Result<Self> {
+        let preset = match config.get("theme").map(|v| v.as_str()) {
+            None | Some(Some("default" | "dark")) => Preset::Dark,
+            Some(Some("light")) => Preset::Light,
+            Some(Some("high-contrast")) => Preset::HighContrast,
+            Some(Some("deuteranopia")) => Preset::Deuteranopia,
+            Some(_) => {
+                bail!("`theme` should be one of dark, light, high-contrast or deuteranopia")
+            }
+        };
+        let mut colors = vec![];
+        for (name, value) in config.table("colors") {
+            let role = ROLES
+                .iter()
+                .find(|(n, _)| *n == name)
+                .map(|&(_, role)| role)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Unknown color `colors.{name}`, expected one of {}",
+                        ROLES.iter().map(|(n, _)| *n).collect::<Vec<_>>().join(", ")
+                    )
+                })?;
+            let style = match value {
+                Value::String(s) => parse_style(s),
+                _ => None,
+            }
+            .ok_or_else(|| {
+                anyhow!(
+                    "`colors.{name}` should be a style like \"bold blue\" or \"#e69f00 on black\""
+                )
+            })?;
+            colors.push((role, style));
+        }
+        Ok(Self { preset, colors })
+    }
+
+    /// The theme used from now on, which can only be set once
+    pub fn set(self) {
+        _ = THEME.set(self);
+    }
+
+    pub fn current() -> &'static Self {
+        THEME.get().unwrap_or(&DEFAULT)
+    }
+
+    pub fn style<D: Display>(&self, role: Role, mut content: StyledContent<D>) -> StyledContent<D> {
+        if let Some((_, style)) = self.colors.iter().find(|(r, _)| *r == role) {
+            *content.style_mut() = *style;
+            return content;
+        }
+        match (self.preset, role) {
+            (_, Role::Question | Role::Answer | Role::Hint | Role::Grade)
+            | (Preset::HighContrast, Role::Muted) => content,
+            (Preset::Dark, Role::Title | Role::Bad) => content.red(),
+            (Preset::Dark, Role::Prompt | Role::Good | Role::Highlight) => content.yellow(),
+            (_, Role::Muted) => content.dark_grey(),
+            (Preset::Light, Role::Title | Role::Bad) => content.dark_red(),
+            (Preset::Light, Role::Prompt) => content.dark_blue(),
+            (Preset::Light, Role::Good) => content.dark_green(),
+            (Preset::Light, Role::Highlight) => content.dark_magenta(),
+            (Preset::HighContrast, Role::Title | Role::Bad) => content.bold().reverse(),
+            (Preset::HighContrast, Role::Prompt | Role::Good) => content.bold(),
+            (Preset::HighContrast, Role::Highlight) => content.underlined(),
+            (Preset::Deuteranopia, Role::Title | Role::Good) => content.blue(),
+            (Preset::Deuteranopia, Role::Bad) => content.with(Color::Rgb {
+                r: 0xe6,
+                g: 0x9f,
+                b: 0x00,
+            }),
+            (Preset::Deuteranopia, Role::Prompt | Role::Highlight) => content.cyan(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn presets() {
+        let theme = |s| Theme::from_config(&Config::parse(s).unwrap());
+        assert_eq!(theme("").unwrap().preset, Preset::Dark);
+        assert_eq!(
+            theme("theme = \"high-contrast\"").unwrap().preset,
+            Preset::HighContrast
+        );
+        assert!(theme("theme = \"solarized\"").is_err());
+        // Good and bad never look the same
+        for preset in [
+            Preset::Dark,
+            Preset::Light,
+            Preset::HighContrast,
+            Preset::Deuteranopia,
+        ] {
+            let theme = Theme {
+                preset,
+                colors: vec![],
+            };
+            let styled = |role| theme.style(role, crossterm::style::style("x")).to_string();
+            assert_ne!(styled(Role::Good), styled(Role::Bad));
+        }
+
+        let custom = theme(
+            "theme = \"light\"\n[colors]\ntitle = \"bold #e69f00 on black\"\nanswer = \"cyan\"",
+        )
+        .unwrap();
+        let styled = |role| custom.style(role, crossterm::style::style("x")).to_string();
+        assert_eq!(
+            styled(Role::Title),
+            "\x1b[48;5;0m\x1b[38;2;230;159;0m\x1b[1mx\x1b[0m"
+        );
+        assert_eq!(styled(Role::Answer), "\x1b[38;5;14mx\x1b[39m");
+        assert_eq!(styled(Role::Prompt), "\x1b[38;5;4mx\x1b[39m");
+        assert!(theme("[colors]\nquestion = \"blurple\"").is_err());
+        assert!(theme("[colors]\nbanner = \"red\"").is_err());
+    }
+}
@@ -0,0 +1,125 @@
+//! Installs a systemd user timer which runs `cardsharp notify` every day
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{anyhow, bail};
+
+const NAME: &str = "cardsharp-notify";
+
+/// Checks a time of day like `09:30`
+fn parse_time(time: &str) -> anyhow::Result<(u8, u8)> {
+    let err = || anyhow!("Invalid time `{time}`, expected `HH:MM`");
+    let (hour, minute) = time.split_once(':').ok_or_else(err)?;
+    let (hour, minute): (u8, u8) = (
+        hour.parse().map_err(|_| err())?,
+        minute.parse().map_err(|_| err())?,
+    );
+    if hour > 23 || minute > 59 {
+        return Err(err());
+    }
+    Ok((hour, minute))
+}
+
+/// Quotes an argument for `ExecStart`
+fn quote(arg: &str) -> String {
+    format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn service(exe: &Path, files: &[PathBuf]) -> String {
+    let mut res = String::new();
+    _ = writeln!(
+        res,
+        "[Unit]\nDescription=Remind to review cardsharp cards\n"
+    );
+    _ = writeln!(res, "[Service]\nType=oneshot");
+    // Keep using the same database and config as the current shell
+    for var in ["CARDSHARP_DB", "CARDSHARP_CONFIG"] {
+        if let Ok(value) = std::env::var(var) {
+            _ = writeln!(res, "Environment={}", quote(&format!("{var}={value}")));
+        }
+    }
+    _ = write!(res, "ExecStart={} notify", quote(&exe.to_string_lossy()));
+    if let Some(db) = crate::DB_FILE.get() {
+        _ = write!(res, " --db {}", quote(&db.to_string_lossy()));
+    }
+    for file in files {
+        _ = write!(res, " {}", quote(&file.to_string_lossy()));
+    }
+    res.push('\n');
+    res
+}
+
+fn timer(times: &[(u8, u8)]) -> String {
+    let mut res = String::new();
+    _ = writeln!(res, "[Unit]\nDescription=Daily cardsharp review reminder\n");
+    _ = writeln!(res, "[Timer]");
+    for (hour, minute) in times {
+        _ = writeln!(res, "OnCalendar=*-*-* {hour:02}:{minute:02}:00");
+    }
+    _ = writeln!(res, "Persistent=true\n\n[Install]\nWantedBy=timers.target");
+    res
+}
+
+fn systemctl(args: &[&str]) -> anyhow::Result<()> {
+    let status = Command::new("systemctl")
+        .arg("--user")
+        .args(args)
+        .status()?;
+    if !status.success() {
+        bail!("`systemctl --user {}` failed", args.join(" "));
+    }
+    Ok(())
+}
+
+/// Writes the service and timer units, and enables the timer unless `enable` is false
+pub fn install(times: &[String], files: &[PathBuf], enable: bool) -> anyhow::Result<()> {
+    let times = times
+        .iter()
+        .map(|t| parse_time(t))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if times.is_empty() {
+        bail!("No times given to remind at");
+    }
+    // The timer runs from another directory
+    let files = files
+        .iter()
+        .map(std::fs::canonicalize)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let dir = std::env::var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|_| std::env::var("HOME").map(|h| Path::new(&h).join(".config")))?
+        .join("systemd/user");
+    std::fs::create_dir_all(&dir)?;
+    let exe = std::env::current_exe()?;
+    std::fs::write(dir.join(format!("{NAME}.service")), service(&exe, &files))?;
+    std::fs::write(dir.join(format!("{NAME}.timer")), timer(&times))?;
+    eprintln!("Wrote {NAME}.service and {NAME}.timer to {}", dir.display());
+
+    if enable {
+        systemctl(&["daemon-reload"])?;
+        systemctl(&["enable", "--now", &format!("{NAME}.timer")])?;
+        eprintln!("Enabled {NAME}.timer");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn units() {
+        assert_eq!(parse_time("09:05").unwrap(), (9, 5));
+        assert!(parse_time("24:00").is_err());
+        assert!(parse_time("9").is_err());
+        assert!(
+            timer(&[(9, 5), (18, 30)])
+                .contains("OnCalendar=*-*-* 09:05:00\nOnCalendar=*-*-* 18:30:00\n")
+        );
+        assert_eq!(quote("a \"b\""), "\"a \\\"b\\\"\"");
+    }
+}
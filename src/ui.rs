@@ -1,40 +1,462 @@
-use std::io::{Stdout, Write};
+use std::{
+    fmt::{Display, Write as _},
+    io::{BufRead, IsTerminal, Stdout, Write},
+    path::Path,
+    sync::OnceLock,
+};
 
 use crossterm::{
-    cursor::MoveTo,
-    event::{Event, KeyCode, KeyEvent, KeyModifiers},
+    Command as _,
+    cursor::{MoveTo, RestorePosition, SavePosition},
+    event::Event,
     execute,
-    style::{Print, Stylize},
+    style::{
+        self, ContentStyle, Print, SetAttributes, SetBackgroundColor, SetForegroundColor,
+        StyledContent,
+    },
     terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen, WindowSize},
 };
 
-use crate::{CardBody, fsrs::Grade};
+use anyhow::bail;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    CardBody,
+    config::{Config, Value},
+    diff::{self, Change},
+    fsrs::Grade,
+    grading::{self, Grading, GradingMode},
+    graphics,
+    input::{self, Edit},
+    keymap::{Command, Keymap},
+    list, markdown, open,
+    theme::{Role, Theme},
+};
+
+/// Whether the terminal can move the cursor around, which dumb terminals and logs can not
+pub fn is_dumb_terminal() -> bool {
+    std::env::var("TERM").is_ok_and(|t| t == "dumb")
+}
+
+fn detect_color() -> bool {
+    if std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) || is_dumb_terminal() {
+        return false;
+    }
+    if std::env::var_os("CLICOLOR_FORCE").is_some_and(|v| !v.is_empty() && v != "0") {
+        return true;
+    }
+    std::io::stdout().is_terminal()
+}
+
+/// Whether styles should be printed. Detected once, from the environment and whether stdout is a terminal
+pub fn color_enabled() -> bool {
+    static COLOR: OnceLock<bool> = OnceLock::new();
+    *COLOR.get_or_init(detect_color)
+}
+
+/// Styled text, which is printed plainly when styles are not supported
+pub struct Styled<D: Display>(StyledContent<D>);
+
+impl<D: Display> Display for Styled<D> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if color_enabled() {
+            self.0.fmt(f)
+        } else {
+            self.0.content().fmt(f)
+        }
+    }
+}
+
+/// Like [`crossterm::style::Stylize`], but respecting [`color_enabled`], and with colors
+/// given by the role of the text in the current [`Theme`]
+pub trait Stylize: Display + Sized {
+    fn bold(self) -> Styled<Self> {
+        Styled(style::Stylize::bold(style::style(self)))
+    }
+    fn italic(self) -> Styled<Self> {
+        Styled(style::Stylize::italic(style::style(self)))
+    }
+    fn reverse(self) -> Styled<Self> {
+        Styled(style::Stylize::reverse(style::style(self)))
+    }
+    fn styled(self, role: Role) -> Styled<Self> {
+        Styled(Theme::current().style(role, style::style(self)))
+    }
+    fn title(self) -> Styled<Self> {
+        self.styled(Role::Title)
+    }
+    fn prompt(self) -> Styled<Self> {
+        self.styled(Role::Prompt)
+    }
+    fn bad(self) -> Styled<Self> {
+        self.styled(Role::Bad)
+    }
+    fn good(self) -> Styled<Self> {
+        self.styled(Role::Good)
+    }
+    fn highlight(self) -> Styled<Self> {
+        self.styled(Role::Highlight)
+    }
+    fn muted(self) -> Styled<Self> {
+        self.styled(Role::Muted)
+    }
+}
+
+impl<D: Display> Stylize for D {}
+
+/// Text already styled in places, in the style of `role` everywhere else
+fn paint(text: &str, role: Role) -> String {
+    let style = *Theme::current().style(role, style::style("")).style();
+    if !color_enabled() || style == ContentStyle::new() {
+        return text.to_string();
+    }
+    let mut start = String::new();
+    if let Some(color) = style.foreground_color {
+        _ = SetForegroundColor(color).write_ansi(&mut start);
+    }
+    if let Some(color) = style.background_color {
+        _ = SetBackgroundColor(color).write_ansi(&mut start);
+    }
+    if !style.attributes.is_empty() {
+        _ = SetAttributes(style.attributes).write_ansi(&mut start);
+    }
+    restyle(text, &start)
+}
+
+/// Text with the escape codes `start` starting a style at each line, and after each style
+/// within the text ends
+fn restyle(text: &str, start: &str) -> String {
+    let mut lines = vec![];
+    for mut rest in text.split('\n') {
+        let mut line = start.to_string();
+        while let Some(i) = rest.find("\x1b[") {
+            let end = rest[i + 2..]
+                .find(|c: char| c.is_ascii_alphabetic())
+                .map_or(rest.len(), |j| i + 2 + j + 1);
+            let code = &rest[i..end];
+            line.push_str(&rest[..end]);
+            // Resets of all styles, colors, intensity, italics, underlines or reversing
+            if [
+                "\x1b[m", "\x1b[0m", "\x1b[39m", "\x1b[49m", "\x1b[22m", "\x1b[23m", "\x1b[24m",
+                "\x1b[27m",
+            ]
+            .contains(&code)
+            {
+                line.push_str(start);
+            }
+            rest = &rest[end..];
+        }
+        line.push_str(rest);
+        line.push_str("\x1b[0m");
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+/// The number of columns a grapheme cluster takes up in the terminal. East Asian wide
+/// characters and emoji take up two
+fn grapheme_width(grapheme: &str) -> usize {
+    let Some(c) = grapheme.chars().next() else {
+        return 0;
+    };
+    let wide = matches!(u32::from(c),
+        0x1100..=0x115F
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F900..=0x1F9FF
+        | 0x20000..=0x3FFFD)
+        // Emoji presentation selector
+        || grapheme.contains('\u{FE0F}');
+    if wide { 2 } else { 1 }
+}
+
+/// How much of the hidden text of a cloze shows
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Hide {
+    /// The mask is as wide as the hidden text
+    Length,
+    /// Always `[...]`, which does not tell short answers from long ones
+    Fixed,
+    /// The first letter, and the mask for the rest
+    FirstLetter,
+}
+
+/// How the `_cloze_` deletions in questions are hidden, set with `cloze_hide` and
+/// `cloze_mask` in the config
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ClozeMask {
+    pub hide: Hide,
+    /// The character hidden text is replaced with
+    pub mask: char,
+}
+
+impl Default for ClozeMask {
+    fn default() -> Self {
+        Self {
+            hide: Hide::Length,
+            mask: '_',
+        }
+    }
+}
+
+impl ClozeMask {
+    pub fn from_config(config: &Config) -> anyhow::Result<Self> {
+        let hide = match config.get("cloze_hide").map(|v| v.as_str()) {
+            None | Some(Some("length")) => Hide::Length,
+            Some(Some("fixed")) => Hide::Fixed,
+            Some(Some("first-letter")) => Hide::FirstLetter,
+            Some(_) => bail!("`cloze_hide` should be one of length, fixed or first-letter"),
+        };
+        let mask = match config.get("cloze_mask") {
+            None => '_',
+            Some(value) => {
+                let mut chars = value.as_str().unwrap_or_default().chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => c,
+                    _ => bail!("`cloze_mask` should be a single character"),
+                }
+            }
+        };
+        Ok(Self { hide, mask })
+    }
+
+    /// Replaces the `_cloze_` deletions in a question with the mask
+    pub fn hide(self, ques: &str) -> String {
+        let mut res = String::with_capacity(ques.len());
+        // The graphemes of the cloze being read so far, if in one
+        let mut cloze: Option<Vec<&str>> = None;
+        for grapheme in ques.graphemes(true) {
+            match (&mut cloze, grapheme) {
+                (None, "_") => cloze = Some(vec![]),
+                (Some(hidden), "_") => {
+                    self.push_hidden(&mut res, hidden);
+                    cloze = None;
+                }
+                (Some(hidden), grapheme) => hidden.push(grapheme),
+                (None, grapheme) => res.push_str(grapheme),
+            }
+        }
+        // An unclosed cloze hides the rest of the question
+        if let Some(hidden) = cloze {
+            self.push_hidden(&mut res, &hidden);
+        }
+        res
+    }
+
+    fn push_hidden(self, res: &mut String, hidden: &[&str]) {
+        let masked = |graphemes: &[&str]| {
+            graphemes
+                .iter()
+                .flat_map(|g| std::iter::repeat_n(self.mask, grapheme_width(g)))
+                .collect::<String>()
+        };
+        match (self.hide, hidden) {
+            (Hide::Length, _) => res.push_str(&masked(hidden)),
+            (Hide::Fixed, _) => res.push_str("[...]"),
+            (Hide::FirstLetter, [first, rest @ ..]) => {
+                res.push_str(first);
+                res.push_str(&masked(rest));
+            }
+            (Hide::FirstLetter, []) => {}
+        }
+    }
+}
 
+/// Replaces the `_cloze_` deletions in a question with `_`s as wide as the hidden text
 pub fn hide_cloze(ques: &str) -> String {
-    let mut hidden = false;
-    ques.chars()
-        .filter_map(|c| {
-            if c == '_' {
-                hidden = !hidden;
-                None
-            } else if hidden {
-                Some('_')
+    ClozeMask::default().hide(ques)
+}
+
+/// The width of text in the terminal, ignoring styles
+fn text_width(text: &str) -> usize {
+    text.graphemes(true).map(grapheme_width).sum()
+}
+
+/// Text without the escape codes styling it
+fn strip_styles(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' && chars.next_if_eq(&'[').is_some() {
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            res.push(c);
+        }
+    }
+    res
+}
+
+/// The rows text takes up in `columns`, including where long lines wrap. Lines with an image
+/// take up `image_rows`
+fn text_rows(text: &str, columns: usize, image_rows: usize) -> usize {
+    text.lines()
+        .map(|line| {
+            if ["\x1b_G", "\x1bP", "\x1b]1337"]
+                .iter()
+                .any(|s| line.contains(s))
+            {
+                image_rows
             } else {
-                Some(c)
+                text_width(&strip_styles(line))
+                    .div_ceil(columns.max(1))
+                    .max(1)
             }
         })
-        .collect()
+        .sum()
 }
 
-fn title(stdout: &mut Stdout, winsize: &WindowSize) -> anyhow::Result<()> {
-    let header_text = "CARDSHARP\r\n\n";
+/// The lines from `scroll` on which fit in `available` rows, given the rows each line takes up.
+/// Everything is shown if it fits, and otherwise a row is left for showing where the lines are.
+/// Scrolling stops once the last line is in view
+fn scroll_window(rows: &[usize], scroll: usize, available: usize) -> std::ops::Range<usize> {
+    let fits =
+        |range: std::ops::Range<usize>, available| rows[range].iter().sum::<usize>() <= available;
+    if fits(0..rows.len(), available) {
+        return 0..rows.len();
+    }
+    let available = available.saturating_sub(1);
+    let last = (0..rows.len())
+        .find(|&start| fits(start..rows.len(), available))
+        .unwrap_or(rows.len() - 1);
+    let start = scroll.min(last);
+    let mut end = start + 1;
+    while end < rows.len() && fits(start..end + 1, available) {
+        end += 1;
+    }
+    start..end
+}
+
+/// Which lines of a back too long for the screen are in view
+#[derive(Default)]
+struct Scroll {
+    /// The first line in view
+    start: usize,
+    /// The number of lines in view, when last printed
+    page: usize,
+}
+
+impl Scroll {
+    /// Prints the lines in view in `available` rows, followed by where they are if not all fit.
+    /// Lines wrap at `columns`, and those with an image take up `image_rows`
+    fn print(
+        &mut self,
+        lines: &[String],
+        available: usize,
+        (columns, image_rows): (usize, usize),
+        keys: &Keymap,
+    ) {
+        let rows: Vec<usize> = lines
+            .iter()
+            .map(|l| text_rows(l, columns, image_rows))
+            .collect();
+        let window = scroll_window(&rows, self.start, available);
+        (self.start, self.page) = (window.start, window.len());
+        print!("{}", lines[window.clone()].join("\n"));
+        if window.len() < lines.len() {
+            let help = keys.help(&[
+                (&[Command::ScrollUp, Command::ScrollDown], "scroll"),
+                (&[Command::PageUp, Command::PageDown], "turn pages"),
+            ]);
+            let position = format!(
+                "lines {}–{} of {}, {help}",
+                window.start + 1,
+                window.end,
+                lines.len()
+            );
+            print!("\n{}", position.muted());
+        }
+    }
+
+    /// Scrolls for the commands which do, ignoring others
+    fn command(&mut self, command: Command) {
+        match command {
+            Command::ScrollDown => self.start += 1,
+            Command::ScrollUp => self.start = self.start.saturating_sub(1),
+            Command::PageDown => self.start += self.page.max(1),
+            Command::PageUp => self.start = self.start.saturating_sub(self.page.max(1)),
+            _ => {}
+        }
+    }
+}
+
+/// As much of the progress of the session as fits next to the banner, dropping the last parts first
+fn fit_status(header: &str, status: &str, columns: usize) -> String {
+    let mut parts: Vec<&str> = status.split(" · ").filter(|p| !p.is_empty()).collect();
+    loop {
+        if parts.is_empty() {
+            return String::new();
+        }
+        let text = format!(" · {}", parts.join(" · "));
+        if text_width(header) + text_width(&text) <= columns {
+            return text;
+        }
+        parts.pop();
+    }
+}
+
+/// The buttons in rows which fit in `columns`, with the selected one highlighted
+fn button_rows(help: &[String], selected: usize, columns: usize) -> String {
+    let mut res = String::new();
+    let mut used = 0;
+    for (i, help) in help.iter().enumerate() {
+        let width = text_width(help) + 2;
+        if used > 0 && used + 1 + width > columns {
+            res.push('\n');
+            used = 0;
+        } else if used > 0 {
+            res.push(' ');
+            used += 1;
+        }
+        let button = format!(" {help} ").styled(Role::Grade);
+        if i == selected {
+            _ = write!(res, "{}", button.reverse());
+        } else {
+            _ = write!(res, "{button}");
+        }
+        used += width;
+    }
+    res
+}
+
+/// Prints the banner, followed by the progress of the session
+fn title(
+    stdout: &mut Stdout,
+    winsize: &WindowSize,
+    read_only: bool,
+    status: &str,
+) -> anyhow::Result<()> {
+    let header_text = if read_only {
+        "CARDSHARP (READ ONLY)"
+    } else {
+        "CARDSHARP"
+    };
+    let status_text = fit_status(header_text, status, usize::from(winsize.columns));
+    let width = text_width(header_text) + text_width(&status_text);
     execute!(
         stdout,
         MoveTo(
-            (winsize.columns - header_text.len().try_into().unwrap_or(u16::MAX)) / 2,
+            winsize
+                .columns
+                .saturating_sub(width.try_into().unwrap_or(u16::MAX))
+                / 2,
             0
         ),
-        Print(header_text.red()),
+        Print(header_text.title()),
+        Print(status_text.muted()),
+        Print("\r\n\n"),
     )?;
     Ok(())
 }
@@ -42,77 +464,810 @@ fn title(stdout: &mut Stdout, winsize: &WindowSize) -> anyhow::Result<()> {
 fn print_question(stdout: &mut Stdout, question: &str) -> anyhow::Result<()> {
     execute!(
         stdout,
-        Print("REVIEW: ".yellow()),
-        Print(format!("{question}\r\n\n"))
+        Print("REVIEW: ".prompt()),
+        Print(format!(
+            "{}\r\n\n",
+            paint(&markdown::render(question), Role::Question).replace('\n', "\r\n")
+        ))
     )?;
     Ok(())
 }
 
-pub fn review_card(card: &CardBody) -> anyhow::Result<Option<Grade>> {
+/// Whether there is anything to reveal. Cards with neither a back nor a cloze are prompts
+/// for free recall, which are graded straight away
+fn has_answer(card: &CardBody) -> bool {
+    !card.back.trim().is_empty() || card.front.contains('_')
+}
+
+/// The `HINT:` line in the back of a card, which can be shown before the answer
+fn hint(card: &CardBody) -> Option<&str> {
+    card.back
+        .lines()
+        .find_map(|l| l.trim().strip_prefix("HINT:"))
+        .map(str::trim)
+}
+
+/// The answer to type for a typed card: its back, without any hint
+fn expected_answer(card: &CardBody) -> String {
+    card.back
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with("HINT:"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The typed answer and the expected one, with the characters which differ marked
+fn compare_typed(typed: &str, expected: &str) -> String {
+    let changes = diff::diff(typed, expected);
+    if diff::is_right(&changes) {
+        return format!("{} {}", "You typed".bold(), typed.good());
+    }
+    let (mut typed, mut expected) = (String::new(), String::new());
+    for change in changes {
+        match change {
+            Change::Same(s) => {
+                typed.push_str(s);
+                expected.push_str(s);
+            }
+            Change::Extra(s) => _ = write!(typed, "{}", s.bad()),
+            Change::Missing(s) => _ = write!(expected, "{}", s.highlight()),
+        }
+    }
+    format!(
+        "{} {typed}\n{} {expected}",
+        "You typed".bold(),
+        "Expected ".bold()
+    )
+}
+
+/// What to do after showing a card
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Action {
+    Grade(Grade),
+    /// Leave the card out of reviews for a few hours
+    Snooze,
+    /// Show the card again at the end of the session
+    Skip,
+    /// Leave the card out of reviews until tomorrow
+    Bury,
+    /// Take back the previous answer, and show that card again
+    Undo,
+    /// The card was edited, so show it again as it is in its file now
+    Edited,
+    Quit,
+}
+
+impl Action {
+    /// What to do when a command which ends showing the card is given
+    fn of(command: Command) -> Option<Self> {
+        match command {
+            Command::Quit => Some(Self::Quit),
+            Command::Snooze => Some(Self::Snooze),
+            Command::Skip => Some(Self::Skip),
+            Command::Bury => Some(Self::Bury),
+            Command::Undo => Some(Self::Undo),
+            _ => None,
+        }
+    }
+}
+
+/// Settings for how cards are presented
+pub struct Ui {
+    pub grading: Grading,
+    /// How clozes in questions are hidden
+    pub cloze: ClozeMask,
+    /// What the other keys do, as set in the `[keys]` table of the config
+    pub keys: Keymap,
+    /// Print plain lines of text for screen readers, instead of drawing on the screen
+    pub accessible: bool,
+    /// Show that answers are not being saved
+    pub read_only: bool,
+    /// Only show this many lines of long backs, until expanded
+    pub collapse_lines: Option<usize>,
+    /// Type the items of list answers, and see which were missed, instead of only revealing them
+    pub type_lists: bool,
+    /// How long snoozed cards are left out of reviews
+    pub snooze_hours: f32,
+    /// How to show images on the backs of cards
+    pub images: graphics::Protocol,
+    /// Images of upcoming cards prepared in the background
+    preloaded: graphics::Preloaded,
+    /// Opens cards to edit them
+    pub opener: open::Opener,
+}
+
+impl Ui {
+    /// Sets up the interface from the command line flags, and the config for anything they leave out
+    pub fn new(
+        config: &Config,
+        grading: Option<GradingMode>,
+        accessible: bool,
+        read_only: bool,
+    ) -> anyhow::Result<Self> {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let collapse_lines = match config.get("collapse_lines") {
+            Some(Value::Number(n)) if *n >= 1.0 => Some(*n as usize),
+            Some(_) => bail!("`collapse_lines` should be a positive number"),
+            None => None,
+        };
+        let grading = Grading::new(grading, config)?;
+        Ok(Self {
+            keys: Keymap::new(config, &grading)?,
+            grading,
+            cloze: ClozeMask::from_config(config)?,
+            accessible: accessible || matches!(config.get("accessible"), Some(Value::Bool(true))),
+            read_only,
+            collapse_lines,
+            type_lists: matches!(config.get("type_lists"), Some(Value::Bool(true))),
+            snooze_hours: config.snooze_hours(None)?,
+            images: graphics::Protocol::from_config(config)?,
+            preloaded: graphics::Preloaded::default(),
+            opener: open::Opener::from_config(config)?,
+        })
+    }
+
+    /// Shows a card and asks for a grade, or whether to snooze it or quit instead.
+    /// `status` is the progress of the session, shown in the header, and `intervals` are the
+    /// days until the card would be due again after each grade
+    pub fn review_card(
+        &self,
+        card: &CardBody,
+        status: &str,
+        intervals: &[(Grade, f32)],
+    ) -> anyhow::Result<Action> {
+        if self.accessible || is_dumb_terminal() {
+            review_card_plain(card, intervals, self)
+        } else {
+            review_card(card, status, intervals, self)
+        }
+    }
+
+    /// Starts preparing the images of a card which will be shown next
+    pub fn preload(&self, card: &CardBody) {
+        if self.accessible || is_dumb_terminal() || card.generated {
+            return;
+        }
+        if let Ok(winsize) = terminal::window_size() {
+            graphics::preload(
+                &markdown::render(card.back.trim()),
+                card.file.parent().unwrap_or(Path::new("")),
+                self.images,
+                &winsize,
+                &self.preloaded,
+            );
+        }
+    }
+
+    /// Asks a generated question, and grades the typed answer as good if it is right, or again otherwise.
+    /// Returns `None` if the user wants to quit
+    pub fn review_generated(
+        &self,
+        question: &str,
+        answer: i64,
+        status: &str,
+    ) -> anyhow::Result<Option<Grade>> {
+        if self.accessible || is_dumb_terminal() {
+            let keys = self.keys.plain();
+            println!("Question: {question}");
+            println!(
+                "Type the answer, then Enter. Type {}.",
+                keys.help(&[(&[Command::Quit], "quit")])
+            );
+            let Some(line) = read_line()? else {
+                return Ok(None);
+            };
+            if keys.line(&line) == Some(Command::Quit) {
+                return Ok(None);
+            }
+            let right = line.parse() == Ok(answer);
+            if right {
+                println!("Right.\n");
+            } else {
+                println!("Wrong, the answer is {answer}.\n");
+            }
+            return Ok(Some(if right { Grade::Good } else { Grade::Again }));
+        }
+        review_generated(question, answer, status, self)
+    }
+}
+
+/// Asks a yes or no question, where yes is the default
+pub fn confirm(question: &str) -> anyhow::Result<bool> {
+    print!("{question} [Y/n] ");
+    std::io::stdout().flush()?;
+    Ok(read_line()?.is_none_or(|l| !l.eq_ignore_ascii_case("n")))
+}
+
+/// The items of the answer, if it should be typed as a list
+fn list_items<'a>(card: &'a CardBody, ui: &Ui) -> Option<Vec<&'a str>> {
+    ui.type_lists.then(|| list::items(&card.back)).flatten()
+}
+
+/// Reads typed items until an empty line, and compares them to the expected ones.
+/// Returns `None` to quit
+fn type_items(expected: &[&str], keys: &Keymap) -> anyhow::Result<Option<String>> {
+    let mut typed = vec![];
+    loop {
+        match read_line()? {
+            None => return Ok(None),
+            Some(line) if typed.is_empty() && keys.line(&line) == Some(Command::Quit) => {
+                return Ok(None);
+            }
+            Some(line) if line.is_empty() => break,
+            Some(line) => typed.push(line),
+        }
+    }
+    Ok(Some(list::compare(expected, &typed).summary()))
+}
+
+/// Reads a line from stdin, or `None` at the end of input
+fn read_line() -> anyhow::Result<Option<String>> {
+    let mut line = String::new();
+    if std::io::stdin().lock().read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim().to_string()))
+}
+
+/// Reads the answer to a typed card and says whether it was right, returning `false` to quit
+fn type_answer_plain(card: &CardBody, keys: &Keymap) -> anyhow::Result<bool> {
+    println!(
+        "Type the answer, then Enter. Type {}.",
+        keys.help(&[(&[Command::Quit], "quit")])
+    );
+    let line = match read_line()? {
+        None => return Ok(false),
+        Some(line) if keys.line(&line) == Some(Command::Quit) => return Ok(false),
+        Some(line) => line,
+    };
+    let expected = expected_answer(card);
+    if diff::is_right(&diff::diff(&line, &expected)) {
+        println!("Right.");
+    } else {
+        println!("Not quite, the answer is {expected}.");
+    }
+    Ok(true)
+}
+
+fn review_card_plain(
+    card: &CardBody,
+    intervals: &[(Grade, f32)],
+    ui: &Ui,
+) -> anyhow::Result<Action> {
+    let keys = ui.keys.plain();
+    println!("Question: {}", ui.cloze.hide(card.front.trim()));
+    if card.typed {
+        if !type_answer_plain(card, &keys)? {
+            return Ok(Action::Quit);
+        }
+    } else if let Some(expected) = list_items(card, ui) {
+        println!(
+            "Type the {} items, one per line, then an empty line. Type {}.",
+            expected.len(),
+            keys.help(&[(&[Command::Quit], "quit")])
+        );
+        let Some(summary) = type_items(&expected, &keys)? else {
+            return Ok(Action::Quit);
+        };
+        println!("You got {summary}.");
+        println!("Answer:\n{}", card.back.trim());
+    } else if has_answer(card) {
+        let front = keys.without_grades();
+        let hint = hint(card).filter(|_| !front.keys(Command::Hint).is_empty());
+        let mut commands: Vec<(&[Command], &str)> = vec![];
+        if hint.is_some() {
+            commands.push((&[Command::Hint], "show a hint"));
+        }
+        commands.extend([
+            (&[Command::Snooze][..], "snooze"),
+            (&[Command::Skip], "skip"),
+            (&[Command::Bury], "bury until tomorrow"),
+            (&[Command::Undo], "undo the last answer"),
+            (&[Command::Quit], "quit"),
+        ]);
+        loop {
+            println!(
+                "Press Enter to show the answer, or type {}, then Enter.",
+                front.help(&commands)
+            );
+            let Some(line) = read_line()? else {
+                return Ok(Action::Quit);
+            };
+            let command = front.line(&line);
+            match (command.and_then(Action::of), command, hint) {
+                (Some(action), ..) => return Ok(action),
+                (None, Some(Command::Hint), Some(hint)) => println!("Hint: {hint}"),
+                _ => break,
+            }
+        }
+        println!("Answer: {}", card.front.trim().replace('_', ""));
+        let back = card.back.trim();
+        if !back.is_empty() {
+            println!("{back}");
+        }
+    }
+    ask_grade_plain(intervals, &ui.grading, &keys)
+}
+
+/// Asks for a grade on its own line, or what to do instead
+fn ask_grade_plain(
+    intervals: &[(Grade, f32)],
+    grading: &Grading,
+    keys: &Keymap,
+) -> anyhow::Result<Action> {
+    let choices = grading
+        .buttons
+        .iter()
+        .map(|b| match intervals.iter().find(|(g, _)| *g == b.grade) {
+            Some(&(_, days)) => format!(
+                "{} for {}, due again in {}",
+                b.key,
+                b.label,
+                grading::format_interval(days)
+            ),
+            None => format!("{} for {}", b.key, b.label),
+        })
+        .collect::<Vec<_>>()
+        .join("; ");
+    loop {
+        println!(
+            "Grade the card: type {choices}, then Enter. Type {}.",
+            keys.help(&[
+                (&[Command::Snooze], "snooze"),
+                (&[Command::Skip], "skip"),
+                (&[Command::Bury], "bury until tomorrow"),
+                (&[Command::Undo], "undo the last answer"),
+                (&[Command::Quit], "quit")
+            ])
+        );
+        let Some(line) = read_line()? else {
+            return Ok(Action::Quit);
+        };
+        let command = keys.line(&line);
+        if let Some(action) = command.and_then(Action::of) {
+            match action {
+                Action::Snooze => println!("Snoozed.\n"),
+                Action::Skip => println!("Skipped.\n"),
+                Action::Bury => println!("Buried until tomorrow.\n"),
+                _ => {}
+            }
+            return Ok(action);
+        }
+        let grade = match command {
+            Some(Command::Grade(grade)) => Some(grade),
+            Some(Command::Confirm) => Some(grading.buttons[grading.default_selection()].grade),
+            _ => None,
+        };
+        if let Some(grade) = grade {
+            let label = grading
+                .buttons
+                .iter()
+                .find(|b| b.grade == grade)
+                .map_or("good", |b| b.label);
+            println!("Graded {label}.\n");
+            return Ok(Action::Grade(grade));
+        }
+        println!("Not a valid grade.");
+    }
+}
+
+fn review_generated(
+    question: &str,
+    answer: i64,
+    status: &str,
+    ui: &Ui,
+) -> anyhow::Result<Option<Grade>> {
+    let mut stdout = std::io::stdout();
+    let winsize = terminal::window_size()?;
+
+    execute!(
+        &mut stdout,
+        EnterAlternateScreen,
+        MoveTo(0, 0),
+        Clear(ClearType::All)
+    )?;
+    title(&mut stdout, &winsize, ui.read_only, status)?;
+    print_question(&mut stdout, question)?;
+    print!("{}", "Answer: ".bold());
+    stdout.flush()?;
+    // Typed in cooked mode, so the answer is echoed and can be edited
+    let line = read_line()?;
+    let res = match line {
+        None => None,
+        Some(line) if ui.keys.plain().line(&line) == Some(Command::Quit) => None,
+        Some(line) => {
+            let right = line.parse() == Ok(answer);
+            if right {
+                print!("{}", "Right!".bold());
+            } else {
+                print!("{} the answer is {answer}", "Wrong,".bad());
+            }
+            print!("\n\nPress any key to continue....");
+            stdout.flush()?;
+            crossterm::terminal::enable_raw_mode()?;
+            let quit = loop {
+                if let Event::Key(event) = crossterm::event::read()? {
+                    break ui.keys.command(event) == Some(Command::Quit);
+                }
+            };
+            crossterm::terminal::disable_raw_mode()?;
+            (!quit).then_some(if right { Grade::Good } else { Grade::Again })
+        }
+    };
+    execute!(stdout, LeaveAlternateScreen)?;
+    Ok(res)
+}
+
+fn review_card(
+    card: &CardBody,
+    status: &str,
+    intervals: &[(Grade, f32)],
+    ui: &Ui,
+) -> anyhow::Result<Action> {
     let mut stdout = std::io::stdout();
     let mut winsize = terminal::window_size()?;
-    let front = card.front.trim();
-    let back = card.back.trim();
 
     execute!(&mut stdout, EnterAlternateScreen)?;
     crossterm::terminal::enable_raw_mode()?;
+    let typed = if card.typed {
+        type_answer(&mut stdout, &mut winsize, card, status, ui)?
+            .map(|line| compare_typed(&line, &expected_answer(card)))
+    } else if let Some(expected) = list_items(card, ui) {
+        type_list(&mut stdout, &winsize, card, status, &expected, ui)?
+            .map(|summary| format!("{} {summary}", "You got".bold()))
+    } else {
+        None
+    };
+    let res = if card.typed || list_items(card, ui).is_some() {
+        match typed {
+            Some(typed) => ask_grade(
+                &mut stdout,
+                &mut winsize,
+                card,
+                status,
+                Some(&typed),
+                intervals,
+                ui,
+            ),
+            None => Ok(Action::Quit),
+        }
+    } else if !has_answer(card) {
+        ask_grade(&mut stdout, &mut winsize, card, status, None, intervals, ui)
+    } else {
+        match show_question(&mut stdout, &mut winsize, card, status, ui)? {
+            Some(action) => Ok(action),
+            None => ask_grade(&mut stdout, &mut winsize, card, status, None, intervals, ui),
+        }
+    };
+    crossterm::terminal::disable_raw_mode()?;
+    execute!(stdout, LeaveAlternateScreen)?;
+    res
+}
+
+/// Opens the card to edit it, leaving the review screen until the command finishes
+fn edit(stdout: &mut Stdout, card: &CardBody, ui: &Ui) -> anyhow::Result<()> {
+    crossterm::terminal::disable_raw_mode()?;
+    execute!(stdout, LeaveAlternateScreen)?;
+    if let Err(e) = ui.opener.open(card) {
+        print!("{e}\nPress Enter to continue...");
+        stdout.flush()?;
+        read_line()?;
+    }
+    execute!(stdout, EnterAlternateScreen)?;
+    crossterm::terminal::enable_raw_mode()?;
+    Ok(())
+}
 
+/// Shows the question until a key is pressed to reveal the answer, returning `None` to reveal it,
+/// or what to do instead
+fn show_question(
+    stdout: &mut Stdout,
+    winsize: &mut WindowSize,
+    card: &CardBody,
+    status: &str,
+    ui: &Ui,
+) -> anyhow::Result<Option<Action>> {
+    let front = card.front.trim();
+    let keys = ui.keys.without_grades();
+    let hint = hint(card).filter(|_| !keys.keys(Command::Hint).is_empty());
+    let mut show_hint = false;
     loop {
-        execute!(&mut stdout, MoveTo(0, 0), Clear(ClearType::All))?;
-        title(&mut stdout, &winsize)?;
-        print_question(&mut stdout, &hide_cloze(front))?;
-        print!("Press any key to show backside....");
+        execute!(stdout, MoveTo(0, 0), Clear(ClearType::All))?;
+        title(stdout, winsize, ui.read_only, status)?;
+        print_question(stdout, &ui.cloze.hide(front))?;
+        let show = keys.show_keys();
+        match hint {
+            Some(hint) if show_hint => {
+                print!(
+                    "{} {}\r\n\nPress {} to show backside....",
+                    "Hint:".muted(),
+                    hint.styled(Role::Hint),
+                    show.as_deref().unwrap_or("any key")
+                );
+            }
+            Some(_) => print!(
+                "Press {} for a hint, or {} to show backside....",
+                keys.keys(Command::Hint),
+                show.as_deref().unwrap_or("any other key")
+            ),
+            None => print!(
+                "Press {} to show backside....",
+                show.as_deref().unwrap_or("any key")
+            ),
+        }
+        let help = keys.help(&[
+            (&[Command::Edit], "edit"),
+            (&[Command::Snooze], "snooze for a few hours"),
+            (&[Command::Skip], "skip"),
+            (&[Command::Bury], "bury until tomorrow"),
+            (&[Command::Undo], "undo the last answer"),
+        ]);
+        print!("\r\n{}", help.muted());
         stdout.flush()?;
 
         match crossterm::event::read()? {
-            Event::Key(
-                KeyEvent {
-                    code: KeyCode::Esc | KeyCode::Char('q'),
-                    ..
+            Event::Key(event) => match keys.command(event) {
+                Some(command) if let Some(action) = Action::of(command) => {
+                    return Ok(Some(action));
+                }
+                Some(Command::Hint) if hint.is_some() && !show_hint => show_hint = true,
+                Some(Command::Edit) => {
+                    edit(stdout, card, ui)?;
+                    return Ok(Some(Action::Edited));
                 }
-                | KeyEvent {
-                    code: KeyCode::Char('c'),
-                    modifiers: KeyModifiers::CONTROL,
-                    ..
-                },
-            ) => return Ok(None),
-            Event::Key(_) => break,
+                _ if keys.shows(event) => return Ok(None),
+                _ => {}
+            },
+            Event::Resize(_, _) => {
+                *winsize = terminal::window_size()?;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Shows the question and has the answer typed on the line below it, returning what was typed
+/// or `None` to quit
+fn type_answer(
+    stdout: &mut Stdout,
+    winsize: &mut WindowSize,
+    card: &CardBody,
+    status: &str,
+    ui: &Ui,
+) -> anyhow::Result<Option<String>> {
+    let mut line = input::Line::default();
+    loop {
+        execute!(stdout, MoveTo(0, 0), Clear(ClearType::All))?;
+        title(stdout, winsize, ui.read_only, status)?;
+        print_question(stdout, &ui.cloze.hide(card.front.trim()))?;
+        print!("{}\r\n\n", "Type the answer, then Enter".muted());
+        // The cursor is put back where typing continues
+        print!("{} {}", "Answer:".bold(), &line.text[..line.cursor]);
+        execute!(stdout, SavePosition)?;
+        print!("{}", &line.text[line.cursor..]);
+        execute!(stdout, RestorePosition)?;
+        stdout.flush()?;
+
+        match crossterm::event::read()? {
+            Event::Key(event) => match line.key(event) {
+                Edit::Changed => {}
+                Edit::Done => return Ok(Some(line.text.trim().to_string())),
+                Edit::Cancel => return Ok(None),
+            },
             Event::Resize(_, _) => {
-                winsize = terminal::window_size()?;
+                *winsize = terminal::window_size()?;
             }
             _ => {}
         }
     }
+}
 
-    let res = loop {
-        execute!(&mut stdout, MoveTo(0, 0), Clear(ClearType::All))?;
-        title(&mut stdout, &winsize)?;
-        print_question(&mut stdout, front)?;
+/// Shows the question and has the items of the answer typed, returning how they compare or `None` to quit
+fn type_list(
+    stdout: &mut Stdout,
+    winsize: &WindowSize,
+    card: &CardBody,
+    status: &str,
+    expected: &[&str],
+    ui: &Ui,
+) -> anyhow::Result<Option<String>> {
+    execute!(stdout, MoveTo(0, 0), Clear(ClearType::All))?;
+    title(stdout, winsize, ui.read_only, status)?;
+    print_question(stdout, &ui.cloze.hide(card.front.trim()))?;
+    print!(
+        "{}\r\n",
+        format!(
+            "Type the {} items, one per line, then an empty line",
+            expected.len()
+        )
+        .muted()
+    );
+    stdout.flush()?;
+    // Typed in cooked mode, so the items are echoed and can be edited
+    crossterm::terminal::disable_raw_mode()?;
+    let res = type_items(expected, &ui.keys.plain());
+    crossterm::terminal::enable_raw_mode()?;
+    res
+}
 
+/// Shows the answer, and how a typed answer compared to it if there is one, and asks for a grade
+fn ask_grade(
+    stdout: &mut Stdout,
+    winsize: &mut WindowSize,
+    card: &CardBody,
+    status: &str,
+    typed: Option<&str>,
+    intervals: &[(Grade, f32)],
+    ui: &Ui,
+) -> anyhow::Result<Action> {
+    let grading = &ui.grading;
+    let front = card.front.trim();
+    let back = graphics::show(
+        &markdown::render(card.back.trim()),
+        card.file.parent().unwrap_or(Path::new("")),
+        ui.images,
+        winsize,
+        &ui.preloaded,
+    );
+    // The button chosen with the arrow keys, confirmed with Enter
+    let mut selected = grading.default_selection();
+    let lines = back.lines().count();
+    let collapsible = ui.collapse_lines.filter(|&n| lines > n);
+    let mut expanded = false;
+    let mut scroll = Scroll::default();
+    loop {
+        print!("{}", graphics::clear(ui.images));
+        execute!(stdout, MoveTo(0, 0), Clear(ClearType::All))?;
+        title(stdout, winsize, ui.read_only, status)?;
+        print_question(stdout, front)?;
+
+        let buttons = button_rows(
+            &grading.button_help(intervals),
+            selected,
+            usize::from(winsize.columns),
+        );
         crossterm::terminal::disable_raw_mode()?;
-        print!("{back}\n1:again\t2: hard\t3/space: good\t4: easy");
+        if let Some(typed) = typed {
+            println!("{typed}");
+        }
+        let mut shown: Vec<String> = back.lines().map(|l| paint(l, Role::Answer)).collect();
+        if let Some(n) = collapsible.filter(|_| !expanded) {
+            shown.truncate(n);
+            let more = format!("… press o to expand ({} more lines)", lines - n);
+            shown.push(more.muted().to_string());
+        }
+        let (columns, image_rows) = (usize::from(winsize.columns), graphics::image_rows(winsize));
+        // The header, question, typed answer and buttons, each followed by a blank line or not
+        let used = 2
+            + text_rows(&format!("REVIEW: {}", markdown::render(front)), columns, 0)
+            + 1
+            + typed.map_or(0, |t| text_rows(t, columns, 0))
+            + text_rows(&buttons, columns, 0)
+            + 1;
+        scroll.print(
+            &shown,
+            usize::from(winsize.rows).saturating_sub(used),
+            (columns, image_rows),
+            &ui.keys,
+        );
+        let help = ui.keys.help(&[
+            (&[Command::Previous, Command::Next], "choose"),
+            (&[Command::Confirm], "confirm"),
+            (&[Command::Edit], "edit"),
+            (&[Command::Snooze], "snooze"),
+            (&[Command::Skip], "skip"),
+            (&[Command::Bury], "bury"),
+            (&[Command::Undo], "undo"),
+        ]);
+        print!("\n{buttons}\n{}", help.muted());
         crossterm::terminal::enable_raw_mode()?;
         stdout.flush()?;
 
         match crossterm::event::read()? {
             Event::Key(event) => {
-                let grade = match event.code {
-                    KeyCode::Char('1') => Grade::Again,
-                    KeyCode::Char('2') => Grade::Hard,
-                    KeyCode::Char('3' | ' ') => Grade::Good,
-                    KeyCode::Char('4') => Grade::Easy,
-                    KeyCode::Esc | KeyCode::Char('q') => break Ok(None),
-                    _ => continue,
+                let command = ui.keys.command(event);
+                if let Some(action) = command.and_then(Action::of) {
+                    return Ok(action);
+                }
+                let grade = match command {
+                    Some(Command::Grade(grade)) => Some(grade),
+                    Some(Command::Confirm) => Some(grading.buttons[selected].grade),
+                    Some(Command::Expand) if collapsible.is_some() => {
+                        expanded = !expanded;
+                        None
+                    }
+                    Some(Command::Edit) => {
+                        edit(stdout, card, ui)?;
+                        return Ok(Action::Edited);
+                    }
+                    Some(Command::Previous) => {
+                        selected = selected.saturating_sub(1);
+                        None
+                    }
+                    Some(Command::Next) => {
+                        selected = (selected + 1).min(grading.buttons.len() - 1);
+                        None
+                    }
+                    Some(command) => {
+                        scroll.command(command);
+                        None
+                    }
+                    None => None,
                 };
-                break Ok(Some(grade));
+                if let Some(grade) = grade {
+                    return Ok(Action::Grade(grade));
+                }
             }
             Event::Resize(_, _) => {
-                winsize = terminal::window_size()?;
+                *winsize = terminal::window_size()?;
             }
             _ => {}
         }
-    };
-    crossterm::terminal::disable_raw_mode()?;
-    execute!(std::io::stdout(), LeaveAlternateScreen)?;
-    res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn cloze_width() {
+        assert_eq!(hide_cloze("_Simonides_ of Ceos"), "_________ of Ceos");
+        assert_eq!(hide_cloze("Tokyo is _東京_"), "Tokyo is ____");
+        // An e with a combining accent, and a family emoji joined from several
+        assert_eq!(hide_cloze("_cafe\u{301}_ _👨‍👩‍👧_"), "____ __");
+
+        let mask = |hide, mask| ClozeMask { hide, mask };
+        assert_eq!(
+            mask(Hide::Fixed, '_').hide("_Simonides_ of _Ceos_"),
+            "[...] of [...]"
+        );
+        assert_eq!(
+            mask(Hide::FirstLetter, '•').hide("Tokyo is _東京_, _Paris"),
+            "Tokyo is 東••, P••••"
+        );
+        assert_eq!(mask(Hide::Length, '•').hide("__ is empty"), " is empty");
+        assert!(ClozeMask::from_config(&Config::parse("cloze_mask = \"..\"").unwrap()).is_err());
+    }
+
+    #[test]
+    pub fn narrow() {
+        let status = "jp · 12 left · 0 again";
+        assert_eq!(
+            fit_status("CARDSHARP", status, 80),
+            " · jp · 12 left · 0 again"
+        );
+        assert_eq!(fit_status("CARDSHARP", status, 26), " · jp · 12 left");
+        assert_eq!(fit_status("CARDSHARP", status, 5), "");
+
+        let help = ["1: again", "2: hard", "3/space: good", "4: easy"].map(String::from);
+        assert_eq!(
+            button_rows(&help, 0, 80),
+            " 1: again   2: hard   3/space: good   4: easy "
+        );
+        assert_eq!(
+            button_rows(&help, 0, 26),
+            " 1: again   2: hard \n 3/space: good   4: easy "
+        );
+    }
+
+    #[test]
+    pub fn restyling() {
+        assert_eq!(
+            restyle("Paris, \x1b[1mFrance\x1b[0m\nEurope", "\x1b[36m"),
+            "\x1b[36mParis, \x1b[1mFrance\x1b[0m\x1b[36m\x1b[0m\n\x1b[36mEurope\x1b[0m"
+        );
+    }
+
+    #[test]
+    pub fn scrolling() {
+        assert_eq!(text_rows("\x1b[1mFemur\x1b[0m\n0123456789", 5, 3), 3);
+        let rows = [1, 2, 1, 1, 1];
+        assert_eq!(scroll_window(&rows, 3, 6), 0..5);
+        assert_eq!(scroll_window(&rows, 0, 4), 0..2);
+        assert_eq!(scroll_window(&rows, 1, 4), 1..3);
+        // The last lines stay in view however far down it is scrolled
+        assert_eq!(scroll_window(&rows, 9, 4), 2..5);
+    }
 }
@@ -0,0 +1,273 @@
+//! Review history kept next to the notes. A directory containing a `.cardsharp` directory is a
+//! vault, and every answer to one of its cards is appended to `.cardsharp/revlog.jsonl`.
+//! Committing the log with the notes means any clone of the repository can be reviewed with
+//! its history intact.
+//!
+//! Each line is a JSON object like `{"card":"fguvqzmI","time":1700000000.123,"stability":2.3,"difficulty":5.5}`,
+//! with the time in seconds to the millisecond, and the `grade`, `duration_ms`, and unless the card was new, the `elapsed_days` and `recall` of the answer.
+use std::{
+    collections::{BTreeMap, BTreeSet, btree_map},
+    fmt::Write as _,
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    Answer, CardBody, CardId, days_between, from_millis,
+    fsrs::{FSRSParams, Grade},
+    journal::{Entry, format_time, parse_time},
+    millis, next_state, save_answer, save_review,
+};
+
+const LOG: &str = ".cardsharp/revlog.jsonl";
+
+/// The root of the vault a file is in, if any
+fn root(file: &Path) -> Option<PathBuf> {
+    let file = std::fs::canonicalize(file).ok()?;
+    file.ancestors()
+        .skip(1)
+        .find(|dir| dir.join(".cardsharp").is_dir())
+        .map(Path::to_path_buf)
+}
+
+fn format_line(entry: &Entry) -> anyhow::Result<String> {
+    let mut res = format!(
+        r#"{{"card":"{}","time":{},"stability":{},"difficulty":{}"#,
+        entry.id,
+        format_time(entry.time)?,
+        entry.fsrs.stability,
+        entry.fsrs.difficulty
+    );
+    if let Some(answer) = entry.answer {
+        _ = write!(
+            res,
+            r#","grade":{},"duration_ms":{}"#,
+            answer.grade as u8,
+            answer.duration.as_millis()
+        );
+        if let Some((days, recall)) = answer.last {
+            _ = write!(res, r#","elapsed_days":{days},"recall":{recall}"#);
+        }
+    }
+    res.push('}');
+    Ok(res)
+}
+
+fn parse_line(line: &str) -> anyhow::Result<Entry> {
+    let err = || anyhow!("Invalid line `{line}` in review log");
+    let fields: BTreeMap<&str, &str> = line
+        .trim()
+        .strip_prefix('{')
+        .and_then(|l| l.strip_suffix('}'))
+        .ok_or_else(err)?
+        .split(',')
+        .filter_map(|f| f.split_once(':'))
+        .map(|(k, v)| (k.trim().trim_matches('"'), v.trim().trim_matches('"')))
+        .collect();
+    let field = |key| fields.get(key).copied().ok_or_else(err);
+    let answer = match fields.get("grade") {
+        Some(grade) => Some(Answer {
+            grade: Grade::try_from(grade.parse::<u8>()?)?,
+            last: match fields.get("elapsed_days") {
+                Some(days) => Some((days.parse()?, field("recall")?.parse()?)),
+                None => None,
+            },
+            duration: Duration::from_millis(field("duration_ms")?.parse()?),
+        }),
+        None => None,
+    };
+    Ok(Entry {
+        id: CardId::parse(field("card")?)?,
+        time: parse_time(field("time")?).ok_or_else(err)?,
+        fsrs: FSRSParams {
+            stability: field("stability")?.parse()?,
+            difficulty: field("difficulty")?.parse()?,
+        },
+        answer,
+    })
+}
+
+/// Recomputes the state after every review of a card from the grades, in the order they were
+/// given. Reviews without a grade, such as imported ones, keep their state, and the grades after
+/// them start from it
+fn replay(tx: &rusqlite::Transaction, id: u64) -> anyhow::Result<()> {
+    let mut statement = tx.prepare(
+        "select review.rowid, review.last_reviewed, review.stability, review.difficulty, review_log.grade
+             from review left join review_log
+                 on review_log.card = review.card and review_log.time = review.last_reviewed
+             where review.card = ?1
+             order by review.last_reviewed, review.rowid",
+    )?;
+    let rows = statement
+        .query_map([id], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, u64>(1)?,
+                FSRSParams {
+                    stability: row.get(2)?,
+                    difficulty: row.get(3)?,
+                },
+                row.get::<_, Option<u8>>(4)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut last: Option<(SystemTime, FSRSParams)> = None;
+    for (rowid, millis, fsrs, grade) in rows {
+        let time = from_millis(millis);
+        let elapsed = last.map(|(t, fsrs)| (days_between(t, time), fsrs));
+        let fsrs = match grade {
+            Some(grade) => next_state(elapsed, Grade::try_from(grade)?),
+            None => fsrs,
+        };
+        tx.execute(
+            "update review set stability = ?2, difficulty = ?3 where rowid = ?1",
+            (rowid, fsrs.stability, fsrs.difficulty),
+        )?;
+        if grade.is_some() {
+            tx.execute(
+                "update review_log set elapsed_days = ?3, recall = ?4 where card = ?1 and time = ?2",
+                (
+                    id,
+                    millis,
+                    elapsed.map(|(days, _)| days),
+                    elapsed.map(|(days, fsrs)| fsrs.recall_probability(days)),
+                ),
+            )?;
+        }
+        last = Some((time, fsrs));
+    }
+    Ok(())
+}
+
+/// Saves the answers in the logs of the vaults the files are in, which are not in the database yet.
+///
+/// The states in the log were worked out on the machine which gave the answer, without the answers
+/// other machines had not synced yet. So when an answer from the log is older than one already in
+/// the database, the states of the card are recomputed from all its answers in order
+pub fn sync(sqlite: &mut rusqlite::Connection, files: &[PathBuf]) -> anyhow::Result<()> {
+    let mut roots: Vec<PathBuf> = files.iter().filter_map(|f| root(f)).collect();
+    roots.sort();
+    roots.dedup();
+
+    // The last review of each card before syncing, and the cards with older reviews in the logs
+    let mut newest: BTreeMap<u64, Option<u64>> = BTreeMap::new();
+    let mut out_of_order = BTreeSet::new();
+    let tx = sqlite.transaction()?;
+    for root in roots {
+        let path = root.join(LOG);
+        let Ok(data) = std::fs::read_to_string(&path) else {
+            continue;
+        };
+        for (lineno, line) in data.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry = parse_line(line)
+                .map_err(|e| e.context(format!("{}:{}", path.display(), lineno + 1)))?;
+            let time = millis(entry.time)?;
+            let known: bool = tx.query_row(
+                "select exists(select 1 from review where card = ?1 and last_reviewed = ?2)",
+                (entry.id.as_int(), time),
+                |row| row.get(0),
+            )?;
+            if !known {
+                let id = entry.id.as_int();
+                let last = match newest.entry(id) {
+                    btree_map::Entry::Occupied(last) => *last.get(),
+                    btree_map::Entry::Vacant(last) => *last.insert(tx.query_row(
+                        "select max(last_reviewed) from review where card = ?1",
+                        [id],
+                        |row| row.get(0),
+                    )?),
+                };
+                if last.is_some_and(|last: u64| time < last) {
+                    out_of_order.insert(id);
+                }
+                save_review(&tx, entry.id, entry.time, entry.fsrs)?;
+                if let Some(answer) = &entry.answer {
+                    save_answer(&tx, entry.id, entry.time, answer)?;
+                }
+            }
+        }
+    }
+    for &id in &out_of_order {
+        replay(&tx, id)?;
+    }
+    tx.commit()?;
+    if !out_of_order.is_empty() {
+        eprintln!(
+            "Recomputed {} cards answered out of order on several machines",
+            out_of_order.len()
+        );
+    }
+    Ok(())
+}
+
+/// Appends answers to the logs of the vaults their cards are in
+pub fn append(cards: &[CardBody], entries: &[Entry]) -> anyhow::Result<()> {
+    let mut logs: BTreeMap<PathBuf, String> = BTreeMap::new();
+    for entry in entries {
+        let Some(root) = cards
+            .iter()
+            .find(|c| c.id == entry.id)
+            .and_then(|c| root(&c.file))
+        else {
+            continue;
+        };
+        let log = logs.entry(root.join(LOG)).or_default();
+        log.push_str(&format_line(entry)?);
+        log.push('\n');
+    }
+    for (path, lines) in logs {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?
+            .write_all(lines.as_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn log_lines() {
+        let entry = Entry {
+            id: CardId::parse("fguvqzmI").unwrap(),
+            time: SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            fsrs: FSRSParams::new(2.3065, 5.5),
+            answer: None,
+        };
+        let line = format_line(&entry).unwrap();
+        assert_eq!(
+            line,
+            r#"{"card":"fguvqzmI","time":1700000000,"stability":2.3065,"difficulty":5.5}"#
+        );
+        assert_eq!(parse_line(&line).unwrap(), entry);
+        // Fields may come in any order, and unknown ones are ignored
+        assert_eq!(
+            parse_line(r#"{ "time": 1700000000, "device": 3, "card": "fguvqzmI", "difficulty": 5.5, "stability": 2.3065 }"#).unwrap(),
+            entry
+        );
+        assert!(parse_line(r#"{"card":"fguvqzmI"}"#).is_err());
+
+        let entry = Entry {
+            answer: Some(Answer {
+                grade: Grade::Good,
+                last: None,
+                duration: Duration::from_millis(4200),
+            }),
+            ..entry
+        };
+        let line = format_line(&entry).unwrap();
+        assert!(line.ends_with(r#""grade":3,"duration_ms":4200}"#));
+        assert_eq!(parse_line(&line).unwrap(), entry);
+    }
+}
@@ -0,0 +1,339 @@
+//! Just enough of the ZIP format to read and write Anki packages: entries which are stored
+//! or compressed with deflate can be read, and entries are written without compression
+use std::path::Path;
+
+use anyhow::{anyhow, bail};
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in data {
+        crc ^= u32::from(b);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn u16_at(data: &[u8], i: usize) -> anyhow::Result<usize> {
+    let bytes = data
+        .get(i..i + 2)
+        .ok_or_else(|| anyhow!("Truncated ZIP file"))?;
+    Ok(usize::from(u16::from_le_bytes([bytes[0], bytes[1]])))
+}
+
+fn u32_at(data: &[u8], i: usize) -> anyhow::Result<u32> {
+    let bytes = data
+        .get(i..i + 4)
+        .ok_or_else(|| anyhow!("Truncated ZIP file"))?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Reads all entries of a ZIP file
+pub fn read(path: &Path) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+    let data = std::fs::read(path)?;
+    // The end of central directory record is at the end, followed by a comment of at most 64 KiB
+    let end = (0..data.len().saturating_sub(21))
+        .rev()
+        .take(65536 + 22)
+        .find(|&i| data[i..].starts_with(&[0x50, 0x4b, 0x05, 0x06]))
+        .ok_or_else(|| anyhow!("{} is not a ZIP file", path.display()))?;
+    let count = u16_at(&data, end + 10)?;
+    let mut pos = u32_at(&data, end + 16)? as usize;
+
+    let mut res = vec![];
+    for _ in 0..count {
+        if u32_at(&data, pos)? != 0x0201_4b50 {
+            bail!("Invalid central directory in ZIP file");
+        }
+        let method = u16_at(&data, pos + 10)?;
+        let crc = u32_at(&data, pos + 16)?;
+        let compressed = u32_at(&data, pos + 20)? as usize;
+        let name_len = u16_at(&data, pos + 28)?;
+        let extra_len = u16_at(&data, pos + 30)?;
+        let comment_len = u16_at(&data, pos + 32)?;
+        let offset = u32_at(&data, pos + 42)? as usize;
+        let name = data
+            .get(pos + 46..pos + 46 + name_len)
+            .ok_or_else(|| anyhow!("Truncated ZIP file"))?;
+        let name = String::from_utf8_lossy(name).into_owned();
+        pos += 46 + name_len + extra_len + comment_len;
+
+        // The local header repeats the name, but may have a different extra field
+        let start = offset + 30 + u16_at(&data, offset + 26)? + u16_at(&data, offset + 28)?;
+        let raw = data
+            .get(start..start + compressed)
+            .ok_or_else(|| anyhow!("Truncated entry {name} in ZIP file"))?;
+        let contents = match method {
+            0 => raw.to_vec(),
+            8 => inflate(raw).map_err(|e| e.context(format!("In entry {name}")))?,
+            _ => bail!("Entry {name} uses unsupported compression method {method}"),
+        };
+        if crc32(&contents) != crc {
+            bail!("Entry {name} in ZIP file is corrupted");
+        }
+        res.push((name, contents));
+    }
+    Ok(res)
+}
+
+/// Writes entries to a ZIP file, without compression
+pub fn write(path: &Path, entries: &[(String, Vec<u8>)]) -> anyhow::Result<()> {
+    let mut data = vec![];
+    let mut directory = vec![];
+    for (name, contents) in entries {
+        let offset = u32::try_from(data.len())?;
+        let size = u32::try_from(contents.len())?;
+        let name_len = u16::try_from(name.len())?;
+        // Version 1.0, names in UTF-8, stored, at midnight of 1980-01-01
+        let mut header = vec![];
+        header.extend_from_slice(&10u16.to_le_bytes());
+        header.extend_from_slice(&0x0800u16.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(&0x21u16.to_le_bytes());
+        header.extend_from_slice(&crc32(contents).to_le_bytes());
+        header.extend_from_slice(&size.to_le_bytes());
+        header.extend_from_slice(&size.to_le_bytes());
+        header.extend_from_slice(&name_len.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+
+        data.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+        data.extend_from_slice(&header);
+        data.extend_from_slice(name.as_bytes());
+        data.extend_from_slice(contents);
+
+        directory.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+        directory.extend_from_slice(&20u16.to_le_bytes());
+        directory.extend_from_slice(&header);
+        // No comment, on the first disk, without attributes
+        directory.extend_from_slice(&[0; 10]);
+        directory.extend_from_slice(&offset.to_le_bytes());
+        directory.extend_from_slice(name.as_bytes());
+    }
+    let count = u16::try_from(entries.len())?;
+    let directory_offset = u32::try_from(data.len())?;
+    let directory_size = u32::try_from(directory.len())?;
+    data.extend_from_slice(&directory);
+    data.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+    data.extend_from_slice(&[0; 4]);
+    data.extend_from_slice(&count.to_le_bytes());
+    data.extend_from_slice(&count.to_le_bytes());
+    data.extend_from_slice(&directory_size.to_le_bytes());
+    data.extend_from_slice(&directory_offset.to_le_bytes());
+    data.extend_from_slice(&0u16.to_le_bytes());
+    std::fs::write(path, data)?;
+    Ok(())
+}
+
+/// Reads the bits of a deflate stream, starting from the least significant bit of each byte
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Bits<'_> {
+    fn bits(&mut self, n: usize) -> anyhow::Result<usize> {
+        let mut res = 0;
+        for i in 0..n {
+            let byte = self
+                .data
+                .get(self.pos / 8)
+                .ok_or_else(|| anyhow!("Unexpected end of compressed data"))?;
+            res |= usize::from((byte >> (self.pos % 8)) & 1) << i;
+            self.pos += 1;
+        }
+        Ok(res)
+    }
+}
+
+/// A canonical Huffman code, as the number of codes of each length and the symbols in order of their codes
+struct Huffman {
+    counts: [usize; 16],
+    symbols: Vec<usize>,
+}
+
+impl Huffman {
+    fn new(lengths: &[usize]) -> Self {
+        let mut counts = [0; 16];
+        for &len in lengths {
+            counts[len] += 1;
+        }
+        counts[0] = 0;
+        let mut symbols = vec![];
+        for len in 1..16 {
+            symbols.extend((0..lengths.len()).filter(|&s| lengths[s] == len));
+        }
+        Self { counts, symbols }
+    }
+
+    fn decode(&self, bits: &mut Bits) -> anyhow::Result<usize> {
+        let (mut code, mut first, mut index) = (0, 0, 0);
+        for len in 1..16 {
+            code |= bits.bits(1)?;
+            let count = self.counts[len];
+            if code < first + count {
+                return Ok(self.symbols[index + code - first]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        bail!("Invalid code in compressed data")
+    }
+}
+
+const LENGTH_BASE: [usize; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [usize; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [usize; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [usize; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// The codes of a block compressed with the codes given in its header
+fn dynamic_codes(bits: &mut Bits) -> anyhow::Result<(Huffman, Huffman)> {
+    let literals = bits.bits(5)? + 257;
+    let distances = bits.bits(5)? + 1;
+    let code_lengths = bits.bits(4)? + 4;
+    let mut lengths = [0; 19];
+    for &i in &[
+        16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+    ][..code_lengths]
+    {
+        lengths[i] = bits.bits(3)?;
+    }
+    let lengths_code = Huffman::new(&lengths);
+
+    let mut lengths = vec![];
+    while lengths.len() < literals + distances {
+        let (len, repeat) = match lengths_code.decode(bits)? {
+            16 => (
+                *lengths
+                    .last()
+                    .ok_or_else(|| anyhow!("Repeat of no length in compressed data"))?,
+                3 + bits.bits(2)?,
+            ),
+            17 => (0, 3 + bits.bits(3)?),
+            18 => (0, 11 + bits.bits(7)?),
+            len => (len, 1),
+        };
+        lengths.extend(std::iter::repeat_n(len, repeat));
+    }
+    if lengths.len() > literals + distances {
+        bail!("Too many code lengths in compressed data");
+    }
+    Ok((
+        Huffman::new(&lengths[..literals]),
+        Huffman::new(&lengths[literals..]),
+    ))
+}
+
+/// Decompresses raw deflate data
+pub fn inflate(data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut bits = Bits { data, pos: 0 };
+    let mut res = vec![];
+    loop {
+        let last = bits.bits(1)? == 1;
+        let (literals, distances) = match bits.bits(2)? {
+            0 => {
+                // Stored, starting at the next byte
+                let start = bits.pos.div_ceil(8);
+                let len = u16_at(data, start)?;
+                let block = data
+                    .get(start + 4..start + 4 + len)
+                    .ok_or_else(|| anyhow!("Unexpected end of compressed data"))?;
+                res.extend_from_slice(block);
+                bits.pos = (start + 4 + len) * 8;
+                if last {
+                    return Ok(res);
+                }
+                continue;
+            }
+            1 => {
+                let mut lengths = [8; 288];
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+            }
+            2 => dynamic_codes(&mut bits)?,
+            _ => bail!("Invalid block type in compressed data"),
+        };
+        loop {
+            let symbol = literals.decode(&mut bits)?;
+            if symbol < 256 {
+                res.push(u8::try_from(symbol)?);
+                continue;
+            }
+            if symbol == 256 {
+                break;
+            }
+            let i = symbol - 257;
+            let (Some(base), Some(extra)) = (LENGTH_BASE.get(i), LENGTH_EXTRA.get(i)) else {
+                bail!("Invalid length in compressed data");
+            };
+            let len = base + bits.bits(*extra)?;
+            let i = distances.decode(&mut bits)?;
+            let (Some(base), Some(extra)) = (DIST_BASE.get(i), DIST_EXTRA.get(i)) else {
+                bail!("Invalid distance in compressed data");
+            };
+            let dist = base + bits.bits(*extra)?;
+            if dist > res.len() {
+                bail!("Distance too far back in compressed data");
+            }
+            // Copied byte by byte, since the copy may overlap what it produces
+            for _ in 0..len {
+                res.push(res[res.len() - dist]);
+            }
+        }
+        if last {
+            return Ok(res);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn deflate() {
+        // Compressed with Python's `zlib.compressobj(9, zlib.DEFLATED, -15)`
+        assert_eq!(
+            inflate(&[243, 72, 205, 201, 201, 7, 0]).unwrap(),
+            b"Hello".to_vec()
+        );
+        assert_eq!(
+            inflate(&[221, 204, 49, 18, 67, 64, 20, 135, 241, 171, 252, 15, 144, 113, 0, 141, 38, 10, 141, 34, 99, 152, 148, 15, 203, 190, 88, 251, 88, 187, 17, 78, 207, 184, 67, 26, 245, 247, 155, 175, 208, 10, 115, 224, 102, 64, 237, 100, 181, 232, 228, 135, 79, 24, 167, 5, 242, 85, 14, 254, 204, 134, 246, 13, 173, 244, 17, 138, 191, 225, 87, 90, 102, 105, 21, 163, 210, 228, 193, 203, 85, 27, 154, 216, 147, 129, 116, 200, 108, 203, 148, 32, 87, 43, 158, 202, 104, 126, 64, 236, 101, 222, 52, 6, 75, 112, 124, 46, 239, 120, 57, 0]).unwrap(),
+            b"The quick brown fox jumps over the lazy dog. The quick brown fox jumps over the lazy dog. The quick brown fox jumps over the lazy dog. REVIEW: What is the capital of India? New Delhi, on the Yamuna river. REVIEW: What is the capital of India? New Delhi, on the Yamuna river. REVIEW: What is the capital of India? New Delhi, on the Yamuna river. REVIEW: What is the capital of India? New Delhi, on the Yamuna river. ".to_vec()
+        );
+        assert_eq!(
+            inflate(&[1, 7, 0, 248, 255, 115, 116, 111, 114, 101, 100, 33]).unwrap(),
+            b"stored!".to_vec()
+        );
+    }
+
+    #[test]
+    pub fn archive() {
+        let path = std::env::temp_dir().join(format!("cardsharp-zip-{}", std::process::id()));
+        let entries = vec![
+            ("collection.anki21".to_string(), vec![1, 2, 3]),
+            ("media".to_string(), b"{}".to_vec()),
+        ];
+        write(&path, &entries).unwrap();
+        assert_eq!(read(&path).unwrap(), entries);
+        std::fs::remove_file(path).unwrap();
+    }
+}